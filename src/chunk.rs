@@ -0,0 +1,311 @@
+//! Chunk files, the exchange format for Smalltalk source. A chunk ends with
+//! `!`, a `!` inside a chunk is doubled. An empty chunk announces a
+//! declaration like `Account methodsFor: 'accessing'`, the chunks following
+//! it are the methods, up to the next empty chunk. All other chunks are
+//! doits, evaluated in file order, class definitions among them.
+
+use std::{collections::BTreeMap, error::Error, fmt::Display, fs, path::Path, rc::Rc};
+
+use santiago::lexer::Position;
+use tracing::info;
+
+use crate::{
+    parse_method, parse_script,
+    parser::{position_at, Diagnostic, SyntaxError, AST},
+    runtime::{
+        cls::{ClassDef, ClassTable},
+        mtd,
+        sel::SelectorSet,
+    },
+    Workspace,
+};
+
+#[derive(Debug)]
+pub struct ChunkError(pub String);
+
+impl Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ChunkError {}
+
+/// the text of a chunk without the terminating `!`, doubled `!` are single again
+pub struct Chunk {
+    pub text: String,
+    /// byte offset of the text in the file
+    pub offset: usize,
+}
+
+impl Chunk {
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+}
+
+/// splits a chunk file, text after the last `!` is a chunk as well unless
+/// it is only white space
+pub fn chunks(source: &str) -> Vec<Chunk> {
+    let mut result = vec![];
+    let mut text = String::new();
+    let mut offset = 0;
+    let mut chars = source.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c != '!' {
+            text.push(c);
+        } else if chars.next_if(|(_, c)| *c == '!').is_some() {
+            text.push('!');
+        } else {
+            result.push(Chunk {
+                text: std::mem::take(&mut text),
+                offset,
+            });
+            offset = idx + 1;
+        }
+    }
+    if !text.trim().is_empty() {
+        result.push(Chunk { text, offset });
+    }
+    result
+}
+
+/// reads a chunk file into the workspace, answers the names of the
+/// classes it defined
+pub fn file_in(workspace: &mut Workspace, file: &str, source: &str) -> Result<Vec<&'static str>, Box<dyn Error>> {
+    let mut classes = vec![];
+    let mut chunks = chunks(source).into_iter();
+    while let Some(chunk) = chunks.next() {
+        if !chunk.is_empty() {
+            if let Some(name) = doit(workspace, file, source, &chunk)? {
+                classes.push(name);
+            }
+            continue;
+        }
+        let declaration = match chunks.next() {
+            Some(d) => d,
+            None => break,
+        };
+        let words: Vec<&str> = declaration.text.split_whitespace().collect();
+        match words.as_slice() {
+            [_, "commentStamp:", ..] => {
+                chunks.next();
+            }
+            [class, "methodsFor:", ..] | [class, "class", "methodsFor:", ..] => {
+                let class_side = words[1] == "class";
+                if ClassTable::get(class).is_none() {
+                    if let Some(class) = mtd::builtin(class) {
+                        for m in chunks.by_ref() {
+                            if m.is_empty() {
+                                break;
+                            }
+                            let method = parse_method(m.text.clone()).map_err(|e| located(e, file, source, &m))?;
+                            mtd::define_method(class, class_side, &method[0].as_abstract_syntax_tree())
+                                .map_err(|e| located(Box::new(e), file, source, &m))?;
+                        }
+                        continue;
+                    }
+                }
+                let class = ClassTable::get(class)
+                    .ok_or_else(|| located_error(file, source, &declaration, format!("class {} is not defined", class)))?;
+                let category = declaration.text.split_once('\'').map(|(_, c)| string_value(c));
+                for m in chunks.by_ref() {
+                    if m.is_empty() {
+                        break;
+                    }
+                    let selector = file_in_method(&class, class_side, file, source, &m)?;
+                    if let Some(category) = category {
+                        class.set_method_category(class_side, selector, category);
+                    }
+                }
+            }
+            _ => {
+                let msg = format!("unsupported declaration {}", declaration.text.trim());
+                return Err(located_error(file, source, &declaration, msg));
+            }
+        }
+    }
+    Ok(classes)
+}
+
+pub fn file_in_path(workspace: &mut Workspace, path: &Path) -> Result<Vec<&'static str>, Box<dyn Error>> {
+    let source = fs::read_to_string(path)?;
+    file_in(workspace, &path.display().to_string(), &source)
+}
+
+/// the classes with their methods in chunk format, methods are grouped by category
+pub fn file_out(classes: &[&str]) -> Result<String, ChunkError> {
+    let mut out = String::new();
+    for name in classes {
+        let class = ClassTable::get(name).ok_or_else(|| ChunkError(format!("class {} is not defined", name)))?;
+        out.push_str(&format!(
+            "{} subclass: #{}\n    instanceVariableNames: '{}'\n    classVariableNames: '{}'\n    category: '{}'!\n\n",
+            class.superclass.unwrap_or("nil"),
+            class.name,
+            class.inst_vars.join(" "),
+            class.class_vars.join(" "),
+            class.category().replace('\'', "''"),
+        ));
+        for class_side in [false, true] {
+            let mut categories: BTreeMap<&str, Vec<Rc<AST>>> = BTreeMap::new();
+            for m in class.methods(class_side) {
+                if let AST::Method { name, .. } = &*m {
+                    let category = class.method_category(class_side, name);
+                    categories.entry(category).or_default().push(m.clone());
+                }
+            }
+            let side = if class_side { " class" } else { "" };
+            for (category, methods) in categories {
+                out.push_str(&format!(
+                    "!{}{} methodsFor: '{}'!\n",
+                    class.name,
+                    side,
+                    category.replace('\'', "''")
+                ));
+                for (idx, m) in methods.iter().enumerate() {
+                    if idx > 0 {
+                        out.push('\n');
+                    }
+                    out.push_str(&m.to_source().replace('!', "!!"));
+                    out.push_str("\n!");
+                }
+                out.push_str(" !\n\n");
+            }
+        }
+    }
+    Ok(out)
+}
+
+pub fn file_out_path(path: &Path, classes: &[&str]) -> Result<(), Box<dyn Error>> {
+    fs::write(path, file_out(classes)?)?;
+    Ok(())
+}
+
+/// defines the class of a class definition, evaluates everything else
+fn doit(
+    workspace: &mut Workspace,
+    file: &str,
+    source: &str,
+    chunk: &Chunk,
+) -> Result<Option<&'static str>, Box<dyn Error>> {
+    let trees = parse_script(chunk.text.clone()).map_err(|e| located(e, file, source, chunk))?;
+    if let Some((class, category)) = class_definition(&trees[0].as_abstract_syntax_tree()) {
+        let class = ClassTable::define(class);
+        class.set_category(category);
+        info!("{}: defined class {}", file, class.name);
+        return Ok(Some(class.name));
+    }
+    workspace.evaluate(&chunk.text)?;
+    Ok(None)
+}
+
+fn file_in_method(
+    class: &ClassDef,
+    class_side: bool,
+    file: &str,
+    source: &str,
+    chunk: &Chunk,
+) -> Result<&'static str, Box<dyn Error>> {
+    let trees = parse_method(chunk.text.clone()).map_err(|e| located(e, file, source, chunk))?;
+    let method = trees[0].as_abstract_syntax_tree();
+    let selector = match &method {
+        AST::Method { name, .. } => *name,
+        _ => return Err(located_error(file, source, chunk, "not a method".to_string())),
+    };
+    if class_side {
+        class.add_class_method(method);
+    } else {
+        class.add_method(method);
+    }
+    Ok(selector)
+}
+
+/// `Super subclass: #Name instanceVariableNames: '...' classVariableNames: '...'
+/// category: '...'`, the keywords after the first are optional, `package:` is
+/// taken for `category:`
+fn class_definition(ast: &AST) -> Option<(ClassDef, &'static str)> {
+    let (receiver, msgs) = match ast {
+        AST::Statements(stmts) if stmts.len() == 1 => match &stmts[0] {
+            AST::InvokeSequence(receiver, msgs) if msgs.len() == 1 => (receiver, msgs),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (selector, args) = match &msgs[0] {
+        AST::Message { name, args, .. } if name.starts_with("subclass:") => (*name, args),
+        _ => return None,
+    };
+    let superclass = match &**receiver {
+        AST::Name("nil") | AST::Variable("nil", _) => None,
+        AST::Name(n) | AST::Variable(n, _) => Some(*n),
+        _ => return None,
+    };
+    let name = match &args[0] {
+        AST::Symbol(s) => *s,
+        _ => return None,
+    };
+    let (mut inst_vars, mut class_vars, mut pools, mut category) = (vec![], vec![], vec![], "Unclassified");
+    for (keyword, arg) in selector.split_inclusive(':').zip(args).skip(1) {
+        let value = match arg {
+            AST::String(s) => s.as_str(),
+            _ => return None,
+        };
+        let names = || value.split_whitespace().map(SelectorSet::get).collect();
+        match keyword {
+            "instanceVariableNames:" => inst_vars = names(),
+            "classVariableNames:" => class_vars = names(),
+            "category:" | "package:" => category = SelectorSet::get(value),
+            "poolDictionaries:" => pools = names(),
+            _ => return None,
+        }
+    }
+    let class = ClassDef::new(name, superclass, &inst_vars, &class_vars);
+    pools.into_iter().for_each(|p| class.import_pool(p));
+    Some((class, category))
+}
+
+/// the value of a string literal, `rest` starts after the opening quote
+fn string_value(rest: &str) -> &'static str {
+    let mut value = String::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+            break;
+        }
+        value.push(c);
+    }
+    SelectorSet::get(&value)
+}
+
+/// moves the diagnostics of a syntax error in a chunk to their place in the file
+fn located(e: Box<dyn Error>, file: &str, source: &str, chunk: &Chunk) -> Box<dyn Error> {
+    match e.downcast::<SyntaxError>() {
+        Ok(e) => {
+            let start = position_at(source, chunk.offset);
+            let diagnostics = e
+                .0
+                .into_iter()
+                .map(|d| {
+                    let position = Position {
+                        line: start.line + d.position.line - 1,
+                        column: if d.position.line == 1 {
+                            start.column + d.position.column - 1
+                        } else {
+                            d.position.column
+                        },
+                    };
+                    Diagnostic::new(file, source, position, d.message)
+                })
+                .collect();
+            Box::new(SyntaxError(diagnostics))
+        }
+        Err(e) => e,
+    }
+}
+
+fn located_error(file: &str, source: &str, chunk: &Chunk, message: String) -> Box<dyn Error> {
+    let text = &chunk.text;
+    let skipped = text.len() - text.trim_start().len();
+    let position = position_at(source, chunk.offset + skipped);
+    Box::new(SyntaxError(vec![Diagnostic::new(file, source, position, message)]))
+}
@@ -1,46 +1,125 @@
 
+pub mod file;
+mod opt;
+
 use std::{
+    cell::RefCell,
     collections::BTreeMap,
     fmt::Display,
     ops::{Deref, DerefMut},
     rc::Rc,
-    sync::Mutex,
 };
 
-use once_cell::sync::Lazy;
+use num_bigint::BigInt;
+use santiago::lexer::Position;
 
 use crate::{
+    image::Snapshot,
     parse_script,
     parser::AST,
     runtime::{
-        chr::CharReceiver, int::IntReceiver, sel::SelectorSet, str::StringReceiver, Receiver,
+        arr::ArrayReceiver,
+        blk,
+        boo::{self, FalseReceiver, TrueReceiver},
+        byt::ByteArrayReceiver,
+        chr::CharReceiver,
+        cls::{self, ClassReceiver},
+        exc,
+        int::IntReceiver,
+        mtd::{self, SendSite},
+        nil::NilReciever,
+        num::{FloatReceiver, Number},
+        sel::SelectorSet,
+        str::StringReceiver,
+        sym::SymbolReceiver,
+        val::Value,
+        Receiver,
     },
+    debug, global, heap, trace,
+    unwind::{self, Exit},
     BlockContext, ContextRef,
 };
 
-#[allow(dead_code)]
-struct MethodCache {
-    cache: Mutex<BTreeMap<String, &'static CompiledMethod>>,
+thread_local! {
+    static METHOD_CACHE: RefCell<BTreeMap<String, Rc<CompiledMethod>>> = const { RefCell::new(BTreeMap::new()) };
 }
 
+/// compiled methods by label, the methods of user classes are compiled on
+/// their first send and dropped again when the class changes them
+pub(crate) struct MethodCache;
+
 impl MethodCache {
     #[allow(dead_code)]
     pub fn add(name: &str, meth: CompiledMethod) {
-        let mut c = METHOD_CACHE.cache.try_lock().unwrap();
-
-        c.insert(name.into(), Box::leak(Box::new(meth)));
+        Self::insert(name, Rc::new(meth));
     }
 
     #[allow(dead_code)]
-    pub fn get(name: &str) -> &'static CompiledMethod {
-        let c = METHOD_CACHE.cache.try_lock().unwrap();
-        c.get(name).unwrap()
+    pub fn get(name: &str) -> Option<Rc<CompiledMethod>> {
+        METHOD_CACHE.with(|c| c.borrow().get(name).cloned())
+    }
+
+    pub(crate) fn insert(name: &str, meth: Rc<CompiledMethod>) {
+        METHOD_CACHE.with(|c| c.borrow_mut().insert(name.into(), meth));
+    }
+
+    /// the cached methods by name, written to images
+    pub(crate) fn entries() -> Vec<(String, Rc<CompiledMethod>)> {
+        METHOD_CACHE.with(|c| c.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+
+    /// the compiled form of `method`, a method of `class`
+    pub(crate) fn lookup_or_compile(
+        class: &str,
+        class_side: bool,
+        method: &AST,
+    ) -> Result<Rc<CompiledMethod>, CompileError> {
+        let label = match method {
+            AST::Method { name, .. } => method_label(class, class_side, name),
+            _ => panic!("not a method: {:?}", method),
+        };
+        if let Some(m) = Self::get(&label) {
+            return Ok(m);
+        }
+        let m = Rc::new(CompiledMethod::compile_method(method, &label)?);
+        Self::insert(&label, m.clone());
+        Ok(m)
+    }
+
+    /// drops a method after it was replaced, with `None` all methods of the class
+    pub(crate) fn forget(class: &str, class_side: bool, selector: Option<&str>) {
+        mtd::changed();
+        METHOD_CACHE.with(|c| match selector {
+            Some(selector) => {
+                c.borrow_mut().remove(&method_label(class, class_side, selector));
+            }
+            None => {
+                let own = |k: &String| k.split_once(">>").is_some_and(|(c, _)| c.trim_end_matches(" class") == class);
+                c.borrow_mut().retain(|k, _| !own(k));
+            }
+        })
     }
 }
 
-static METHOD_CACHE: Lazy<MethodCache> = Lazy::new(|| MethodCache {
-    cache: Mutex::new(BTreeMap::new()),
-});
+/// `Class>>selector` or `Class class>>selector`, names methods in stack traces
+pub(crate) fn method_label(class: &str, class_side: bool, selector: &str) -> String {
+    match class_side {
+        true => format!("{} class>>{}", class, selector),
+        false => format!("{}>>{}", class, selector),
+    }
+}
+
+/// a parse tree the compiler has no code for
+#[derive(Debug)]
+pub struct CompileError(pub String);
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for CompileError {}
 
 #[derive(Debug)]
 pub struct CompiledMethod {
@@ -64,99 +143,304 @@ impl DerefMut for CompiledMethod {
 #[derive(Debug)]
 pub struct CompiledMethodData {
     blocks: Vec<ByteCode>,
-    stack: Vec<CodeAddress>,
     current_block: usize,
+    /// the names visible while compiling, the ones of a block are dropped
+    /// at its end
     names: Vec<(String, CodeAddress)>,
+    /// the script or method the code was compiled from
+    source: String,
+    /// `UndefinedObject>>DoIt` for scripts, `Class>>selector` for methods
+    label: &'static str,
+    block_label: &'static str,
+    selector: Option<&'static str>,
 }
 
+/// a block closure: the code of the block and the activation it was created in
 pub struct CompiledBlock {
-    method: &'static CompiledMethod,
+    method: Rc<CompiledMethod>,
     ctx: ContextRef,
     block: usize,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone)]
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Clone)]
 pub struct CodeAddress(pub usize, pub usize);
 
 #[derive(Debug)]
 pub struct ByteCode {
     result: Option<CodeAddress>,
     opcode: Vec<Operation>,
+    /// the block the block is nested in
+    outer: usize,
+    args: usize,
+    /// the arguments and temporaries of the block by name
+    vars: Vec<(String, CodeAddress)>,
+    /// source positions of the sends, by step
+    positions: BTreeMap<usize, Position>,
+    /// the source of the block, kept for images
+    source: String,
+    sends: SendSites,
 }
 
-#[derive(Debug)]
+/// the send sites of a block by step, made on their first send
+#[derive(Default)]
+struct SendSites(RefCell<Vec<Option<Rc<SendSite>>>>);
+
+impl std::fmt::Debug for SendSites {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendSites").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Operation {
     Int(isize),
     Str(String),
     Invoke(String, CodeAddress, Vec<CodeAddress>),
+    /// a send to `super`
+    Super(String, CodeAddress, Vec<CodeAddress>),
     Block(usize),
+    /// a name looked up when the step runs: a workspace variable, an
+    /// instance or class variable of the receiver, or a global
     Global(String),
+    /// assigns to a name looked up like `Global`
+    Store(String, CodeAddress),
     Char(char),
     String(String),
     Arg(usize),
     Return(CodeAddress),
     Param(usize),
     Myself,
+    /// a declared temporary, nil until assigned
+    Temp,
     Move(CodeAddress, Option<CodeAddress>),
+    Symbol(String),
+    Literal(LiteralValue),
+    /// a brace array `{a. b}`
+    Array(Vec<CodeAddress>),
+    /// continues at a step of the block
+    Jump(usize),
+    /// continues at a step of the block if the value is the Boolean given
+    JumpIf(CodeAddress, bool, usize),
+}
+
+/// constant values of literal arrays, they are instantiated every time the
+/// literal is evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    Int(isize),
+    LargeInt(BigInt),
+    Float(f64),
+    Char(char),
+    String(String),
+    Symbol(String),
+    Array(Vec<LiteralValue>),
+    ByteArray(Vec<u8>),
+    True,
+    False,
+    Nil,
+}
+
+impl LiteralValue {
+    pub fn from_ast(ast: &AST) -> Self {
+        match ast {
+            AST::Int(v) => LiteralValue::Int(*v),
+            AST::LargeInt(v) => LiteralValue::LargeInt(v.clone()),
+            AST::Float(v) => LiteralValue::Float(*v),
+            AST::Char(v) => LiteralValue::Char(*v),
+            AST::String(v) => LiteralValue::String(v.to_string()),
+            AST::Symbol(v) => LiteralValue::Symbol(v.to_string()),
+            AST::LiteralArray(v) => LiteralValue::Array(v.iter().map(LiteralValue::from_ast).collect()),
+            AST::ByteArray(v) => LiteralValue::ByteArray(v.clone()),
+            AST::Variable("true", _) => LiteralValue::True,
+            AST::Variable("false", _) => LiteralValue::False,
+            _ => LiteralValue::Nil,
+        }
+    }
+
+    pub fn to_receiver(&self) -> Rc<dyn Receiver> {
+        match self {
+            LiteralValue::Int(v) => Rc::new(IntReceiver::new(*v)),
+            LiteralValue::LargeInt(v) => Number::Large(v.clone()).into_receiver(),
+            LiteralValue::Float(v) => Rc::new(FloatReceiver::new(*v)),
+            LiteralValue::Char(v) => Rc::new(CharReceiver::new(*v)),
+            LiteralValue::String(v) => Rc::new(StringReceiver::new(v.clone())),
+            LiteralValue::Symbol(v) => Rc::new(SymbolReceiver::new(v)),
+            LiteralValue::Array(v) => Rc::new(ArrayReceiver(v.iter().map(|x| x.to_receiver()).collect())),
+            LiteralValue::ByteArray(v) => Rc::new(ByteArrayReceiver::new(v.clone())),
+            LiteralValue::True => TrueReceiver::get(),
+            LiteralValue::False => FalseReceiver::get(),
+            LiteralValue::Nil => NilReciever::get(),
+        }
+    }
+
+    /// the literal as a value, immediates are not boxed
+    pub fn to_value(&self) -> Value {
+        match self {
+            LiteralValue::Int(v) => Value::Int(*v),
+            LiteralValue::Char(v) => Value::Char(*v),
+            LiteralValue::True => Value::Bool(true),
+            LiteralValue::False => Value::Bool(false),
+            LiteralValue::Nil => Value::Nil,
+            _ => Value::Object(self.to_receiver()),
+        }
+    }
 }
 
 impl CompiledMethod {
-    fn process_step(&'static self, ctx: ContextRef) {
-        let CodeAddress(block, step) = ctx.ip();
-        let op = &self.blocks[block].opcode[step];
-        let v: Rc<dyn Receiver> = match op {
-            Operation::Int(v) => Rc::new(IntReceiver::new(*v)),
+    fn process_step(self: &Rc<Self>, ctx: &ContextRef, code: &ByteCode) {
+        let ip = ctx.ip();
+        let v = match &code.opcode[ip.1] {
+            Operation::Int(v) => Value::Int(*v),
             Operation::Invoke(selector, receiver, args) => {
                 let receiver = ctx.get_value(receiver);
                 let args = ctx.get_values(args.as_slice());
-                let r = receiver.receive_message(SelectorSet::get(selector), args);
-                r
+                code.set_position(ip.1);
+                code.send_site(ip.1, selector).send(&receiver, args)
+            }
+            Operation::Super(selector, receiver, args) => {
+                let receiver = ctx.get_value(receiver);
+                let args = ctx.get_values(args.as_slice());
+                code.set_position(ip.1);
+                let selector = SelectorSet::get(selector);
+                match ctx.class() {
+                    Some((class, class_side)) => cls::send_super(&class, class_side, receiver, selector, args),
+                    None => receiver.receive_message(selector, args),
+                }
             }
             Operation::Block(b) => {
-                let bctx = BlockContext::new(ctx.clone());
-                Rc::new(CompiledBlock::new(self, bctx, *b))
+                let block: Rc<dyn Receiver> = Rc::new(CompiledBlock::new(self.clone(), ctx.clone(), *b));
+                heap::track_receiver(&block);
+                Value::Object(block)
+            }
+            Operation::Char(v) => Value::Char(*v),
+            Operation::Str(v) | Operation::String(v) => Value::Object(Rc::new(StringReceiver::new(v.clone()))),
+            Operation::Return(addr) => {
+                let mut v = ctx.get_value(addr);
+                if ip.0 != 0 {
+                    // a resumed BlockCannotReturn ends the block with the resumption value
+                    v = unwind::return_from(ctx.home(), v);
+                }
+                // a return in the method itself ends the method
+                ctx.set_result(v);
+                ctx.call(CodeAddress(ip.0, code.opcode.len()));
+                return;
+            }
+            Operation::Symbol(v) => Value::Object(Rc::new(SymbolReceiver::new(v))),
+            Operation::Global(name) => match ctx.lookup(name) {
+                Some(v) => v,
+                None => global(name),
+            },
+            Operation::Store(name, from) => {
+                let v = ctx.get_value(from);
+                ctx.assign(SelectorSet::get(name), v.clone());
+                v
             }
-            Operation::Char(v) => Rc::new(CharReceiver::new(*v)),
-            Operation::String(v) => Rc::new(StringReceiver::new(v.clone())),
-            Operation::Return(addr) => ctx.get_value(addr),
-            Operation::Move(from, Some(to)) => {
+            Operation::Literal(v) => v.to_value(),
+            Operation::Arg(n) | Operation::Param(n) => ctx.arg(*n),
+            Operation::Myself => Value::from_receiver(ctx.receiver()),
+            Operation::Temp => Value::Nil,
+            Operation::Move(from, to) => {
                 let v = ctx.get_value(from);
-                ctx.set_value(to, v.clone());
+                if let Some(to) = to {
+                    ctx.set_value(to, v.clone());
+                }
                 v
             }
-            _ => todo!("{:?}", op),
+            Operation::Array(items) => {
+                let items = ctx.get_values(items.as_slice()).iter().map(Value::to_receiver).collect();
+                let array: Rc<dyn Receiver> = Rc::new(ArrayReceiver(items));
+                heap::track_receiver(&array);
+                Value::Object(array)
+            }
+            Operation::Jump(step) => {
+                ctx.call(CodeAddress(ip.0, *step));
+                return;
+            }
+            Operation::JumpIf(cond, when, step) => {
+                if boo::truth(&ctx.get_value(cond)) == *when {
+                    ctx.call(CodeAddress(ip.0, *step));
+                } else {
+                    ctx.next_ip();
+                }
+                return;
+            }
         };
-        {
-            let ip = &ctx.ip();
-            ctx.set_value(ip, v);
-            ctx.next_ip();
+        ctx.set_value(&ip, v);
+        ctx.next_ip();
+    }
+
+    /// runs the method in `ctx`, a method activation that ends early on a return
+    pub fn run(self: &Rc<Self>, ctx: ContextRef) -> Value {
+        let _frame = trace::enter(self.label);
+        let _activation = heap::enter(&ctx);
+        let home = ctx.home();
+        unwind::activate(home, || self.run_block(&ctx))
+    }
+
+    /// runs from the current instruction to the end of its block
+    fn run_block(self: &Rc<Self>, ctx: &ContextRef) -> Value {
+        let CodeAddress(block, _) = ctx.ip();
+        let code = &self.blocks[block];
+        while ctx.ip().1 < code.opcode.len() {
+            debug::before_step(self, ctx);
+            self.process_step(ctx, code);
+        }
+        if let Some(v) = ctx.result() {
+            return v;
+        }
+        match code.result {
+            Some(addr) => ctx.get_value(&addr),
+            None => Value::Nil,
         }
     }
+}
 
-    pub fn run(&'static self, ctx: ContextRef) -> Rc<dyn Receiver> {
-        while !self.done(ctx.clone()) {
-            self.process_step(ctx.clone());
+impl CompiledBlock {
+    pub fn new(method: Rc<CompiledMethod>, ctx: ContextRef, block: usize) -> Self {
+        Self { method, ctx, block }
+    }
+
+    /// the blocks read from an image that captured one context: their
+    /// sources are compiled as a script that declares the `captured`
+    /// variables, they run in `ctx` that has the receiver and the named
+    /// variables they saw
+    pub(crate) fn restore(sources: &[&str], captured: &[&str], ctx: ContextRef) -> Result<Vec<Rc<Self>>, String> {
+        let mut script = String::new();
+        if !captured.is_empty() {
+            script = format!("| {} | ", captured.join(" "));
         }
-        let CodeAddress(block, _) = ctx.ip();
-        ctx.get_value(&self.blocks[block].result.unwrap())
+        script.push_str(&sources.join(". "));
+        let method = compile_script(script.clone()).map_err(|e| e.to_string())?;
+        // the blocks of the script itself are the restored ones, in order
+        let blocks: Vec<usize> = (1..method.blocks.len()).filter(|b| method.blocks[*b].outer == 0).collect();
+        if blocks.len() != sources.len() {
+            return Err(format!("not a list of blocks: {}", script));
+        }
+        Ok(blocks.into_iter().map(|b| Rc::new(Self::new(method.clone(), ctx.clone(), b))).collect())
     }
 
-    fn done(&self, ctx: ContextRef) -> bool {
-        let CodeAddress(blk, stp) = ctx.ip();
-        let b = &self.blocks[blk];
-        b.opcode.len() <= stp
+    /// sets a variable the block captured from its context
+    pub(crate) fn set_captured(&self, name: &str, value: Value) {
+        let found = self.method.blocks[0].vars.iter().find(|(n, _)| n == name);
+        if let Some((_, addr)) = found {
+            self.ctx.set_value(addr, value);
+        }
     }
 
-    fn get_operation(&self, ip: CodeAddress) -> &Operation {
-        let CodeAddress(blk, stp) = ip;
-        let b = &self.blocks[blk];
-        &b.opcode[stp]
+    fn code(&self) -> &ByteCode {
+        &self.method.blocks[self.block]
     }
-}
 
-impl CompiledBlock {
-    pub fn new(method: &'static CompiledMethod, ctx: ContextRef, block: usize) -> Self {
-        Self { method, ctx, block }
+    /// evaluates the block in a new activation
+    fn value(&self, args: Vec<Value>) -> Value {
+        let code = self.code();
+        if args.len() != code.args {
+            return exc::wrong_argument_count(code.args, args.len());
+        }
+        let ctx: ContextRef = BlockContext::new(self.ctx.clone(), self.block, args);
+        let _frame = trace::enter(self.method.block_label);
+        let _activation = heap::enter(&ctx);
+        self.method.run_block(&ctx)
     }
 }
 
@@ -164,40 +448,83 @@ impl Receiver for CompiledBlock {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
-            "value:" => {
-                // let mut p = self.ctx.params.lock().unwrap();
-                // for x in _args {
-                //     p.push(x.clone())
-                // }
-                self.ctx.call(CodeAddress(self.block, 0));
-                while let Operation::Arg(n) = self.method.get_operation(self.ctx.ip()) {
-                    self.ctx.set_value(&self.ctx.ip(), args[*n].clone());
-                    self.ctx.next_ip();
-                }
-
-                self.method.run(self.ctx.clone())
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(String::from("a BlockClosure"));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !blk::PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "value" | "value:" | "value:value:" | "value:value:value:" | "value:value:value:value:" => {
+                self.value(args)
             }
-            "value" => {
-                // while let Operation::Arg(n) = self.method.get_operation(self.ctx.ip()) {
-                //     self.ctx.set_value(&self.ctx.ip(), args[*n].clone());
-                //     self.ctx.next_ip();
-                // }
-                self.ctx.call(CodeAddress(self.block, 0));
-                self.method.run(self.ctx.clone())
+            "numArgs" => Value::Int(self.code().args as isize),
+            "on:do:" => exc::on_do(
+                || self.receive_message("value", vec![]),
+                args[0].clone(),
+                args[1].clone(),
+            ),
+            "ensure:" => exc::ensure(|| self.receive_message("value", vec![]), args[0].clone()),
+            "ifCurtailed:" => {
+                exc::if_curtailed(|| self.receive_message("value", vec![]), args[0].clone())
             }
-            s => todo!("doesn't understand {}", s),
+            _ => match blk::loops(self, selector, &args) {
+                Some(r) => r,
+                None => self.does_not_understand(selector, args),
+            },
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "BlockClosure"
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        visit(heap::address(&self.ctx));
+    }
+
+    /// the source of the block and the context it captured. Blocks made in
+    /// one activation share the context, so it is written once for them.
+    fn snapshot(&self) -> Option<Snapshot> {
+        let (class, class_side): (Rc<dyn Receiver>, _) = match self.ctx.class() {
+            Some((c, side)) => (Rc::new(ClassReceiver(c)), side),
+            None => (NilReciever::get(), false),
+        };
+        let variables = self.ctx.variables();
+        let mut s = Snapshot::new("Context", "")
+            .slot("self", self.ctx.receiver())
+            .slot("class", class)
+            .slot("classSide", Number::Small(class_side as isize).into_receiver())
+            .shared("variables", heap::address(&variables), Snapshot::of_variables(&variables));
+        // the enclosing blocks, the innermost last so its names win
+        let mut scopes = vec![];
+        let mut b = self.block;
+        while b != 0 {
+            b = self.method.blocks[b].outer;
+            scopes.push(b);
+        }
+        let mut captured = BTreeMap::new();
+        for b in scopes.into_iter().rev() {
+            for (name, addr) in self.method.blocks[b].vars.iter().filter(|(n, _)| n != "self") {
+                if let Some(v) = self.ctx.find_value(addr) {
+                    captured.insert(SelectorSet::get(name), v);
+                }
+            }
+        }
+        for (name, v) in captured {
+            s = s.slot(name, v.to_receiver());
+        }
+        let context = heap::address(&self.ctx);
+        Some(Snapshot::new("CompiledBlock", self.code().source.clone()).shared("context", context, s))
     }
 }
 
@@ -209,12 +536,35 @@ impl Display for CodeAddress {
 
 impl Copy for CodeAddress {}
 
+impl CodeAddress {
+    /// reads the form `Display` writes, `r1-4`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (block, step) = text.strip_prefix('r')?.split_once('-')?;
+        Some(CodeAddress(block.parse().ok()?, step.parse().ok()?))
+    }
+}
+
 impl ByteCode {
     pub fn new() -> Self {
         Self {
             result: None,
             opcode: vec![],
+            outer: 0,
+            args: 0,
+            vars: vec![],
+            positions: BTreeMap::new(),
+            source: String::new(),
+            sends: SendSites::default(),
+        }
+    }
+
+    /// the send site of the send at `step`
+    fn send_site(&self, step: usize, selector: &str) -> Rc<SendSite> {
+        let mut sends = self.sends.0.borrow_mut();
+        if sends.len() <= step {
+            sends.resize(step + 1, None);
         }
+        sends[step].get_or_insert_with(|| Rc::new(SendSite::new(selector))).clone()
     }
 
     fn push(&mut self, op: Operation) -> usize {
@@ -222,10 +572,6 @@ impl ByteCode {
         self.opcode.len() - 1
     }
 
-    fn set_result(&mut self, addr: CodeAddress) {
-        self.result = Some(addr);
-    }
-
     fn result(&self) -> Option<CodeAddress> {
         self.result
     }
@@ -233,11 +579,24 @@ impl ByteCode {
     fn len(&self) -> usize {
         self.opcode.len()
     }
+
+    /// tells the stack trace which send of the block is running
+    fn set_position(&self, step: usize) {
+        if let Some(p) = self.positions.get(&step) {
+            trace::set_position(p);
+        }
+    }
+}
+
+impl Default for ByteCode {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display for CompiledMethod {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{:15}", "Compiled Method:")?;
+        writeln!(f, "{:15} {}", "Compiled Method:", self.label)?;
         for block_idx in 0..self.blocks.len() {
             writeln!(f, "---")?;
             let b = &self.blocks[block_idx];
@@ -248,7 +607,7 @@ impl Display for CompiledMethod {
             for idx in 0..b.opcode.len() {
                 let addr = CodeAddress(block_idx, idx);
                 let name = {
-                    match self.names.iter().find(|x| x.1 == addr) {
+                    match b.vars.iter().find(|x| x.1 == addr) {
                         Some((k, _)) => k.as_str(),
                         None => "",
                     }
@@ -265,114 +624,259 @@ impl CompiledMethod {
         let data = CompiledMethodData {
             current_block: 0,
             blocks: vec![ByteCode::new()],
-            stack: vec![],
             names: vec![],
+            source: String::new(),
+            label: "UndefinedObject>>DoIt",
+            block_label: "[] in UndefinedObject>>DoIt",
+            selector: None,
         };
         Self { data }
     }
 
+    /// compiles a method of a class, `label` names it in stack traces
+    pub fn compile_method(method: &AST, label: &str) -> Result<Self, CompileError> {
+        let AST::Method {
+            name,
+            params,
+            temps,
+            body,
+            ..
+        } = method
+        else {
+            panic!("not a method: {:?}", method)
+        };
+        let mut code = Self::new();
+        code.source = method.to_source();
+        code.label = SelectorSet::get(label);
+        code.block_label = SelectorSet::get(&format!("[] in {}", label));
+        code.selector = Some(name);
+        code.blocks[0].args = params.len();
+        for (idx, p) in params.iter().enumerate() {
+            let addr = code.push(Operation::Param(idx));
+            code.define(p.to_string(), addr);
+        }
+        for t in temps {
+            let addr = code.push(Operation::Temp);
+            code.define(t.to_string(), addr);
+        }
+        code.compile(body)?;
+        // without a return the method answers self
+        let myself = code.push(Operation::Myself);
+        code.blocks[0].result = Some(myself);
+        code.optimize();
+        Ok(code)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    /// the selector of a method, `None` for scripts
+    pub fn selector(&self) -> Option<&'static str> {
+        self.selector
+    }
+
+    pub fn operation(&self, addr: &CodeAddress) -> Option<&Operation> {
+        self.blocks.get(addr.0)?.opcode.get(addr.1)
+    }
+
+    /// the source position of a send, other steps have none
+    pub fn position(&self, addr: &CodeAddress) -> Option<Position> {
+        self.blocks.get(addr.0)?.positions.get(&addr.1).cloned()
+    }
+
+    /// the first send of the block on the source line
+    pub(crate) fn first_step_on_line(&self, block: usize, line: usize) -> Option<usize> {
+        let positions = &self.blocks.get(block)?.positions;
+        positions.iter().find(|(_, p)| p.line == line).map(|(step, _)| *step)
+    }
+
+    /// the blocks the code at `block` sees the values of, innermost first
+    fn scopes(&self, block: usize) -> Vec<usize> {
+        let mut scopes = vec![block];
+        let mut b = block;
+        while b != 0 {
+            b = self.blocks[b].outer;
+            scopes.push(b);
+        }
+        scopes
+    }
+
+    /// the arguments and temporaries the running block sees, the ones not
+    /// yet set are left out
+    pub(crate) fn named_values(&self, ctx: &ContextRef) -> Vec<(String, Value)> {
+        let mut values: Vec<(String, Value)> = vec![];
+        for b in self.scopes(ctx.ip().0) {
+            for (name, addr) in self.blocks[b].vars.iter() {
+                if values.iter().any(|(n, _)| n == name) {
+                    continue;
+                }
+                if let Some(v) = ctx.find_value(addr) {
+                    values.push((name.clone(), v));
+                }
+            }
+        }
+        values
+    }
+
+    /// the values the steps the running block sees have computed so far
+    pub(crate) fn step_values(&self, ctx: &ContextRef) -> Vec<(CodeAddress, Value)> {
+        let mut values = vec![];
+        for b in self.scopes(ctx.ip().0) {
+            for step in 0..self.blocks[b].len() {
+                let addr = CodeAddress(b, step);
+                if let Some(v) = ctx.find_value(&addr) {
+                    values.push((addr, v));
+                }
+            }
+        }
+        values
+    }
+
     pub fn define(&mut self, name: String, idx: CodeAddress) {
+        self.blocks[idx.0].vars.push((name.clone(), idx));
         self.names.push((name, idx));
     }
 
+    /// the innermost definition of `name`
     fn idx_for(&self, name: &str) -> Option<CodeAddress> {
-        let r = self.names.iter().find(|x| x.0 == name);
-        match r {
-            Some((_, v)) => Some(*v),
-            None => None,
-        }
+        self.names.iter().rev().find(|x| x.0 == name).map(|(_, v)| *v)
     }
 
-    pub fn compile(&mut self, ast: &AST) -> CodeAddress {
-        match ast {
+    pub fn compile(&mut self, ast: &AST) -> Result<CodeAddress, CompileError> {
+        let addr = match ast {
             AST::Int(v) => self.push(Operation::Int(*v)),
             AST::Char(v) => self.push(Operation::Char(*v)),
             AST::String(v) => self.push(Operation::String(v.to_string())),
-            AST::Block { params, body, .. } => {
-                let old_block = self.current_block;
-                self.current_block = self.blocks.len();
-                self.blocks.push(ByteCode::new());
-
-                for param_idx in 0..params.len() {
-                    let idx = self.push(Operation::Arg(param_idx));
-                    self.define(params[param_idx].to_string(), idx);
+            AST::Symbol(v) => self.push(Operation::Symbol(v.to_string())),
+            AST::LiteralArray(_) | AST::ByteArray(_) | AST::LargeInt(_) | AST::Float(_) => {
+                self.push(Operation::Literal(LiteralValue::from_ast(ast)))
+            }
+            AST::Table(items) => {
+                let items = items.iter().map(|x| self.compile(x)).collect::<Result<_, _>>()?;
+                self.push(Operation::Array(items))
+            }
+            AST::Block {
+                params,
+                temps,
+                body,
+                ..
+            } => {
+                let outer = self.current_block;
+                let block = self.blocks.len();
+                let mut code = ByteCode::new();
+                code.outer = outer;
+                code.args = params.len();
+                code.source = ast.to_source();
+                self.blocks.push(code);
+                self.current_block = block;
+                let scope = self.names.len();
+                for (idx, p) in params.iter().enumerate() {
+                    let addr = self.push(Operation::Arg(idx));
+                    self.define(p.to_string(), addr);
                 }
-                self.compile(body);
-                let block = Operation::Block(self.current_block);
-                {
-                    let block_num = self.current_block;
-                    let pos = self.len();
-                    if let Some(addr) = self.stack.pop() {
-                        let block = &mut self.blocks[block_num];
-                        block.set_result(addr);
-                    } else {
-                        let addr = CodeAddress(block_num, pos - 1);
-                        let block = &mut self.blocks[block_num];
-                        block.set_result(addr);
-                    }
+                for t in temps {
+                    let addr = self.push(Operation::Temp);
+                    self.define(t.to_string(), addr);
                 }
-                self.current_block = old_block;
-                let result_idx = self.push(block);
-                result_idx
+                let result = self.compile(body)?;
+                self.blocks[block].result = Some(result);
+                self.names.truncate(scope);
+                self.current_block = outer;
+                self.push(Operation::Block(block))
             }
             AST::Return(x) => {
-                let idx = self.compile(x);
+                let idx = self.compile(x)?;
                 self.push(Operation::Return(idx))
             }
             AST::Statements(s) => {
-                let mut n = CodeAddress(0, 0);
+                let mut n = None;
                 for stmt in s {
-                    n = self.compile(stmt);
+                    n = Some(self.compile(stmt)?);
+                }
+                match n {
+                    Some(n) => n,
+                    None => self.push(Operation::Literal(LiteralValue::Nil)),
                 }
-                n
             }
-            AST::InvokeSequence(a, b) => {
-                let mut n = self.compile(a);
-                self.stack.push(n);
-                for x in b {
-                    n = self.compile(x);
-                    self.stack.push(n);
+            AST::InvokeSequence(target, msgs) => {
+                let is_super = matches!(**target, AST::Variable("super", _));
+                let mut n = self.compile(target)?;
+                for (idx, m) in msgs.iter().enumerate() {
+                    n = self.compile_send(n, m, is_super && idx == 0)?;
                 }
-                let block_num = self.current_block;
-                self.blocks[block_num].result = Some(n);
                 n
             }
-            AST::Message { name, args } => {
-                if let Some(receiver_idx) = self.stack.pop() {
-                    let mut argv = vec![];
-
-                    for x in args {
-                        let n = self.compile(x);
-                        argv.push(n);
+            AST::InvokeCascade(first, cascades) => {
+                if let AST::InvokeSequence(target, msgs) = &**first {
+                    let is_super = matches!(**target, AST::Variable("super", _));
+                    let mut receiver = self.compile(target)?;
+                    let mut n = receiver;
+                    if let Some((last, init)) = msgs.split_last() {
+                        for (idx, m) in init.iter().enumerate() {
+                            receiver = self.compile_send(receiver, m, is_super && idx == 0)?;
+                        }
+                        let cascade_super = is_super && init.is_empty();
+                        n = self.compile_send(receiver, last, cascade_super)?;
+                        for c in cascades {
+                            if let AST::InvokeSequence(_, parts) = c {
+                                n = receiver;
+                                for (idx, m) in parts.iter().enumerate() {
+                                    n = self.compile_send(n, m, cascade_super && idx == 0)?;
+                                }
+                            }
+                        }
                     }
-                    let c = Operation::Invoke(name.to_string(), receiver_idx, argv);
-                    self.push(c)
+                    n
                 } else {
-                    panic!()
+                    return Err(unsupported(ast))
                 }
             }
-            AST::Variable(name) => match self.idx_for(*name) {
+            AST::Variable(name, _) => match self.idx_for(name) {
                 Some(idx) => idx,
-                None => {
-                    let idx = self.push(Operation::Global(name.to_string()));
-                    self.define(name.to_string(), idx);
-                    idx
-                }
+                None => match *name {
+                    "self" | "super" => self.push(Operation::Myself),
+                    "true" | "false" | "nil" => self.push(Operation::Literal(LiteralValue::from_ast(ast))),
+                    // looked up when it runs, it may be assigned later on
+                    _ => self.push(Operation::Global(name.to_string())),
+                },
             },
             AST::Assign(namet, v) => {
                 if let AST::Name(name) = **namet {
-                    let idx = self.compile(v);
-                    if idx.0 > 0 {
-                        self.push(Operation::Move(idx, self.idx_for(name)));
-                    } else {
-                        self.define(name.to_string(), idx);
+                    let idx = self.compile(v)?;
+                    match self.idx_for(name) {
+                        Some(to) => self.push(Operation::Move(idx, Some(to))),
+                        None => self.push(Operation::Store(name.to_string(), idx)),
                     }
-                    idx
                 } else {
-                    panic!()
+                    return Err(unsupported(ast))
                 }
             }
-            _ => todo!(),
+            // an empty expression, like the receiver of a cascade without one
+            AST::Empty => self.push(Operation::Literal(LiteralValue::Nil)),
+            _ => return Err(unsupported(ast)),
+        };
+        Ok(addr)
+    }
+
+    /// compiles the arguments of a message and its send to the value at `receiver`
+    fn compile_send(&mut self, receiver: CodeAddress, m: &AST, to_super: bool) -> Result<CodeAddress, CompileError> {
+        if let AST::Message { name, args, position } = m {
+            let args = args.iter().map(|x| self.compile(x)).collect::<Result<_, _>>()?;
+            let op = if to_super {
+                Operation::Super(name.to_string(), receiver, args)
+            } else {
+                Operation::Invoke(name.to_string(), receiver, args)
+            };
+            let addr = self.push(op);
+            self.blocks[addr.0].positions.insert(addr.1, position.clone());
+            Ok(addr)
+        } else {
+            Ok(receiver)
         }
     }
 
@@ -385,6 +889,10 @@ impl CompiledMethod {
     pub fn len(&self) -> usize {
         self.blocks[self.current_block].len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl Default for CompiledMethod {
@@ -393,18 +901,67 @@ impl Default for CompiledMethod {
     }
 }
 
+/// the error for a node the compiler has no code for
+fn unsupported(ast: &AST) -> CompileError {
+    CompileError(format!("can't compile {:?}", ast))
+}
+
+/// compiles the initializer of a variable or a program: an expression, or
+/// a method body without selector that declares its temporaries. The code
+/// answers the value of the last statement.
+pub fn compile_initializer(code: &AST) -> Result<Rc<CompiledMethod>, CompileError> {
+    let mut o = CompiledMethod::new();
+    o.source = code.to_source();
+    compile_body(&mut o, code)?;
+    Ok(Rc::new(o))
+}
+
+/// compiles `code` into the first block of `o`, the temporaries of a method
+/// without selector are declared first
+fn compile_body(o: &mut CompiledMethod, code: &AST) -> Result<(), CompileError> {
+    let body = match code {
+        AST::Method { temps, body, .. } => {
+            for t in temps {
+                let addr = o.push(Operation::Temp);
+                o.define(t.to_string(), addr);
+            }
+            body
+        }
+        code => code,
+    };
+    let result = o.compile(body)?;
+    o.blocks[0].result = Some(result);
+    o.optimize();
+    Ok(())
+}
+
+/// compiles and runs an initializer in `ctx`, answers its value or the
+/// message of the error that ended it
+pub(crate) fn run_initializer(code: &AST, ctx: ContextRef) -> Result<Value, String> {
+    let mut invalid = None;
+    let exit = unwind::run_as(unwind::NO_HOME, || match compile_initializer(code) {
+        Ok(code) => code.run(ctx),
+        Err(e) => {
+            invalid = Some(e.to_string());
+            Value::Nil
+        }
+    });
+    if let Some(e) = invalid {
+        return Err(e);
+    }
+    match exit {
+        Exit::Normal(v) | Exit::Unwound(_, v) => Ok(v),
+        Exit::Failed(payload) => Err(exc::failure_message(&payload)),
+    }
+}
+
 pub fn compile_script(
     input_string: String,
-) -> Result<&'static CompiledMethod, Box<dyn std::error::Error>> {
-    let parse_trees = parse_script(input_string)?;
+) -> Result<Rc<CompiledMethod>, Box<dyn std::error::Error>> {
+    let parse_trees = parse_script(input_string.clone())?;
     let mut o = CompiledMethod::new();
-    for t in parse_trees {
-        let ast = t.as_abstract_syntax_tree();
-        println!("-> {}", t.to_string());
-        println!("-> {:#?}", &ast);
-        o.compile(&ast);
-    }
-    let l0 = Box::new(o);
-    let l = Box::leak(l0);
-    Ok(l)
+    o.source = input_string;
+    // the trees of an ambiguous parse mean the same, the first one is compiled
+    compile_body(&mut o, &parse_trees[0].as_abstract_syntax_tree())?;
+    Ok(Rc::new(o))
 }
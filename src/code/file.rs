@@ -0,0 +1,569 @@
+//! The file format of compiled code, so methods can be stored compiled and
+//! loaded without parsing and compiling them again. Numbers are little
+//! endian, counts and indexes are u32:
+//!
+//! - header: the magic `TTCM`, the format version as u16 and the SHA3-256
+//!   checksum of the source the code was compiled from
+//! - the label, the selector (empty for scripts) and the source
+//! - the constant pool: names, selectors and literals, opcodes refer to
+//!   them by index
+//! - the blocks: outer block, argument count, result, variables, opcodes
+//!   and the source map from steps to source positions
+
+use std::{fmt::Display, fs, path::Path};
+
+use num_bigint::BigInt;
+use santiago::lexer::Position;
+use sha3::{Digest, Sha3_256};
+use tracing::info;
+
+use super::{method_label, ByteCode, CodeAddress, CompiledMethod, LiteralValue, Operation};
+use crate::{parse_method, parser::AST, repo, runtime::sel::SelectorSet};
+
+const MAGIC: &[u8; 4] = b"TTCM";
+pub const VERSION: u16 = 1;
+/// appended to the name of a method file for its compiled form
+pub const EXTENSION: &str = "ttc";
+
+#[derive(Debug)]
+pub struct CodeFileError(pub String);
+
+impl Display for CodeFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for CodeFileError {}
+
+pub type Checksum = [u8; 32];
+
+pub fn checksum(source: &str) -> Checksum {
+    Sha3_256::digest(source.as_bytes()).into()
+}
+
+/// the compiled method in the file format
+pub fn write(method: &CompiledMethod) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u32(method.blocks.len());
+    for b in method.blocks.iter() {
+        w.block(b);
+    }
+    let body = std::mem::take(&mut w.out);
+
+    let mut out = Writer::default();
+    out.out.extend_from_slice(MAGIC);
+    out.out.extend_from_slice(&VERSION.to_le_bytes());
+    out.out.extend_from_slice(&checksum(&method.source));
+    out.str(method.label);
+    out.str(method.selector.unwrap_or(""));
+    out.str(&method.source);
+    out.u32(w.pool.len());
+    for c in w.pool.iter() {
+        out.literal(c);
+    }
+    out.out.extend_from_slice(&body);
+    out.out
+}
+
+/// a compiled method from the file format
+pub fn read(bytes: &[u8]) -> Result<CompiledMethod, CodeFileError> {
+    read_checked(bytes, None)
+}
+
+/// reads compiled code, unless it was compiled from a source other than
+/// `source`. Stale code is detected by the checksum in the header, before
+/// the rest is read.
+pub fn read_current(bytes: &[u8], source: &str) -> Result<CompiledMethod, CodeFileError> {
+    read_checked(bytes, Some(checksum(source)))
+}
+
+fn read_checked(bytes: &[u8], expected: Option<Checksum>) -> Result<CompiledMethod, CodeFileError> {
+    let mut r = Reader::new(bytes);
+    if r.take(4)? != MAGIC {
+        return Err(CodeFileError(String::from("not compiled code")));
+    }
+    let version = u16::from_le_bytes([r.u8()?, r.u8()?]);
+    if version != VERSION {
+        return Err(CodeFileError(format!(
+            "compiled code of version {}, expected {}",
+            version, VERSION
+        )));
+    }
+    let sum = r.take(32)?;
+    if expected.is_some_and(|e| e[..] != sum[..]) {
+        return Err(CodeFileError(String::from("the compiled code is stale")));
+    }
+    let mut m = CompiledMethod::new();
+    m.label = SelectorSet::get(&r.str()?);
+    m.block_label = SelectorSet::get(&format!("[] in {}", m.label));
+    m.selector = match r.str()?.as_str() {
+        "" => None,
+        s => Some(SelectorSet::get(s)),
+    };
+    m.source = r.str()?;
+    if checksum(&m.source)[..] != sum[..] {
+        return Err(CodeFileError(format!("{}: the checksum does not match the source", m.label)));
+    }
+    for _ in 0..r.u32()? {
+        let c = r.literal()?;
+        r.pool.push(c);
+    }
+    m.blocks = (0..r.u32()?).map(|_| r.block()).collect::<Result<_, _>>()?;
+    if m.blocks.is_empty() {
+        return Err(CodeFileError(String::from("compiled code without blocks")));
+    }
+    Ok(m)
+}
+
+pub fn save(path: &Path, method: &CompiledMethod) -> Result<(), CodeFileError> {
+    fs::write(path, write(method)).map_err(|e| io_error(path, e))
+}
+
+pub fn load(path: &Path) -> Result<CompiledMethod, CodeFileError> {
+    let bytes = fs::read(path).map_err(|e| io_error(path, e))?;
+    read(&bytes).map_err(|e| CodeFileError(format!("{}: {}", path.display(), e)))
+}
+
+/// compiles a method of `class` from its source
+pub fn compile(class: &str, source: &str) -> Result<CompiledMethod, CodeFileError> {
+    let trees = parse_method(source.to_string()).map_err(|e| CodeFileError(e.to_string()))?;
+    let method = trees[0].as_abstract_syntax_tree();
+    match &method {
+        AST::Method { name, .. } => {
+            let mut m = CompiledMethod::compile_method(&method, &method_label(class, false, name))
+                .map_err(|e| CodeFileError(e.to_string()))?;
+            m.source = source.to_string();
+            Ok(m)
+        }
+        _ => Err(CodeFileError(format!("not a method: {}", source))),
+    }
+}
+
+/// compiles every method of a `defs` tree into `out`, keeping the layout
+/// of the tree, answers the number of methods compiled
+pub fn precompile_defs(defs: &Path, out: &Path) -> Result<usize, CodeFileError> {
+    Ok(load_defs(defs, out)?.len())
+}
+
+/// the methods of a `defs` tree as class name and compiled method. They
+/// are read from the compiled files in `compiled`, methods whose compiled
+/// file is missing or stale are compiled again and their file rewritten.
+pub fn load_defs(defs: &Path, compiled: &Path) -> Result<Vec<(String, CompiledMethod)>, CodeFileError> {
+    let mut result = vec![];
+    for class_dir in sorted_entries(defs)? {
+        if !class_dir.is_dir() {
+            continue;
+        }
+        let class = repo::class_name(&class_dir);
+        let out_dir = compiled.join(class_dir.file_name().unwrap_or_default());
+        for file in sorted_entries(&class_dir)? {
+            let source = fs::read_to_string(&file).map_err(|e| io_error(&file, e))?;
+            let mut name = file.file_name().unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(EXTENSION);
+            let target = out_dir.join(name);
+            let current = fs::read(&target).ok().and_then(|b| read_current(&b, &source).ok());
+            let m = match current {
+                Some(m) => m,
+                None => {
+                    info!("compiling {}", file.display());
+                    let m = compile(&class, &source)
+                        .map_err(|e| CodeFileError(format!("{}: {}", file.display(), e)))?;
+                    fs::create_dir_all(&out_dir).map_err(|e| io_error(&out_dir, e))?;
+                    save(&target, &m)?;
+                    m
+                }
+            };
+            result.push((class.clone(), m));
+        }
+    }
+    Ok(result)
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<std::path::PathBuf>, CodeFileError> {
+    let entries = fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> CodeFileError {
+    CodeFileError(format!("{}: {}", path.display(), e))
+}
+
+#[derive(Default)]
+struct Writer {
+    out: Vec<u8>,
+    /// the constants referred to by the opcodes written so far
+    pool: Vec<LiteralValue>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.out.push(v);
+    }
+
+    fn u32(&mut self, v: usize) {
+        self.out.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.out.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len());
+        self.out.extend_from_slice(v);
+    }
+
+    fn str(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+
+    /// the index of a constant in the pool
+    fn constant(&mut self, c: LiteralValue) {
+        let idx = match self.pool.iter().position(|x| *x == c) {
+            Some(idx) => idx,
+            None => {
+                self.pool.push(c);
+                self.pool.len() - 1
+            }
+        };
+        self.u32(idx);
+    }
+
+    fn name(&mut self, v: &str) {
+        self.constant(LiteralValue::String(v.to_string()));
+    }
+
+    fn addr(&mut self, a: &CodeAddress) {
+        self.u32(a.0);
+        self.u32(a.1);
+    }
+
+    fn addrs(&mut self, v: &[CodeAddress]) {
+        self.u32(v.len());
+        for a in v {
+            self.addr(a);
+        }
+    }
+
+    fn literal(&mut self, c: &LiteralValue) {
+        match c {
+            LiteralValue::Int(v) => {
+                self.u8(0);
+                self.i64(*v as i64);
+            }
+            LiteralValue::LargeInt(v) => {
+                self.u8(1);
+                self.bytes(&v.to_signed_bytes_le());
+            }
+            LiteralValue::Float(v) => {
+                self.u8(2);
+                self.out.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            LiteralValue::Char(v) => {
+                self.u8(3);
+                self.u32(*v as usize);
+            }
+            LiteralValue::String(v) => {
+                self.u8(4);
+                self.str(v);
+            }
+            LiteralValue::Symbol(v) => {
+                self.u8(5);
+                self.str(v);
+            }
+            LiteralValue::Array(v) => {
+                self.u8(6);
+                self.u32(v.len());
+                for x in v {
+                    self.literal(x);
+                }
+            }
+            LiteralValue::ByteArray(v) => {
+                self.u8(7);
+                self.bytes(v);
+            }
+            LiteralValue::True => self.u8(8),
+            LiteralValue::False => self.u8(9),
+            LiteralValue::Nil => self.u8(10),
+        }
+    }
+
+    fn block(&mut self, b: &ByteCode) {
+        self.u32(b.outer);
+        self.u32(b.args);
+        match &b.result {
+            Some(a) => {
+                self.u8(1);
+                self.addr(a);
+            }
+            None => self.u8(0),
+        }
+        self.u32(b.vars.len());
+        for (name, a) in b.vars.iter() {
+            self.name(name);
+            self.addr(a);
+        }
+        self.u32(b.opcode.len());
+        for op in b.opcode.iter() {
+            self.operation(op);
+        }
+        self.u32(b.positions.len());
+        for (step, p) in b.positions.iter() {
+            self.u32(*step);
+            self.u32(p.line);
+            self.u32(p.column);
+        }
+        self.name(&b.source);
+    }
+
+    fn operation(&mut self, op: &Operation) {
+        match op {
+            Operation::Int(v) => {
+                self.u8(0);
+                self.i64(*v as i64);
+            }
+            Operation::Str(v) => {
+                self.u8(1);
+                self.name(v);
+            }
+            Operation::Invoke(selector, receiver, args) => {
+                self.u8(2);
+                self.name(selector);
+                self.addr(receiver);
+                self.addrs(args);
+            }
+            Operation::Super(selector, receiver, args) => {
+                self.u8(3);
+                self.name(selector);
+                self.addr(receiver);
+                self.addrs(args);
+            }
+            Operation::Block(b) => {
+                self.u8(4);
+                self.u32(*b);
+            }
+            Operation::Global(name) => {
+                self.u8(5);
+                self.name(name);
+            }
+            Operation::Store(name, from) => {
+                self.u8(6);
+                self.name(name);
+                self.addr(from);
+            }
+            Operation::Char(v) => {
+                self.u8(7);
+                self.u32(*v as usize);
+            }
+            Operation::String(v) => {
+                self.u8(8);
+                self.name(v);
+            }
+            Operation::Arg(n) => {
+                self.u8(9);
+                self.u32(*n);
+            }
+            Operation::Return(a) => {
+                self.u8(10);
+                self.addr(a);
+            }
+            Operation::Param(n) => {
+                self.u8(11);
+                self.u32(*n);
+            }
+            Operation::Myself => self.u8(12),
+            Operation::Temp => self.u8(13),
+            Operation::Move(from, to) => {
+                self.u8(14);
+                self.addr(from);
+                match to {
+                    Some(to) => {
+                        self.u8(1);
+                        self.addr(to);
+                    }
+                    None => self.u8(0),
+                }
+            }
+            Operation::Symbol(v) => {
+                self.u8(15);
+                self.name(v);
+            }
+            Operation::Literal(v) => {
+                self.u8(16);
+                self.constant(v.clone());
+            }
+            Operation::Array(items) => {
+                self.u8(17);
+                self.addrs(items);
+            }
+            Operation::Jump(step) => {
+                self.u8(18);
+                self.u32(*step);
+            }
+            Operation::JumpIf(cond, when, step) => {
+                self.u8(19);
+                self.addr(cond);
+                self.u8(*when as u8);
+                self.u32(*step);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    pool: Vec<LiteralValue>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            at: 0,
+            pool: vec![],
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CodeFileError> {
+        match self.bytes.get(self.at..self.at + n) {
+            Some(v) => {
+                self.at += n;
+                Ok(v)
+            }
+            None => Err(CodeFileError(String::from("truncated compiled code"))),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, CodeFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, CodeFileError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    }
+
+    fn i64(&mut self) -> Result<i64, CodeFileError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(b))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], CodeFileError> {
+        let n = self.u32()?;
+        self.take(n)
+    }
+
+    fn str(&mut self) -> Result<String, CodeFileError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|e| CodeFileError(e.to_string()))
+    }
+
+    fn constant(&mut self) -> Result<LiteralValue, CodeFileError> {
+        let idx = self.u32()?;
+        match self.pool.get(idx) {
+            Some(c) => Ok(c.clone()),
+            None => Err(CodeFileError(format!("no constant {}", idx))),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, CodeFileError> {
+        match self.constant()? {
+            LiteralValue::String(v) => Ok(v),
+            c => Err(CodeFileError(format!("not a name: {:?}", c))),
+        }
+    }
+
+    fn addr(&mut self) -> Result<CodeAddress, CodeFileError> {
+        Ok(CodeAddress(self.u32()?, self.u32()?))
+    }
+
+    fn addrs(&mut self) -> Result<Vec<CodeAddress>, CodeFileError> {
+        (0..self.u32()?).map(|_| self.addr()).collect()
+    }
+
+    fn char(&mut self) -> Result<char, CodeFileError> {
+        let v = self.u32()? as u32;
+        char::from_u32(v).ok_or_else(|| CodeFileError(format!("not a character: {}", v)))
+    }
+
+    fn literal(&mut self) -> Result<LiteralValue, CodeFileError> {
+        Ok(match self.u8()? {
+            0 => LiteralValue::Int(self.i64()? as isize),
+            1 => LiteralValue::LargeInt(BigInt::from_signed_bytes_le(self.bytes()?)),
+            2 => LiteralValue::Float(f64::from_bits(self.i64()? as u64)),
+            3 => LiteralValue::Char(self.char()?),
+            4 => LiteralValue::String(self.str()?),
+            5 => LiteralValue::Symbol(self.str()?),
+            6 => LiteralValue::Array((0..self.u32()?).map(|_| self.literal()).collect::<Result<_, _>>()?),
+            7 => LiteralValue::ByteArray(self.bytes()?.to_vec()),
+            8 => LiteralValue::True,
+            9 => LiteralValue::False,
+            10 => LiteralValue::Nil,
+            tag => return Err(CodeFileError(format!("unknown constant tag {}", tag))),
+        })
+    }
+
+    fn block(&mut self) -> Result<ByteCode, CodeFileError> {
+        let mut b = ByteCode::new();
+        b.outer = self.u32()?;
+        b.args = self.u32()?;
+        if self.u8()? == 1 {
+            b.result = Some(self.addr()?);
+        }
+        for _ in 0..self.u32()? {
+            let name = self.name()?;
+            let addr = self.addr()?;
+            b.vars.push((name, addr));
+        }
+        for _ in 0..self.u32()? {
+            let op = self.operation()?;
+            b.opcode.push(op);
+        }
+        for _ in 0..self.u32()? {
+            let step = self.u32()?;
+            let line = self.u32()?;
+            let column = self.u32()?;
+            b.positions.insert(step, Position { line, column });
+        }
+        b.source = self.name()?;
+        Ok(b)
+    }
+
+    fn operation(&mut self) -> Result<Operation, CodeFileError> {
+        Ok(match self.u8()? {
+            0 => Operation::Int(self.i64()? as isize),
+            1 => Operation::Str(self.name()?),
+            2 => Operation::Invoke(self.name()?, self.addr()?, self.addrs()?),
+            3 => Operation::Super(self.name()?, self.addr()?, self.addrs()?),
+            4 => Operation::Block(self.u32()?),
+            5 => Operation::Global(self.name()?),
+            6 => Operation::Store(self.name()?, self.addr()?),
+            7 => Operation::Char(self.char()?),
+            8 => Operation::String(self.name()?),
+            9 => Operation::Arg(self.u32()?),
+            10 => Operation::Return(self.addr()?),
+            11 => Operation::Param(self.u32()?),
+            12 => Operation::Myself,
+            13 => Operation::Temp,
+            14 => {
+                let from = self.addr()?;
+                let to = match self.u8()? {
+                    1 => Some(self.addr()?),
+                    _ => None,
+                };
+                Operation::Move(from, to)
+            }
+            15 => Operation::Symbol(self.name()?),
+            16 => Operation::Literal(self.constant()?),
+            17 => Operation::Array(self.addrs()?),
+            18 => Operation::Jump(self.u32()?),
+            19 => Operation::JumpIf(self.addr()?, self.u8()? == 1, self.u32()?),
+            tag => return Err(CodeFileError(format!("unknown opcode {}", tag))),
+        })
+    }
+}
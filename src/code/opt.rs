@@ -0,0 +1,479 @@
+//! The optimizer, run over a method once it is compiled. Control
+//! structures sent with literal blocks (`ifTrue:`, `and:`, `whileTrue:`,
+//! `to:do:` and friends) are inlined as jumps into the block that sends
+//! them, sends of arithmetic and comparisons to integer constants are
+//! folded, and steps whose values nobody reads are dropped.
+
+use std::collections::{HashMap, HashSet};
+
+use num_integer::Integer;
+
+use super::{ByteCode, CodeAddress, CompiledMethodData, LiteralValue, Operation};
+
+/// block number of the addresses of steps the inliner adds, they are
+/// mapped to real addresses with the others
+const SYNTHETIC: usize = usize::MAX;
+
+impl CompiledMethodData {
+    pub fn optimize(&mut self) {
+        self.inline_blocks();
+        self.fold_constants();
+        self.drop_dead_code();
+    }
+
+    fn inline_blocks(&mut self) {
+        let reads = self.reads();
+        let literal = |b: &ByteCode, addr: &CodeAddress, args: usize| -> Option<usize> {
+            match b.opcode.get(addr.1) {
+                Some(Operation::Block(n)) if reads.get(addr) == Some(&1) && self.blocks[*n].args == args => {
+                    Some(*n)
+                }
+                _ => None,
+            }
+        };
+        let mut inlined = HashSet::new();
+        for b in self.blocks.iter() {
+            for op in b.opcode.iter() {
+                if let Operation::Invoke(selector, receiver, args) = op {
+                    let same = |a: &CodeAddress| a.0 == receiver.0;
+                    if !same(receiver) || !args.iter().all(same) {
+                        continue;
+                    }
+                    let found = match (selector.as_str(), args.as_slice()) {
+                        ("ifTrue:" | "ifFalse:" | "and:" | "or:", [a]) => vec![literal(b, a, 0)],
+                        ("ifTrue:ifFalse:" | "ifFalse:ifTrue:", [a, c]) => vec![literal(b, a, 0), literal(b, c, 0)],
+                        ("whileTrue:" | "whileFalse:", [a]) => vec![literal(b, receiver, 0), literal(b, a, 0)],
+                        ("whileTrue" | "whileFalse", []) => vec![literal(b, receiver, 0)],
+                        ("to:do:", [_, a]) => vec![literal(b, a, 1)],
+                        _ => continue,
+                    };
+                    if found.iter().all(Option::is_some) {
+                        inlined.extend(found.into_iter().flatten());
+                    }
+                }
+            }
+        }
+        if inlined.is_empty() {
+            return;
+        }
+
+        let mut inliner = Inliner {
+            old: &self.blocks,
+            inlined: &inlined,
+            map: HashMap::new(),
+            host: HashMap::new(),
+            synthetic: 0,
+        };
+        let mut kept = vec![];
+        let mut numbers = HashMap::new();
+        for n in 0..self.blocks.len() {
+            if inlined.contains(&n) {
+                continue;
+            }
+            numbers.insert(n, kept.len());
+            let mut out = ByteCode::new();
+            out.args = self.blocks[n].args;
+            out.source = self.blocks[n].source.clone();
+            out.outer = self.blocks[n].outer;
+            out.result = self.blocks[n].result;
+            inliner.emit(n, kept.len(), &mut out);
+            kept.push(out);
+        }
+        let Inliner { map, host, .. } = inliner;
+        let block_number = |n: usize| match numbers.get(&n) {
+            Some(n) => *n,
+            None => host[&n],
+        };
+        let addr = |a: &CodeAddress| match map.get(a) {
+            Some(a) => *a,
+            None => panic!("optimizer lost step {}", a),
+        };
+        for b in kept.iter_mut() {
+            b.outer = block_number(b.outer);
+            b.result = b.result.as_ref().map(addr);
+            for v in b.vars.iter_mut() {
+                v.1 = addr(&v.1);
+            }
+            for op in b.opcode.iter_mut() {
+                op.map_addresses(&addr);
+                if let Operation::Block(n) = op {
+                    *n = block_number(*n);
+                }
+            }
+        }
+        self.blocks = kept;
+    }
+
+    /// arithmetic and comparisons of integer constants are done right away
+    fn fold_constants(&mut self) {
+        for (n, b) in self.blocks.iter_mut().enumerate() {
+            // only constants computed by the block itself
+            let constant = |ops: &[Operation], a: &CodeAddress| match ops.get(a.1) {
+                Some(Operation::Int(v)) if a.0 == n => Some(*v),
+                _ => None,
+            };
+            for step in 0..b.opcode.len() {
+                let folded = match &b.opcode[step] {
+                    Operation::Invoke(selector, x, args) => match (constant(&b.opcode, x), args.as_slice()) {
+                        (Some(x), [y]) => constant(&b.opcode, y).and_then(|y| fold(selector, x, y)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(op) = folded {
+                    b.opcode[step] = op;
+                    b.positions.remove(&step);
+                }
+            }
+        }
+    }
+
+    /// drops steps without side effects whose values are never read,
+    /// moves into variables nobody reads among them
+    fn drop_dead_code(&mut self) {
+        loop {
+            let reads = self.reads();
+            let targets = self.targets();
+            let unread = |a: &CodeAddress| !reads.contains_key(a);
+            let mut dropped = false;
+            let mut maps = vec![];
+            for (n, b) in self.blocks.iter().enumerate() {
+                let keep: Vec<bool> = b
+                    .opcode
+                    .iter()
+                    .enumerate()
+                    .map(|(step, op)| {
+                        let a = CodeAddress(n, step);
+                        let pure = match op {
+                            Operation::Move(_, Some(to)) => unread(to),
+                            op => op.is_pure(),
+                        };
+                        !(pure && unread(&a) && !targets.contains(&a))
+                    })
+                    .collect();
+                dropped |= keep.contains(&false);
+                maps.push(keep);
+            }
+            if !dropped {
+                return;
+            }
+            self.compact(&maps);
+        }
+    }
+
+    /// removes the steps not kept, later steps move up
+    fn compact(&mut self, keep: &[Vec<bool>]) {
+        // the new step of every old one, a removed step maps to the step
+        // following it
+        let steps: Vec<Vec<usize>> = keep
+            .iter()
+            .map(|k| {
+                let mut n = 0;
+                let mut v: Vec<usize> = k
+                    .iter()
+                    .map(|kept| {
+                        let at = n;
+                        n += *kept as usize;
+                        at
+                    })
+                    .collect();
+                v.push(n);
+                v
+            })
+            .collect();
+        let addr = |a: &CodeAddress| CodeAddress(a.0, steps[a.0][a.1]);
+        for (n, b) in self.blocks.iter_mut().enumerate() {
+            let opcode = std::mem::take(&mut b.opcode);
+            b.opcode = opcode
+                .into_iter()
+                .zip(keep[n].iter())
+                .filter(|(_, k)| **k)
+                .map(|(mut op, _)| {
+                    op.map_addresses(&addr);
+                    match &mut op {
+                        Operation::Jump(step) | Operation::JumpIf(_, _, step) => *step = steps[n][*step],
+                        _ => {}
+                    }
+                    op
+                })
+                .collect();
+            b.result = b.result.as_ref().map(addr);
+            b.vars.retain(|(_, a)| keep[n][a.1]);
+            for v in b.vars.iter_mut() {
+                v.1 = addr(&v.1);
+            }
+            b.positions = std::mem::take(&mut b.positions)
+                .into_iter()
+                .filter(|(step, _)| keep[n][*step])
+                .map(|(step, p)| (steps[n][step], p))
+                .collect();
+        }
+    }
+
+    /// how often the value of each step is read, the results of the blocks count
+    fn reads(&self) -> HashMap<CodeAddress, usize> {
+        let mut reads = HashMap::new();
+        for b in self.blocks.iter() {
+            for a in b.opcode.iter().flat_map(|op| op.reads()).chain(b.result.iter()) {
+                *reads.entry(*a).or_insert(0) += 1;
+            }
+        }
+        reads
+    }
+
+    /// the variables moves store into
+    fn targets(&self) -> HashSet<CodeAddress> {
+        let mut targets = HashSet::new();
+        for b in self.blocks.iter() {
+            for op in b.opcode.iter() {
+                if let Operation::Move(_, Some(to)) = op {
+                    targets.insert(*to);
+                }
+            }
+        }
+        targets
+    }
+}
+
+/// the value of a send to an integer constant with a constant argument,
+/// results a SmallInteger can't hold are left to the send
+fn fold(selector: &str, x: isize, y: isize) -> Option<Operation> {
+    let int = |v: Option<isize>| v.map(Operation::Int);
+    let boolean = |v: bool| {
+        Some(Operation::Literal(if v {
+            LiteralValue::True
+        } else {
+            LiteralValue::False
+        }))
+    };
+    match selector {
+        "+" => int(x.checked_add(y)),
+        "-" => int(x.checked_sub(y)),
+        "*" => int(x.checked_mul(y)),
+        "//" if y != 0 => int(Some(Integer::div_floor(&x, &y))),
+        "\\\\" if y != 0 => int(Some(Integer::mod_floor(&x, &y))),
+        "<" => boolean(x < y),
+        ">" => boolean(x > y),
+        "<=" => boolean(x <= y),
+        ">=" => boolean(x >= y),
+        "=" => boolean(x == y),
+        "~=" => boolean(x != y),
+        _ => None,
+    }
+}
+
+impl Operation {
+    /// the values the step reads, the target of a move is written
+    fn reads(&self) -> Vec<&CodeAddress> {
+        match self {
+            Operation::Invoke(_, r, args) | Operation::Super(_, r, args) => {
+                std::iter::once(r).chain(args.iter()).collect()
+            }
+            Operation::Store(_, a) | Operation::Return(a) | Operation::Move(a, _) | Operation::JumpIf(a, _, _) => {
+                vec![a]
+            }
+            Operation::Array(items) => items.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// a step that only computes its own value
+    fn is_pure(&self) -> bool {
+        matches!(
+            self,
+            Operation::Int(_)
+                | Operation::Str(_)
+                | Operation::String(_)
+                | Operation::Char(_)
+                | Operation::Symbol(_)
+                | Operation::Literal(_)
+                | Operation::Temp
+                | Operation::Myself
+                | Operation::Block(_)
+                | Operation::Array(_)
+                | Operation::Move(_, None)
+        )
+    }
+
+    fn map_addresses(&mut self, f: &impl Fn(&CodeAddress) -> CodeAddress) {
+        match self {
+            Operation::Invoke(_, r, args) | Operation::Super(_, r, args) => {
+                *r = f(r);
+                for a in args.iter_mut() {
+                    *a = f(a);
+                }
+            }
+            Operation::Store(_, a) | Operation::Return(a) | Operation::JumpIf(a, _, _) => *a = f(a),
+            Operation::Move(a, to) => {
+                *a = f(a);
+                if let Some(to) = to {
+                    *to = f(to);
+                }
+            }
+            Operation::Array(items) => {
+                for a in items.iter_mut() {
+                    *a = f(a);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// copies the steps of the kept blocks, the inlined blocks go where they
+/// are sent. The copied steps keep their old addresses, `map` tells where
+/// each old address went.
+struct Inliner<'a> {
+    old: &'a [ByteCode],
+    inlined: &'a HashSet<usize>,
+    map: HashMap<CodeAddress, CodeAddress>,
+    /// the block the steps of an inlined block went to
+    host: HashMap<usize, usize>,
+    synthetic: usize,
+}
+
+impl Inliner<'_> {
+    /// copies the steps of block `from` to `out`, the block numbered `into`
+    fn emit(&mut self, from: usize, into: usize, out: &mut ByteCode) {
+        let old = self.old;
+        let b = &old[from];
+        out.vars.extend(b.vars.iter().cloned());
+        for (step, op) in b.opcode.iter().enumerate() {
+            let addr = CodeAddress(from, step);
+            match op {
+                Operation::Block(n) if self.inlined.contains(n) => {}
+                // the arguments of inlined blocks are set by the inlined structure
+                Operation::Arg(_) if self.inlined.contains(&from) => {}
+                Operation::Invoke(selector, receiver, args) if self.inlines(selector, receiver, args) => {
+                    self.control(from, step, into, out);
+                }
+                _ => {
+                    let to = self.push(out, into, op.clone(), addr);
+                    if let Some(p) = b.positions.get(&step) {
+                        out.positions.insert(to.1, p.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn inlines(&self, selector: &str, receiver: &CodeAddress, args: &[CodeAddress]) -> bool {
+        let block = |a: &CodeAddress| match self.old[a.0].opcode.get(a.1) {
+            Some(Operation::Block(n)) => self.inlined.contains(n),
+            _ => false,
+        };
+        match selector {
+            "whileTrue:" | "whileFalse:" | "whileTrue" | "whileFalse" => {
+                block(receiver) && args.iter().all(block)
+            }
+            "to:do:" => args.len() == 2 && block(&args[1]),
+            "ifTrue:" | "ifFalse:" | "and:" | "or:" | "ifTrue:ifFalse:" | "ifFalse:ifTrue:" => {
+                !args.is_empty() && args.iter().all(block)
+            }
+            _ => false,
+        }
+    }
+
+    /// the number of the literal block at `addr`
+    fn block_at(&self, addr: &CodeAddress) -> usize {
+        match self.old[addr.0].opcode[addr.1] {
+            Operation::Block(n) => n,
+            _ => panic!("no literal block at {}", addr),
+        }
+    }
+
+    /// copies the steps of an inlined block, answers the old address of its result
+    fn inline(&mut self, addr: &CodeAddress, into: usize, out: &mut ByteCode) -> CodeAddress {
+        let n = self.block_at(addr);
+        self.host.insert(n, into);
+        self.emit(n, into, out);
+        self.old[n].result.expect("block without result")
+    }
+
+    /// the control structure sent at `step` of block `from` as jumps, its
+    /// value ends up at the old address of the send
+    fn control(&mut self, from: usize, step: usize, into: usize, out: &mut ByteCode) {
+        let addr = CodeAddress(from, step);
+        let position = self.old[from].positions.get(&step).cloned();
+        let Operation::Invoke(selector, receiver, args) = &self.old[from].opcode[step] else {
+            unreachable!()
+        };
+        let (receiver, args) = (*receiver, args.clone());
+        match selector.as_str() {
+            "ifTrue:" | "ifFalse:" => {
+                self.push(out, into, Operation::Temp, addr);
+                let jump = out.push(Operation::Temp);
+                let result = self.inline(&args[0], into, out);
+                self.synthetic_push(out, into, Operation::Move(result, Some(addr)));
+                let when = selector == "ifFalse:";
+                out.opcode[jump] = Operation::JumpIf(receiver, when, out.len());
+            }
+            "ifTrue:ifFalse:" | "ifFalse:ifTrue:" => {
+                self.push(out, into, Operation::Temp, addr);
+                let jump = out.push(Operation::Temp);
+                let result = self.inline(&args[0], into, out);
+                self.synthetic_push(out, into, Operation::Move(result, Some(addr)));
+                let to_end = out.push(Operation::Temp);
+                let when = selector == "ifFalse:ifTrue:";
+                out.opcode[jump] = Operation::JumpIf(receiver, when, out.len());
+                let result = self.inline(&args[1], into, out);
+                self.synthetic_push(out, into, Operation::Move(result, Some(addr)));
+                out.opcode[to_end] = Operation::Jump(out.len());
+            }
+            "and:" | "or:" => {
+                self.push(out, into, Operation::Move(receiver, None), addr);
+                let jump = out.push(Operation::Temp);
+                let result = self.inline(&args[0], into, out);
+                self.synthetic_push(out, into, Operation::Move(result, Some(addr)));
+                out.opcode[jump] = Operation::JumpIf(receiver, selector == "or:", out.len());
+            }
+            "whileTrue:" | "whileFalse:" | "whileTrue" | "whileFalse" => {
+                self.push(out, into, Operation::Temp, addr);
+                let top = out.len();
+                let condition = self.inline(&receiver, into, out);
+                let jump = out.push(Operation::Temp);
+                if let Some(body) = args.first() {
+                    self.inline(body, into, out);
+                }
+                out.push(Operation::Jump(top));
+                let when = selector.starts_with("whileFalse");
+                out.opcode[jump] = Operation::JumpIf(condition, when, out.len());
+            }
+            "to:do:" => {
+                // the receiver is the value, the argument of the block counts
+                self.push(out, into, Operation::Move(receiver, None), addr);
+                let counter = CodeAddress(self.block_at(&args[1]), 0);
+                self.push(out, into, Operation::Move(receiver, None), counter);
+                let one = self.synthetic_push(out, into, Operation::Int(1));
+                let top = out.len();
+                let test = self.synthetic_push(out, into, Operation::Invoke("<=".into(), counter, vec![args[0]]));
+                let jump = out.push(Operation::Temp);
+                self.inline(&args[1], into, out);
+                let next = self.synthetic_push(out, into, Operation::Invoke("+".into(), counter, vec![one]));
+                self.synthetic_push(out, into, Operation::Move(next, Some(counter)));
+                out.push(Operation::Jump(top));
+                out.opcode[jump] = Operation::JumpIf(test, false, out.len());
+                if let Some(p) = position {
+                    out.positions.insert(self.map[&test].1, p.clone());
+                    out.positions.insert(self.map[&next].1, p);
+                }
+            }
+            _ => unreachable!("{} is not inlined", selector),
+        }
+    }
+
+    /// copies a step, `addr` is the old address it is known by
+    fn push(&mut self, out: &mut ByteCode, into: usize, op: Operation, addr: CodeAddress) -> CodeAddress {
+        let to = CodeAddress(into, out.push(op));
+        self.map.insert(addr, to);
+        to
+    }
+
+    /// adds a step that has no old address, answers the one made up for it
+    fn synthetic_push(&mut self, out: &mut ByteCode, into: usize, op: Operation) -> CodeAddress {
+        let addr = CodeAddress(SYNTHETIC, self.synthetic);
+        self.synthetic += 1;
+        self.push(out, into, op, addr);
+        addr
+    }
+}
@@ -294,22 +294,25 @@ impl DBTable {
     }
 
     pub fn load_table_meta(&mut self, con: &Connection) {
+        self.try_load_table_meta(con).unwrap()
+    }
+
+    pub fn try_load_table_meta(&mut self, con: &Connection) -> rusqlite::Result<()> {
         trace!("load table_info for {}", self.name);
-        let mut s = con
-            .prepare(format!("pragma table_info({:})", self.name).as_str())
-            .unwrap();
-        let mut q = s.query(()).expect("ok");
+        let mut s = con.prepare(format!("pragma table_info({:})", self.name).as_str())?;
+        let mut q = s.query(())?;
         self.fields = vec![];
-        while let Ok(Some(r)) = q.next() {
+        while let Some(r) = q.next()? {
             let db_field = DBField {
-                name: r.get(1).unwrap(),
-                datatype: r.get(2).unwrap(),
-                default: r.get(4).unwrap(),
-                key: 0 < r.get(5).unwrap(),
-                has_null: 0 == r.get::<usize, i64>(3).unwrap(),
+                name: r.get(1)?,
+                datatype: r.get(2)?,
+                default: r.get(4)?,
+                key: 0 < r.get::<usize, i64>(5)?,
+                has_null: 0 == r.get::<usize, i64>(3)?,
             };
             self.fields.push(db_field);
         }
+        Ok(())
     }
 
     fn key(&self) -> Vec<&str> {
@@ -340,14 +343,20 @@ impl Database {
     }
 
     pub fn connect(&self, file: Option<&str>) {
+        self.try_connect(file).unwrap()
+    }
+
+    /// connects like `connect`, answers the error if the file can't be
+    /// opened or is no database
+    pub fn try_connect(&self, file: Option<&str>) -> rusqlite::Result<()> {
         let con = match file {
-            Some(path) => Connection::open(path).unwrap(),
-            None => Connection::open_in_memory().unwrap(),
+            Some(path) => Connection::open(path)?,
+            None => Connection::open_in_memory()?,
         };
 
         let mut l = self.locked();
         l.con = Some(con);
-        l.load_meta();
+        l.try_load_meta()
     }
 
     pub fn is_connected(&self) -> bool {
@@ -399,6 +408,13 @@ impl Database {
         x.execute_query(arg)
     }
 
+    /// runs the query like `execute_query`, answers the error instead of
+    /// logging it
+    pub fn try_query(&self, arg: &str) -> rusqlite::Result<Vec<DBRow>> {
+        let x = self.locked();
+        x.try_query(arg)
+    }
+
     pub fn execute_query_with_params(&self, arg: &str, params: Vec<SqlValue>) -> Vec<DBRow> {
         let x = self.locked();
         (*x).execute_query_with_params(arg, params)
@@ -586,58 +602,68 @@ impl DatabaseImpl {
     }
 
     pub fn execute_query(&self, arg: &str) -> Vec<DBRow> {
+        match self.try_query(arg) {
+            Ok(result) => result,
+            Err(x) => {
+                error!("SELECT ERROR {:#?}", x);
+                vec![]
+            }
+        }
+    }
+
+    pub fn try_query(&self, arg: &str) -> rusqlite::Result<Vec<DBRow>> {
         let mut result: Vec<DBRow> = vec![];
         if let Some(con) = &self.con {
-            let mut stmt_m = con.prepare(arg).unwrap();
-            let sql_result = stmt_m.query([]);
-            // let n = sql_result.column_count();
-            match sql_result {
-                Ok(mut rows) => {
-                    let names = rows
-                        .as_ref()
-                        .unwrap()
-                        .column_names()
-                        .iter()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<_>>();
-                    while let Some(row) = rows.next().unwrap() {
-                        let mut res_row = DBRow::new("#query");
-                        for idx in 0..names.len() {
-                            let v = Value::from(row.get::<_, Value>(idx).unwrap());
-                            res_row.insert(names[idx].clone(), SqlValue(v));
-                        }
-                        result.push(res_row);
-                    }
-                }
-                Err(x) => {
-                    error!("SELECT ERROR {:#?}", x);
+            let mut stmt_m = con.prepare(arg)?;
+            let mut rows = stmt_m.query([])?;
+            let names = rows
+                .as_ref()
+                .unwrap()
+                .column_names()
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>();
+            while let Some(row) = rows.next()? {
+                let mut res_row = DBRow::new("#query");
+                for (idx, name) in names.iter().enumerate() {
+                    let v = row.get::<_, Value>(idx)?;
+                    res_row.insert(name.clone(), SqlValue(v));
                 }
+                result.push(res_row);
             }
         };
-        result
+        Ok(result)
     }
 
     pub fn collect_tables(&mut self) {
+        self.try_collect_tables().unwrap()
+    }
+
+    fn try_collect_tables(&mut self) -> rusqlite::Result<()> {
         if let Some(con) = &self.con {
-            let mut s = con
-                .prepare(format!("select name from sqlite_schema where type = 'table'").as_str())
-                .unwrap();
-            let mut q = s.query(()).unwrap();
-            while let Ok(Some(r)) = q.next() {
-                let table_name: String = r.get(0).unwrap();
+            let mut s = con.prepare("select name from sqlite_schema where type = 'table'")?;
+            let mut q = s.query(())?;
+            while let Some(r) = q.next()? {
+                let table_name: String = r.get(0)?;
                 self.tables.push(DBTable::new(table_name.as_str()));
             }
         }
+        Ok(())
     }
 
     pub fn load_meta(&mut self) {
+        self.try_load_meta().unwrap()
+    }
+
+    fn try_load_meta(&mut self) -> rusqlite::Result<()> {
         info!("loading metadata");
-        self.collect_tables();
+        self.try_collect_tables()?;
         if let Some(con) = &self.con {
             for t in self.tables.iter_mut() {
-                t.load_table_meta(con);
+                t.try_load_table_meta(con)?;
             }
         }
+        Ok(())
     }
 
     pub fn activate_structure(&mut self, model: DataModel) {
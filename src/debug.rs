@@ -0,0 +1,285 @@
+//! The debugger of the compiled code. Before each step the VM asks it
+//! whether to stop, at a breakpoint or while stepping from send to send.
+//! A stop hands the running activation to the attached handler, the action
+//! it answers says how to go on.
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    rc::Rc,
+};
+
+use santiago::lexer::Position;
+
+use crate::{
+    code::{CodeAddress, CompiledMethod, Operation},
+    runtime::{val::Value, Receiver},
+    trace, ContextRef,
+};
+
+/// where to stop, in the method with the label: at the first send of a
+/// source line or at a step of the bytecode
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Line(String, usize),
+    Address(String, CodeAddress),
+}
+
+impl Breakpoint {
+    /// reads `Class>>selector 12` or `Class>>selector r1-4`, scripts are
+    /// `UndefinedObject>>DoIt`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (label, at) = text.trim().rsplit_once(char::is_whitespace)?;
+        let label = label.trim().to_string();
+        match CodeAddress::parse(at) {
+            Some(addr) => Some(Breakpoint::Address(label, addr)),
+            None => Some(Breakpoint::Line(label, at.parse().ok()?)),
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Breakpoint::Line(label, _) | Breakpoint::Address(label, _) => label,
+        }
+    }
+
+    fn hit(&self, method: &CompiledMethod, addr: &CodeAddress) -> bool {
+        if self.label() != method.label() {
+            return false;
+        }
+        match self {
+            Breakpoint::Line(_, line) => method.first_step_on_line(addr.0, *line) == Some(addr.1),
+            Breakpoint::Address(_, at) => at == addr,
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(label, line) => write!(f, "{} line {}", label, line),
+            Breakpoint::Address(label, addr) => write!(f, "{} {}", label, addr),
+        }
+    }
+}
+
+/// how to go on after a stop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// runs until the next breakpoint
+    Continue,
+    /// stops at the next send, also in the method it calls
+    StepInto,
+    /// stops at the next send of the same activation or of its callers
+    StepOver,
+    /// stops at the next send after the activation has ended
+    StepOut,
+}
+
+/// the activation the code stopped in, before the step at its address runs
+pub struct Stop<'a> {
+    method: &'a CompiledMethod,
+    ctx: &'a ContextRef,
+    depth: usize,
+}
+
+impl Stop<'_> {
+    /// `Class>>selector` of the method, blocks stop with the label of their method
+    pub fn label(&self) -> &'static str {
+        self.method.label()
+    }
+
+    pub fn address(&self) -> CodeAddress {
+        self.ctx.ip()
+    }
+
+    pub fn position(&self) -> Option<Position> {
+        self.method.position(&self.address())
+    }
+
+    /// the selector the step sends, `None` if it is no send
+    pub fn selector(&self) -> Option<&str> {
+        match self.method.operation(&self.address())? {
+            Operation::Invoke(selector, ..) | Operation::Super(selector, ..) => Some(selector),
+            _ => None,
+        }
+    }
+
+    /// the number of active methods and blocks
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn receiver(&self) -> Rc<dyn Receiver> {
+        self.ctx.receiver()
+    }
+
+    /// the arguments and temporaries of the running block and the blocks
+    /// around it
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        self.method.named_values(self.ctx)
+    }
+
+    /// the values computed so far by address
+    pub fn values(&self) -> Vec<(CodeAddress, Value)> {
+        self.method.step_values(self.ctx)
+    }
+
+    pub fn value_at(&self, addr: &CodeAddress) -> Option<Value> {
+        self.values().into_iter().find(|(a, _)| a == addr).map(|(_, v)| v)
+    }
+
+    /// a variable by name: a temporary, `self`, an instance or class
+    /// variable or a variable of the workspace
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        if name == "self" {
+            return Some(Value::from_receiver(self.receiver()));
+        }
+        match self.variables().into_iter().find(|(n, _)| n == name) {
+            Some((_, v)) => Some(v),
+            None => self.ctx.lookup(name),
+        }
+    }
+
+    /// the active frames, innermost first
+    pub fn stack_trace(&self) -> Vec<String> {
+        trace::stack_trace()
+    }
+
+    /// the method, the address and the source position with the selector sent
+    pub fn describe(&self) -> String {
+        let mut text = format!("{} {}", self.label(), self.address());
+        if let Some(p) = self.position() {
+            text.push_str(&format!(" line {} column {}", p.line, p.column));
+        }
+        if let Some(selector) = self.selector() {
+            text.push_str(&format!(", sends #{}", selector));
+        }
+        text
+    }
+}
+
+type Handler = Box<dyn FnMut(&Stop) -> Action>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stepping {
+    Off,
+    Into,
+    /// to a send at the depth or below
+    Over(usize),
+    /// to a send below the depth
+    Out(usize),
+}
+
+struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    stepping: Stepping,
+    handler: Option<Handler>,
+}
+
+thread_local! {
+    static DEBUGGER: RefCell<Debugger> = const {
+        RefCell::new(Debugger {
+            breakpoints: Vec::new(),
+            stepping: Stepping::Off,
+            handler: None,
+        })
+    };
+    /// whether a step may stop, checked before anything else
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+fn update<T>(f: impl FnOnce(&mut Debugger) -> T) -> T {
+    DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        let r = f(&mut d);
+        let active = !d.breakpoints.is_empty() || d.stepping != Stepping::Off;
+        ACTIVE.with(|a| a.set(active));
+        r
+    })
+}
+
+/// the handler is called at every stop, it replaces an earlier one
+pub fn attach(handler: impl FnMut(&Stop) -> Action + 'static) {
+    update(|d| d.handler = Some(Box::new(handler)));
+}
+
+pub fn detach() {
+    update(|d| d.handler = None);
+}
+
+/// answers the number of the breakpoint, counted from 1
+pub fn add_breakpoint(breakpoint: Breakpoint) -> usize {
+    update(|d| {
+        d.breakpoints.push(breakpoint);
+        d.breakpoints.len()
+    })
+}
+
+/// removes the breakpoint with the number, answers false if there is none
+pub fn remove_breakpoint(number: usize) -> bool {
+    update(|d| {
+        if !(1..=d.breakpoints.len()).contains(&number) {
+            return false;
+        }
+        d.breakpoints.remove(number - 1);
+        true
+    })
+}
+
+pub fn clear_breakpoints() {
+    update(|d| d.breakpoints.clear());
+}
+
+pub fn breakpoints() -> Vec<Breakpoint> {
+    DEBUGGER.with(|d| d.borrow().breakpoints.clone())
+}
+
+/// stops at the next send that runs
+pub fn step_into() {
+    update(|d| d.stepping = Stepping::Into);
+}
+
+/// runs to the next breakpoint, ends stepping
+pub fn stop_stepping() {
+    update(|d| d.stepping = Stepping::Off);
+}
+
+/// called by the VM before it runs the step at the instruction pointer
+pub(crate) fn before_step(method: &CompiledMethod, ctx: &ContextRef) {
+    if !ACTIVE.with(Cell::get) {
+        return;
+    }
+    let addr = ctx.ip();
+    let depth = trace::depth();
+    let handler = DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        let d = &mut *d;
+        let is_send = method.position(&addr).is_some();
+        let stepped = match d.stepping {
+            Stepping::Off => false,
+            Stepping::Into => is_send,
+            Stepping::Over(at) => is_send && depth <= at,
+            Stepping::Out(at) => is_send && depth < at,
+        };
+        if stepped || d.breakpoints.iter().any(|b| b.hit(method, &addr)) {
+            // the handler is taken while it runs, code it evaluates is not debugged
+            d.handler.take()
+        } else {
+            None
+        }
+    });
+    let Some(mut handler) = handler else {
+        return;
+    };
+    let action = handler(&Stop { method, ctx, depth });
+    update(|d| {
+        d.handler.get_or_insert(handler);
+        d.stepping = match action {
+            Action::Continue => Stepping::Off,
+            Action::StepInto => Stepping::Into,
+            Action::StepOver => Stepping::Over(depth),
+            Action::StepOut => Stepping::Out(depth),
+        };
+    });
+}
@@ -0,0 +1,262 @@
+//! The object memory. The heap owns the objects that can refer to other
+//! objects: instances, collections, blocks, activations and their
+//! variables. They stay alive as long as the heap holds them and are freed
+//! by a mark-and-sweep collection: everything reachable from the roots is
+//! marked, the references of the rest are cleared and the heap drops it.
+//! Objects that refer to nothing, numbers, strings, symbols, are left to
+//! their reference counts.
+//!
+//! The roots are the running activations, the globals and pool variables,
+//! the class variables, the installed exception handlers and the variables
+//! of the workspaces. The primitives hold objects on the Rust stack as
+//! well, which the heap can't walk. Those are found by counting: references
+//! to an object that neither the heap, the roots nor other objects of the
+//! heap account for come from the Rust stack, they make the object a root
+//! too.
+//!
+//! Collections run at safe points, when an activation starts or an
+//! evaluation ends, once enough objects were allocated since the last one.
+//! `Smalltalk garbageCollect` runs one right away.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    rc::Rc,
+};
+
+use tracing::info;
+
+use crate::{
+    runtime::{cls::ClassTable, exc, glb, sel::SelectorSet, val::Value, Receiver},
+    ContextRef,
+};
+
+/// a safe point collects once this many objects were allocated since the
+/// last collection
+const THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static OBJECTS: RefCell<Vec<Object>> = const { RefCell::new(Vec::new()) };
+    static ACTIVATIONS: RefCell<Vec<ContextRef>> = const { RefCell::new(Vec::new()) };
+    static WORKSPACES: RefCell<Vec<Rc<VariableMap>>> = const { RefCell::new(Vec::new()) };
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static NEXT_COLLECTION: Cell<usize> = const { Cell::new(THRESHOLD) };
+    static COLLECTIONS: Cell<usize> = const { Cell::new(0) };
+    static RECLAIMED: Cell<usize> = const { Cell::new(0) };
+}
+
+type VariableMap = RefCell<BTreeMap<&'static str, Value>>;
+
+/// an object owned by the heap
+enum Object {
+    Receiver(Rc<dyn Receiver>),
+    Context(ContextRef),
+    Variables(Rc<VariableMap>),
+}
+
+/// the state of the heap
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeapStats {
+    /// the objects the heap owns
+    pub objects: usize,
+    pub collections: usize,
+    /// the objects freed by all collections so far
+    pub reclaimed: usize,
+    /// the interned selectors and names of the loaded code, string
+    /// contents are not interned
+    pub selectors: usize,
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "objects: {}, collections: {}, reclaimed: {}, selectors: {}",
+            self.objects, self.collections, self.reclaimed, self.selectors
+        )
+    }
+}
+
+/// the address of the object, it identifies the object to the collector
+pub fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const u8 as usize
+}
+
+pub fn track_receiver(r: &Rc<dyn Receiver>) {
+    allocate(Object::Receiver(r.clone()));
+}
+
+pub fn track_context(ctx: &ContextRef) {
+    allocate(Object::Context(ctx.clone()));
+}
+
+pub fn track_variables(v: &Rc<VariableMap>) {
+    allocate(Object::Variables(v.clone()));
+}
+
+fn allocate(o: Object) {
+    OBJECTS.with(|objects| objects.borrow_mut().push(o));
+    ALLOCATED.set(ALLOCATED.get() + 1);
+}
+
+/// a running activation, a root until it is left
+pub struct Activation(usize);
+
+impl Drop for Activation {
+    fn drop(&mut self) {
+        ACTIVATIONS.with(|a| a.borrow_mut().truncate(self.0));
+    }
+}
+
+/// makes `ctx` a root while the activation runs. The start of an
+/// activation is a safe point.
+pub fn enter(ctx: &ContextRef) -> Activation {
+    let depth = ACTIVATIONS.with(|a| {
+        let mut a = a.borrow_mut();
+        a.push(ctx.clone());
+        a.len() - 1
+    });
+    safe_point();
+    Activation(depth)
+}
+
+/// keeps the variables of a workspace as a root until it is dropped
+pub struct Root(usize);
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        // a workspace may outlive the heap of its thread
+        let _ = WORKSPACES.try_with(|w| {
+            let mut w = w.borrow_mut();
+            if let Some(i) = w.iter().position(|v| address(v) == self.0) {
+                w.remove(i);
+            }
+        });
+    }
+}
+
+pub fn root(variables: &Rc<VariableMap>) -> Root {
+    WORKSPACES.with(|w| w.borrow_mut().push(variables.clone()));
+    Root(address(variables))
+}
+
+/// collects if enough objects were allocated since the last collection
+pub fn safe_point() {
+    if ALLOCATED.get() >= NEXT_COLLECTION.get() {
+        collect();
+    }
+}
+
+impl Object {
+    fn address(&self) -> usize {
+        match self {
+            Object::Receiver(r) => address(r),
+            Object::Context(c) => address(c),
+            Object::Variables(v) => address(v),
+        }
+    }
+
+    /// the references to the object, without the one of the heap
+    fn count(&self) -> usize {
+        match self {
+            Object::Receiver(r) => Rc::strong_count(r) - 1,
+            Object::Context(c) => Rc::strong_count(c) - 1,
+            Object::Variables(v) => Rc::strong_count(v) - 1,
+        }
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        match self {
+            Object::Receiver(r) => r.references(visit),
+            Object::Context(c) => c.references(visit),
+            Object::Variables(v) => {
+                // a map being changed right now is in use anyway
+                if let Ok(map) = v.try_borrow() {
+                    map.values().for_each(|v| v.references(visit));
+                }
+            }
+        }
+    }
+
+    fn clear(&self) {
+        match self {
+            Object::Receiver(r) => r.clear_references(),
+            Object::Context(c) => c.clear_references(),
+            Object::Variables(v) => v.borrow_mut().clear(),
+        }
+    }
+}
+
+/// the objects the interpreter refers to directly
+fn roots(visit: &mut dyn FnMut(usize)) {
+    ACTIVATIONS.with(|a| a.borrow().iter().for_each(|c| visit(address(c))));
+    WORKSPACES.with(|w| w.borrow().iter().for_each(|v| visit(address(v))));
+    glb::references(visit);
+    ClassTable::references(visit);
+    exc::references(visit);
+}
+
+/// marks the objects reachable from the roots and sweeps the others,
+/// answers the number of objects freed
+pub fn collect() -> usize {
+    let objects = OBJECTS.with(|o| o.take());
+    let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, o)| (o.address(), i)).collect();
+
+    // the references the roots and the objects don't account for come
+    // from the Rust stack
+    let mut marked = vec![false; objects.len()];
+    let mut pending = vec![];
+    let mut unknown: Vec<usize> = objects.iter().map(|o| o.count()).collect();
+    roots(&mut |a| {
+        if let Some(&i) = index.get(&a) {
+            unknown[i] = unknown[i].saturating_sub(1);
+            pending.push(i);
+        }
+    });
+    for o in objects.iter() {
+        o.references(&mut |a| {
+            if let Some(&i) = index.get(&a) {
+                unknown[i] = unknown[i].saturating_sub(1);
+            }
+        });
+    }
+    pending.extend((0..objects.len()).filter(|&i| unknown[i] > 0));
+
+    while let Some(i) = pending.pop() {
+        if marked[i] {
+            continue;
+        }
+        marked[i] = true;
+        objects[i].references(&mut |a| {
+            if let Some(&j) = index.get(&a) {
+                if !marked[j] {
+                    pending.push(j);
+                }
+            }
+        });
+    }
+
+    let (live, garbage): (Vec<_>, Vec<_>) = objects.into_iter().zip(marked).partition(|(_, m)| *m);
+    let survivors = live.len();
+    OBJECTS.with(|o| o.borrow_mut().extend(live.into_iter().map(|(o, _)| o)));
+    garbage.iter().for_each(|(o, _)| o.clear());
+    let freed = garbage.len();
+    drop(garbage);
+
+    COLLECTIONS.set(COLLECTIONS.get() + 1);
+    RECLAIMED.set(RECLAIMED.get() + freed);
+    ALLOCATED.set(0);
+    NEXT_COLLECTION.set(THRESHOLD.max(survivors));
+    info!("garbage collection freed {} of {} objects", freed, freed + survivors);
+    freed
+}
+
+pub fn stats() -> HeapStats {
+    HeapStats {
+        objects: OBJECTS.with(|o| o.borrow().len()),
+        collections: COLLECTIONS.get(),
+        reclaimed: RECLAIMED.get(),
+        selectors: SelectorSet::len(),
+    }
+}
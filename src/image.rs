@@ -0,0 +1,616 @@
+//! Images: the state of a session saved to an SQLite file and restored
+//! from it. An image holds the user defined classes with their methods as
+//! source, the compiled scripts of the method cache, the workspace bindings,
+//! the globals and pools and every object reachable from those. Objects describe themselves with
+//! `Receiver::snapshot`.
+
+use std::{collections::HashMap, fmt::Display, path::Path, rc::Rc};
+
+use crate::{
+    code::{compile_script, CompiledBlock, MethodCache},
+    data::model::{DataModel, Table},
+    dbx::{DBRow, Database, SqlValue},
+    global, heap, parse_method,
+    parser::AST,
+    runtime::{
+        arr::ArrayReceiver,
+        boo::{FalseReceiver, TrueReceiver},
+        byt::ByteArrayReceiver,
+        chr::CharReceiver,
+        cls::{ClassDef, ClassTable, InstanceReceiver},
+        dct::{AssociationReceiver, DictionaryReceiver},
+        exc, glb,
+        ivl::IntervalReceiver,
+        msg::MessageReceiver,
+        nil::NilReciever,
+        num::Number,
+        ord::OrderedCollectionReceiver,
+        pnt::PointReceiver,
+        sel::SelectorSet,
+        set::SetReceiver,
+        stm::StreamReceiver,
+        str::StringReceiver,
+        sym::SymbolReceiver,
+        val::Value,
+        Receiver,
+    },
+    new_variables, MethodContext, Variables,
+};
+
+const VERSION: &str = "3";
+
+/// named objects, the variables of a workspace
+pub type Bindings = Vec<(&'static str, Rc<dyn Receiver>)>;
+
+/// an object as stored in an image: the kind selects how it is rebuilt,
+/// `data` is its own state and `slots` are the objects it refers to
+pub struct Snapshot {
+    pub kind: &'static str,
+    pub data: String,
+    pub slots: Vec<(&'static str, Slot)>,
+}
+
+pub enum Slot {
+    Object(Rc<dyn Receiver>),
+    /// state that is no object but shared like one, the address tells who
+    /// shares it
+    Shared(usize, Snapshot),
+}
+
+impl Snapshot {
+    pub fn new(kind: &'static str, data: impl Into<String>) -> Self {
+        Self {
+            kind,
+            data: data.into(),
+            slots: vec![],
+        }
+    }
+
+    pub fn slot(mut self, name: &'static str, value: Rc<dyn Receiver>) -> Self {
+        self.slots.push((name, Slot::Object(value)));
+        self
+    }
+
+    pub fn shared(mut self, name: &'static str, address: usize, part: Snapshot) -> Self {
+        self.slots.push((name, Slot::Shared(address, part)));
+        self
+    }
+
+    /// named variables, a workspace stores its bindings this way
+    pub fn of_variables(variables: &Variables) -> Self {
+        let mut s = Snapshot::new("Variables", "");
+        for (name, value) in variables.borrow().iter() {
+            s = s.slot(name, value.to_receiver());
+        }
+        s
+    }
+}
+
+#[derive(Debug)]
+pub struct ImageError(pub String);
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+fn model() -> DataModel {
+    DataModel::new("image")
+        .table(
+            Table::new("image_meta")
+                .field("name", true, "text")
+                .field("value", false, "text"),
+        )
+        .table(
+            Table::new("image_object")
+                .field("id", true, "integer")
+                .field("kind", false, "text")
+                .field("data", false, "text"),
+        )
+        .table(
+            Table::new("image_slot")
+                .field("owner", true, "integer")
+                .field("idx", true, "integer")
+                .field("name", false, "text")
+                .field("target", false, "integer"),
+        )
+        .table(
+            Table::new("image_class")
+                .field("name", true, "text")
+                .field("seq", false, "integer")
+                .field("superclass", false, "text")
+                .field("inst_vars", false, "text")
+                .field("class_vars", false, "text")
+                .field("pools", false, "text"),
+        )
+        .table(
+            Table::new("image_method")
+                .field("class", true, "text")
+                .field("side", true, "integer")
+                .field("selector", true, "text")
+                .field("source", false, "text"),
+        )
+        // class variables have the class as scope, workspace bindings an empty one
+        .table(
+            Table::new("image_binding")
+                .field("scope", true, "text")
+                .field("name", true, "text")
+                .field("target", false, "integer"),
+        )
+        // globals have an empty scope, pool variables their pool
+        .table(
+            Table::new("image_global")
+                .field("scope", true, "text")
+                .field("name", true, "text")
+                .field("constant", false, "integer")
+                .field("target", false, "integer"),
+        )
+        .table(Table::new("image_pool").field("name", true, "text"))
+        .table(
+            Table::new("image_code")
+                .field("name", true, "text")
+                .field("source", false, "text"),
+        )
+}
+
+/// writes the classes, the method cache and the objects reachable from the
+/// workspace `variables` to a new image at `path`. The image is written next
+/// to it first, an existing image is only replaced once the new one is
+/// complete.
+pub fn save(path: &Path, variables: &Variables) -> Result<(), ImageError> {
+    let name = path.file_name().ok_or_else(|| ImageError(format!("{} is no file", path.display())))?;
+    let temp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let io = |e: std::io::Error| ImageError(e.to_string());
+    if temp.exists() {
+        std::fs::remove_file(&temp).map_err(io)?;
+    }
+    if let Err(e) = write(&temp, variables) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    std::fs::rename(&temp, path).map_err(io)
+}
+
+fn write(path: &Path, variables: &Variables) -> Result<(), ImageError> {
+    let db = Database::new();
+    db.try_connect(path.to_str())
+        .map_err(|e| ImageError(format!("can't write {}: {}", path.display(), e)))?;
+    db.activate_structure(model());
+    let mut w = Writer {
+        db: &db,
+        ids: HashMap::new(),
+        keep: vec![],
+    };
+    w.row("image_meta", &[("name", "version".into()), ("value", VERSION.into())]);
+
+    let builtin: Vec<&str> = exc::builtin_classes().iter().map(|c| c.name).collect();
+    let classes = ClassTable::names()
+        .into_iter()
+        .filter(|n| !builtin.contains(n))
+        .filter_map(ClassTable::get);
+    for (seq, class) in classes.enumerate() {
+        w.row(
+            "image_class",
+            &[
+                ("name", class.name.into()),
+                ("seq", (seq as u64).into()),
+                ("superclass", class.superclass.unwrap_or("nil").into()),
+                ("inst_vars", class.inst_vars.join(" ").as_str().into()),
+                ("class_vars", class.class_vars.join(" ").as_str().into()),
+                ("pools", class.pools().join(" ").as_str().into()),
+            ],
+        );
+        for side in [false, true] {
+            for m in class.methods(side) {
+                if let AST::Method { name, .. } = &*m {
+                    w.row(
+                        "image_method",
+                        &[
+                            ("class", class.name.into()),
+                            ("side", side.into()),
+                            ("selector", (*name).into()),
+                            ("source", m.to_source().as_str().into()),
+                        ],
+                    );
+                }
+            }
+        }
+        for n in class.class_vars.iter() {
+            if let Some(value) = class.class_var(n) {
+                w.binding(class.name, n, &value)?;
+            }
+        }
+    }
+    // compiled methods come back from their source, only scripts are kept
+    for (name, code) in MethodCache::entries().into_iter().filter(|(_, c)| c.selector().is_none()) {
+        w.row(
+            "image_code",
+            &[("name", name.as_str().into()), ("source", code.source().into())],
+        );
+    }
+    // blocks of the workspace share its variables, they are written like theirs
+    let workspace = w.write(heap::address(variables), Snapshot::of_variables(variables))?;
+    w.row("image_meta", &[("name", "workspace".into()), ("value", workspace.to_string().as_str().into())]);
+    for entry in glb::globals() {
+        w.global("", entry)?;
+    }
+    for (pool, vars) in glb::pools() {
+        w.row("image_pool", &[("name", pool.into())]);
+        for entry in vars {
+            w.global(pool, entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// restores the classes and the method cache from the image at `path`,
+/// answers the variables of the workspace
+pub fn load(path: &Path) -> Result<Variables, ImageError> {
+    if !path.exists() {
+        return Err(ImageError(format!("no image at {}", path.display())));
+    }
+    let not_image = |_| ImageError(format!("{} is not an image of version {}", path.display(), VERSION));
+    let db = Database::new();
+    db.try_connect(path.to_str()).map_err(not_image)?;
+    let query = |sql: &str| {
+        db.try_query(sql)
+            .map_err(|e| ImageError(format!("can't read {}: {}", path.display(), e)))
+    };
+    let version = db.try_query("select value from image_meta where name = 'version'").map_err(not_image)?;
+    match version.first().and_then(|r| r.get("value")) {
+        Some(v) if String::from(v.clone()) == VERSION => {}
+        _ => return Err(ImageError(format!("{} is not an image of version {}", path.display(), VERSION))),
+    }
+
+    // classes come first, instances are created from them
+    for r in query("select * from image_class order by seq")? {
+        let names = |k: &str| -> Vec<&'static str> {
+            text(&r, k).split_whitespace().map(SelectorSet::get).collect()
+        };
+        let superclass = match text(&r, "superclass").as_str() {
+            "nil" => None,
+            s => Some(SelectorSet::get(s)),
+        };
+        let name = SelectorSet::get(&text(&r, "name"));
+        let class = ClassTable::define(ClassDef::new(name, superclass, &names("inst_vars"), &names("class_vars")));
+        names("pools").into_iter().for_each(|p| class.import_pool(p));
+    }
+    for r in query("select * from image_method")? {
+        let class = class_named(&text(&r, "class"))?;
+        let source = text(&r, "source");
+        let trees = parse_method(source).map_err(|e| ImageError(e.to_string()))?;
+        let method = trees[0].as_abstract_syntax_tree();
+        if number(&r, "side") == 0 {
+            class.add_method(method);
+        } else {
+            class.add_class_method(method);
+        }
+    }
+    for r in query("select * from image_code")? {
+        let code = compile_script(text(&r, "source")).map_err(|e| ImageError(e.to_string()))?;
+        MethodCache::insert(&text(&r, "name"), code);
+    }
+
+    let mut reader = Reader {
+        rows: HashMap::new(),
+        objects: HashMap::new(),
+        variables: HashMap::new(),
+        contexts: HashMap::new(),
+        captured: vec![],
+    };
+    for r in query("select * from image_object")? {
+        reader.rows.insert(number(&r, "id"), (text(&r, "kind"), text(&r, "data"), vec![]));
+    }
+    for r in query("select * from image_slot order by owner, idx")? {
+        let owner = number(&r, "owner");
+        let name = SelectorSet::get(&text(&r, "name"));
+        if let Some(row) = reader.rows.get_mut(&owner) {
+            row.2.push((name, number(&r, "target")));
+        }
+    }
+    reader.restore()?;
+
+    for r in query("select * from image_binding")? {
+        let name = SelectorSet::get(&text(&r, "name"));
+        let value = reader.object(number(&r, "target"))?;
+        class_named(&text(&r, "scope"))?.set_class_var(name, value);
+    }
+    for r in query("select * from image_pool")? {
+        glb::declare_pool(SelectorSet::get(&text(&r, "name")));
+    }
+    for r in query("select * from image_global")? {
+        let name = SelectorSet::get(&text(&r, "name"));
+        let constant = number(&r, "constant") == 1;
+        let value = reader.object(number(&r, "target"))?;
+        match text(&r, "scope").as_str() {
+            "" => glb::restore(name, constant, value),
+            pool => glb::restore_pool_var(SelectorSet::get(pool), name, constant, value),
+        }
+    }
+    let workspace = query("select value from image_meta where name = 'workspace'")?;
+    let id = workspace.first().and_then(|r| text(r, "value").parse().ok());
+    id.and_then(|id| reader.variables.get(&id).cloned())
+        .ok_or_else(|| ImageError(format!("{} has no workspace", path.display())))
+}
+
+struct Writer<'a> {
+    db: &'a Database,
+    /// object ids by address
+    ids: HashMap<usize, u64>,
+    /// keeps the written objects alive, so their addresses are not reused.
+    /// Shared state is kept alive by the objects sharing it.
+    keep: Vec<Rc<dyn Receiver>>,
+}
+
+impl Writer<'_> {
+    fn row(&self, table: &str, values: &[(&str, SqlValue)]) {
+        let mut row = DBRow::new(table);
+        for (k, v) in values {
+            row.set(k, v.clone());
+        }
+        self.db.modify_from(table, &row);
+    }
+
+    fn binding(&mut self, scope: &str, name: &str, value: &Rc<dyn Receiver>) -> Result<(), ImageError> {
+        let target = self.object(value)?;
+        self.row(
+            "image_binding",
+            &[("scope", scope.into()), ("name", name.into()), ("target", target.into())],
+        );
+        Ok(())
+    }
+
+    fn global(&mut self, scope: &str, (name, constant, value): glb::Entry) -> Result<(), ImageError> {
+        let target = self.object(&value)?;
+        self.row(
+            "image_global",
+            &[
+                ("scope", scope.into()),
+                ("name", name.into()),
+                ("constant", constant.into()),
+                ("target", target.into()),
+            ],
+        );
+        Ok(())
+    }
+
+    /// writes the object and the ones it refers to, answers its id
+    fn object(&mut self, o: &Rc<dyn Receiver>) -> Result<u64, ImageError> {
+        let address = heap::address(o);
+        if let Some(id) = self.ids.get(&address) {
+            return Ok(*id);
+        }
+        let snapshot = o
+            .snapshot()
+            .ok_or_else(|| ImageError(format!("a {} can't be stored in an image", o.class_name())))?;
+        self.keep.push(o.clone());
+        self.write(address, snapshot)
+    }
+
+    /// writes the snapshot of what lives at `address` unless it is written
+    /// already, answers its id
+    fn write(&mut self, address: usize, snapshot: Snapshot) -> Result<u64, ImageError> {
+        if let Some(id) = self.ids.get(&address) {
+            return Ok(*id);
+        }
+        let id = self.ids.len() as u64 + 1;
+        self.ids.insert(address, id);
+        for (idx, (name, slot)) in snapshot.slots.into_iter().enumerate() {
+            let target = match slot {
+                Slot::Object(value) => self.object(&value)?,
+                Slot::Shared(address, part) => self.write(address, part)?,
+            };
+            self.row(
+                "image_slot",
+                &[
+                    ("owner", id.into()),
+                    ("idx", (idx as u64).into()),
+                    ("name", name.into()),
+                    ("target", target.into()),
+                ],
+            );
+        }
+        self.row(
+            "image_object",
+            &[
+                ("id", id.into()),
+                ("kind", snapshot.kind.into()),
+                ("data", snapshot.data.as_str().into()),
+            ],
+        );
+        Ok(id)
+    }
+}
+
+/// the names and ids of the objects a stored object refers to
+type Slots = Vec<(&'static str, u64)>;
+
+type Row = (String, String, Slots);
+
+struct Reader {
+    rows: HashMap<u64, Row>,
+    objects: HashMap<u64, Rc<dyn Receiver>>,
+    /// the named variables of the workspace and of the contexts
+    variables: HashMap<u64, Variables>,
+    /// the blocks that captured each context
+    contexts: HashMap<u64, Vec<u64>>,
+    /// a block of each restored context with the values of the variables
+    /// it captured, set once all objects exist
+    captured: Vec<(Rc<CompiledBlock>, Slots)>,
+}
+
+impl Reader {
+    /// instances can be part of cycles, they are created empty before all
+    /// other objects and filled in afterwards
+    fn restore(&mut self) -> Result<(), ImageError> {
+        let mut ids: Vec<u64> = self.rows.keys().copied().collect();
+        ids.sort();
+        let of_kind = |kind: &str| -> Vec<u64> { ids.iter().copied().filter(|id| self.rows[id].0 == kind).collect() };
+        let instances = of_kind("Instance");
+        let variables = of_kind("Variables");
+        let contexts = of_kind("Context");
+        for id in of_kind("CompiledBlock") {
+            if let [(_, context)] = self.rows[&id].2.as_slice() {
+                self.contexts.entry(*context).or_default().push(id);
+            }
+        }
+        for id in instances.iter() {
+            let o = InstanceReceiver::new(class_named(&self.rows[id].1)?);
+            self.objects.insert(*id, o);
+        }
+        for id in variables.iter() {
+            self.variables.insert(*id, new_variables());
+        }
+        for id in ids.into_iter().filter(|id| !variables.contains(id) && !contexts.contains(id)) {
+            self.object(id)?;
+        }
+        for id in instances {
+            let slots = self.rows[&id].2.clone();
+            let o = self.objects[&id].clone();
+            for (name, target) in slots {
+                o.set_inst_var(name, self.object(target)?);
+            }
+        }
+        for id in variables {
+            let map = self.variables[&id].clone();
+            for (name, target) in self.rows[&id].2.clone() {
+                let value = Value::from_receiver(self.object(target)?);
+                map.borrow_mut().insert(name, value);
+            }
+        }
+        for (block, slots) in std::mem::take(&mut self.captured) {
+            for (name, target) in slots {
+                block.set_captured(name, Value::from_receiver(self.object(target)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// blocks saved by the VM run on the VM again. The blocks that captured
+    /// one context are compiled together, so they share it once more. The
+    /// receiver and class of the context are read first, its variables can
+    /// refer back to the blocks.
+    fn context(&mut self, id: u64) -> Result<(), ImageError> {
+        let blocks = self.contexts.remove(&id).unwrap_or_default();
+        let bad = || ImageError(format!("invalid Context {}", id));
+        let slots = match self.rows.get(&id) {
+            Some((kind, _, slots)) if kind == "Context" => slots.clone(),
+            _ => return Err(bad()),
+        };
+        let [(_, myself), (_, class), (_, side), (_, variables), captured @ ..] = slots.as_slice() else {
+            return Err(bad());
+        };
+        let myself = self.object(*myself)?;
+        let class = self.object(*class)?.class_def();
+        // 1 on the class side, 0 on the instance side
+        let class_side = self.object(*side)?.as_int() == Some(1);
+        let variables = self.variables.get(variables).cloned().ok_or_else(bad)?;
+        let ctx = MethodContext::restored(myself, class.map(|c| (c, class_side)), variables);
+        let sources: Vec<&str> = blocks.iter().map(|b| self.rows[b].1.as_str()).collect();
+        let names: Vec<&str> = captured.iter().map(|(name, _)| *name).collect();
+        let made = CompiledBlock::restore(&sources, &names, ctx).map_err(ImageError)?;
+        for (id, block) in blocks.iter().zip(made.iter()) {
+            let block: Rc<dyn Receiver> = block.clone();
+            heap::track_receiver(&block);
+            self.objects.insert(*id, block);
+        }
+        if let Some(block) = made.into_iter().next() {
+            self.captured.push((block, captured.to_vec()));
+        }
+        Ok(())
+    }
+
+    fn object(&mut self, id: u64) -> Result<Rc<dyn Receiver>, ImageError> {
+        if let Some(o) = self.objects.get(&id) {
+            return Ok(o.clone());
+        }
+        let (kind, data, slots) = match self.rows.get(&id) {
+            Some(row) => row.clone(),
+            None => return Err(ImageError(format!("object {} is missing", id))),
+        };
+        if let ("CompiledBlock", [(_, context)]) = (kind.as_str(), slots.as_slice()) {
+            self.context(*context)?;
+            return self.objects.get(&id).cloned().ok_or_else(|| ImageError(format!("invalid CompiledBlock {:?}", data)));
+        }
+        let mut values = vec![];
+        for (_, target) in slots {
+            values.push(self.object(target)?);
+        }
+        let bad = || ImageError(format!("invalid {} {:?}", kind, data));
+        let o: Rc<dyn Receiver> = match kind.as_str() {
+            "nil" => NilReciever::get(),
+            "true" => TrueReceiver::get(),
+            "false" => FalseReceiver::get(),
+            "SmallInteger" => Number::Small(data.parse().map_err(|_| bad())?).into_receiver(),
+            "LargeInteger" => Number::Large(data.parse().map_err(|_| bad())?).into_receiver(),
+            "Fraction" => Number::Fraction(data.parse().map_err(|_| bad())?).into_receiver(),
+            "Float" => Number::Float(data.parse().map_err(|_| bad())?).into_receiver(),
+            "Character" => Rc::new(CharReceiver::new(data.chars().next().ok_or_else(bad)?)),
+            "String" => Rc::new(StringReceiver::new(data)),
+            "Symbol" => Rc::new(SymbolReceiver::new(&data)),
+            "Array" => Rc::new(ArrayReceiver(values)),
+            "OrderedCollection" => OrderedCollectionReceiver::new(values, None),
+            "SortedCollection" => {
+                let mut values = values.into_iter();
+                let block = values.next().ok_or_else(bad)?;
+                OrderedCollectionReceiver::new(values.collect(), Some(block))
+            }
+            "Set" | "Bag" => SetReceiver::new(values, kind == "Bag"),
+            "Dictionary" => {
+                if values.len() % 2 != 0 {
+                    return Err(bad());
+                }
+                DictionaryReceiver::new(values.chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect())
+            }
+            "Association" => match values.as_slice() {
+                [key, value] => AssociationReceiver::new(key.clone(), value.clone()),
+                _ => return Err(bad()),
+            },
+            "Interval" => {
+                let bounds: Result<Vec<isize>, _> = data.split_whitespace().map(|b| b.parse()).collect();
+                match bounds.map_err(|_| bad())?.as_slice() {
+                    [from, to, by] => Rc::new(IntervalReceiver::new(*from, *to, *by)),
+                    _ => return Err(bad()),
+                }
+            }
+            "ByteArray" => {
+                let bytes: Result<Vec<u8>, _> = data.split_whitespace().map(|b| b.parse()).collect();
+                Rc::new(ByteArrayReceiver::new(bytes.map_err(|_| bad())?))
+            }
+            "Point" => match data.split_once(' ') {
+                Some((x, y)) => Rc::new(PointReceiver::new(
+                    x.parse().map_err(|_| bad())?,
+                    y.parse().map_err(|_| bad())?,
+                )),
+                None => return Err(bad()),
+            },
+            "ReadStream" | "WriteStream" | "ReadWriteStream" => {
+                let collection = values.first().cloned().ok_or_else(bad)?;
+                StreamReceiver::restore(&kind, &data, &Value::Object(collection)).ok_or_else(bad)?
+            }
+            "Message" => {
+                let args = values.into_iter().map(Value::from_receiver).collect();
+                Rc::new(MessageReceiver::new(SelectorSet::get(&data), args))
+            }
+            "Global" => global(&data).to_receiver(),
+            _ => return Err(ImageError(format!("unknown kind of object {}", kind))),
+        };
+        self.objects.insert(id, o.clone());
+        Ok(o)
+    }
+}
+
+fn class_named(name: &str) -> Result<Rc<ClassDef>, ImageError> {
+    ClassTable::get(name).ok_or_else(|| ImageError(format!("class {} is not defined", name)))
+}
+
+fn text(row: &DBRow, k: &str) -> String {
+    row.get(k).map(|v| String::from(v.clone())).unwrap_or_default()
+}
+
+fn number(row: &DBRow, k: &str) -> u64 {
+    row.get(k).map(|v| u64::from(v.clone())).unwrap_or_default()
+}
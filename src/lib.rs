@@ -1,560 +1,761 @@
-use std::{collections::BTreeMap, fmt::Display, rc::Rc, sync::Arc};
-
-use code::CodeAddress;
-use parser::AST;
-use runtime::{
-    arr::ArrayReceiver,
-    blk::BlockReceiver,
-    int::{IntMetaReceiver, IntReceiver},
-    nil::NilReciever,
-    pnt::PointMetaReceiver,
-    sel::SelectorSet,
-    str::StringReceiver,
-    Object, ObjectPtr, Receiver, chr::CharReceiver,
-};
-
-use santiago::{
-    lexer::{lex, Lexeme, LexerError, Position},
-    parser::{parse, ParseError, Tree},
-};
-use std::{path::Path, sync::Mutex};
-use tracing::{info, level_filters::LevelFilter};
-
-use once_cell::sync::Lazy;
-
-pub mod code;
-pub mod controls;
-pub mod data;
-pub mod dbx;
-pub mod error;
-pub mod parser;
-pub mod runtime;
-pub mod tsort;
-pub mod ui;
-pub mod agent;
-
-pub static TRACING: Lazy<bool> = Lazy::new(|| init_tracing("test", LevelFilter::INFO));
-
-pub fn init_tracing(name:&str, max_level: LevelFilter) -> bool {
-    let n = format!("{}-{}.log", name, chrono::Utc::now().format("%Y-%m-%dT%H%M%S"));
-    let path = Path::new(n.as_str());
-    let log_file = std::fs::File::create(path).unwrap();
-    let subscriber = tracing_subscriber::fmt()
-        .with_writer(Mutex::new(log_file))
-        .with_ansi(false)
-        .with_max_level(max_level)
-        .finish();
-    let _ = tracing::subscriber::set_global_default(subscriber);
-    true
-}
-
-struct AppError {
-    msg: Box<dyn std::fmt::Display>,
-}
-
-#[derive(Clone)]
-pub struct MethodContext(Arc<FrameData>);
-
-pub struct BlockContext {
-    parent: ContextRef,
-}
-
-pub struct FrameData {
-    instruction_pointer: Mutex<CodeAddress>,
-    values: Mutex<BTreeMap<CodeAddress, Rc<dyn Receiver>>>,
-}
-
-struct Context {
-    receiver_names: Mutex<BTreeMap<&'static str, Rc<dyn Receiver>>>,
-    myself: Rc<dyn Receiver>,
-}
-
-pub type ContextRef = Rc<dyn ContextTrait>;
-
-// pub struct ContextRef {
-//     ctx: Rc<dyn ContextTrait>,
-// }
-
-impl std::fmt::Display for AppError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.msg.fmt(f)
-    }
-}
-
-impl std::fmt::Debug for AppError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "AppError<")?;
-        self.msg.fmt(f)?;
-        write!(f, ">")
-    }
-}
-impl std::error::Error for AppError {}
-
-pub fn evaluate_script(
-    input_string: String,
-) -> Result<Rc<dyn Receiver>, Box<dyn std::error::Error>> {
-    let parse_trees = parse_script(input_string)?;
-    let mut ctx = Context::new(NilReciever::get());
-    let mut o = NilReciever::get();
-    for t in parse_trees {
-        let ast = t.as_abstract_syntax_tree();
-        println!("-> {}", t.to_string());
-        println!("-> {:#?}", &ast);
-        o = ctx.eval_to_reciever(&ast);
-        println!("eval -> {}", o);
-    }
-    Ok(o)
-}
-
-pub trait ContextTrait {
-    fn ip(&self) -> CodeAddress;
-    fn next_ip(&self);
-    fn call(&self, addr: CodeAddress);
-    fn get_value(&self, addr: &CodeAddress) -> Rc<dyn Receiver>;
-    fn get_values(&self, addrs: &[CodeAddress]) -> Vec<Rc<dyn Receiver>>;
-    fn set_value(&self, addr: &CodeAddress, value: Rc<dyn Receiver>);
-}
-
-impl ContextTrait for BlockContext {
-    fn ip(&self) -> CodeAddress {
-        self.parent.ip()
-    }
-
-    fn next_ip(&self) {
-        self.parent.next_ip()
-    }
-
-    fn get_value(&self, addr: &CodeAddress) -> Rc<dyn Receiver> {
-        self.parent.get_value(addr)
-    }
-
-    fn get_values(&self, addrs: &[CodeAddress]) -> Vec<Rc<dyn Receiver>> {
-        self.parent.get_values(addrs)
-    }
-
-    fn set_value(&self, addr: &CodeAddress, value: Rc<dyn Receiver>) {
-        self.parent.set_value(addr, value);
-    }
-
-    fn call(&self, addr: CodeAddress) {
-        self.parent.call(addr)
-    }
-}
-
-// impl Frame {
-//     pub fn lock(&self) -> MutexGuard<'_, FrameData> {
-//         match self.0.try_lock() {
-//             Ok(l) => l,
-//             Err(e) => {
-//                 println!("try lock: {}", e);
-//                 panic!();
-//             },
-//         }
-//     }
-// }
-
-impl ContextTrait for MethodContext {
-    fn ip(&self) -> CodeAddress {
-        self.0.instruction_pointer.lock().unwrap().clone()
-    }
-
-    fn next_ip(&self) {
-        let ip = &mut self.0.instruction_pointer.lock().unwrap();
-        ip.1 += 1;
-    }
-
-    fn get_value(&self, addr: &CodeAddress) -> Rc<dyn Receiver> {
-        let vs = self.0.values.lock().unwrap();
-        match vs.get(addr) {
-            Some(val) => val.clone(),
-            None => panic!("undefined value {}", addr),
-        }
-    }
-
-    fn get_values(&self, addrs: &[CodeAddress]) -> Vec<Rc<dyn Receiver>> {
-        let mut result = vec![];
-        let vs = self.0.values.lock().unwrap();
-        for addr in addrs {
-            result.push(vs.get(addr).unwrap().clone());
-        }
-        result
-    }
-
-    fn set_value(&self, addr: &CodeAddress, value: Rc<dyn Receiver>) {
-        let vs = &mut self.0.values.lock().unwrap();
-        vs.insert(addr.clone(), value);
-    }
-
-    fn call(&self, addr: CodeAddress) {
-        let ip = &mut self.0.instruction_pointer.lock().unwrap();
-        **ip = addr;
-    }
-}
-
-impl MethodContext {
-    pub fn new() -> ContextRef {
-        let c1 = MethodContext(Arc::new(FrameData::new()));
-        let ctx: Rc<dyn ContextTrait> = Rc::new(c1);
-        ctx
-    }
-
-    #[allow(dead_code)]
-    fn done(&self) -> bool {
-        (*self.0).done()
-    }
-
-    #[allow(dead_code)]
-    fn set_ip(&self, start: CodeAddress) -> CodeAddress {
-        (*self.0).set_ip(start)
-    }
-
-    #[allow(dead_code)]
-    fn result(&self, idx: usize) -> Rc<dyn Receiver> {
-        self.0.result(idx)
-    }
-}
-
-impl FrameData {
-    pub fn new() -> Self {
-        Self {
-            instruction_pointer: Mutex::new(CodeAddress(0, 0)),
-            values: Mutex::new(BTreeMap::new()),
-        }
-    }
-
-    // pub fn run(&mut self) -> Rc<dyn Receiver> {
-    //     while !self.done {
-    //         self.process_step();
-    //     }
-    //     match self.method.blocks[0].result {
-    //         Some(result_addr) => match self.values.get(&result_addr) {
-    //             Some(v) => v.clone(),
-    //             None => panic!("value {} undefined.", result_addr),
-    //         },
-    //         None => todo!(),
-    //     }
-    // }
-
-    // fn process_step(&self, method_context: ContextRef) {
-    //     let op;
-    //     let block;
-    //     let step;
-    //     {
-    //         let CodeAddress(ip_block, ip_step) = *self.ip.try_lock().unwrap();
-    //         block = ip_block;
-    //         step = ip_step;
-    //         op = &self.method.blocks[block].opcode[step];
-    //         println!("{:?}", op);
-    //     }
-    //     let v: Rc<dyn Receiver> = match op {
-    //         Operation::Int(v) => Rc::new(IntReceiver::new(*v)),
-    //         Operation::Str(_) => todo!(),
-    //         Operation::Invoke(selector, receiver, args) => {
-    //             let receiver = self.get_value(receiver);
-    //             let args = self.get_values(args);
-    //             let r = receiver.receive_message(SelectorSet::get(selector), args);
-    //             r
-    //         }
-    //         Operation::Block(b) => {
-    //             let r = Rc::new(BlockContext::new(
-    //                 CodeAddress(*b, 0),
-    //                 method_context.clone(),
-    //             ));
-    //             r
-    //         }
-    //         Operation::Global(_) => todo!(),
-    //         Operation::Char(v) => Rc::new(IntReceiver::new(*v as isize)),
-    //         Operation::String(v) => Rc::new(StringReceiver::new(v.clone())),
-    //         Operation::Arg(_) => todo!(),
-    //         Operation::Return(_) => todo!(),
-    //         Operation::Param(_) => todo!(),
-    //         Operation::Myself => todo!(),
-    //     };
-    //     {
-    //         let mut ip = self.ip.try_lock().unwrap();
-    //         self.values.try_lock().unwrap().insert(*ip, v);
-    //         // if step + 1 < self.method.blocks[block].opcode.len() {
-    //         *ip = CodeAddress(block, step + 1);
-    //         // }
-    //     }
-    // }
-
-    fn result(&self, _idx: usize) -> Rc<dyn Receiver> {
-        // match self.method.blocks[idx].result {
-        //     Some(addr) => self.get_value(&addr),
-        //     None => NilReciever::get(),
-        // }
-        todo!()
-    }
-
-    #[allow(dead_code)]
-    fn get_values(&self, args: &Vec<CodeAddress>) -> Vec<Rc<dyn Receiver>> {
-        let values = self.values.try_lock().unwrap();
-        args.iter()
-            .map(|x| values.get(x).unwrap().clone())
-            .collect::<Vec<_>>()
-    }
-
-    #[allow(dead_code)]
-    fn get_value(&self, receiver: &CodeAddress) -> Rc<dyn Receiver> {
-        let values = self.values.try_lock().unwrap();
-        values.get(receiver).unwrap().clone()
-    }
-
-    fn done(&self) -> bool {
-        // let CodeAddress(block, idx) = self.instruction_pointer.try_lock().unwrap().clone();
-        // let blk = &self.method.blocks[block];
-        // blk.opcode.len() <= idx
-        todo!()
-    }
-
-    fn set_ip(&self, _start: CodeAddress) -> CodeAddress {
-        // let mut ip = self.ip.try_lock().unwrap();
-        // let r = ip.clone();
-        // *ip = start;
-        // r
-        CodeAddress(0, 0)
-    }
-}
-
-impl BlockContext {
-    pub fn new(ctx: ContextRef) -> Rc<Self> {
-        let r = Self {
-            // start: CodeAddress(0, 0),
-            parent: ctx,
-            // params: Mutex::new(vec![]),
-        };
-        let rr = Rc::new(r);
-        rr
-    }
-}
-
-pub fn parse_method(
-    input_string: String,
-) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
-    let lexing_rules = parser::lexer_rules();
-    let grammar = parser::grammar();
-    let mut lexemes = handle_lex_error(lex(&lexing_rules, &input_string))?;
-    lexemes.insert(
-        0,
-        Rc::new(Lexeme {
-            kind: "METHOD".into(),
-            raw: String::new(),
-            position: Position { line: 0, column: 0 },
-        }),
-    );
-    let parse_trees = handle_error(parse(&grammar, &lexemes))?;
-    Ok(parse_trees)
-}
-
-pub fn parse_script(
-    input_string: String,
-) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
-    let lexing_rules = parser::lexer_rules();
-    let grammar = parser::grammar();
-    let mut lexemes = handle_lex_error(lex(&lexing_rules, &input_string))?;
-    lexemes.insert(
-        0,
-        Rc::new(Lexeme {
-            kind: "EVALUATE".into(),
-            raw: String::new(),
-            position: Position { line: 0, column: 0 },
-        }),
-    );
-    let parse_trees = handle_error(parse(&grammar, &lexemes))?;
-    Ok(parse_trees)
-}
-
-#[allow(dead_code)]
-impl Context {
-    fn new(myself: Rc<dyn Receiver>) -> Self {
-        Self {
-            receiver_names: Mutex::new(BTreeMap::new()),
-            myself,
-        }
-    }
-
-    pub fn set_receiver(&self, name: &'static str, rec: Rc<dyn Receiver>) {
-        let mut map = self.receiver_names.try_lock().unwrap();
-        map.insert(name, rec);
-    }
-
-    pub fn get_receiver(&self, name: &'static str) -> Option<Rc<dyn Receiver>> {
-        let map = self.receiver_names.try_lock().unwrap();
-        match map.get(name) {
-            Some(r) => Some(r.clone()),
-            None => None,
-        }
-    }
-
-    fn eval(&mut self, t: &AST) -> ObjectPtr {
-        match t {
-            AST::Int(n) => Object::new_string(n.to_string().as_str()),
-            AST::String(_) => todo!(),
-            AST::Name(_) => todo!(),
-            AST::Method {
-                name: _,
-                params: _,
-                temps: _,
-                body: _,
-            } => todo!(),
-            AST::Return(_) => todo!(),
-            AST::PatternPart(_, _, _) => todo!(),
-            AST::List(_, _) => todo!(),
-            AST::Table(t) => {
-                panic!("eval {:?}", t);
-            }
-            AST::Message { name: _, args: _ } => todo!(),
-            AST::Variable(_) => todo!(),
-            AST::Empty => todo!(),
-            AST::Statements(s) => {
-                let mut r = Object::new_string("<none>".into());
-                for x in s {
-                    r = self.eval(x);
-                }
-                r
-            }
-            AST::InvokeSequence(target, msgs) => {
-                let target_obj = self.eval(target);
-                let mut r = Object::new_string("<nomsg>".into());
-                for m in msgs {
-                    if let AST::Message { name, args } = m {
-                        let mut oargs = vec![];
-                        for v in args {
-                            oargs.push(self.eval(v));
-                        }
-                        r = target_obj.send(name, oargs.as_slice());
-                    }
-                }
-                r
-            }
-            _ => todo!("{:?}", t),
-        }
-    }
-
-    fn eval_to_reciever(&mut self, t: &AST) -> Rc<dyn runtime::Receiver> {
-        match t {
-            AST::Int(n) => Rc::new(IntReceiver::new(*n)),
-            AST::String(s) => Rc::new(StringReceiver::new(String::from(*s))),
-            AST::Name(_) => todo!(),
-            AST::Method {
-                name: _,
-                params: _,
-                temps: _,
-                body: _,
-            } => todo!(),
-            AST::Return(x) => self.eval_to_reciever(x),
-            AST::PatternPart(_, _, _) => todo!(),
-            AST::List(_, _) => todo!(),
-            AST::Table(t) => {
-                let v: Vec<Rc<dyn Receiver>> = t.iter().map(|x| self.eval_to_reciever(x)).collect();
-                Rc::new(ArrayReceiver(v))
-            }
-            AST::Message { name: _, args: _ } => todo!(),
-            AST::Variable(name) => {
-                if let Some(r) = self.get_receiver(*name) {
-                    r
-                } else {
-                    match *name {
-                        "self" => self.myself.clone(),
-                        "Point" => Rc::new(PointMetaReceiver),
-                        "Integer" => Rc::new(IntMetaReceiver),
-                        _ => todo!("name not known: {}", name),
-                    }
-                }
-            }
-            AST::Empty => todo!(),
-            AST::Statements(s) => {
-                let mut r = NilReciever::get();
-                for x in s {
-                    r = self.eval_to_reciever(x);
-                }
-                r
-            }
-            AST::InvokeSequence(target, msgs) => {
-                let mut receiver = self.eval_to_reciever(target);
-                for m in msgs {
-                    if let AST::Message { name, args } = m {
-                        let mut oargs = vec![];
-                        for v in args {
-                            match v {
-                                AST::Empty => panic!("{:#?}", args),
-                                _ => {
-                                    let o = self.eval_to_reciever(v);
-                                    oargs.push(o);
-                                }
-                            }
-                        }
-                        receiver = receiver.receive_message(name, oargs);
-                    }
-                }
-                receiver
-            }
-            AST::Assign(name, expr) => {
-                if let AST::Name(name) = **name {
-                    let value = self.eval_to_reciever(expr);
-                    self.set_receiver(SelectorSet::get(name), value.clone());
-                    value
-                } else {
-                    panic!("unexpected {:?}", t)
-                }
-            }
-            AST::Block {
-                params,
-                temps,
-                body,
-            } => {
-                info!("instantiate block");
-                let r = BlockReceiver::new(self.myself.clone(), params, temps, body.clone());
-                let map = self.receiver_names.try_lock().unwrap();
-                for (k, v) in map.iter() {
-                    info!("push {} to block context", *k);
-                    r.define(*k, v.clone());
-                }
-                Rc::new(r)
-            }
-            AST::Char(c) => Rc::new(CharReceiver::new(*c)),
-            _ => todo!("{:?}", t),
-        }
-    }
-}
-
-type Lexemes = Vec<Rc<Lexeme>>;
-
-fn handle_lex_error(r: Result<Lexemes, LexerError>) -> Result<Lexemes, AppError> {
-    match r {
-        Ok(l) => Ok(l),
-        Err(e) => Err(AppError { msg: Box::new(e) }),
-    }
-}
-
-type ParseTrees = Vec<Rc<Tree<AST>>>;
-
-fn handle_error(r: Result<ParseTrees, ParseError<AST>>) -> Result<ParseTrees, AppError> {
-    match r {
-        Ok(t) => Ok(t),
-        Err(e) => Err(AppError { msg: Box::new(e) }),
-    }
-}
-
-#[allow(dead_code)]
-fn handle_parse_tree(parse_trees: Vec<Rc<Tree<AST>>>) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Parse Trees:");
-    for tree in &parse_trees {
-        println!("{tree}");
-        let ast = tree.as_abstract_syntax_tree();
-        println!("Abstract Syntax Tree:");
-        println!("{ast:#?}");
-    }
-    // println!("Evaluated:");
-    // println!("{}", eval(&ast));
-    Ok(())
-}
-
-#[allow(dead_code)]
-fn print_lexemes(lexemes: &Vec<Rc<Lexeme>>) {
-    println!("Lexemes:");
-    for lexeme in lexemes {
-        println!("  {lexeme}");
-    }
-}
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fmt::Display,
+    rc::Rc,
+};
+
+use code::CodeAddress;
+use parser::{Diagnostic, SyntaxError, AST};
+use runtime::{
+    cls::{ClassDef, ClassTable},
+    exc, glb,
+    nil::NilReciever,
+    rfl,
+    sel::SelectorSet,
+    sys::SystemReceiver,
+    val::Value,
+    Receiver,
+};
+use unwind::{Exit, Home};
+
+use santiago::{
+    lexer::{lex, Lexeme, Position},
+    parser::{parse, Tree},
+};
+use std::{path::Path, sync::Mutex};
+use tracing::{error, info, level_filters::LevelFilter};
+
+use once_cell::sync::Lazy;
+
+pub mod agent;
+pub mod chunk;
+pub mod code;
+pub mod controls;
+pub mod data;
+pub mod dbx;
+pub mod debug;
+pub mod error;
+pub mod heap;
+pub mod image;
+pub mod parser;
+pub mod repl;
+pub mod repo;
+pub mod runtime;
+pub mod trace;
+pub mod tsort;
+pub mod ui;
+pub mod unwind;
+
+pub static TRACING: Lazy<bool> = Lazy::new(|| init_tracing("test", LevelFilter::INFO));
+
+pub fn init_tracing(name: &str, max_level: LevelFilter) -> bool {
+    let n = format!(
+        "{}-{}.log",
+        name,
+        chrono::Utc::now().format("%Y-%m-%dT%H%M%S")
+    );
+    let path = Path::new(n.as_str());
+    let log_file = std::fs::File::create(path).unwrap();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(Mutex::new(log_file))
+        .with_ansi(false)
+        .with_max_level(max_level)
+        .finish();
+    let _ = tracing::subscriber::set_global_default(subscriber);
+    true
+}
+
+struct AppError {
+    msg: Box<dyn std::fmt::Display>,
+}
+
+/// named variables, the ones of a workspace and the ones a script assigns
+/// without declaring them
+pub type Variables = Rc<RefCell<BTreeMap<&'static str, Value>>>;
+
+#[derive(Clone)]
+pub struct MethodContext(Rc<FrameData>);
+
+/// the activation of a compiled block, the values of the enclosing blocks
+/// are found through its parent
+pub struct BlockContext {
+    parent: ContextRef,
+    block: usize,
+    instruction_pointer: Cell<CodeAddress>,
+    values: Registers,
+    args: Vec<Value>,
+}
+
+/// the values of the steps of one block by step, a new activation has none
+#[derive(Default)]
+struct Registers(RefCell<Vec<Option<Value>>>);
+
+impl Registers {
+    fn get(&self, step: usize) -> Option<Value> {
+        self.0.borrow().get(step).cloned().flatten()
+    }
+
+    fn set(&self, step: usize, value: Value) {
+        let mut r = self.0.borrow_mut();
+        if r.len() <= step {
+            r.resize(step + 1, None);
+        }
+        r[step] = Some(value);
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(r) = self.0.try_borrow() {
+            r.iter().flatten().for_each(|v| v.references(visit));
+        }
+    }
+
+    fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+pub struct FrameData {
+    instruction_pointer: Cell<CodeAddress>,
+    values: Registers,
+    home: Home,
+    receiver: Rc<dyn Receiver>,
+    /// the class defining the method and whether it runs on the class side
+    class: Option<(Rc<ClassDef>, bool)>,
+    args: Vec<Value>,
+    variables: Variables,
+    /// the value of a return in the method itself
+    result: Mutex<Option<Value>>,
+}
+
+pub type ContextRef = Rc<dyn ContextTrait>;
+
+// pub struct ContextRef {
+//     ctx: Rc<dyn ContextTrait>,
+// }
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.msg.fmt(f)
+    }
+}
+
+impl std::fmt::Debug for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AppError<")?;
+        self.msg.fmt(f)?;
+        write!(f, ">")
+    }
+}
+impl std::error::Error for AppError {}
+
+pub fn evaluate_script(
+    input_string: String,
+) -> Result<Rc<dyn Receiver>, Box<dyn std::error::Error>> {
+    Workspace::new().evaluate(&input_string)
+}
+
+/// a session, the variables assigned by its scripts are kept from one
+/// evaluation to the next and can be saved to an image
+pub struct Workspace {
+    variables: Variables,
+    _root: heap::Root,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::with_variables(new_variables())
+    }
+
+    fn with_variables(variables: Variables) -> Self {
+        let _root = heap::root(&variables);
+        Self { variables, _root }
+    }
+
+    /// compiles the script and runs it, answers the value of its last statement
+    pub fn evaluate(
+        &mut self,
+        input: &str,
+    ) -> Result<Rc<dyn Receiver>, Box<dyn std::error::Error>> {
+        let ctx = MethodContext::with_variables(self.variables.clone());
+        // a failure while compiling ends the script like one while it runs
+        let mut invalid = None;
+        let exit = unwind::run_as(unwind::NO_HOME, || {
+            match code::compile_script(input.to_string()) {
+                Ok(code) => code.run(ctx),
+                Err(e) => {
+                    invalid = Some(e);
+                    Value::Nil
+                }
+            }
+        });
+        heap::safe_point();
+        if let Some(e) = invalid {
+            return Err(e);
+        }
+        match exit {
+            Exit::Normal(v) | Exit::Unwound(_, v) => {
+                info!("eval -> {}", v);
+                Ok(v.to_receiver())
+            }
+            Exit::Failed(payload) => {
+                let msg = exc::failure_message(&payload);
+                error!("script failed: {}", msg);
+                Err(Box::new(AppError { msg: Box::new(msg) }))
+            }
+        }
+    }
+
+    /// the variables of the session by name
+    pub fn bindings(&self) -> image::Bindings {
+        let map = self.variables.borrow();
+        map.iter().map(|(k, v)| (*k, v.to_receiver())).collect()
+    }
+
+    pub fn bind(&self, name: &str, value: Rc<dyn Receiver>) {
+        self.variables
+            .borrow_mut()
+            .insert(SelectorSet::get(name), Value::from_receiver(value));
+    }
+
+    /// writes the session together with the classes to an image
+    pub fn save_image(&self, path: &Path) -> Result<(), image::ImageError> {
+        image::save(path, &self.variables)
+    }
+
+    /// a session with the bindings of the image, the classes of the image
+    /// are defined as well
+    pub fn load_image(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Self::with_variables(image::load(path)?))
+    }
+}
+
+/// names visible everywhere: the constants and the classes
+pub(crate) fn global(name: &str) -> Value {
+    match name {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "nil" => Value::Nil,
+        "Smalltalk" => Value::Object(Rc::new(SystemReceiver)),
+        _ => match rfl::class_named(name).or_else(|| glb::named(name)) {
+            Some(c) => Value::Object(c),
+            None => exc::signal_error("UndeclaredVariable", &format!("name not known: {}", name)),
+        },
+    }
+}
+
+pub trait ContextTrait {
+    fn ip(&self) -> CodeAddress;
+    fn next_ip(&self);
+    fn call(&self, addr: CodeAddress);
+    /// the value at `addr`, kept by the activation of the block it belongs to
+    fn find_value(&self, addr: &CodeAddress) -> Option<Value>;
+    fn set_value(&self, addr: &CodeAddress, value: Value);
+    /// the method activation returns inside this context go to
+    fn home(&self) -> Home;
+    /// an argument of the method or block
+    fn arg(&self, idx: usize) -> Value;
+    /// `self` of the method
+    fn receiver(&self) -> Rc<dyn Receiver>;
+    /// the class defining the method and whether it runs on the class side
+    fn class(&self) -> Option<(Rc<ClassDef>, bool)>;
+    fn variables(&self) -> Variables;
+    /// the value of a return in the method itself, blocks return to their home instead
+    fn set_result(&self, value: Value);
+    fn result(&self) -> Option<Value>;
+    /// the objects the activation holds, the collector follows them to find cycles
+    fn references(&self, visit: &mut dyn FnMut(usize));
+    /// drops the values of the activation, called by the collector for garbage
+    fn clear_references(&self);
+
+    fn get_value(&self, addr: &CodeAddress) -> Value {
+        match self.find_value(addr) {
+            Some(val) => val,
+            None => panic!("undefined value {}", addr),
+        }
+    }
+
+    fn get_values(&self, addrs: &[CodeAddress]) -> Vec<Value> {
+        addrs.iter().map(|addr| self.get_value(addr)).collect()
+    }
+
+    /// a named variable, an instance variable or a class variable
+    fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some(v) = self.variables().borrow().get(name) {
+            return Some(v.clone());
+        }
+        if let Some(v) = self.receiver().inst_var(name) {
+            return Some(Value::from_receiver(v));
+        }
+        self.class()
+            .and_then(|(c, _)| c.class_var(name))
+            .map(Value::from_receiver)
+    }
+
+    /// assigns an instance or class variable, other names become named variables
+    fn assign(&self, name: &'static str, value: Value) {
+        let variables = self.variables();
+        if !variables.borrow().contains_key(name) {
+            if self.receiver().set_inst_var(name, value.to_receiver()) {
+                return;
+            }
+            if let Some((c, _)) = self.class() {
+                if c.set_class_var(name, value.to_receiver()) {
+                    return;
+                }
+            }
+            if glb::assign(name, value.to_receiver()) {
+                return;
+            }
+        }
+        variables.borrow_mut().insert(name, value);
+    }
+}
+
+impl ContextTrait for BlockContext {
+    fn ip(&self) -> CodeAddress {
+        self.instruction_pointer.get()
+    }
+
+    fn next_ip(&self) {
+        let CodeAddress(block, step) = self.instruction_pointer.get();
+        self.instruction_pointer.set(CodeAddress(block, step + 1));
+    }
+
+    fn call(&self, addr: CodeAddress) {
+        self.instruction_pointer.set(addr);
+    }
+
+    fn find_value(&self, addr: &CodeAddress) -> Option<Value> {
+        if addr.0 == self.block {
+            self.values.get(addr.1)
+        } else {
+            self.parent.find_value(addr)
+        }
+    }
+
+    fn set_value(&self, addr: &CodeAddress, value: Value) {
+        if addr.0 == self.block {
+            self.values.set(addr.1, value);
+        } else {
+            self.parent.set_value(addr, value);
+        }
+    }
+
+    fn home(&self) -> Home {
+        self.parent.home()
+    }
+
+    fn arg(&self, idx: usize) -> Value {
+        self.args[idx].clone()
+    }
+
+    fn receiver(&self) -> Rc<dyn Receiver> {
+        self.parent.receiver()
+    }
+
+    fn class(&self) -> Option<(Rc<ClassDef>, bool)> {
+        self.parent.class()
+    }
+
+    fn variables(&self) -> Variables {
+        self.parent.variables()
+    }
+
+    fn set_result(&self, _value: Value) {
+        panic!("a block returns to its home")
+    }
+
+    fn result(&self) -> Option<Value> {
+        None
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        visit(heap::address(&self.parent));
+        self.values.references(visit);
+        self.args.iter().for_each(|v| v.references(visit));
+    }
+
+    fn clear_references(&self) {
+        self.values.clear();
+    }
+}
+
+impl ContextTrait for MethodContext {
+    fn ip(&self) -> CodeAddress {
+        self.0.instruction_pointer.get()
+    }
+
+    fn next_ip(&self) {
+        let CodeAddress(block, step) = self.0.instruction_pointer.get();
+        self.0.instruction_pointer.set(CodeAddress(block, step + 1));
+    }
+
+    fn find_value(&self, addr: &CodeAddress) -> Option<Value> {
+        self.0.values.get(addr.1)
+    }
+
+    fn set_value(&self, addr: &CodeAddress, value: Value) {
+        self.0.values.set(addr.1, value);
+    }
+
+    fn call(&self, addr: CodeAddress) {
+        self.0.instruction_pointer.set(addr);
+    }
+
+    fn home(&self) -> Home {
+        self.0.home
+    }
+
+    fn arg(&self, idx: usize) -> Value {
+        self.0.args[idx].clone()
+    }
+
+    fn receiver(&self) -> Rc<dyn Receiver> {
+        self.0.receiver.clone()
+    }
+
+    fn class(&self) -> Option<(Rc<ClassDef>, bool)> {
+        self.0.class.clone()
+    }
+
+    fn variables(&self) -> Variables {
+        self.0.variables.clone()
+    }
+
+    fn set_result(&self, value: Value) {
+        *self.0.result.lock().unwrap() = Some(value);
+    }
+
+    fn result(&self) -> Option<Value> {
+        self.0.result.lock().unwrap().clone()
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        let frame = &self.0;
+        visit(heap::address(&frame.receiver));
+        visit(heap::address(&frame.variables));
+        frame.values.references(visit);
+        frame.args.iter().for_each(|v| v.references(visit));
+        if let Ok(result) = frame.result.try_lock() {
+            result.iter().for_each(|v| v.references(visit));
+        }
+    }
+
+    fn clear_references(&self) {
+        self.0.values.clear();
+        *self.0.result.lock().unwrap() = None;
+    }
+}
+
+impl MethodContext {
+    /// the activation of a script with its own variables
+    pub fn new() -> ContextRef {
+        Self::with_variables(new_variables())
+    }
+
+    /// the activation of a script sharing the variables of a workspace
+    pub fn with_variables(variables: Variables) -> ContextRef {
+        let mut frame = FrameData::new();
+        frame.variables = variables;
+        Self::activate(frame)
+    }
+
+    /// the activation of a method of `class` sent to `receiver`
+    pub fn for_method(
+        receiver: Rc<dyn Receiver>,
+        class: Option<(Rc<ClassDef>, bool)>,
+        args: Vec<Value>,
+    ) -> ContextRef {
+        let mut frame = FrameData::new();
+        frame.receiver = receiver;
+        frame.class = class;
+        frame.args = args;
+        frame.variables = new_variables();
+        Self::activate(frame)
+    }
+
+    /// the activation blocks read from an image captured, it has the
+    /// named variables they saw
+    pub(crate) fn restored(
+        receiver: Rc<dyn Receiver>,
+        class: Option<(Rc<ClassDef>, bool)>,
+        variables: Variables,
+    ) -> ContextRef {
+        let mut frame = FrameData::new();
+        frame.receiver = receiver;
+        frame.class = class;
+        frame.variables = variables;
+        Self::activate(frame)
+    }
+
+    fn activate(frame: FrameData) -> ContextRef {
+        let ctx: ContextRef = Rc::new(MethodContext(Rc::new(frame)));
+        heap::track_context(&ctx);
+        ctx
+    }
+}
+
+/// an empty set of named variables, known to the heap
+pub(crate) fn new_variables() -> Variables {
+    let variables = Rc::new(RefCell::new(BTreeMap::new()));
+    heap::track_variables(&variables);
+    variables
+}
+
+impl FrameData {
+    pub fn new() -> Self {
+        Self {
+            instruction_pointer: Cell::new(CodeAddress(0, 0)),
+            values: Registers::default(),
+            home: unwind::new_home(),
+            receiver: NilReciever::get(),
+            class: None,
+            args: vec![],
+            variables: Rc::new(RefCell::new(BTreeMap::new())),
+            result: Mutex::new(None),
+        }
+    }
+}
+
+impl BlockContext {
+    pub fn new(parent: ContextRef, block: usize, args: Vec<Value>) -> Rc<Self> {
+        let ctx = Rc::new(Self {
+            parent,
+            block,
+            instruction_pointer: Cell::new(CodeAddress(block, 0)),
+            values: Registers::default(),
+            args,
+        });
+        heap::track_context(&(ctx.clone() as ContextRef));
+        ctx
+    }
+}
+
+pub fn parse_method(
+    input_string: String,
+) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
+    parse_source("METHOD", INPUT_NAME, &input_string)
+}
+
+/// parses a method stored in a file, errors name the file
+pub fn parse_method_file(path: &Path) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
+    let input = std::fs::read_to_string(path)?;
+    parse_source("METHOD", &path.display().to_string(), &input)
+}
+
+pub fn parse_definition(
+    input_string: String,
+) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
+    parse_source("DEFINE", INPUT_NAME, &input_string)
+}
+
+/// parses class definitions and registers them in the `ClassTable`,
+/// answers the names of the defined classes. Globals, pools and program
+/// initializers among the definitions are declared, their initializers run
+/// once the classes are defined.
+pub fn define_classes(
+    input_string: String,
+) -> Result<Vec<&'static str>, Box<dyn std::error::Error>> {
+    let parse_trees = parse_definition(input_string)?;
+    let mut names = vec![];
+    let mut program = glb::Program::default();
+    for t in parse_trees {
+        if let AST::Statements(elements) = t.as_abstract_syntax_tree() {
+            for e in elements.iter() {
+                match e {
+                    AST::Class { .. } => names.push(ClassTable::define_from(e)?.name),
+                    _ => program.add(e),
+                }
+            }
+        }
+    }
+    program.initialize()?;
+    Ok(names)
+}
+
+pub fn parse_script(
+    input_string: String,
+) -> Result<Vec<Rc<Tree<AST>>>, Box<dyn std::error::Error>> {
+    parse_source("EVALUATE", INPUT_NAME, &input_string)
+}
+
+type Lexemes = Vec<Rc<Lexeme>>;
+
+type ParseTrees = Vec<Rc<Tree<AST>>>;
+
+/// the file name used in errors for sources that don't come from a file
+const INPUT_NAME: &str = "input";
+
+/// lexes and parses `input` starting with the rule for `kind`. After a
+/// syntax error parsing resumes behind the next statement, or the next class
+/// for definitions, so one pass reports all errors of the input.
+fn parse_source(
+    kind: &str,
+    file: &str,
+    input: &str,
+) -> Result<ParseTrees, Box<dyn std::error::Error>> {
+    let grammar = parser::grammar();
+    let (lexemes, mut errors) = lex_source(file, input);
+    let mut start = 0;
+    let mut kind = kind;
+    loop {
+        let mut chunk = vec![Rc::new(Lexeme {
+            kind: kind.into(),
+            raw: String::new(),
+            position: Position { line: 0, column: 0 },
+        })];
+        chunk.extend_from_slice(&lexemes[start..]);
+        let e = match parse(&grammar, &chunk) {
+            Ok(trees) if errors.is_empty() => {
+                // constructs the grammar accepts but the actions reject
+                let mut invalid: Vec<(Position, String)> = trees
+                    .iter()
+                    .flat_map(|t| t.as_abstract_syntax_tree().errors())
+                    .collect();
+                if invalid.is_empty() {
+                    return Ok(trees);
+                }
+                invalid.sort_by_key(|(p, m)| (p.line, p.column, m.clone()));
+                invalid.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+                let found = invalid
+                    .into_iter()
+                    .map(|(p, m)| Diagnostic::new(file, input, p, m));
+                errors.extend(found);
+                break;
+            }
+            Ok(_) => break,
+            Err(e) => e,
+        };
+        let at =
+            e.at.as_ref()
+                .and_then(|l| lexemes[start..].iter().position(|x| Rc::ptr_eq(x, l)))
+                .map(|idx| start + idx);
+        let Some(idx) = at else {
+            let end = parser::position_at(input, input.trim_end().len());
+            errors.push(Diagnostic::new(
+                file,
+                input,
+                end,
+                String::from("unexpected end of input"),
+            ));
+            break;
+        };
+        let l = &lexemes[idx];
+        let message = format!("unexpected '{}'", l.raw);
+        errors.push(Diagnostic::new(file, input, l.position.clone(), message));
+        match resync(kind, &lexemes, start, idx) {
+            Some(next) if next < lexemes.len() => start = next,
+            _ => break,
+        }
+        // the rest of a method body is a sequence of statements
+        if kind == "METHOD" {
+            kind = "EVALUATE";
+        }
+    }
+    errors.sort_by_key(|d| (d.position.line, d.position.column));
+    for e in errors.iter() {
+        error!("{}", e);
+    }
+    Err(Box::new(SyntaxError(errors)))
+}
+
+/// lexes all of `input`, invalid characters are reported and skipped
+fn lex_source(file: &str, input: &str) -> (Lexemes, Vec<Diagnostic>) {
+    let rules = parser::lexer_rules();
+    let mut lexemes = vec![];
+    let mut errors = vec![];
+    let mut offset = 0;
+    while offset < input.len() {
+        let rest = &input[offset..];
+        let (found, failed) = match lex(&rules, rest) {
+            Ok(found) => (found, None),
+            // the part in front of the invalid character is fine
+            Err(e) => (
+                lex(&rules, &rest[..e.byte_index]).unwrap_or_default(),
+                Some(e.byte_index),
+            ),
+        };
+        let base = parser::position_at(input, offset);
+        for l in found {
+            let position = if l.position.line == 1 {
+                Position {
+                    line: base.line,
+                    column: base.column + l.position.column - 1,
+                }
+            } else {
+                Position {
+                    line: base.line + l.position.line - 1,
+                    column: l.position.column,
+                }
+            };
+            lexemes.push(Rc::new(Lexeme {
+                kind: l.kind.clone(),
+                raw: l.raw.clone(),
+                position,
+            }));
+        }
+        let Some(bad) = failed else {
+            break;
+        };
+        let c = rest[bad..].chars().next().unwrap();
+        let position = parser::position_at(input, offset + bad);
+        errors.push(Diagnostic::new(
+            file,
+            input,
+            position,
+            format!("invalid character '{}'", c),
+        ));
+        offset += bad + c.len_utf8();
+    }
+    (lexemes, errors)
+}
+
+/// where parsing resumes after an error at `idx`: behind the statement
+/// containing it, or at the next class for definitions
+fn resync(kind: &str, lexemes: &[Rc<Lexeme>], start: usize, idx: usize) -> Option<usize> {
+    if kind == "DEFINE" {
+        // `Superclass subclass: Name [`
+        return (idx + 1..lexemes.len())
+            .find(|j| lexemes[*j].raw == "subclass:" && *j > start + 1)
+            .map(|j| j - 1);
+    }
+    let mut depth = 0;
+    for (i, l) in lexemes.iter().enumerate().skip(start) {
+        match l.kind.as_str() {
+            "[" | "(" | "{" | "#(" | "#[" => depth += 1,
+            "]" | ")" | "}" => depth -= 1,
+            "." if depth <= 0 && i >= idx => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[allow(dead_code)]
+fn handle_parse_tree(parse_trees: Vec<Rc<Tree<AST>>>) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Parse Trees:");
+    for tree in &parse_trees {
+        println!("{tree}");
+        let ast = tree.as_abstract_syntax_tree();
+        println!("Abstract Syntax Tree:");
+        println!("{ast:#?}");
+    }
+    // println!("Evaluated:");
+    // println!("{}", eval(&ast));
+    Ok(())
+}
+
+#[allow(dead_code)]
+fn print_lexemes(lexemes: &Vec<Rc<Lexeme>>) {
+    println!("Lexemes:");
+    for lexeme in lexemes {
+        println!("  {lexeme}");
+    }
+}
@@ -1,45 +1,106 @@
-
-
-
-use rustyline::{
-    error::ReadlineError,
-    validate::{ValidationContext, ValidationResult},
-    Completer, Helper, Highlighter, Hinter,
-};
-
-use tt_rust::evaluate_script;
-
-
-
-#[derive(Completer, Helper, Highlighter, Hinter)]
-struct InputValidator {}
-
-impl rustyline::validate::Validator for InputValidator {
-    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
-        use ValidationResult::{Incomplete, Valid};
-        let input = ctx.input();
-        let result = if !input.ends_with('.') {
-            Incomplete
-        } else {
-            Valid(None)
-        };
-        Ok(result)
-    }
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut rl = rustyline::Editor::with_config(
-        rustyline::Config::builder()
-            // .history_ignore_space(true)
-            .completion_type(rustyline::CompletionType::List)
-            .edit_mode(rustyline::EditMode::Vi)
-            .build(),
-    )?;
-    rl.set_helper(Some(InputValidator {}));
-
-    let input_string = rl.readline("> ")?;
-
-    evaluate_script(input_string)?;
-    Ok(())
-}
-
+use std::{cell::RefCell, env, path::PathBuf, rc::Rc};
+
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Editor, Helper,
+};
+
+use tt_rust::{
+    debug,
+    repl::{self, Session},
+};
+
+/// completes with the names the session knows, an input is complete when
+/// its brackets are closed
+struct SessionHelper {
+    session: Rc<RefCell<Session>>,
+}
+
+impl Completer for SessionHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        // the session is busy while the debugger reads its commands
+        match self.session.try_borrow() {
+            Ok(session) => Ok(session.completions(line, pos)),
+            Err(_) => Ok((pos, vec![])),
+        }
+    }
+}
+
+impl Validator for SessionHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
+        if repl::is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+impl Hinter for SessionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for SessionHelper {}
+
+impl Helper for SessionHelper {}
+
+fn home_file(name: &str) -> PathBuf {
+    env::var_os("HOME").map_or_else(|| PathBuf::from(name), |home| PathBuf::from(home).join(name))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let image = env::args()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("tt.image"), PathBuf::from);
+    let session = Rc::new(RefCell::new(Session::open(&image)?));
+    let history = home_file(".tt_history");
+
+    let mut rl: Editor<SessionHelper, DefaultHistory> = Editor::with_config(
+        rustyline::Config::builder()
+            .auto_add_history(true)
+            .completion_type(rustyline::CompletionType::List)
+            .edit_mode(rustyline::EditMode::Vi)
+            .build(),
+    )?;
+    rl.set_helper(Some(SessionHelper {
+        session: session.clone(),
+    }));
+    let _ = rl.load_history(&history);
+    let rl = Rc::new(RefCell::new(rl));
+    let reader = rl.clone();
+    debug::attach(repl::debugger(
+        move |prompt| reader.borrow_mut().readline(prompt).ok(),
+        |text| println!("{}", text),
+    ));
+
+    println!("{} (:help lists the commands)", image.display());
+    loop {
+        let input = rl.borrow_mut().readline("> ");
+        match input {
+            Ok(input) => {
+                // saved before the input runs, a crash of the session keeps it
+                if let Err(e) = rl.borrow_mut().save_history(&history) {
+                    eprintln!("history not saved to {}: {}", history.display(), e);
+                }
+                match session.borrow_mut().run(&input) {
+                    Some(output) if output.is_empty() => {}
+                    Some(output) => println!("{}", output),
+                    None => break,
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // the lines read by the debugger since the last input
+    rl.borrow_mut().save_history(&history)?;
+    Ok(())
+}
@@ -1,389 +1,1183 @@
-use santiago::lexer::LexerRules;
-use tracing::{error, info};
-
-pub fn lexer_rules() -> LexerRules {
-    santiago::lexer_rules!(
-        "DEFAULT" | "INT" = pattern r"[0-9]+";
-        "DEFAULT" | "IDENTIFIER" = pattern r"[a-zA-Z_][a-zA-Z_0-9]*";
-        "DEFAULT" | "KEYWORD" = pattern r"[a-zA-Z_][a-zA-Z_0-9]*:";
-        "DEFAULT" | "STRING" = pattern r"'[^']*'";
-         // "DEFAULT" | "LOCAL" = pattern r":[a-zA-Z_][a-zA-Z_0-9]*";
-        "DEFAULT" | "COMMENT" = pattern "\"[^\"]*\"" => |l| l.skip();
-        "DEFAULT" | ":" = string ":";
-        "DEFAULT" | "END_OF_CHUNK" = string "!";
-        "DEFAULT" | "." = string ".";
-        "DEFAULT" | "[" = string "[";
-        "DEFAULT" | "]" = string "]";
-        "DEFAULT" | "(" = string "(";
-        "DEFAULT" | ")" = string ")";
-        "DEFAULT" | "|" = string "|";
-        "DEFAULT" | "{" = string "{";
-        "DEFAULT" | "}" = string "}";
-        "DEFAULT" | "ASSIGN" = string ":=";
-        "DEFAULT" | "ASSIGN" = string "<-";
-        "DEFAULT" | "BINARY" = pattern r"[-%&,*+/<=>?@\~!]+";
-        "DEFAULT" | "CHAR" = pattern r"\$.";
-        "DEFAULT" | "WS" = pattern r"\s" => |lexer| lexer.skip();
-        "DEFAULT" | "RETURN" = string "^";
-    )
-}
-
-// use santiago::grammar::Associativity;
-use santiago::grammar::Grammar;
-
-use crate::runtime::sel::SelectorSet;
-
-#[derive(Debug, Clone)]
-pub enum AST {
-    Int(isize),
-    Char(char),
-    String(&'static str),
-    Name(&'static str),
-    Method {
-        name: &'static str,
-        params: Vec<&'static str>,
-        temps: Vec<&'static str>,
-        body: Box<AST>,
-    },
-    Block {
-        params: Vec<&'static str>,
-        temps: Vec<&'static str>,
-        body: Box<AST>,
-    },
-    Return(Box<AST>),
-    PatternPart(String, Option<Box<AST>>, Box<AST>),
-    List(Box<AST>, Box<AST>),
-    Table(Vec<Box<AST>>),
-    Statements(Vec<AST>),
-    InvokeSequence(Box<AST>, Vec<AST>),
-    InvokeCascade(Box<AST>, Vec<AST>),
-    Message {
-        name: &'static str,
-        args: Vec<AST>,
-    },
-    Variable(&'static str),
-    Assign(Box<AST>, Box<AST>),
-    Dummy(String),
-    Empty,
-}
-
-impl From<&AST> for String {
-    fn from(s: &AST) -> Self {
-        println!("AST to String: {:?}", s);
-        match s {
-            AST::Empty => String::from("<empty>"),
-            AST::Name(x) => (*x).into(),
-            _ => format!("{:?}", s),
-        }
-    }
-}
-
-fn gen_method(name: &AST, _temps: &AST, body: &AST) -> AST {
-    AST::Method {
-        name: selector_from(name),
-        params: params_from(name),
-        temps: vec![],
-        body: Box::new(body.clone()),
-    }
-}
-
-fn names_from(t: &AST) -> Vec<&'static str> {
-    match t {
-        AST::Table(tab) => {
-            let mut r = vec![];
-            for x in tab {
-                match **x {
-                    AST::Name(n) => r.push(n),
-                    _ => panic!("unexpected"),
-                }
-            }
-            r
-        }
-        _ => panic!("not expected"),
-    }
-}
-
-fn params_from(name: &AST) -> Vec<&'static str> {
-    match name {
-        AST::PatternPart(_, Some(x0), rest) => match *x0.clone() {
-            AST::Name(x) => {
-                let mut start = vec![x];
-                start.extend_from_slice(params_from(rest).as_slice());
-                start
-            }
-            _ => unreachable!(),
-        },
-        AST::Empty => vec![],
-        AST::PatternPart(_, None, _) => vec![],
-        _ => {
-            println!("prams_from {:?}", name);
-            unreachable!()
-        }
-    }
-}
-
-fn args_from(name: &AST) -> Vec<AST> {
-    match name {
-        AST::PatternPart(_, Some(x), rest) => {
-            let mut start: Vec<AST> = vec![*x.clone()];
-            start.extend_from_slice(args_from(rest).as_slice());
-            start
-        }
-        AST::Empty => vec![],
-        AST::PatternPart(_, None, _) => vec![],
-        _ => {
-            println!("prams_from {:?}", name);
-            unreachable!()
-        }
-    }
-}
-
-fn selector_from(name: &AST) -> &'static str {
-    match name {
-        AST::PatternPart(x, _, rest) => {
-            let r = selector_from(rest);
-            SelectorSet::get(format!("{}{}", x, &r).as_str())
-        }
-        AST::Empty => "",
-        AST::Name(s) => SelectorSet::get(*s),
-        _ => {
-            println!("selector_from {:?}", name);
-            unreachable!()
-        }
-    }
-}
-
-#[allow(dead_code)]
-fn table_add(a: &AST, b: &AST) -> AST {
-    if let AST::Table(t) = a {
-        let mut t_new = t.clone();
-        t_new.push(Box::new(b.clone()));
-        AST::Table(t_new)
-    } else {
-        unreachable!()
-    }
-}
-
-#[allow(dead_code)]
-fn table_from(n: &AST) -> AST {
-    AST::Table(vec![Box::new(n.clone())])
-}
-
-pub fn grammar() -> Grammar<AST> {
-    santiago::grammar!(
-        "cmd" => rules "define_cmd" "def";
-        "cmd" => rules "method_cmd" "method definition" => |r:Vec<AST>| r[1].clone();
-        "cmd" => rules "eval_cmd" "statements" => |r:Vec<AST>| r[1].clone();
-        "cmd" => rules "eval_cmd" "statements" "dot"=> |r:Vec<AST>| r[1].clone();
-
-        "def" => rules "method definition";
-        "def" => empty => |_| AST::Empty;
-
-        "method definition" => rules "message pattern" "temporaries" "statements"
-            => |r| gen_method(&r[0], &r[1], &r[2]);
-
-        "chunk sep" => lexemes "END_OF_CHUNK" => |_| AST::Dummy(String::from("chunk separator"));
-        "temporaries" => empty  => |_| AST::Dummy(String::from("temporaries"));
-        "temporaries" => rules "bar" "identifiers" "bar" => |r| r[1].clone();
-        "identifiers" => rules "identifier" => |r| r[0].clone();
-        "identifiers" => rules "identifiers" "identifier" => |r| r[0].clone();
-        "message pattern" => rules "unary pattern" => |r| r[0].clone();
-        "message pattern" => rules "binary pattern" => |r| r[0].clone();
-        "message pattern" => rules "keyword pattern" => |r| r[0].clone();
-        "unary pattern" => rules "unarySelector"
-            => |r| AST::PatternPart((&r[0]).into(), None, Box::new(AST::Empty));
-        "binary pattern" => rules "binarySelector" "identifier"
-            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(AST::Empty));
-        "keyword pattern" => rules "keyword"  "identifier"
-            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(AST::Empty));
-        "keyword pattern" => rules "keyword"  "identifier" "keyword pattern"
-            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(r[2].clone()));
-        "statements" => rules "expression"
-            => |r| {  AST::Statements(vec![r[0].clone()])  };
-        "statements" => rules "return statement" => |r| AST::Statements(vec![r[0].clone()]);
-        "statements" => rules "return statement" "dot" => |r| AST::Statements(vec![r[0].clone()]);
-        "statements" => rules "expression" "dot" "statements"
-            => |r| {
-            if let AST::Statements(x) = &r[2]{
-                let mut v = vec![r[0].clone()];
-                for e in x {v.push(e.clone());}
-                AST::Statements(v)
-            }
-            else {
-                AST::Statements(vec![r[0].clone()])
-            }
-        };
-        // "statements" => rules "expression" => |r| AST::Statements(vec![r[0].clone()]);
-        "return statement" => rules "return op" "expression"
-            => |r| AST::Return(Box::new(r[1].clone()));
-        "expression" => rules "basic expression" => |r| r[0].clone();
-        "expression" => rules "assignment" => |r| r[0].clone();
-        "assignment" => rules "identifier" "assignmentOperator" "expression"
-            => |r| AST::Assign(Box::new(r[0].clone()), Box::new(r[2].clone()));
-        "basic expression" => rules "primary" => |r| r[0].clone();
-        "basic expression" => rules "primary" "messages"
-                => |r| if let AST::InvokeSequence(_, msgs) = &r[1] {
-                    AST::InvokeSequence(Box::new(r[0].clone()), msgs.clone())
-                }
-                else {
-                    error!("sub tree is not a messages list. {:#?}", r);
-                    r[0].clone()
-                };
-
-        "binary messages" => rules "unary messages"
-            => |r| r[0].clone();
-        "binary messages" => rules "binary messages" "binary message"
-            => |r| {
-            if let AST::InvokeSequence(target, msgs) = &r[0] {
-                let mut ms = msgs.clone();
-                ms.push(r[1].clone());
-                AST::InvokeSequence(target.clone(), ms)
-            }
-            else {
-                panic!()
-                // AST::Dummy(String::from("no invoke seq"))
-            }
-        };
-
-
-        "messages" => rules "binary messages" "keyword message"
-            => |r| {
-                if let AST::InvokeSequence(target, msgs) = &r[0] {
-                    let mut ms = msgs.clone();
-                    ms.push(r[1].clone());
-                    AST::InvokeSequence(target.clone(), ms)
-                }
-                else {
-                    panic!()
-                    // AST::Dummy(String::from("no invoke seq"))
-                }
-            };
-        "messages" => rules "binary messages"
-            => |r| r[0].clone();
-        "unary messages" => empty
-            =>|_| AST::InvokeSequence(Box::new(AST::Empty), vec![]);
-        "unary messages" => rules "unary messages" "unary message"
-            => |r| {
-                if let AST::InvokeSequence(t, v0) = &r[0]{
-                    let mut v = v0.clone();
-                    v.push(r[1].clone());
-                    AST::InvokeSequence(t.clone(), v) }
-                else {
-                    panic!();
-                }
-        };
-
-        "unary message" => rules "unarySelector"
-            => |r|     AST::Message {  name: selector_from(&r[0]),
-                                       args: vec![] };
-        "unary expression" => rules "primary" "unary messages"
-            => |r| match &r[1] {
-                        AST::InvokeSequence(_,seq) =>   if seq.len() == 0 {
-                                                            r[0].clone()
-                                                        }
-                                                        else {
-                                                            let result = AST::InvokeSequence(Box::new(r[0].clone()), seq.clone());
-                                                            result
-                                                        },
-                        _ => todo!(),
-        };
-
-        "binary message" => rules "binarySelector" "unary expression" // "expression" is not working, since it would generate an implict right associated tree, which is wrong for Smalltalk
-            => |r| AST::Message{name: selector_from(&r[0]),
-                                args: vec![r[1].clone()]};
-
-        "binary expression" => rules "unary expression"
-            => |r| r[0].clone();
-        "binary expression" => rules "binary expression" "binarySelector" "unary expression"
-            => |r| match (&r[0], &r[1]) {
-                    (AST::InvokeSequence(receiver, msgs), AST::Name(name)) => {
-                        let mut ms = msgs.clone();
-                        ms.push(AST::Message { name, args: vec![r[2].clone()] });
-                        AST::InvokeSequence(receiver.clone(), ms )},
-                    (receiver, AST::Name(name)) => {
-                            let ms = vec![AST::Message { name, args: vec![r[2].clone()] }];
-                            AST::InvokeSequence(Box::new(receiver.clone()), ms )},
-                        _ => todo!("{:?}", &r),
-            };
-
-        "keyword message" => rules "keyword message parts"
-            => |r|   AST::Message { name: selector_from(&r[0]),
-                                    args: args_from(&r[0]) };
-
-        "keyword message parts" => rules "keyword" "keyword argument" "keyword message parts"
-            => |r| {
-                info!("message part: {:?} {:?}", &r[0], &r[1]);
-                AST::PatternPart(String::from(&r[0]),
-                                    Some(r[1].clone().into()),
-                                    Box::new(r[2].clone()))};
-        "keyword message parts" => rules "keyword" "keyword argument"
-            => |r| {
-                info!("message part: {:?} {:?}", &r[0], &r[1]);
-                AST::PatternPart(String::from(&r[0]),
-                                    Some(r[1].clone().into()),
-                                    Box::new(AST::Empty))};
-        "keyword argument" => rules "binary expression"
-            => |r| r[0].clone();
-        "primary" => lexemes "STRING" => |l| {
-            let s = &l[0].raw;
-            let s0 = &s[1..s.len()-1];
-            AST::String(SelectorSet::get(s0))};
-        "primary" => lexemes "IDENTIFIER" => |l| AST::Variable(SelectorSet::get(&l[0].raw));
-        "primary" => lexemes "CHAR" => |l| if let Some(c) = l[0].raw.chars().nth(1) {
-                AST::Char(c)
-            } else {
-                AST::Empty
-            };
-        "primary" => lexemes "INT" => |l| AST::Int(l[0].raw.to_string().parse::<isize>().unwrap());
-        "primary" => rules "block constructor" => |r| r[0].clone();
-        "primary" => rules "openBrace" "expression" "closeBrace" => |r| AST::Table(vec![Box::new(r[1].clone())]);
-        "primary" => rules "openParen" "expression" "closeParen" => |r| r[1].clone();
-        "block constructor" => rules "blockStart" "block args" "temporaries" "block body" "blockEnd"
-            => |r| AST::Block{
-                            params: names_from(&r[1]),
-                            temps: vec![],
-                            body: Box::new(r[3].clone()) };
-        "block args" => rules "block arguments" "bar"
-            => |r| r[0].clone();
-        "block args" => empty
-            => |_| AST::Table(vec![]);
-        "block arguments" => empty => |_| AST::Table(vec![]);
-        "block arguments" => rules "block arguments" "colon" "identifier"
-            => |r| {
-                if let AST::Table(mut lst) = r[0].clone()
-                {
-                    lst.push(Box::new(r[2].clone()));
-                    AST::Table(lst)
-                }
-                else {
-                    panic!("no table")
-                }
-            };
-        "block body" => rules "statements" => |r| r[0].clone();
-        "dot" => lexemes "." => |_| AST::Empty;
-        "return op" => lexemes "RETURN" => |_| AST::Empty;
-        "unarySelector" => lexemes "IDENTIFIER" => |l| AST::Name(SelectorSet::get(&l[0].raw));
-        "identifier" => lexemes "IDENTIFIER" => |l| AST::Name(SelectorSet::get(&l[0].raw));
-        "binarySelector" => lexemes "BINARY" => |l| AST::Name(SelectorSet::get(&l[0].raw));
-        "assignmentOperator" => lexemes "ASSIGN" => |_| AST::Empty;
-        "keyword" => lexemes "KEYWORD" => |l| AST::Name(SelectorSet::get(&l[0].raw));
-        "blockStart" => lexemes "[" => |_| AST::Empty;
-        "blockEnd" => lexemes "]" => |_| AST::Empty;
-        "colon" => lexemes ":" => |_| AST::Empty;
-        "bar" => lexemes "|" => |_| AST::Empty;
-        "openParen" => lexemes "(" => |_| AST::Empty;
-        "closeParen" => lexemes ")" => |_| AST::Empty;
-        "openBrace" => lexemes "{" => |_| AST::Empty;
-        "closeBrace" => lexemes "}" => |_| AST::Empty;
-        "define_cmd" => lexemes "DEFINE" => |_| AST::Empty;
-        "eval_cmd" => lexemes "EVALUATE" => |_| AST::Empty;
-        "method_cmd" => lexemes "METHOD" => |_| AST::Empty;
-    )
-}
-
-pub fn eval(value: &AST) -> isize {
-    match value {
-        AST::Int(int) => *int,
-        _ => unreachable!(),
-    }
-}
+use std::fmt::Display;
+
+use santiago::lexer::{Lexeme, LexerRules, Position};
+use tracing::{error, info};
+
+pub fn lexer_rules() -> LexerRules {
+    santiago::lexer_rules!(
+        "DEFAULT" | "INT" = pattern r"[0-9]+r[0-9A-Z]+|[0-9]+(e[0-9]+)?";
+        "DEFAULT" | "FLOAT" = pattern r"[0-9]+\.[0-9]+(e-?[0-9]+)?";
+        "DEFAULT" | "IDENTIFIER" = pattern r"[a-zA-Z_][a-zA-Z_0-9]*";
+        "DEFAULT" | "KEYWORD" = pattern r"[a-zA-Z_][a-zA-Z_0-9]*:";
+        "DEFAULT" | "STRING" = pattern r"'[^']*'";
+        "DEFAULT" | "SYMBOL" = pattern r"#([a-zA-Z_][a-zA-Z_0-9]*:)+|#[a-zA-Z_][a-zA-Z_0-9]*|#[-%&,*+/<=>?@\\~!|]+|#'[^']*'";
+        "DEFAULT" | "#(" = string "#(";
+        "DEFAULT" | "#[" = string "#[";
+         // "DEFAULT" | "LOCAL" = pattern r":[a-zA-Z_][a-zA-Z_0-9]*";
+        "DEFAULT" | "COMMENT" = pattern "\"[^\"]*\"" => |l| l.skip();
+        "DEFAULT" | ":" = string ":";
+        "DEFAULT" | "END_OF_CHUNK" = string "!";
+        "DEFAULT" | "." = string ".";
+        "DEFAULT" | ";" = string ";";
+        "DEFAULT" | "[" = string "[";
+        "DEFAULT" | "]" = string "]";
+        "DEFAULT" | "(" = string "(";
+        "DEFAULT" | ")" = string ")";
+        "DEFAULT" | "|" = string "|";
+        "DEFAULT" | "{" = string "{";
+        "DEFAULT" | "}" = string "}";
+        "DEFAULT" | "ASSIGN" = string ":=";
+        "DEFAULT" | "ASSIGN" = string "<-";
+        "DEFAULT" | "BINARY" = pattern r"[-%&,*+/<=>?@\\~!]+";
+        "DEFAULT" | "CHAR" = pattern r"\$.";
+        "DEFAULT" | "WS" = pattern r"\s" => |lexer| lexer.skip();
+        "DEFAULT" | "RETURN" = string "^";
+    )
+}
+
+/// a syntax error, shown as `file:line:col: message` followed by the
+/// source line and a caret under the column
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: String,
+    pub position: Position,
+    pub message: String,
+    pub line: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, source: &str, position: Position, message: String) -> Self {
+        let line = source.lines().nth(position.line.saturating_sub(1)).unwrap_or("");
+        Self {
+            file: file.to_string(),
+            position,
+            message,
+            line: line.to_string(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Position { line, column } = self.position;
+        writeln!(f, "{}:{}:{}: {}", self.file, line, column, self.message)?;
+        writeln!(f, "{}", self.line)?;
+        // keep tabs so the caret lines up with the source line
+        let indent: String = self
+            .line
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{}^", indent)
+    }
+}
+
+/// all syntax errors found in one source
+#[derive(Debug)]
+pub struct SyntaxError(pub Vec<Diagnostic>);
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, d) in self.0.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// the line and column of a byte offset into `source`, both counted from 1
+pub fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map(|n| n + 1).unwrap_or(0);
+    Position {
+        line,
+        column: before[start..].chars().count() + 1,
+    }
+}
+
+// use santiago::grammar::Associativity;
+use santiago::grammar::Grammar;
+
+use num_bigint::BigInt;
+
+use crate::runtime::sel::SelectorSet;
+
+#[derive(Debug, Clone)]
+pub enum AST {
+    Int(isize),
+    LargeInt(BigInt),
+    Float(f64),
+    Char(char),
+    String(String),
+    Name(&'static str),
+    /// a selector or keyword with the position of its token
+    Selector(&'static str, Position),
+    /// a punctuation token, only kept for its position
+    Mark(Position),
+    Method {
+        name: &'static str,
+        params: Vec<&'static str>,
+        temps: Vec<&'static str>,
+        body: Box<AST>,
+        position: Position,
+    },
+    Block {
+        params: Vec<&'static str>,
+        temps: Vec<&'static str>,
+        body: Box<AST>,
+        position: Position,
+    },
+    Return(Box<AST>),
+    PatternPart(String, Option<Box<AST>>, Box<AST>, Position),
+    List(Box<AST>, Box<AST>),
+    Table(Vec<Box<AST>>),
+    Statements(Vec<AST>),
+    InvokeSequence(Box<AST>, Vec<AST>),
+    InvokeCascade(Box<AST>, Vec<AST>),
+    Message {
+        name: &'static str,
+        args: Vec<AST>,
+        position: Position,
+    },
+    Variable(&'static str, Position),
+    Symbol(&'static str),
+    LiteralArray(Vec<AST>),
+    ByteArray(Vec<u8>),
+    Assign(Box<AST>, Box<AST>),
+    Class {
+        name: &'static str,
+        superclass: &'static str,
+        inst_vars: Vec<&'static str>,
+        class_vars: Vec<(&'static str, AST)>,
+        methods: Vec<AST>,
+        class_methods: Vec<AST>,
+        /// the pool dictionaries the class imports
+        pools: Vec<&'static str>,
+    },
+    /// a global or a pool variable, a constant can't be assigned once it is
+    /// initialized
+    Global {
+        name: &'static str,
+        constant: bool,
+        init: Option<Box<AST>>,
+    },
+    /// a pool dictionary with its variables
+    Pool {
+        name: &'static str,
+        vars: Vec<AST>,
+    },
+    /// a program initializer, a method without a selector
+    Initializer(Box<AST>),
+    Dummy(String),
+    /// an invalid construct the grammar accepts, it is reported as a syntax
+    /// error when the source is parsed
+    Error(String, Position),
+    Empty,
+}
+
+impl From<&AST> for String {
+    fn from(s: &AST) -> Self {
+        match s {
+            AST::Empty => String::from("<empty>"),
+            AST::Name(x) | AST::Selector(x, _) => (*x).into(),
+            _ => format!("{:?}", s),
+        }
+    }
+}
+
+fn gen_method(name: &AST, temps: &AST, body: &AST) -> AST {
+    AST::Method {
+        name: selector_from(name),
+        params: params_from(name),
+        temps: names_from(temps),
+        body: Box::new(body.clone()),
+        position: position_of(name),
+    }
+}
+
+/// a script that declares temporaries is a method without selector, like
+/// the initializer of a program
+fn gen_script(temps: &AST, body: &AST) -> AST {
+    let temps = names_from(temps);
+    if temps.is_empty() {
+        return body.clone();
+    }
+    AST::Method {
+        name: "",
+        params: vec![],
+        temps,
+        body: Box::new(body.clone()),
+        position: position_of(body),
+    }
+}
+
+fn method_with_pattern(pattern: &AST, method_body: &AST) -> AST {
+    match method_body {
+        AST::Method { temps, body, .. } => AST::Method {
+            name: selector_from(pattern),
+            params: params_from(pattern),
+            temps: temps.clone(),
+            body: body.clone(),
+            position: position_of(pattern),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn empty_class() -> AST {
+    AST::Class {
+        name: "",
+        superclass: "",
+        inst_vars: vec![],
+        class_vars: vec![],
+        methods: vec![],
+        class_methods: vec![],
+        pools: vec![],
+    }
+}
+
+fn gen_class(name: &AST, keyword: &AST, superclass: &AST, body: &AST) -> AST {
+    if !matches!(keyword, AST::Selector("subclass:", _)) {
+        let message = format!("class definition expects 'subclass:', not '{}'", selector_from(keyword));
+        return AST::Error(message, position_of(keyword));
+    }
+    match body {
+        AST::Class {
+            inst_vars,
+            class_vars,
+            methods,
+            class_methods,
+            pools,
+            ..
+        } => AST::Class {
+            name: selector_from(name),
+            superclass: selector_from(superclass),
+            inst_vars: inst_vars.clone(),
+            class_vars: class_vars.clone(),
+            methods: methods.clone(),
+            class_methods: class_methods.clone(),
+            pools: pools.clone(),
+        },
+        AST::Error(..) => body.clone(),
+        _ => unreachable!(),
+    }
+}
+
+/// merges the members of a class body element into the class collected so far
+fn class_add(a: &AST, b: &AST) -> AST {
+    match (a, b) {
+        (
+            AST::Class {
+                name,
+                superclass,
+                inst_vars,
+                class_vars,
+                methods,
+                class_methods,
+                pools,
+            },
+            AST::Class {
+                inst_vars: iv,
+                class_vars: cv,
+                methods: m,
+                class_methods: cm,
+                pools: p,
+                ..
+            },
+        ) => AST::Class {
+            name,
+            superclass,
+            inst_vars: [inst_vars.as_slice(), iv.as_slice()].concat(),
+            class_vars: [class_vars.as_slice(), cv.as_slice()].concat(),
+            methods: [methods.as_slice(), m.as_slice()].concat(),
+            class_methods: [class_methods.as_slice(), cm.as_slice()].concat(),
+            pools: [pools.as_slice(), p.as_slice()].concat(),
+        },
+        (AST::Error(..), _) => a.clone(),
+        (_, AST::Error(..)) => b.clone(),
+        _ => unreachable!(),
+    }
+}
+
+/// `<import: Pool ...>` in a class body
+fn gen_import(open: &AST, keyword: &AST, names: &AST, close: &AST) -> AST {
+    if !matches!((open, keyword, close), (AST::Selector("<", _), AST::Selector("import:", _), AST::Selector(">", _))) {
+        let message = format!(
+            "pool import expects '<import: ...>', not '{} {} ... {}'",
+            selector_from(open),
+            selector_from(keyword),
+            selector_from(close)
+        );
+        return AST::Error(message, position_of(open));
+    }
+    AST::Class {
+        name: "",
+        superclass: "",
+        inst_vars: vec![],
+        class_vars: vec![],
+        methods: vec![],
+        class_methods: vec![],
+        pools: names_from(names),
+    }
+}
+
+/// `Global Name := value.` or `Constant Name := value.`, the value is optional
+fn gen_global(designator: &AST, name: &AST, init: Option<&AST>) -> AST {
+    let constant = match selector_from(designator) {
+        "Global" => false,
+        "Constant" => true,
+        other => {
+            let message = format!("global definition expects 'Global' or 'Constant', not '{}'", other);
+            return AST::Error(message, position_of(designator));
+        }
+    };
+    AST::Global {
+        name: selector_from(name),
+        constant,
+        init: init.map(|x| Box::new(x.clone())),
+    }
+}
+
+fn gen_pool(keyword: &AST, name: &AST, vars: &AST) -> AST {
+    if selector_from(keyword) != "Pool" {
+        let message = format!("pool definition expects 'Pool', not '{}'", selector_from(keyword));
+        return AST::Error(message, position_of(keyword));
+    }
+    match vars {
+        AST::Statements(vars) => AST::Pool {
+            name: selector_from(name),
+            vars: vars.clone(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// `Eval [ ... ]`, the body is kept as a method without selector
+fn gen_initializer(keyword: &AST, body: &AST) -> AST {
+    if selector_from(keyword) != "Eval" {
+        let message = format!("program initializer expects 'Eval', not '{}'", selector_from(keyword));
+        return AST::Error(message, position_of(keyword));
+    }
+    AST::Initializer(Box::new(body.clone()))
+}
+
+fn statements_add(a: &AST, b: &AST) -> AST {
+    match a {
+        AST::Statements(v) => {
+            let mut v = v.clone();
+            v.push(b.clone());
+            AST::Statements(v)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn names_from(t: &AST) -> Vec<&'static str> {
+    match t {
+        AST::Table(tab) => {
+            let mut r = vec![];
+            for x in tab {
+                match **x {
+                    AST::Name(n) => r.push(n),
+                    _ => panic!("unexpected"),
+                }
+            }
+            r
+        }
+        _ => panic!("not expected"),
+    }
+}
+
+fn params_from(name: &AST) -> Vec<&'static str> {
+    match name {
+        AST::PatternPart(_, Some(x0), rest, _) => match *x0.clone() {
+            AST::Name(x) => {
+                let mut start = vec![x];
+                start.extend_from_slice(params_from(rest).as_slice());
+                start
+            }
+            _ => unreachable!(),
+        },
+        AST::Empty => vec![],
+        AST::PatternPart(_, None, _, _) => vec![],
+        _ => {
+            println!("prams_from {:?}", name);
+            unreachable!()
+        }
+    }
+}
+
+fn args_from(name: &AST) -> Vec<AST> {
+    match name {
+        AST::PatternPart(_, Some(x), rest, _) => {
+            let mut start: Vec<AST> = vec![*x.clone()];
+            start.extend_from_slice(args_from(rest).as_slice());
+            start
+        }
+        AST::Empty => vec![],
+        AST::PatternPart(_, None, _, _) => vec![],
+        _ => {
+            println!("prams_from {:?}", name);
+            unreachable!()
+        }
+    }
+}
+
+fn selector_from(name: &AST) -> &'static str {
+    match name {
+        AST::PatternPart(x, _, rest, _) => {
+            let r = selector_from(rest);
+            SelectorSet::get(format!("{}{}", x, &r).as_str())
+        }
+        AST::Empty => "",
+        AST::Name(s) | AST::Selector(s, _) => SelectorSet::get(s),
+        _ => {
+            println!("selector_from {:?}", name);
+            unreachable!()
+        }
+    }
+}
+
+/// binding strength of a printed expression: primaries, unary, binary and
+/// keyword sends, and statements that only fit inside parentheses
+const PRIMARY: u8 = 0;
+const UNARY: u8 = 1;
+const BINARY: u8 = 2;
+const KEYWORD: u8 = 3;
+const STATEMENT: u8 = 4;
+
+fn source_of(t: &AST) -> (String, u8) {
+    match t {
+        AST::Int(n) => (n.to_string(), PRIMARY),
+        AST::LargeInt(n) => (n.to_string(), PRIMARY),
+        AST::Float(f) => (float_source(*f), PRIMARY),
+        AST::Char(c) => (format!("${}", c), PRIMARY),
+        AST::String(s) => (format!("'{}'", s), PRIMARY),
+        AST::Symbol(s) => (symbol_source(s), PRIMARY),
+        AST::LiteralArray(v) => {
+            let items: Vec<String> = v.iter().map(|x| source_of(x).0).collect();
+            (format!("#({})", items.join(" ")), PRIMARY)
+        }
+        AST::ByteArray(v) => {
+            let items: Vec<String> = v.iter().map(|x| x.to_string()).collect();
+            (format!("#[{}]", items.join(" ")), PRIMARY)
+        }
+        AST::Name(n) | AST::Selector(n, _) | AST::Variable(n, _) => (n.to_string(), PRIMARY),
+        AST::Block {
+            params,
+            temps,
+            body,
+            ..
+        } => {
+            let mut r = String::from("[");
+            for p in params {
+                r.push_str(&format!(":{} ", p));
+            }
+            if !params.is_empty() {
+                r.push_str("| ");
+            }
+            if !temps.is_empty() {
+                r.push_str(&format!("| {} | ", temps.join(" ")));
+            }
+            r.push_str(&source_of(body).0);
+            r.push(']');
+            (r, PRIMARY)
+        }
+        AST::Table(v) => {
+            let items: Vec<String> = v.iter().map(|x| source_of(x).0).collect();
+            (format!("{{{}}}", items.join(". ")), PRIMARY)
+        }
+        AST::Return(x) => (format!("^{}", source_of(x).0), STATEMENT),
+        AST::Assign(n, v) => (format!("{} := {}", source_of(n).0, source_of(v).0), STATEMENT),
+        AST::Statements(v) => {
+            let items: Vec<String> = v.iter().map(|x| source_of(x).0).collect();
+            (items.join(". "), STATEMENT)
+        }
+        AST::InvokeSequence(receiver, msgs) => sends_source(receiver, msgs),
+        AST::InvokeCascade(first, cascades) => match &**first {
+            AST::InvokeSequence(receiver, msgs) if !msgs.is_empty() => {
+                let (r, level) = sends_source(receiver, &msgs[..msgs.len() - 1]);
+                let mut r = send_source(r, level, &msgs[msgs.len() - 1]).0;
+                for c in cascades {
+                    if let AST::InvokeSequence(_, parts) = c {
+                        let parts: Vec<String> = parts.iter().map(message_source).collect();
+                        r.push_str(&format!("; {}", parts.join(" ")));
+                    }
+                }
+                (r, STATEMENT)
+            }
+            _ => unreachable!(),
+        },
+        AST::Method {
+            name,
+            params,
+            temps,
+            body,
+            ..
+        } => {
+            let mut r = if params.is_empty() {
+                name.to_string()
+            } else if params.len() == 1 && !name.ends_with(':') {
+                format!("{} {}", name, params[0])
+            } else {
+                let parts: Vec<String> = keywords(name)
+                    .iter()
+                    .zip(params)
+                    .map(|(k, p)| format!("{} {}", k, p))
+                    .collect();
+                parts.join(" ")
+            };
+            if !temps.is_empty() {
+                r.push_str(&format!("\n    | {} |", temps.join(" ")));
+            }
+            if let AST::Statements(stmts) = &**body {
+                for (idx, x) in stmts.iter().enumerate() {
+                    let sep = if idx + 1 < stmts.len() { "." } else { "" };
+                    r.push_str(&format!("\n    {}{}", source_of(x).0, sep));
+                }
+            }
+            (r, STATEMENT)
+        }
+        _ => (String::new(), PRIMARY),
+    }
+}
+
+fn sends_source(receiver: &AST, msgs: &[AST]) -> (String, u8) {
+    let (mut r, mut level) = source_of(receiver);
+    for m in msgs {
+        (r, level) = send_source(r, level, m);
+    }
+    (r, level)
+}
+
+/// appends a message to a receiver with binding strength `level`
+fn send_source(receiver: String, level: u8, m: &AST) -> (String, u8) {
+    let kind = message_level(m);
+    let max = if kind == UNARY { UNARY } else { BINARY };
+    (format!("{} {}", parenthesized(receiver, level, max), message_source(m)), kind)
+}
+
+fn message_source(m: &AST) -> String {
+    match m {
+        AST::Message { name, args, .. } => match message_level(m) {
+            UNARY => name.to_string(),
+            BINARY => {
+                let (a, level) = source_of(&args[0]);
+                format!("{} {}", name, parenthesized(a, level, UNARY))
+            }
+            _ => {
+                let parts: Vec<String> = keywords(name)
+                    .iter()
+                    .zip(args)
+                    .map(|(k, a)| {
+                        let (a, level) = source_of(a);
+                        format!("{} {}", k, parenthesized(a, level, BINARY))
+                    })
+                    .collect();
+                parts.join(" ")
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn message_level(m: &AST) -> u8 {
+    match m {
+        AST::Message { args, .. } if args.is_empty() => UNARY,
+        AST::Message { name, .. } if !name.ends_with(':') => BINARY,
+        _ => KEYWORD,
+    }
+}
+
+fn parenthesized(source: String, level: u8, max: u8) -> String {
+    if level > max {
+        format!("({})", source)
+    } else {
+        source
+    }
+}
+
+/// `at:put:` becomes `at:` and `put:`
+fn keywords(selector: &str) -> Vec<&str> {
+    selector.split_inclusive(':').collect()
+}
+
+fn float_source(f: f64) -> String {
+    let s = format!("{:?}", f);
+    // the lexer needs a fraction in front of an exponent
+    match s.find('e') {
+        Some(idx) if !s.contains('.') => format!("{}.0{}", &s[..idx], &s[idx..]),
+        _ => s,
+    }
+}
+
+fn symbol_source(s: &str) -> String {
+    let plain = s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    let binary = !s.is_empty() && s.chars().all(|c| "-%&,*+/<=>?@\\~!|".contains(c));
+    if plain || binary {
+        format!("#{}", s)
+    } else {
+        format!("#'{}'", s)
+    }
+}
+
+fn position_of(t: &AST) -> Position {
+    t.position().unwrap_or(Position { line: 0, column: 0 })
+}
+
+fn symbol_from(raw: &str) -> &'static str {
+    let s = &raw[1..];
+    if s.starts_with('\'') {
+        SelectorSet::get(&s[1..s.len() - 1])
+    } else {
+        SelectorSet::get(s)
+    }
+}
+
+impl AST {
+    /// where the node starts in the source. Nodes without a position of their
+    /// own answer the position of their first child that has one.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            AST::Selector(_, p)
+            | AST::Mark(p)
+            | AST::Variable(_, p)
+            | AST::PatternPart(_, _, _, p)
+            | AST::Method { position: p, .. }
+            | AST::Block { position: p, .. }
+            | AST::Message { position: p, .. }
+            | AST::Error(_, p) => Some(p.clone()),
+            AST::Return(x) | AST::List(x, _) => x.position(),
+            AST::Assign(_, x) => x.position(),
+            AST::InvokeSequence(x, v) | AST::InvokeCascade(x, v) => {
+                x.position().or_else(|| v.iter().find_map(|x| x.position()))
+            }
+            AST::Statements(v) | AST::LiteralArray(v) => v.iter().find_map(|x| x.position()),
+            AST::Table(v) => v.iter().find_map(|x| x.position()),
+            AST::Class { methods, class_methods, .. } => {
+                methods.iter().chain(class_methods).find_map(|x| x.position())
+            }
+            _ => None,
+        }
+    }
+
+    /// the invalid constructs in the tree with their positions
+    pub fn errors(&self) -> Vec<(Position, String)> {
+        let mut found = vec![];
+        self.collect_errors(&mut found);
+        found
+    }
+
+    fn collect_errors(&self, found: &mut Vec<(Position, String)>) {
+        let children: Vec<&AST> = match self {
+            AST::Error(message, position) => {
+                found.push((position.clone(), message.clone()));
+                vec![]
+            }
+            AST::Method { body, .. } | AST::Block { body, .. } | AST::Return(body) | AST::Initializer(body) => {
+                vec![body]
+            }
+            AST::PatternPart(_, x, y, _) => x.iter().chain([y]).map(|x| &**x).collect(),
+            AST::List(x, y) | AST::Assign(x, y) => vec![x, y],
+            AST::Table(v) => v.iter().map(|x| &**x).collect(),
+            AST::Statements(v) | AST::LiteralArray(v) | AST::Pool { vars: v, .. } => v.iter().collect(),
+            AST::InvokeSequence(x, v) | AST::InvokeCascade(x, v) => [&**x].into_iter().chain(v).collect(),
+            AST::Message { args, .. } => args.iter().collect(),
+            AST::Class {
+                class_vars,
+                methods,
+                class_methods,
+                ..
+            } => class_vars.iter().map(|(_, x)| x).chain(methods).chain(class_methods).collect(),
+            AST::Global { init, .. } => init.iter().map(|x| &**x).collect(),
+            _ => vec![],
+        };
+        children.into_iter().for_each(|x| x.collect_errors(found));
+    }
+
+    /// prints the node as source code that parses to the same tree,
+    /// methods are printed in the form read by `parse_method`
+    pub fn to_source(&self) -> String {
+        source_of(self).0
+    }
+
+    /// decimal integers with an optional exponent like `1e6`, or radix
+    /// integers like `16rFF`. Values beyond a machine word become `LargeInt`.
+    /// Answers the message of the syntax error for invalid literals.
+    pub fn from_int_literal(raw: &str) -> Result<AST, String> {
+        let n = if let Some((radix, digits)) = raw.split_once('r') {
+            let radix = match radix.parse::<u32>() {
+                Ok(radix) if (2..=36).contains(&radix) => radix,
+                _ => return Err(format!("radix of {} must be between 2 and 36", raw)),
+            };
+            match BigInt::parse_bytes(digits.as_bytes(), radix) {
+                Some(n) => n,
+                None => return Err(format!("invalid digits in {}", raw)),
+            }
+        } else if let Some((mantissa, exponent)) = raw.split_once('e') {
+            let exponent = match exponent.parse::<u32>() {
+                Ok(exponent) if exponent <= MAX_EXPONENT => exponent,
+                _ => return Err(format!("exponent of {} must be at most {}", raw, MAX_EXPONENT)),
+            };
+            let mantissa = mantissa.parse::<BigInt>().map_err(|_| format!("invalid digits in {}", raw))?;
+            mantissa * BigInt::from(10).pow(exponent)
+        } else {
+            raw.parse::<BigInt>().map_err(|_| format!("invalid digits in {}", raw))?
+        };
+        match isize::try_from(&n) {
+            Ok(small) => Ok(AST::Int(small)),
+            Err(_) => Ok(AST::LargeInt(n)),
+        }
+    }
+}
+
+/// the largest exponent of an integer literal, larger ones take too long to compute
+const MAX_EXPONENT: u32 = 10_000;
+
+/// the integer of an INT lexeme, an error node for invalid literals
+fn int_from(raw: &str, position: &Position) -> AST {
+    AST::from_int_literal(raw).unwrap_or_else(|message| AST::Error(message, position.clone()))
+}
+
+/// a number literal with a minus directly in front of its digits
+fn negative(minus: &Lexeme, digits: &Lexeme, number: AST) -> AST {
+    let adjacent = minus.position.line == digits.position.line && minus.position.column + 1 == digits.position.column;
+    if minus.raw != "-" || !adjacent {
+        return AST::Error(format!("unexpected '{}'", minus.raw), minus.position.clone());
+    }
+    match number {
+        AST::Int(n) => AST::Int(-n),
+        AST::LargeInt(n) => match isize::try_from(-&n) {
+            Ok(small) => AST::Int(small),
+            Err(_) => AST::LargeInt(-n),
+        },
+        AST::Float(f) => AST::Float(-f),
+        other => other,
+    }
+}
+
+fn literal_array_add(a: &AST, b: &AST) -> AST {
+    match (a, b) {
+        (AST::LiteralArray(v), _) => {
+            let mut v = v.clone();
+            v.push(b.clone());
+            AST::LiteralArray(v)
+        }
+        (AST::ByteArray(v), AST::Int(n)) => {
+            let mut v = v.clone();
+            v.push(*n as u8);
+            AST::ByteArray(v)
+        }
+        (AST::Error(..), _) => a.clone(),
+        (_, AST::Error(..)) => b.clone(),
+        _ => unreachable!(),
+    }
+}
+
+fn table_add(a: &AST, b: &AST) -> AST {
+    if let AST::Table(t) = a {
+        let mut t_new = t.clone();
+        t_new.push(Box::new(b.clone()));
+        AST::Table(t_new)
+    } else {
+        unreachable!()
+    }
+}
+
+fn table_from(n: &AST) -> AST {
+    AST::Table(vec![Box::new(n.clone())])
+}
+
+pub fn grammar() -> Grammar<AST> {
+    santiago::grammar!(
+        "cmd" => rules "define_cmd" "def" => |r| r[1].clone();
+        "cmd" => rules "method_cmd" "method definition" => |r:Vec<AST>| r[1].clone();
+        "cmd" => rules "eval_cmd" "temporaries" "statements" => |r:Vec<AST>| gen_script(&r[1], &r[2]);
+
+        "def" => rules "method definition" => |r| r[0].clone();
+        "def" => rules "program elements" => |r| r[0].clone();
+        "def" => empty => |_| AST::Empty;
+
+        "program elements" => rules "program element" => |r| AST::Statements(vec![r[0].clone()]);
+        "program elements" => rules "program elements" "program element" => |r| statements_add(&r[0], &r[1]);
+        "program element" => rules "class definition" => |r| r[0].clone();
+        "program element" => rules "designator" "identifier" "dot" => |r| gen_global(&r[0], &r[1], None);
+        "program element" => rules "designator" "identifier" "assignmentOperator" "expression" "dot"
+            => |r| gen_global(&r[0], &r[1], Some(&r[3]));
+        "program element" => rules "designator" "identifier" "blockStart" "pool body" "blockEnd"
+            => |r| gen_pool(&r[0], &r[1], &r[3]);
+        "program element" => rules "designator" "method body" => |r| gen_initializer(&r[0], &r[1]);
+        "pool body" => empty => |_| AST::Statements(vec![]);
+        "pool body" => rules "pool body" "pool variable" => |r| statements_add(&r[0], &r[1]);
+        "pool variable" => rules "identifier" "dot" => |r| gen_global(&AST::Name("Global"), &r[0], None);
+        "pool variable" => rules "identifier" "assignmentOperator" "expression" "dot"
+            => |r| gen_global(&AST::Name("Global"), &r[0], Some(&r[2]));
+        "pool variable" => rules "designator" "identifier" "assignmentOperator" "expression" "dot"
+            => |r| gen_global(&r[0], &r[1], Some(&r[3]));
+        "class definition" => rules "identifier" "keyword" "identifier" "blockStart" "class body" "blockEnd"
+            => |r| gen_class(&r[2], &r[1], &r[0], &r[4]);
+        "class body" => empty => |_| empty_class();
+        "class body" => rules "class body" "class element" => |r| class_add(&r[0], &r[1]);
+        "class element" => rules "bar" "identifiers" "bar"
+            => |r| AST::Class {
+                            name: "",
+                            superclass: "",
+                            inst_vars: names_from(&r[1]),
+                            class_vars: vec![],
+                            methods: vec![],
+                            class_methods: vec![],
+                            pools: vec![] };
+        "class element" => rules "binarySelector" "keyword" "identifiers" "binarySelector"
+            => |r| gen_import(&r[0], &r[1], &r[2], &r[3]);
+        "class element" => rules "identifier" "assignmentOperator" "expression" "dot"
+            => |r| AST::Class {
+                            name: "",
+                            superclass: "",
+                            inst_vars: vec![],
+                            class_vars: vec![(selector_from(&r[0]), r[2].clone())],
+                            methods: vec![],
+                            class_methods: vec![],
+                            pools: vec![] };
+        "class element" => rules "message pattern" "method body"
+            => |r| AST::Class {
+                            name: "",
+                            superclass: "",
+                            inst_vars: vec![],
+                            class_vars: vec![],
+                            methods: vec![method_with_pattern(&r[0], &r[1])],
+                            class_methods: vec![],
+                            pools: vec![] };
+        "class element" => rules "identifier" "unarySelector" "binarySelector" "message pattern" "method body"
+            => |r| {
+                if !matches!((&r[1], &r[2]), (AST::Selector("class", _), AST::Selector(">>", _))) {
+                    let message = format!(
+                        "class method definition expects 'class >>', not '{} {}'",
+                        selector_from(&r[1]),
+                        selector_from(&r[2])
+                    );
+                    return AST::Error(message, position_of(&r[1]));
+                }
+                AST::Class {
+                            name: "",
+                            superclass: "",
+                            inst_vars: vec![],
+                            class_vars: vec![],
+                            methods: vec![],
+                            class_methods: vec![method_with_pattern(&r[3], &r[4])],
+                            pools: vec![] }
+            };
+        "method body" => rules "blockStart" "temporaries" "statements" "blockEnd"
+            => |r| AST::Method {
+                            name: "",
+                            params: vec![],
+                            temps: names_from(&r[1]),
+                            body: Box::new(r[2].clone()),
+                            position: position_of(&r[0]) };
+        "method body" => rules "blockStart" "temporaries" "blockEnd"
+            => |r| AST::Method {
+                            name: "",
+                            params: vec![],
+                            temps: names_from(&r[1]),
+                            body: Box::new(AST::Statements(vec![])),
+                            position: position_of(&r[0]) };
+
+        "method definition" => rules "message pattern" "temporaries" "statements"
+            => |r| gen_method(&r[0], &r[1], &r[2]);
+
+        "chunk sep" => lexemes "END_OF_CHUNK" => |_| AST::Dummy(String::from("chunk separator"));
+        "temporaries" => empty  => |_| AST::Table(vec![]);
+        "temporaries" => rules "bar" "identifiers" "bar" => |r| r[1].clone();
+        "identifiers" => rules "identifier" => |r| table_from(&r[0]);
+        "identifiers" => rules "identifiers" "identifier" => |r| table_add(&r[0], &r[1]);
+        "message pattern" => rules "unary pattern" => |r| r[0].clone();
+        "message pattern" => rules "binary pattern" => |r| r[0].clone();
+        "message pattern" => rules "keyword pattern" => |r| r[0].clone();
+        "unary pattern" => rules "unarySelector"
+            => |r| AST::PatternPart((&r[0]).into(), None, Box::new(AST::Empty), position_of(&r[0]));
+        "binary pattern" => rules "binarySelector" "identifier"
+            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(AST::Empty), position_of(&r[0]));
+        "keyword pattern" => rules "keyword"  "identifier"
+            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(AST::Empty), position_of(&r[0]));
+        "keyword pattern" => rules "keyword"  "identifier" "keyword pattern"
+            => |r| AST::PatternPart((&r[0]).into(), Some(r[1].clone().into()), Box::new(r[2].clone()), position_of(&r[0]));
+        "statements" => rules "expression"
+            => |r| {  AST::Statements(vec![r[0].clone()])  };
+        "statements" => rules "return statement" => |r| AST::Statements(vec![r[0].clone()]);
+        "statements" => rules "return statement" "dot" => |r| AST::Statements(vec![r[0].clone()]);
+        "statements" => rules "expression" "dot" => |r| AST::Statements(vec![r[0].clone()]);
+        "statements" => rules "expression" "dot" "statements"
+            => |r| {
+            if let AST::Statements(x) = &r[2]{
+                let mut v = vec![r[0].clone()];
+                for e in x {v.push(e.clone());}
+                AST::Statements(v)
+            }
+            else {
+                AST::Statements(vec![r[0].clone()])
+            }
+        };
+        // "statements" => rules "expression" => |r| AST::Statements(vec![r[0].clone()]);
+        "return statement" => rules "return op" "expression"
+            => |r| AST::Return(Box::new(r[1].clone()));
+        "expression" => rules "basic expression" => |r| r[0].clone();
+        "expression" => rules "assignment" => |r| r[0].clone();
+        "assignment" => rules "identifier" "assignmentOperator" "expression"
+            => |r| AST::Assign(Box::new(r[0].clone()), Box::new(r[2].clone()));
+        // "messages" may be empty, so a plain "primary" is covered here as well
+        "basic expression" => rules "primary" "messages"
+                => |r| if let AST::InvokeSequence(_, msgs) = &r[1] {
+                    if msgs.is_empty() {
+                        r[0].clone()
+                    }
+                    else {
+                        AST::InvokeSequence(Box::new(r[0].clone()), msgs.clone())
+                    }
+                }
+                else {
+                    error!("sub tree is not a messages list. {:#?}", r);
+                    r[0].clone()
+                };
+
+        "basic expression" => rules "primary" "messages" "cascaded messages"
+                => |r| match (&r[1], &r[2]) {
+                    (AST::InvokeSequence(_, msgs), AST::InvokeCascade(_, cascades)) => AST::InvokeCascade(
+                        Box::new(AST::InvokeSequence(Box::new(r[0].clone()), msgs.clone())),
+                        cascades.clone()),
+                    _ => unreachable!(),
+                };
+        "cascaded messages" => rules "cascade part"
+            => |r| AST::InvokeCascade(Box::new(AST::Empty), vec![r[0].clone()]);
+        "cascaded messages" => rules "cascaded messages" "cascade part"
+            => |r| {
+                if let AST::InvokeCascade(t, parts) = &r[0] {
+                    let mut ps = parts.clone();
+                    ps.push(r[1].clone());
+                    AST::InvokeCascade(t.clone(), ps)
+                }
+                else {
+                    unreachable!()
+                }
+            };
+        "cascade part" => rules "semicolon" "messages" => |r| r[1].clone();
+
+        "binary messages" => rules "unary messages"
+            => |r| r[0].clone();
+        "binary messages" => rules "binary messages" "binary message"
+            => |r| {
+            if let AST::InvokeSequence(target, msgs) = &r[0] {
+                let mut ms = msgs.clone();
+                ms.push(r[1].clone());
+                AST::InvokeSequence(target.clone(), ms)
+            }
+            else {
+                panic!()
+                // AST::Dummy(String::from("no invoke seq"))
+            }
+        };
+
+
+        "messages" => rules "binary messages" "keyword message"
+            => |r| {
+                if let AST::InvokeSequence(target, msgs) = &r[0] {
+                    let mut ms = msgs.clone();
+                    ms.push(r[1].clone());
+                    AST::InvokeSequence(target.clone(), ms)
+                }
+                else {
+                    panic!()
+                    // AST::Dummy(String::from("no invoke seq"))
+                }
+            };
+        "messages" => rules "binary messages"
+            => |r| r[0].clone();
+        "unary messages" => empty
+            =>|_| AST::InvokeSequence(Box::new(AST::Empty), vec![]);
+        "unary messages" => rules "unary messages" "unary message"
+            => |r| {
+                if let AST::InvokeSequence(t, v0) = &r[0]{
+                    let mut v = v0.clone();
+                    v.push(r[1].clone());
+                    AST::InvokeSequence(t.clone(), v) }
+                else {
+                    panic!();
+                }
+        };
+
+        "unary message" => rules "unarySelector"
+            => |r|     AST::Message {  name: selector_from(&r[0]),
+                                       args: vec![],
+                                       position: position_of(&r[0]) };
+        "unary expression" => rules "primary" "unary messages"
+            => |r| match &r[1] {
+                        AST::InvokeSequence(_,seq) =>   if seq.is_empty() {
+                                                            r[0].clone()
+                                                        }
+                                                        else {
+                                                            AST::InvokeSequence(Box::new(r[0].clone()), seq.clone())
+                                                        },
+                        _ => todo!(),
+        };
+
+        "binary message" => rules "binarySelector" "unary expression" // "expression" is not working, since it would generate an implict right associated tree, which is wrong for Smalltalk
+            => |r| AST::Message{name: selector_from(&r[0]),
+                                args: vec![r[1].clone()],
+                                position: position_of(&r[0])};
+
+        "binary expression" => rules "unary expression"
+            => |r| r[0].clone();
+        "binary expression" => rules "binary expression" "binarySelector" "unary expression"
+            => |r| match (&r[0], &r[1]) {
+                    (AST::InvokeSequence(receiver, msgs), AST::Selector(name, position)) => {
+                        let mut ms = msgs.clone();
+                        ms.push(AST::Message { name, args: vec![r[2].clone()], position: position.clone() });
+                        AST::InvokeSequence(receiver.clone(), ms )},
+                    (receiver, AST::Selector(name, position)) => {
+                            let ms = vec![AST::Message { name, args: vec![r[2].clone()], position: position.clone() }];
+                            AST::InvokeSequence(Box::new(receiver.clone()), ms )},
+                        _ => todo!("{:?}", &r),
+            };
+
+        "keyword message" => rules "keyword message parts"
+            => |r|   AST::Message { name: selector_from(&r[0]),
+                                    args: args_from(&r[0]),
+                                    position: position_of(&r[0]) };
+
+        "keyword message parts" => rules "keyword" "keyword argument" "keyword message parts"
+            => |r| {
+                info!("message part: {:?} {:?}", &r[0], &r[1]);
+                AST::PatternPart(String::from(&r[0]),
+                                    Some(r[1].clone().into()),
+                                    Box::new(r[2].clone()),
+                                    position_of(&r[0]))};
+        "keyword message parts" => rules "keyword" "keyword argument"
+            => |r| {
+                info!("message part: {:?} {:?}", &r[0], &r[1]);
+                AST::PatternPart(String::from(&r[0]),
+                                    Some(r[1].clone().into()),
+                                    Box::new(AST::Empty),
+                                    position_of(&r[0]))};
+        "keyword argument" => rules "binary expression"
+            => |r| r[0].clone();
+        "primary" => lexemes "STRING" => |l| {
+            let s = &l[0].raw;
+            let s0 = &s[1..s.len()-1];
+            AST::String(s0.to_string())};
+        "primary" => lexemes "IDENTIFIER" => |l| AST::Variable(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "primary" => lexemes "CHAR" => |l| if let Some(c) = l[0].raw.chars().nth(1) {
+                AST::Char(c)
+            } else {
+                AST::Empty
+            };
+        "primary" => lexemes "INT" => |l| int_from(&l[0].raw, &l[0].position);
+        "primary" => lexemes "FLOAT" => |l| AST::Float(l[0].raw.parse::<f64>().unwrap());
+        "primary" => lexemes "BINARY" "INT" => |l| negative(l[0], l[1], int_from(&l[1].raw, &l[1].position));
+        "primary" => lexemes "BINARY" "FLOAT"
+            => |l| negative(l[0], l[1], AST::Float(l[1].raw.parse::<f64>().unwrap()));
+        "primary" => lexemes "SYMBOL" => |l| AST::Symbol(symbol_from(&l[0].raw));
+        "primary" => rules "literal array" => |r| r[0].clone();
+        "primary" => rules "byte array" => |r| r[0].clone();
+        "primary" => rules "block constructor" => |r| r[0].clone();
+        "primary" => rules "openBrace" "brace elements" "closeBrace" => |r| r[1].clone();
+        "primary" => rules "openParen" "expression" "closeParen" => |r| r[1].clone();
+        "brace elements" => empty => |_| AST::Table(vec![]);
+        "brace elements" => rules "brace expressions" => |r| r[0].clone();
+        "brace elements" => rules "brace expressions" "dot" => |r| r[0].clone();
+        "brace expressions" => rules "expression" => |r| table_from(&r[0]);
+        "brace expressions" => rules "brace expressions" "dot" "expression" => |r| table_add(&r[0], &r[2]);
+        "literal array" => rules "literalArrayStart" "array elements" "closeParen" => |r| r[1].clone();
+        "array elements" => empty => |_| AST::LiteralArray(vec![]);
+        "array elements" => rules "array elements" "array element" => |r| literal_array_add(&r[0], &r[1]);
+        "array element" => rules "literal array" => |r| r[0].clone();
+        "array element" => rules "byte array" => |r| r[0].clone();
+        "array element" => rules "openParen" "array elements" "closeParen" => |r| r[1].clone();
+        "array element" => lexemes "INT" => |l| int_from(&l[0].raw, &l[0].position);
+        "array element" => lexemes "FLOAT" => |l| AST::Float(l[0].raw.parse::<f64>().unwrap());
+        "array element" => lexemes "STRING" => |l| {
+            let s = &l[0].raw;
+            AST::String(s[1..s.len()-1].to_string())};
+        "array element" => lexemes "CHAR" => |l| if let Some(c) = l[0].raw.chars().nth(1) {
+                AST::Char(c)
+            } else {
+                AST::Empty
+            };
+        "array element" => lexemes "SYMBOL" => |l| AST::Symbol(symbol_from(&l[0].raw));
+        "array element" => lexemes "KEYWORD" => |l| AST::Symbol(SelectorSet::get(&l[0].raw));
+        "array element" => lexemes "BINARY" => |l| AST::Symbol(SelectorSet::get(&l[0].raw));
+        "array element" => lexemes "IDENTIFIER" => |l| {
+            match l[0].raw.as_str() {
+                "true" | "false" | "nil" => AST::Variable(SelectorSet::get(&l[0].raw), l[0].position.clone()),
+                s => AST::Symbol(SelectorSet::get(s)),
+            }};
+        "byte array" => rules "byteArrayStart" "byte elements" "blockEnd" => |r| r[1].clone();
+        "byte elements" => empty => |_| AST::ByteArray(vec![]);
+        "byte elements" => rules "byte elements" "byte element" => |r| literal_array_add(&r[0], &r[1]);
+        "byte element" => lexemes "INT" => |l| match int_from(&l[0].raw, &l[0].position) {
+                AST::Int(n) if (0..=255).contains(&n) => AST::Int(n),
+                e @ AST::Error(..) => e,
+                _ => AST::Error(format!("byte array element {} out of range", l[0].raw), l[0].position.clone()),
+            };
+        "block constructor" => rules "blockStart" "block args" "temporaries" "block body" "blockEnd"
+            => |r| AST::Block{
+                            params: names_from(&r[1]),
+                            temps: names_from(&r[2]),
+                            body: Box::new(r[3].clone()),
+                            position: position_of(&r[0]) };
+        "block args" => rules "block arguments" "bar"
+            => |r| r[0].clone();
+        "block args" => empty
+            => |_| AST::Table(vec![]);
+        "block arguments" => empty => |_| AST::Table(vec![]);
+        "block arguments" => rules "block arguments" "colon" "identifier"
+            => |r| {
+                if let AST::Table(mut lst) = r[0].clone()
+                {
+                    lst.push(Box::new(r[2].clone()));
+                    AST::Table(lst)
+                }
+                else {
+                    panic!("no table")
+                }
+            };
+        "block body" => rules "statements" => |r| r[0].clone();
+        "block body" => empty => |_| AST::Statements(vec![]);
+        "dot" => lexemes "." => |_| AST::Empty;
+        "return op" => lexemes "RETURN" => |_| AST::Empty;
+        "unarySelector" => lexemes "IDENTIFIER" => |l| AST::Selector(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "identifier" => lexemes "IDENTIFIER" => |l| AST::Name(SelectorSet::get(&l[0].raw));
+        // the leading word of a global, a pool or an initializer, kept with its position
+        "designator" => lexemes "IDENTIFIER" => |l| AST::Selector(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "binarySelector" => lexemes "BINARY" => |l| AST::Selector(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "binarySelector" => lexemes "|" => |l| AST::Selector(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "assignmentOperator" => lexemes "ASSIGN" => |_| AST::Empty;
+        "keyword" => lexemes "KEYWORD" => |l| AST::Selector(SelectorSet::get(&l[0].raw), l[0].position.clone());
+        "blockStart" => lexemes "[" => |l| AST::Mark(l[0].position.clone());
+        "blockEnd" => lexemes "]" => |_| AST::Empty;
+        "colon" => lexemes ":" => |_| AST::Empty;
+        "semicolon" => lexemes ";" => |_| AST::Empty;
+        "bar" => lexemes "|" => |_| AST::Empty;
+        "openParen" => lexemes "(" => |_| AST::Empty;
+        "closeParen" => lexemes ")" => |_| AST::Empty;
+        "literalArrayStart" => lexemes "#(" => |_| AST::Empty;
+        "byteArrayStart" => lexemes "#[" => |_| AST::Empty;
+        "openBrace" => lexemes "{" => |_| AST::Empty;
+        "closeBrace" => lexemes "}" => |_| AST::Empty;
+        "define_cmd" => lexemes "DEFINE" => |_| AST::Empty;
+        "eval_cmd" => lexemes "EVALUATE" => |_| AST::Empty;
+        "method_cmd" => lexemes "METHOD" => |_| AST::Empty;
+    )
+}
+
+pub fn eval(value: &AST) -> isize {
+    match value {
+        AST::Int(int) => *int,
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,295 @@
+//! The interactive workspace: a session keeps its variables from one input
+//! to the next, an input is either a script or a `:command`.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    chunk,
+    code::CodeAddress,
+    debug::{self, Action, Breakpoint, Stop},
+    runtime::{glb, rfl, sel::SelectorSet, val::Value, Receiver},
+    Workspace,
+};
+
+pub const HELP: &str = "\
+<script>          print it, shows the printString of the value
+:do <script>      do it, runs the script for its effect
+:print <script>   print it
+:inspect <script> inspect it, shows the class and the contents of the value
+:load <file.st>   files in the classes of a chunk file
+:save [<image>]   saves the session and the classes to an image
+:debug <script>   runs the script in the debugger, it stops at the first send
+:break <method> <line>|<address>
+                  stops at the first send of a line or at a step of a method,
+                  scripts are UndefinedObject>>DoIt
+:breakpoints      lists the breakpoints
+:clear [<n>]      removes a breakpoint or all of them
+:help             this text
+:quit             ends the session";
+
+pub const DEBUG_HELP: &str = "\
+s, step        stops at the next send, also in the method it calls
+n, next        stops at the next send of this method
+o, out         stops after the method has returned
+c, continue    runs to the next breakpoint
+v, vars        shows the arguments and temporaries
+values         shows the values computed so far by address
+p <name>|<address>
+               shows a variable or the value at an address, like r0-3
+bt, where      shows the stack
+h, help        this text";
+
+/// what an input asks for
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    DoIt(&'a str),
+    PrintIt(&'a str),
+    InspectIt(&'a str),
+    Load(&'a str),
+    Save(Option<&'a str>),
+    Debug(&'a str),
+    Break(&'a str),
+    Breakpoints,
+    Clear(Option<&'a str>),
+    Help,
+    Quit,
+}
+
+impl<'a> Command<'a> {
+    /// a script is printed, commands start with a colon
+    pub fn parse(input: &'a str) -> Result<Self, String> {
+        let input = input.trim();
+        let Some(command) = input.strip_prefix(':') else {
+            return Ok(Command::PrintIt(input));
+        };
+        let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let arg = arg.trim();
+        match (name, arg) {
+            ("do", _) => Ok(Command::DoIt(arg)),
+            ("print", _) => Ok(Command::PrintIt(arg)),
+            ("inspect", _) => Ok(Command::InspectIt(arg)),
+            ("load", "") => Err("usage: :load <file.st>".to_string()),
+            ("load", _) => Ok(Command::Load(arg)),
+            ("save", "") => Ok(Command::Save(None)),
+            ("save", _) => Ok(Command::Save(Some(arg))),
+            ("debug", _) => Ok(Command::Debug(arg)),
+            ("break", "") => Err("usage: :break <method> <line>|<address>".to_string()),
+            ("break", _) => Ok(Command::Break(arg)),
+            ("breakpoints", _) => Ok(Command::Breakpoints),
+            ("clear", "") => Ok(Command::Clear(None)),
+            ("clear", _) => Ok(Command::Clear(Some(arg))),
+            ("help", _) => Ok(Command::Help),
+            ("quit" | "q", _) => Ok(Command::Quit),
+            _ => Err(format!("unknown command :{}, :help lists them", name)),
+        }
+    }
+}
+
+/// a workspace with the image it is saved to
+pub struct Session {
+    workspace: Workspace,
+    image: PathBuf,
+}
+
+impl Session {
+    pub fn new(image: &Path) -> Self {
+        Self {
+            workspace: Workspace::new(),
+            image: image.to_path_buf(),
+        }
+    }
+
+    /// a session with the bindings of the image, a new one if there is no image yet
+    pub fn open(image: &Path) -> Result<Self, Box<dyn Error>> {
+        if !image.exists() {
+            return Ok(Self::new(image));
+        }
+        Ok(Self {
+            workspace: Workspace::load_image(image)?,
+            image: image.to_path_buf(),
+        })
+    }
+
+    /// runs the input, answers the text to show or `None` at the end of the session
+    pub fn run(&mut self, input: &str) -> Option<String> {
+        let output = match Command::parse(input) {
+            Ok(Command::Quit) => return None,
+            Ok(command) => self.execute(command),
+            Err(msg) => Err(msg.into()),
+        };
+        Some(output.unwrap_or_else(|e| format!("error: {}", e)))
+    }
+
+    fn execute(&mut self, command: Command) -> Result<String, Box<dyn Error>> {
+        match command {
+            Command::DoIt("") | Command::PrintIt("") | Command::InspectIt("") | Command::Debug("") => Ok(String::new()),
+            Command::DoIt(script) => self.evaluate(script).map(|_| String::new()),
+            Command::PrintIt(script) => self.evaluate(script).map(|v| rfl::print_string(&v)),
+            Command::InspectIt(script) => self.evaluate(script).map(|v| inspect(&v)),
+            Command::Debug(script) => {
+                debug::step_into();
+                self.evaluate(script).map(|v| rfl::print_string(&v))
+            }
+            Command::Break(at) => {
+                let breakpoint = Breakpoint::parse(at).ok_or(format!("not a breakpoint: {}", at))?;
+                let text = breakpoint.to_string();
+                Ok(format!("breakpoint {}: {}", debug::add_breakpoint(breakpoint), text))
+            }
+            Command::Breakpoints => {
+                let breakpoints = debug::breakpoints().into_iter().enumerate();
+                let lines: Vec<String> = breakpoints.map(|(idx, b)| format!("{}: {}", idx + 1, b)).collect();
+                Ok(lines.join("\n"))
+            }
+            Command::Clear(None) => {
+                debug::clear_breakpoints();
+                Ok(String::new())
+            }
+            Command::Clear(Some(number)) => match number.parse() {
+                Ok(number) if debug::remove_breakpoint(number) => Ok(String::new()),
+                _ => Err(format!("no breakpoint {}", number).into()),
+            },
+            Command::Load(file) => {
+                let classes = chunk::file_in_path(&mut self.workspace, Path::new(file))?;
+                Ok(format!("loaded {}", classes.join(" ")))
+            }
+            Command::Save(file) => {
+                if let Some(file) = file {
+                    self.image = PathBuf::from(file);
+                }
+                self.workspace.save_image(&self.image)?;
+                Ok(format!("saved {}", self.image.display()))
+            }
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+
+    /// stepping ends with the script, also when it fails
+    fn evaluate(&mut self, script: &str) -> Result<Rc<dyn Receiver>, Box<dyn Error>> {
+        let result = self.workspace.evaluate(script);
+        debug::stop_stepping();
+        result
+    }
+
+    /// the start of the word before the cursor and the names it can be
+    /// completed to: variables of the session, classes, globals and the
+    /// selectors
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map_or(0, |idx| idx + 1);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() || prefix.starts_with(':') {
+            return (start, vec![]);
+        }
+        let mut names: Vec<&'static str> = self.workspace.bindings().into_iter().map(|(name, _)| name).collect();
+        names.extend(rfl::class_names());
+        names.extend(glb::names());
+        names.extend(rfl::native_selectors());
+        names.retain(|n| n.starts_with(prefix));
+        names.extend(SelectorSet::starting_with(prefix));
+        names.sort_unstable();
+        names.dedup();
+        (start, names.into_iter().map(String::from).collect())
+    }
+}
+
+/// whether the input can be run: commands are one line, a script needs its
+/// brackets closed and its strings and comments ended
+pub fn is_complete(input: &str) -> bool {
+    let input = input.trim();
+    if input.starts_with(':') {
+        return true;
+    }
+    let mut depth = 0;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' if !chars.any(|end| end == c) => return false,
+            '$' => {
+                chars.next();
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+/// the printString of the value with its class, followed by its instance
+/// variables or its elements
+pub fn inspect(r: &Rc<dyn Receiver>) -> String {
+    let mut lines = vec![format!("{} ({})", rfl::print_string(r), r.class_name())];
+    for name in rfl::inst_var_names(&Value::from_receiver(r.clone())) {
+        if let Some(v) = r.inst_var(name) {
+            lines.push(format!("  {}: {}", name, rfl::print_string(&v)));
+        }
+    }
+    for (idx, v) in r.elements().unwrap_or_default().iter().enumerate() {
+        lines.push(format!("  [{}]: {}", idx, rfl::print_string(v)));
+    }
+    lines.join("\n")
+}
+
+/// what a debugger input asks for
+enum Reply {
+    Resume(Action),
+    Show(String),
+}
+
+/// runs one input of the debugger in the activation it stopped in
+fn debug_command(stop: &Stop, input: &str) -> Reply {
+    let input = input.trim();
+    let (name, arg) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let arg = arg.trim();
+    let value = |(name, v): (String, Value)| format!("{}: {}", name, rfl::print_string(&v.to_receiver()));
+    match name {
+        "s" | "step" => Reply::Resume(Action::StepInto),
+        "n" | "next" => Reply::Resume(Action::StepOver),
+        "o" | "out" => Reply::Resume(Action::StepOut),
+        "c" | "continue" => Reply::Resume(Action::Continue),
+        "v" | "vars" => Reply::Show(stop.variables().into_iter().map(value).collect::<Vec<_>>().join("\n")),
+        "values" => {
+            let values = stop.values().into_iter().map(|(addr, v)| (addr.to_string(), v));
+            Reply::Show(values.map(value).collect::<Vec<_>>().join("\n"))
+        }
+        "p" | "print" => {
+            let found = match CodeAddress::parse(arg) {
+                Some(addr) => stop.value_at(&addr),
+                None => stop.lookup(arg),
+            };
+            match found {
+                Some(v) => Reply::Show(value((arg.to_string(), v))),
+                None => Reply::Show(format!("{} has no value here", arg)),
+            }
+        }
+        "bt" | "where" => Reply::Show(stop.stack_trace().join("\n")),
+        "h" | "help" => Reply::Show(DEBUG_HELP.to_string()),
+        "" => Reply::Show(stop.describe()),
+        _ => Reply::Show(format!("unknown debugger command {}, help lists them", name)),
+    }
+}
+
+/// the debugger of an interactive session: it shows where the code stopped
+/// and reads commands until one of them resumes, the end of the input
+/// continues
+pub fn debugger(
+    mut read: impl FnMut(&str) -> Option<String> + 'static,
+    mut write: impl FnMut(&str) + 'static,
+) -> impl FnMut(&Stop) -> Action + 'static {
+    move |stop| {
+        write(&stop.describe());
+        while let Some(input) = read("debug> ") {
+            match debug_command(stop, &input) {
+                Reply::Resume(action) => return action,
+                Reply::Show(text) => write(&text),
+            }
+        }
+        Action::Continue
+    }
+}
@@ -0,0 +1,357 @@
+//! The method repository: method sources kept in SQLite with every version
+//! stored, so the history of a method can be listed, versions compared and
+//! an earlier version made current again. The `defs/<class>/<selector>`
+//! tree is the exchange format, it can be imported and exported.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use tracing::{error, info};
+
+use crate::{
+    code::{self, CompiledMethod},
+    data::model::{DataModel, Table},
+    dbx::{DBRow, Database},
+    parse_method,
+    parser::AST,
+};
+
+thread_local! {
+    static REPOSITORY: RefCell<Option<Rc<MethodRepository>>> = const { RefCell::new(None) };
+}
+
+/// the repository used for stored methods, by default one in memory
+/// holding the methods of the `defs` directory
+pub fn current() -> Rc<MethodRepository> {
+    REPOSITORY.with(|r| {
+        r.borrow_mut()
+            .get_or_insert_with(|| {
+                let repo = MethodRepository::open(None);
+                let defs = Path::new("defs");
+                if defs.exists() {
+                    // the methods read before the error are kept
+                    if let Err(e) = repo.import_defs(defs, "defs") {
+                        error!("can't import the defs: {}", e);
+                    }
+                }
+                Rc::new(repo)
+            })
+            .clone()
+    })
+}
+
+/// replaces the repository used for stored methods
+pub fn install(repo: MethodRepository) {
+    REPOSITORY.with(|r| *r.borrow_mut() = Some(Rc::new(repo)));
+}
+
+#[derive(Debug)]
+pub struct RepositoryError(pub String);
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+/// one version of a method
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodVersion {
+    pub class: String,
+    pub selector: String,
+    pub version: u64,
+    pub source: String,
+    pub author: String,
+    pub timestamp: String,
+}
+
+impl MethodVersion {
+    fn from_row(row: &DBRow) -> Self {
+        Self {
+            class: text(row, "class"),
+            selector: text(row, "selector"),
+            version: row.get("version").map(|v| u64::from(v.clone())).unwrap_or_default(),
+            source: text(row, "source"),
+            author: text(row, "author"),
+            timestamp: text(row, "timestamp"),
+        }
+    }
+}
+
+/// a parsed and compiled method with the version it was made from
+type Parsed = (u64, Rc<AST>, Rc<CompiledMethod>);
+
+pub struct MethodRepository {
+    db: Database,
+    /// parsed methods by class and selector
+    parsed: RefCell<HashMap<(String, String), Parsed>>,
+}
+
+fn model() -> DataModel {
+    DataModel::new("methods").table(
+        Table::new("method_version")
+            .field("class", true, "text")
+            .field("selector", true, "text")
+            .field("version", true, "integer")
+            .field("source", false, "text")
+            .field("author", false, "text")
+            .field("timestamp", false, "text"),
+    )
+}
+
+impl MethodRepository {
+    /// opens the repository in the database file at `path`, or in memory
+    pub fn open(path: Option<&str>) -> Self {
+        let db = Database::new();
+        db.connect(path);
+        db.activate_structure(model());
+        Self {
+            db,
+            parsed: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// stores `source` as the new version of the method it defines, unless
+    /// it is the source of the current version already
+    pub fn store(&self, class: &str, source: &str, author: &str) -> Result<MethodVersion, RepositoryError> {
+        let trees = parse_method(source.to_string()).map_err(|e| RepositoryError(e.to_string()))?;
+        let selector = match trees[0].as_abstract_syntax_tree() {
+            AST::Method { name, .. } => name,
+            _ => return Err(RepositoryError(format!("not a method: {}", source))),
+        };
+        let current = self.current(class, selector);
+        if let Some(m) = current.as_ref().filter(|m| m.source == source) {
+            return Ok(m.clone());
+        }
+        let m = MethodVersion {
+            class: class.to_string(),
+            selector: selector.to_string(),
+            version: current.map(|m| m.version + 1).unwrap_or(1),
+            source: source.to_string(),
+            author: author.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        let mut row = DBRow::new("method_version");
+        row.set("class", class.into());
+        row.set("selector", selector.into());
+        row.set("version", m.version.into());
+        row.set("source", source.into());
+        row.set("author", author.into());
+        row.set("timestamp", m.timestamp.as_str().into());
+        self.db.modify_from("method_version", &row);
+        info!("stored {}>>{} version {}", class, selector, m.version);
+        Ok(m)
+    }
+
+    /// the latest version of a method
+    pub fn current(&self, class: &str, selector: &str) -> Option<MethodVersion> {
+        self.history(class, selector).pop()
+    }
+
+    pub fn version(&self, class: &str, selector: &str, version: u64) -> Option<MethodVersion> {
+        self.history(class, selector).into_iter().find(|m| m.version == version)
+    }
+
+    /// all versions of a method, the oldest first
+    pub fn history(&self, class: &str, selector: &str) -> Vec<MethodVersion> {
+        self.db
+            .execute_query_with_params(
+                "select * from method_version where class = ? and selector = ? order by version",
+                vec![class.into(), selector.into()],
+            )
+            .iter()
+            .map(MethodVersion::from_row)
+            .collect()
+    }
+
+    pub fn classes(&self) -> Vec<String> {
+        self.db
+            .execute_query("select distinct class from method_version order by class")
+            .iter()
+            .map(|r| text(r, "class"))
+            .collect()
+    }
+
+    pub fn selectors(&self, class: &str) -> Vec<String> {
+        self.db
+            .execute_query_with_params(
+                "select distinct selector from method_version where class = ? order by selector",
+                vec![class.into()],
+            )
+            .iter()
+            .map(|r| text(r, "selector"))
+            .collect()
+    }
+
+    /// the changes from one version of a method to another, line by line:
+    /// unchanged lines start with two spaces, removed ones with `- ` and
+    /// added ones with `+ `
+    pub fn diff(&self, class: &str, selector: &str, from: u64, to: u64) -> Result<String, RepositoryError> {
+        let a = self.existing(class, selector, from)?;
+        let b = self.existing(class, selector, to)?;
+        let a: Vec<&str> = a.source.lines().collect();
+        let b: Vec<&str> = b.source.lines().collect();
+        Ok(diff_lines(&a, &b).join("\n"))
+    }
+
+    /// makes an earlier version current again by storing it as a new version
+    pub fn rollback(
+        &self,
+        class: &str,
+        selector: &str,
+        version: u64,
+        author: &str,
+    ) -> Result<MethodVersion, RepositoryError> {
+        let m = self.existing(class, selector, version)?;
+        self.store(class, &m.source, author)
+    }
+
+    /// the current version of a method parsed, it is only parsed again
+    /// after it has changed
+    pub fn method(&self, class: &str, selector: &str) -> Option<Rc<AST>> {
+        self.parsed(class, selector).map(|(_, ast, _)| ast)
+    }
+
+    /// the current version of a method compiled, shares the cache of `method`
+    pub fn compiled(&self, class: &str, selector: &str) -> Option<Rc<CompiledMethod>> {
+        self.parsed(class, selector).map(|(_, _, code)| code)
+    }
+
+    fn parsed(&self, class: &str, selector: &str) -> Option<Parsed> {
+        let m = self.current(class, selector)?;
+        let key = (class.to_string(), selector.to_string());
+        if let Some(parsed) = self.parsed.borrow().get(&key) {
+            if parsed.0 == m.version {
+                return Some(parsed.clone());
+            }
+        }
+        let trees = match parse_method(m.source) {
+            Ok(trees) => trees,
+            Err(e) => panic!("{}>>{}: {}", class, selector, e),
+        };
+        let ast = Rc::new(trees[0].as_abstract_syntax_tree());
+        let label = code::method_label(class, false, selector);
+        let compiled = match CompiledMethod::compile_method(&ast, &label) {
+            Ok(compiled) => Rc::new(compiled),
+            Err(e) => panic!("{}>>{}: {}", class, selector, e),
+        };
+        let parsed = (m.version, ast, compiled);
+        self.parsed.borrow_mut().insert(key, parsed.clone());
+        Some(parsed)
+    }
+
+    /// stores the methods of a `defs` tree, a directory per class holding a
+    /// file per method. The directory name is the class name with a lower
+    /// case initial. Answers the number of methods read.
+    pub fn import_defs(&self, dir: &Path, author: &str) -> Result<usize, RepositoryError> {
+        let mut count = 0;
+        for class_dir in sorted_entries(dir)? {
+            if !class_dir.is_dir() {
+                continue;
+            }
+            let class = class_name(&class_dir);
+            for file in sorted_entries(&class_dir)? {
+                let source = fs::read_to_string(&file).map_err(|e| io_error(&file, e))?;
+                self.store(&class, &source, author)
+                    .map_err(|e| RepositoryError(format!("{}: {}", file.display(), e)))?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// writes the current version of every method to a `defs` tree,
+    /// answers the number of methods written
+    pub fn export_defs(&self, dir: &Path) -> Result<usize, RepositoryError> {
+        let mut count = 0;
+        for class in self.classes() {
+            let class_dir = dir.join(dir_name(&class));
+            fs::create_dir_all(&class_dir).map_err(|e| io_error(&class_dir, e))?;
+            for selector in self.selectors(&class) {
+                if let Some(m) = self.current(&class, &selector) {
+                    let file = class_dir.join(selector.replace(':', "_"));
+                    fs::write(&file, m.source).map_err(|e| io_error(&file, e))?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn existing(&self, class: &str, selector: &str, version: u64) -> Result<MethodVersion, RepositoryError> {
+        self.version(class, selector, version)
+            .ok_or_else(|| RepositoryError(format!("{}>>{} has no version {}", class, selector, version)))
+    }
+}
+
+/// a longest common subsequence of the lines, the lines not in it are the changes
+fn diff_lines(a: &[&str], b: &[&str]) -> Vec<String> {
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut result = vec![];
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            result.push(format!("  {}", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            result.push(format!("- {}", a[i]));
+            i += 1;
+        } else {
+            result.push(format!("+ {}", b[j]));
+            j += 1;
+        }
+    }
+    result
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<std::path::PathBuf>, RepositoryError> {
+    let entries = fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// the class of a `defs` directory, its name with an upper case initial
+pub(crate) fn class_name(dir: &Path) -> String {
+    let name = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// the `defs` directory of a class, its name with a lower case initial
+fn dir_name(class: &str) -> String {
+    let mut chars = class.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> RepositoryError {
+    RepositoryError(format!("{}: {}", path.display(), e))
+}
+
+fn text(row: &DBRow, k: &str) -> String {
+    row.get(k).map(|v| String::from(v.clone())).unwrap_or_default()
+}
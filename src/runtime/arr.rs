@@ -1,10 +1,16 @@
 use std::{rc::Rc, ops::Deref};
 
+use super::{
+    col::{self, Species},
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
 
-
-
-
-use super::{Receiver};
+/// the messages arrays understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &["at:", "basicAt:", "first", "last"];
 
 pub struct ArrayReceiver(pub Vec<Rc<dyn Receiver>>);
 
@@ -16,27 +22,62 @@ impl Deref for ArrayReceiver {
     }
 }
 
+impl ArrayReceiver {
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        // arrays don't grow, a copy stands in for the receiver
+        let myself = Rc::new(ArrayReceiver(self.0.clone()));
+        match col::enumerate(myself, self.0.clone(), Species::Array, Species::Array, selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+}
 
 impl Receiver for ArrayReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
-            "at:" => {
-                let idx = args[0].as_int().unwrap();
-                self[idx as usize].clone()
+            "basic_write_to" => {
+                let items: Vec<String> = self.iter().map(|x| format!("{}", x)).collect();
+                let a0 = StringReceiver::new(format!("#({})", items.join(" ")));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            _ => todo!("array selector {}", selector),
+            _ if !PROTOCOL.contains(&selector) => self.enumerate(selector, args),
+            "at:" | "basicAt:" => match col::index(self.len(), &args[0]) {
+                Some(idx) => Value::from_receiver(self[idx].clone()),
+                None => Value::Nil,
+            },
+            "first" if !self.is_empty() => Value::from_receiver(self[0].clone()),
+            "last" if !self.is_empty() => Value::from_receiver(self[self.len() - 1].clone()),
+            _ => self.enumerate(selector, args),
         }
     }
 
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(self.0.clone())
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        self.iter().for_each(|x| visit(heap::address(x)));
+    }
+
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Array"
     }
-}
\ No newline at end of file
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new("Array", "");
+        Some(self.0.iter().fold(s, |s, x| s.slot("", x.clone())))
+    }
+}
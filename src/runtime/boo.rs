@@ -2,27 +2,80 @@ use std::rc::Rc;
 
 // use once_cell::sync::Lazy;
 
-use super::{str::StringReceiver, Receiver};
+use super::{str::StringReceiver, val::Value, Receiver};
+use crate::image::Snapshot;
 
 // pub static TRUE: Lazy<ObjectPtr> = Lazy::new(|| Object::new());
 
+/// the truth of a condition, others than Booleans are asked `mustBeBoolean`
+pub fn truth(r: &Value) -> bool {
+    match r {
+        Value::Bool(b) => *b,
+        _ => matches!(r.to_receiver().does_not_understand("mustBeBoolean", vec![]), Value::Bool(true)),
+    }
+}
+
+/// the messages `true` and `false` understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "ifTrue:",
+    "ifFalse:",
+    "ifTrue:ifFalse:",
+    "ifFalse:ifTrue:",
+    "and:",
+    "or:",
+    "&",
+    "|",
+    "xor:",
+    "eqv:",
+    "not",
+    "=",
+    "==",
+];
+
+/// the protocol `true` and `false` share, `None` for messages they don't
+/// understand. Conditional blocks not taken answer nil.
+fn receive(value: bool, selector: &'static str, args: &[Value]) -> Option<Value> {
+    if !PROTOCOL.contains(&selector) {
+        return None;
+    }
+    let evaluate = |block: &Value| block.receive_message("value", vec![]);
+    let r = match selector {
+        "ifTrue:" if value => evaluate(&args[0]),
+        "ifFalse:" if !value => evaluate(&args[0]),
+        "ifTrue:" | "ifFalse:" => Value::Nil,
+        "ifTrue:ifFalse:" => evaluate(&args[if value { 0 } else { 1 }]),
+        "ifFalse:ifTrue:" => evaluate(&args[if value { 1 } else { 0 }]),
+        "and:" if value => evaluate(&args[0]),
+        "or:" if !value => evaluate(&args[0]),
+        "and:" | "or:" => Value::Bool(value),
+        "&" => Value::Bool(value && truth(&args[0])),
+        "|" => Value::Bool(value || truth(&args[0])),
+        "xor:" => Value::Bool(value != truth(&args[0])),
+        "eqv:" => Value::Bool(value == truth(&args[0])),
+        "not" => Value::Bool(!value),
+        "=" | "==" => Value::Bool(matches!(args[0], Value::Bool(b) if b == value)),
+        _ => return None,
+    };
+    Some(r)
+}
+
 pub struct TrueReceiver;
 
 impl Receiver for TrueReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
-            "ifTrue:" => args[0].receive_message("value", vec![]),
-            "ifFalse:" => TrueReceiver::get(),
-            "ifTrue:ifFalse:" => args[0].receive_message("value", vec![]),
             "basic_write_to" => {
                 let a0 = StringReceiver::new(format!("True"));
-                args[0].receive_message("write", vec![Rc::new(a0)])
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            _ => todo!("implement {} for True", selector),
+            _ => match receive(true, selector, &args) {
+                Some(r) => r,
+                None => self.does_not_understand(selector, args),
+            },
         }
     }
 
@@ -32,6 +85,14 @@ impl Receiver for TrueReceiver {
     fn as_str(&self) -> Option<&'static str> {
         Some("True")
     }
+
+    fn class_name(&self) -> &'static str {
+        "True"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("true", ""))
+    }
 }
 // pub static NIL_RECIEVER: Lazy<Rc<dyn Receiver>> = Lazy::new(|| Rc::new(NilReciever));
 impl TrueReceiver {
@@ -46,17 +107,17 @@ impl Receiver for FalseReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
             "basic_write_to" => {
                 let a0 = StringReceiver::new(format!("False"));
-                args[0].receive_message("write", vec![Rc::new(a0)])
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            "ifTrue:" => FalseReceiver::get(),
-            "ifFalse:" => args[0].receive_message("value", vec![]),
-            "ifTrue:ifFalse:" => args[1].receive_message("value", vec![]),
-            _ => todo!("implement {} for False", selector),
+            _ => match receive(false, selector, &args) {
+                Some(r) => r,
+                None => self.does_not_understand(selector, args),
+            },
         }
     }
 
@@ -66,6 +127,14 @@ impl Receiver for FalseReceiver {
     fn as_str(&self) -> Option<&'static str> {
         Some("False")
     }
+
+    fn class_name(&self) -> &'static str {
+        "False"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("false", ""))
+    }
 }
 // pub static NIL_RECIEVER: Lazy<Rc<dyn Receiver>> = Lazy::new(|| Rc::new(NilReciever));
 impl FalseReceiver {
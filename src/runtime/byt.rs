@@ -0,0 +1,96 @@
+use std::{rc::Rc, sync::Mutex};
+
+use super::{
+    col::{self, Species},
+    exc,
+    int::IntReceiver,
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::image::Snapshot;
+
+/// the messages byte arrays understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &["at:", "at:put:", "basicAt:", "basicAt:put:", "size"];
+
+pub struct ByteArrayReceiver(Mutex<Vec<u8>>);
+
+impl ByteArrayReceiver {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(Mutex::new(bytes))
+    }
+
+    /// the enumeration protocol, the bytes are enumerated as integers
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let bytes = self.0.lock().unwrap().clone();
+        let elements: Vec<Rc<dyn Receiver>> = bytes
+            .iter()
+            .map(|b| Rc::new(IntReceiver::new(*b as isize)) as Rc<dyn Receiver>)
+            .collect();
+        // a copy stands in for the receiver like for arrays
+        let myself = Rc::new(ByteArrayReceiver::new(bytes));
+        match col::enumerate(myself, elements, Species::Array, Species::Array, selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+}
+
+impl Receiver for ByteArrayReceiver {
+    fn receive_message(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let bytes = self.0.lock().unwrap();
+                let items: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+                let a0 = StringReceiver::new(format!("#[{}]", items.join(" ")));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.enumerate(selector, args),
+            "at:" | "basicAt:" => {
+                // the signal unwinds, the lock is not held while it runs
+                let len = self.0.lock().unwrap().len();
+                match col::index(len, &args[0]) {
+                    Some(idx) => Value::Int(self.0.lock().unwrap()[idx] as isize),
+                    None => Value::Nil,
+                }
+            }
+            "at:put:" | "basicAt:put:" => {
+                let len = self.0.lock().unwrap().len();
+                let Some(idx) = col::index(len, &args[0]) else {
+                    return args[1].clone();
+                };
+                match args[1].as_int().map(u8::try_from) {
+                    Some(Ok(b)) => self.0.lock().unwrap()[idx] = b,
+                    _ => {
+                        exc::signal_error("Error", &format!("{} is not a byte", args[1]));
+                    }
+                }
+                args[1].clone()
+            }
+            "size" => Value::Int(self.0.lock().unwrap().len() as isize),
+            _ => self.enumerate(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "ByteArray"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let bytes: Vec<String> = self.0.lock().unwrap().iter().map(|b| b.to_string()).collect();
+        Some(Snapshot::new("ByteArray", bytes.join(" ")))
+    }
+}
@@ -1,12 +1,37 @@
 use std::rc::Rc;
 
-use super::{
-    boo::{FalseReceiver, TrueReceiver},
-    sel::SelectorSet,
-    str::StringReceiver,
-    Receiver,
-};
+use super::{exc, str::StringReceiver, sym::SymbolReceiver, val::Value, Receiver};
+use crate::image::Snapshot;
 
+/// the messages characters understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "=",
+    "==",
+    "~=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "asInteger",
+    "value",
+    "codePoint",
+    "asCharacter",
+    "asUppercase",
+    "asLowercase",
+    "asString",
+    "asSymbol",
+    "digitValue",
+    "isVowel",
+    "isDigit",
+    "isLetter",
+    "isAlphaNumeric",
+    "isUppercase",
+    "isLowercase",
+    "isSeparator",
+    "isCharacter",
+];
+
+/// a Unicode character, `$a` is one
 pub struct CharReceiver(char);
 
 impl CharReceiver {
@@ -19,22 +44,49 @@ impl Receiver for CharReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
+        let other = || match args[0] {
+            Value::Char(c) => Some(c),
+            _ => None,
+        };
+        let r = Value::Char;
         match selector {
-            "==" => {
-                if self.0 == char::from_u32(args[0].as_int().unwrap().try_into().unwrap()).unwrap()
-                {
-                    TrueReceiver::get()
-                } else {
-                    FalseReceiver::get()
-                }
-            }
             "basic_write_to" => {
                 let a0 = StringReceiver::new(format!("{}", self.0));
-                args[0].receive_message("write", vec![Rc::new(a0)])
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            _ => todo!("message '{}' for char", selector),
+            _ if !PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "==" | "=" => Value::Bool(other() == Some(self.0)),
+            "~=" => Value::Bool(other() != Some(self.0)),
+            "<" | ">" | "<=" | ">=" => match other() {
+                Some(c) => Value::Bool(match selector {
+                    "<" => self.0 < c,
+                    ">" => self.0 > c,
+                    "<=" => self.0 <= c,
+                    _ => self.0 >= c,
+                }),
+                None => exc::signal_error("Error", &format!("{} is not a character", args[0])),
+            },
+            "asInteger" | "value" | "codePoint" => Value::Int(self.0 as isize),
+            "asCharacter" => r(self.0),
+            "asUppercase" => r(self.0.to_uppercase().next().unwrap_or(self.0)),
+            "asLowercase" => r(self.0.to_lowercase().next().unwrap_or(self.0)),
+            "asString" => Value::Object(Rc::new(StringReceiver::new(self.0.to_string()))),
+            "asSymbol" => Value::Object(Rc::new(SymbolReceiver::new(&self.0.to_string()))),
+            "digitValue" => match self.0.to_digit(36) {
+                Some(d) => Value::Int(d as isize),
+                None => exc::signal_error("Error", &format!("{} is not a digit", self.0)),
+            },
+            "isVowel" => Value::Bool("aeiou".contains(self.0.to_ascii_lowercase())),
+            "isDigit" => Value::Bool(self.0.is_ascii_digit()),
+            "isLetter" => Value::Bool(self.0.is_alphabetic()),
+            "isAlphaNumeric" => Value::Bool(self.0.is_alphanumeric()),
+            "isUppercase" => Value::Bool(self.0.is_uppercase()),
+            "isLowercase" => Value::Bool(self.0.is_lowercase()),
+            "isSeparator" => Value::Bool(self.0.is_whitespace()),
+            "isCharacter" => Value::Bool(true),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
@@ -43,8 +95,14 @@ impl Receiver for CharReceiver {
     }
     
     fn as_str(&self) -> Option<&'static str> {
-        let s = String::from(self.0);
-        let s1 = SelectorSet::get(s.as_str());
-        Some(s1)
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Character"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Character", self.0.to_string()))
     }
 }
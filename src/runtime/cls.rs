@@ -0,0 +1,521 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::{Rc, Weak},
+    sync::Mutex,
+};
+
+use crate::{
+    code::{self, MethodCache},
+    heap,
+    image::Snapshot,
+    parser::AST,
+    MethodContext,
+};
+
+use super::{
+    exc,
+    glb::{self, InitializationError},
+    msg::MessageReceiver,
+    mtd::ClassKey,
+    nil::NilReciever,
+    sel::SelectorSet,
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+
+thread_local! {
+    static CLASSES: RefCell<BTreeMap<&'static str, Rc<ClassDef>>> = RefCell::new(
+        exc::builtin_classes()
+            .into_iter()
+            .map(|c| (c.name, Rc::new(c)))
+            .collect(),
+    );
+}
+
+/// table of all user defined classes, looked up by name
+pub struct ClassTable;
+
+impl ClassTable {
+    /// registers a class, replacing an earlier definition with the same name
+    pub fn define(class: ClassDef) -> Rc<ClassDef> {
+        let class = Rc::new(class);
+        MethodCache::forget(class.name, false, None);
+        CLASSES.with(|c| c.borrow_mut().insert(class.name, class.clone()));
+        class
+    }
+
+    pub fn get(name: &str) -> Option<Rc<ClassDef>> {
+        CLASSES.with(|c| c.borrow().get(name).cloned())
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        CLASSES.with(|c| c.borrow().keys().copied().collect())
+    }
+
+    /// the values of the class variables, roots for the heap
+    pub(crate) fn references(visit: &mut dyn FnMut(usize)) {
+        CLASSES.with(|c| {
+            for class in c.borrow().values() {
+                if let Ok(values) = class.class_values.try_lock() {
+                    values.values().for_each(|v| visit(heap::address(v)));
+                }
+            }
+        });
+    }
+
+    /// builds the class from a parsed class definition and registers it,
+    /// the initializers of the class variables run on the class side
+    pub fn define_from(ast: &AST) -> Result<Rc<ClassDef>, InitializationError> {
+        match ast {
+            AST::Class {
+                name,
+                superclass,
+                inst_vars,
+                class_vars,
+                methods,
+                class_methods,
+                pools,
+            } => {
+                let superclass = match *superclass {
+                    "nil" => None,
+                    s => Some(s),
+                };
+                let names: Vec<&'static str> = class_vars.iter().map(|(n, _)| *n).collect();
+                let class = ClassTable::define(ClassDef::new(name, superclass, inst_vars, &names));
+                pools.iter().for_each(|p| class.import_pool(p));
+                for m in methods {
+                    class.add_method(m.clone());
+                }
+                for m in class_methods {
+                    class.add_class_method(m.clone());
+                }
+                for (n, init) in class_vars {
+                    let ctx = MethodContext::for_method(NilReciever::get(), Some((class.clone(), true)), vec![]);
+                    let value = code::run_initializer(init, ctx)
+                        .map_err(|e| InitializationError(format!("{}.{}: {}", name, n, e)))?;
+                    class.set_class_var(n, value.to_receiver());
+                }
+                Ok(class)
+            }
+            _ => panic!("not a class definition: {:?}", ast),
+        }
+    }
+}
+
+pub struct ClassDef {
+    pub name: &'static str,
+    pub superclass: Option<&'static str>,
+    pub inst_vars: Vec<&'static str>,
+    pub class_vars: Vec<&'static str>,
+    methods: Mutex<BTreeMap<&'static str, Rc<AST>>>,
+    class_methods: Mutex<BTreeMap<&'static str, Rc<AST>>>,
+    class_values: Mutex<BTreeMap<&'static str, Rc<dyn Receiver>>>,
+    /// the category of the class and the ones of its methods by side and
+    /// selector, as given by a chunk file
+    category: Mutex<&'static str>,
+    method_categories: Mutex<BTreeMap<(bool, &'static str), &'static str>>,
+    /// the pool dictionaries whose variables the methods see
+    pools: Mutex<Vec<&'static str>>,
+}
+
+impl ClassDef {
+    pub fn new(
+        name: &'static str,
+        superclass: Option<&'static str>,
+        inst_vars: &[&'static str],
+        class_vars: &[&'static str],
+    ) -> Self {
+        let mut values = BTreeMap::new();
+        for n in class_vars {
+            values.insert(*n, NilReciever::get());
+        }
+        Self {
+            name,
+            superclass,
+            inst_vars: inst_vars.into(),
+            class_vars: class_vars.into(),
+            methods: Mutex::new(BTreeMap::new()),
+            class_methods: Mutex::new(BTreeMap::new()),
+            class_values: Mutex::new(values),
+            category: Mutex::new("Unclassified"),
+            method_categories: Mutex::new(BTreeMap::new()),
+            pools: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn add_method(&self, method: AST) {
+        if let AST::Method { name, .. } = method {
+            MethodCache::forget(self.name, false, Some(name));
+            self.methods.lock().unwrap().insert(name, Rc::new(method));
+        }
+    }
+
+    pub fn add_class_method(&self, method: AST) {
+        if let AST::Method { name, .. } = method {
+            MethodCache::forget(self.name, true, Some(name));
+            self.class_methods
+                .lock()
+                .unwrap()
+                .insert(name, Rc::new(method));
+        }
+    }
+
+    /// the methods of the instance or the class side, ordered by selector
+    pub fn methods(&self, class_side: bool) -> Vec<Rc<AST>> {
+        let methods = if class_side {
+            &self.class_methods
+        } else {
+            &self.methods
+        };
+        methods.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn category(&self) -> &'static str {
+        *self.category.lock().unwrap()
+    }
+
+    pub fn set_category(&self, category: &'static str) {
+        *self.category.lock().unwrap() = category;
+    }
+
+    /// the category of a method, `as yet unclassified` if none was given
+    pub fn method_category(&self, class_side: bool, selector: &'static str) -> &'static str {
+        let categories = self.method_categories.lock().unwrap();
+        categories.get(&(class_side, selector)).copied().unwrap_or("as yet unclassified")
+    }
+
+    pub fn set_method_category(&self, class_side: bool, selector: &'static str, category: &'static str) {
+        self.method_categories.lock().unwrap().insert((class_side, selector), category);
+    }
+
+    pub fn import_pool(&self, pool: &'static str) {
+        let mut pools = self.pools.lock().unwrap();
+        if !pools.contains(&pool) {
+            pools.push(pool);
+        }
+    }
+
+    pub fn pools(&self) -> Vec<&'static str> {
+        self.pools.lock().unwrap().clone()
+    }
+
+    pub fn superclass(&self) -> Option<Rc<ClassDef>> {
+        self.superclass.and_then(ClassTable::get)
+    }
+
+    /// true for the class itself and its subclasses
+    pub fn inherits_from(&self, name: &str) -> bool {
+        if self.name == name {
+            return true;
+        }
+        match self.superclass() {
+            Some(s) => s.inherits_from(name),
+            None => false,
+        }
+    }
+
+    /// instance variables including the inherited ones, inherited first
+    pub fn all_inst_vars(&self) -> Vec<&'static str> {
+        let mut r = match self.superclass() {
+            Some(s) => s.all_inst_vars(),
+            None => vec![],
+        };
+        r.extend_from_slice(&self.inst_vars);
+        r
+    }
+
+    /// the method for the selector defined by the class itself
+    pub fn own_method(&self, selector: &str, class_side: bool) -> Option<Rc<AST>> {
+        let methods = if class_side {
+            self.class_methods.lock().unwrap()
+        } else {
+            self.methods.lock().unwrap()
+        };
+        methods.get(selector).cloned()
+    }
+
+    /// finds the method for the selector in this class or one of its superclasses.
+    /// Returns the defining class together with the method.
+    pub fn lookup(
+        self: &Rc<Self>,
+        selector: &str,
+        class_side: bool,
+    ) -> Option<(Rc<ClassDef>, Rc<AST>)> {
+        match self.own_method(selector, class_side) {
+            Some(m) => Some((self.clone(), m)),
+            None => self.superclass()?.lookup(selector, class_side),
+        }
+    }
+
+    /// a class variable or a variable of an imported pool, the ones of the
+    /// class itself before the inherited ones
+    pub fn class_var(&self, name: &str) -> Option<Rc<dyn Receiver>> {
+        let found = self.class_values.lock().unwrap().get(name).cloned();
+        match found.or_else(|| self.pools().iter().find_map(|p| glb::pool_var(p, name))) {
+            Some(v) => Some(v),
+            None => self.superclass()?.class_var(name),
+        }
+    }
+
+    pub fn set_class_var(&self, name: &str, value: Rc<dyn Receiver>) -> bool {
+        {
+            let mut values = self.class_values.lock().unwrap();
+            if let Some(v) = values.get_mut(name) {
+                *v = value;
+                return true;
+            }
+        }
+        if self.pools().iter().any(|p| glb::assign_pool_var(p, name, value.clone())) {
+            return true;
+        }
+        match self.superclass() {
+            Some(s) => s.set_class_var(name, value),
+            None => false,
+        }
+    }
+}
+
+/// runs a method found by `ClassDef::lookup` with `myself` as receiver,
+/// the method is compiled on its first send
+pub fn execute(
+    class: Rc<ClassDef>,
+    class_side: bool,
+    myself: Rc<dyn Receiver>,
+    method: &AST,
+    args: Vec<Value>,
+) -> Value {
+    match MethodCache::lookup_or_compile(class.name, class_side, method) {
+        Ok(code) => code.run(MethodContext::for_method(myself, Some((class, class_side)), args)),
+        Err(e) => exc::signal_error("Error", &e.to_string()),
+    }
+}
+
+/// sends a message to `super`, starting the lookup above the class that defines the running method
+pub fn send_super(
+    class: &Rc<ClassDef>,
+    class_side: bool,
+    myself: Value,
+    selector: &'static str,
+    args: Vec<Value>,
+) -> Value {
+    let myself = myself.to_receiver();
+    match class
+        .superclass()
+        .and_then(|s| s.lookup(selector, class_side))
+    {
+        Some((c, m)) => execute(c, class_side, myself, &m, args),
+        None => {
+            let actual = myself.class_def().unwrap_or(class.clone());
+            if class_side {
+                ClassReceiver(actual).primitive(selector, args)
+            } else {
+                instance_primitive(&actual, myself, selector, args)
+            }
+        }
+    }
+}
+
+/// the class object of a user defined class
+pub struct ClassReceiver(pub Rc<ClassDef>);
+
+impl ClassReceiver {
+    fn primitive(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        if self.0.inherits_from("Exception") {
+            if let Some(r) = exc::class_primitive(&self.0, selector, &args) {
+                return r;
+            }
+        }
+        match selector {
+            "new" | "basicNew" => Value::Object(InstanceReceiver::new(self.0.clone())),
+            "name" => Value::Object(Rc::new(StringReceiver::new(self.0.name.to_string()))),
+            "superclass" => match self.0.superclass() {
+                Some(s) => Value::Object(Rc::new(ClassReceiver(s))),
+                None => Value::Nil,
+            },
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(self.0.name.to_string());
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+}
+
+impl Receiver for ClassReceiver {
+    fn receive_message(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        match self.0.lookup(selector, true) {
+            Some((c, m)) => execute(c, true, Rc::new(ClassReceiver(self.0.clone())), &m, args),
+            None => self.primitive(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        Some(self.0.name)
+    }
+
+    fn class_name(&self) -> &'static str {
+        SelectorSet::get(&format!("{} class", self.0.name))
+    }
+
+    fn class_key(&self) -> ClassKey {
+        (self.0.name, true)
+    }
+
+    fn does_not_understand(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        let myself: Rc<dyn Receiver> = Rc::new(ClassReceiver(self.0.clone()));
+        match self.0.lookup("doesNotUnderstand:", true) {
+            Some((c, m)) => {
+                let message = Value::Object(Rc::new(MessageReceiver::new(selector, args)));
+                execute(c, true, myself, &m, vec![message])
+            }
+            None => exc::does_not_understand(Some(myself), self.class_name(), selector, args),
+        }
+    }
+
+    fn class_def(&self) -> Option<Rc<ClassDef>> {
+        Some(self.0.clone())
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", self.0.name))
+    }
+}
+
+/// an instance of a user defined class
+pub struct InstanceReceiver {
+    myself: Weak<InstanceReceiver>,
+    class: Rc<ClassDef>,
+    names: Vec<&'static str>,
+    vars: Mutex<Vec<Rc<dyn Receiver>>>,
+}
+
+impl InstanceReceiver {
+    pub fn new(class: Rc<ClassDef>) -> Rc<Self> {
+        let names = class.all_inst_vars();
+        let vars = names.iter().map(|_| NilReciever::get()).collect();
+        let instance = Rc::new_cyclic(|myself| Self {
+            myself: myself.clone(),
+            class,
+            names,
+            vars: Mutex::new(vars),
+        });
+        heap::track_receiver(&(instance.clone() as Rc<dyn Receiver>));
+        instance
+    }
+
+    fn myself(&self) -> Rc<dyn Receiver> {
+        self.myself.upgrade().unwrap()
+    }
+}
+
+fn instance_primitive(
+    class: &Rc<ClassDef>,
+    myself: Rc<dyn Receiver>,
+    selector: &'static str,
+    args: Vec<Value>,
+) -> Value {
+    if class.inherits_from("Exception") {
+        if let Some(r) = exc::primitive(&myself, class, selector, &args) {
+            return r;
+        }
+    }
+    match selector {
+        "class" => Value::Object(Rc::new(ClassReceiver(class.clone()))),
+        "yourself" => Value::Object(myself),
+        "==" => Value::Bool(match &args[0] {
+            Value::Object(other) => std::ptr::eq(Rc::as_ptr(&myself) as *const u8, Rc::as_ptr(other) as *const u8),
+            _ => false,
+        }),
+        "basic_write_to" => {
+            let article = match class.name.chars().next() {
+                Some('A' | 'E' | 'I' | 'O' | 'U') => "an",
+                _ => "a",
+            };
+            let a0 = StringReceiver::new(format!("{} {}", article, class.name));
+            args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+        }
+        _ => match class.lookup("doesNotUnderstand:", false) {
+            Some((c, m)) => {
+                let message = Value::Object(Rc::new(MessageReceiver::new(selector, args)));
+                execute(c, false, myself, &m, vec![message])
+            }
+            None => exc::does_not_understand(Some(myself), class.name, selector, args),
+        },
+    }
+}
+
+impl Receiver for InstanceReceiver {
+    fn receive_message(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        match self.class.lookup(selector, false) {
+            Some((c, m)) => execute(c, false, self.myself(), &m, args),
+            None => instance_primitive(&self.class, self.myself(), selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn inst_var(&self, name: &str) -> Option<Rc<dyn Receiver>> {
+        let idx = self.names.iter().position(|n| *n == name)?;
+        Some(self.vars.lock().unwrap()[idx].clone())
+    }
+
+    fn set_inst_var(&self, name: &str, value: Rc<dyn Receiver>) -> bool {
+        match self.names.iter().position(|n| *n == name) {
+            Some(idx) => {
+                self.vars.lock().unwrap()[idx] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn class_def(&self) -> Option<Rc<ClassDef>> {
+        Some(self.class.clone())
+    }
+
+    fn class_name(&self) -> &'static str {
+        self.class.name
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(vars) = self.vars.try_lock() {
+            vars.iter().for_each(|v| visit(heap::address(v)));
+        }
+    }
+
+    fn clear_references(&self) {
+        for v in self.vars.lock().unwrap().iter_mut() {
+            *v = NilReciever::get();
+        }
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let vars = self.vars.lock().unwrap();
+        let s = Snapshot::new("Instance", self.class.name);
+        Some(self.names.iter().zip(vars.iter()).fold(s, |s, (n, v)| s.slot(n, v.clone())))
+    }
+}
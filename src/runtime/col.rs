@@ -0,0 +1,409 @@
+//! The protocol all collections share. The collections hand their elements
+//! to `enumerate` in the order they enumerate them, it answers the messages
+//! that only need the elements.
+
+use std::rc::Rc;
+
+use super::{
+    arr::ArrayReceiver,
+    dct::{self, AssociationReceiver, DictionaryReceiver},
+    exc,
+    ivl::{self, IntervalReceiver},
+    mtd::ClassKey,
+    nil::NilReciever,
+    ord::{self, OrderedCollectionReceiver},
+    sel::SelectorSet,
+    set::SetReceiver,
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
+
+/// the messages all collections understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "do:",
+    "reverseDo:",
+    "do:separatedBy:",
+    "doWithIndex:",
+    "withIndexDo:",
+    "collect:",
+    "select:",
+    "reject:",
+    "detect:ifNone:",
+    "detect:",
+    "anySatisfy:",
+    "allSatisfy:",
+    "count:",
+    "inject:into:",
+    "with:collect:",
+    "includes:",
+    "occurrencesOf:",
+    "size",
+    "isEmpty",
+    "notEmpty",
+    "isCollection",
+    "asArray",
+    "asOrderedCollection",
+    "asSet",
+    "asBag",
+    "asSortedCollection",
+    "asSortedCollection:",
+];
+
+/// the messages the collection classes understand natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &[
+    "new",
+    "new:",
+    "with:",
+    "with:with:",
+    "with:with:with:",
+    "with:with:with:with:",
+    "withAll:",
+];
+
+/// an element as collections compare it, numbers, characters and strings
+/// by their value, other objects by identity
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Bool(bool),
+    Number(String),
+    Char(char),
+    String(String),
+    /// class objects are made when they are needed, the name stands for them
+    Class(&'static str),
+    Object(usize),
+}
+
+impl Key {
+    pub fn of(r: &Rc<dyn Receiver>) -> Self {
+        if let Some(n) = r.as_number() {
+            return Key::Number(n.to_string());
+        }
+        if let Some(s) = r.as_symbol() {
+            return Key::String(s.to_string());
+        }
+        if let (name, true) = r.class_key() {
+            return Key::Class(name);
+        }
+        match r.class_name() {
+            "UndefinedObject" => Key::Nil,
+            "True" => Key::Bool(true),
+            "False" => Key::Bool(false),
+            "Character" => Key::Char(char::from_u32(r.as_int().unwrap() as u32).unwrap()),
+            "String" => Key::String(r.to_string()),
+            _ => Key::Object(heap::address(r)),
+        }
+    }
+
+    /// the key of a value, immediates have theirs without being boxed
+    pub fn of_value(v: &Value) -> Self {
+        match v {
+            Value::Nil => Key::Nil,
+            Value::Bool(b) => Key::Bool(*b),
+            Value::Int(n) => Key::Number(n.to_string()),
+            Value::Char(c) => Key::Char(*c),
+            Value::Object(r) => Key::of(r),
+        }
+    }
+}
+
+/// the kind of collection `select:` and `collect:` answer
+#[derive(Clone)]
+pub enum Species {
+    Array,
+    OrderedCollection,
+    Set,
+    Bag,
+    /// sorted with the block, `None` sorts ascending
+    Sorted(Option<Rc<dyn Receiver>>),
+}
+
+impl Species {
+    pub fn make(&self, elements: Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
+        match self {
+            Species::Array => Rc::new(ArrayReceiver(elements)),
+            Species::OrderedCollection => OrderedCollectionReceiver::new(elements, None),
+            Species::Set => SetReceiver::new(elements, false),
+            Species::Bag => SetReceiver::new(elements, true),
+            Species::Sorted(block) => {
+                let block = block.clone().unwrap_or_else(NilReciever::get);
+                OrderedCollectionReceiver::new(elements, Some(block))
+            }
+        }
+    }
+}
+
+/// sends through the method dictionaries, extensions of the classes
+/// implemented in Rust are found as well
+pub fn send(receiver: &Rc<dyn Receiver>, selector: &'static str, args: Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
+    let args = args.into_iter().map(Value::from_receiver).collect();
+    Value::from_receiver(receiver.clone())
+        .send(selector, args)
+        .to_receiver()
+}
+
+pub fn is_true(v: &Value) -> bool {
+    matches!(v, Value::Bool(true))
+}
+
+pub fn equal(a: &Rc<dyn Receiver>, b: &Value) -> bool {
+    Key::of(a) == Key::of_value(b)
+}
+
+/// whether `a` comes before `b`, the sort block answers it, without one
+/// it's `a <= b`
+pub fn before(block: &Rc<dyn Receiver>, a: &Rc<dyn Receiver>, b: &Rc<dyn Receiver>) -> bool {
+    let (a, b) = (Value::from_receiver(a.clone()), Value::from_receiver(b.clone()));
+    if block.class_name() == "UndefinedObject" {
+        is_true(&a.send("<=", vec![b]))
+    } else {
+        is_true(&Value::Object(block.clone()).send("value:value:", vec![a, b]))
+    }
+}
+
+/// a stable merge sort, the sort block needn't be a total order
+pub fn sort(mut items: Vec<Rc<dyn Receiver>>, block: &Rc<dyn Receiver>) -> Vec<Rc<dyn Receiver>> {
+    if items.len() < 2 {
+        return items;
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = sort(items, block).into_iter().peekable();
+    let mut right = sort(right, block).into_iter().peekable();
+    let mut sorted = vec![];
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if before(block, l, r) {
+            sorted.extend(left.next());
+        } else {
+            sorted.extend(right.next());
+        }
+    }
+    sorted.extend(left);
+    sorted.extend(right);
+    sorted
+}
+
+/// the elements of a collection given as argument
+pub fn elements_of(r: &Value) -> Vec<Rc<dyn Receiver>> {
+    match r.elements() {
+        Some(elements) => elements,
+        None => {
+            exc::signal_error("Error", &format!("a {} is not a collection", r.class_name()));
+            vec![]
+        }
+    }
+}
+
+/// the offset of the element at a Smalltalk index, which counts from 1, a
+/// signal for the ones outside
+pub fn index(len: usize, idx: &Value) -> Option<usize> {
+    match idx.as_int() {
+        Some(i) if i >= 1 && (i as usize) <= len => Some(i as usize - 1),
+        _ => {
+            exc::signal_error("SubscriptOutOfBounds", &format!("index {} out of bounds", idx));
+            None
+        }
+    }
+}
+
+/// `a Set(1 2 3)`
+pub fn write_elements(name: &str, elements: &[Rc<dyn Receiver>], stream: &Value) -> Value {
+    let article = match name.chars().next() {
+        Some('A' | 'E' | 'I' | 'O' | 'U') => "an",
+        _ => "a",
+    };
+    let items: Vec<String> = elements.iter().map(|x| format!("{}", x)).collect();
+    let a0 = StringReceiver::new(format!("{} {}({})", article, name, items.join(" ")));
+    stream.receive_message("write", vec![Value::Object(Rc::new(a0))])
+}
+
+/// the enumeration protocol, `myself` is the collection with the elements,
+/// `selected` the species `select:` answers and `collected` the one of
+/// `collect:`. `None` for other messages.
+pub fn enumerate(
+    myself: Rc<dyn Receiver>,
+    elements: Vec<Rc<dyn Receiver>>,
+    selected: Species,
+    collected: Species,
+    selector: &'static str,
+    args: &[Value],
+) -> Option<Value> {
+    if !PROTOCOL.contains(&selector) {
+        return None;
+    }
+    let element = |x: &Rc<dyn Receiver>| Value::from_receiver(x.clone());
+    let value = |x: &Rc<dyn Receiver>| args[0].send("value:", vec![element(x)]);
+    let collect = |species: Species, items: Vec<Value>| {
+        Value::Object(species.make(items.iter().map(Value::to_receiver).collect()))
+    };
+    let myself = Value::Object(myself);
+    let r = match selector {
+        "do:" => {
+            elements.iter().for_each(|x| {
+                value(x);
+            });
+            myself
+        }
+        "reverseDo:" => {
+            elements.iter().rev().for_each(|x| {
+                value(x);
+            });
+            myself
+        }
+        "do:separatedBy:" => {
+            for (idx, x) in elements.iter().enumerate() {
+                if idx > 0 {
+                    args[1].send("value", vec![]);
+                }
+                value(x);
+            }
+            myself
+        }
+        "doWithIndex:" | "withIndexDo:" => {
+            for (idx, x) in elements.iter().enumerate() {
+                args[0].send("value:value:", vec![element(x), Value::Int(idx as isize + 1)]);
+            }
+            myself
+        }
+        "collect:" => collect(collected, elements.iter().map(value).collect()),
+        "select:" => Value::Object(selected.make(elements.into_iter().filter(|x| is_true(&value(x))).collect())),
+        "reject:" => Value::Object(selected.make(elements.into_iter().filter(|x| !is_true(&value(x))).collect())),
+        "detect:ifNone:" => match elements.iter().find(|x| is_true(&value(x))) {
+            Some(x) => element(x),
+            None => args[1].send("value", vec![]),
+        },
+        "detect:" => match elements.iter().find(|x| is_true(&value(x))) {
+            Some(x) => element(x),
+            None => exc::signal_error("NotFound", "no element satisfies the block"),
+        },
+        "anySatisfy:" => Value::Bool(elements.iter().any(|x| is_true(&value(x)))),
+        "allSatisfy:" => Value::Bool(elements.iter().all(|x| is_true(&value(x)))),
+        "count:" => Value::Int(elements.iter().filter(|x| is_true(&value(x))).count() as isize),
+        "inject:into:" => elements
+            .iter()
+            .fold(args[0].clone(), |acc, x| args[1].send("value:value:", vec![acc, element(x)])),
+        "with:collect:" => {
+            let other = elements_of(&args[0]);
+            if other.len() != elements.len() {
+                return Some(exc::signal_error("Error", "collections of different sizes"));
+            }
+            let pairs = elements.iter().zip(other.iter());
+            collect(
+                collected,
+                pairs
+                    .map(|(x, y)| args[1].send("value:value:", vec![element(x), element(y)]))
+                    .collect(),
+            )
+        }
+        "includes:" => Value::Bool(elements.iter().any(|x| equal(x, &args[0]))),
+        "occurrencesOf:" => Value::Int(elements.iter().filter(|x| equal(x, &args[0])).count() as isize),
+        "size" => Value::Int(elements.len() as isize),
+        "isEmpty" => Value::Bool(elements.is_empty()),
+        "notEmpty" => Value::Bool(!elements.is_empty()),
+        "isCollection" => Value::Bool(true),
+        "asArray" => Value::Object(Species::Array.make(elements)),
+        "asOrderedCollection" => Value::Object(Species::OrderedCollection.make(elements)),
+        "asSet" => Value::Object(Species::Set.make(elements)),
+        "asBag" => Value::Object(Species::Bag.make(elements)),
+        "asSortedCollection" => Value::Object(Species::Sorted(None).make(elements)),
+        "asSortedCollection:" => Value::Object(Species::Sorted(Some(args[0].to_receiver())).make(elements)),
+        _ => return None,
+    };
+    Some(r)
+}
+
+/// the classes of the collections, they make new instances
+pub struct CollectionClass(&'static str);
+
+const CLASSES: &[&str] = &[
+    "Array",
+    "OrderedCollection",
+    "SortedCollection",
+    "Set",
+    "Bag",
+    "Dictionary",
+    "Interval",
+    "Association",
+];
+
+impl CollectionClass {
+    /// the class with this name, `None` if it isn't a collection class
+    pub fn get(name: &str) -> Option<Rc<dyn Receiver>> {
+        let name = CLASSES.iter().find(|c| **c == name)?;
+        Some(Rc::new(CollectionClass(name)))
+    }
+
+    fn species(&self) -> Species {
+        match self.0 {
+            "OrderedCollection" => Species::OrderedCollection,
+            "SortedCollection" => Species::Sorted(None),
+            "Set" => Species::Set,
+            "Bag" => Species::Bag,
+            _ => Species::Array,
+        }
+    }
+
+    /// intervals and associations are made from other arguments than
+    /// elements
+    fn understands(&self, selector: &str) -> bool {
+        let own = match self.0 {
+            "Interval" => ivl::CLASS_PROTOCOL,
+            "Association" => dct::ASSOCIATION_CLASS_PROTOCOL,
+            "SortedCollection" => ord::SORTED_CLASS_PROTOCOL,
+            _ => &[],
+        };
+        own.contains(&selector) || !matches!(self.0, "Interval" | "Association") && CLASS_PROTOCOL.contains(&selector)
+    }
+}
+
+impl Receiver for CollectionClass {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let r = match (self.0, selector) {
+            (_, "basic_write_to") => {
+                let a0 = StringReceiver::new(self.0.to_string());
+                return args[0].receive_message("write", vec![Value::Object(Rc::new(a0))]);
+            }
+            _ if !self.understands(selector) => return self.does_not_understand(selector, args),
+            ("Dictionary", "new" | "new:") => DictionaryReceiver::new(vec![]) as Rc<dyn Receiver>,
+            ("Interval", "from:to:") => return IntervalReceiver::from_args(args[0].as_int(), &args[1], None),
+            ("Interval", "from:to:by:") => {
+                return IntervalReceiver::from_args(args[0].as_int(), &args[1], Some(&args[2]))
+            }
+            ("Association", "key:value:") => AssociationReceiver::new(args[0].to_receiver(), args[1].to_receiver()),
+            ("Array", "new:") => {
+                let n = args[0].as_int().unwrap_or(0).max(0) as usize;
+                Rc::new(ArrayReceiver(vec![NilReciever::get(); n]))
+            }
+            ("SortedCollection", "sortBlock:") => Species::Sorted(Some(args[0].to_receiver())).make(vec![]),
+            ("Dictionary" | "Interval" | "Association", _) => return self.does_not_understand(selector, args),
+            (_, "new" | "new:") => self.species().make(vec![]),
+            (_, "with:" | "with:with:" | "with:with:with:" | "with:with:with:with:") => {
+                self.species().make(args.iter().map(Value::to_receiver).collect())
+            }
+            (_, "withAll:") => self.species().make(elements_of(&args[0])),
+            _ => return self.does_not_understand(selector, args),
+        };
+        Value::Object(r)
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        SelectorSet::get(&format!("{} class", self.0))
+    }
+
+    fn class_key(&self) -> ClassKey {
+        (self.0, true)
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", self.0))
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    arr::ArrayReceiver,
+    col::{self, Key, Species},
+    exc,
+    nil::NilReciever,
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
+
+/// the messages associations understand natively
+pub(crate) const ASSOCIATION_PROTOCOL: &[&str] = &["key", "value", "value:"];
+
+/// the messages the class `Association` understands natively
+pub(crate) const ASSOCIATION_CLASS_PROTOCOL: &[&str] = &["key:value:"];
+
+/// the messages dictionaries understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &[
+    "at:",
+    "at:ifAbsent:",
+    "at:ifAbsentPut:",
+    "at:put:",
+    "add:",
+    "removeKey:",
+    "removeKey:ifAbsent:",
+    "includesKey:",
+    "keys",
+    "values",
+    "associations",
+    "keysAndValuesDo:",
+    "keysDo:",
+    "associationsDo:",
+    "keyAtValue:",
+    "select:",
+    "reject:",
+    "collect:",
+];
+
+/// a key and a value, `key -> value` makes one
+pub struct AssociationReceiver {
+    key: Rc<dyn Receiver>,
+    value: RefCell<Rc<dyn Receiver>>,
+}
+
+impl AssociationReceiver {
+    pub fn new(key: Rc<dyn Receiver>, value: Rc<dyn Receiver>) -> Rc<Self> {
+        let association = Rc::new(Self {
+            key,
+            value: RefCell::new(value),
+        });
+        heap::track_receiver(&(association.clone() as Rc<dyn Receiver>));
+        association
+    }
+}
+
+impl Receiver for AssociationReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(format!("{}->{}", self.key, self.value.borrow()));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !ASSOCIATION_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "key" => Value::from_receiver(self.key.clone()),
+            "value" => Value::from_receiver(self.value.borrow().clone()),
+            "value:" => {
+                *self.value.borrow_mut() = args[0].to_receiver();
+                args[0].clone()
+            }
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Association"
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        visit(heap::address(&self.key));
+        if let Ok(value) = self.value.try_borrow() {
+            visit(heap::address(&*value));
+        }
+    }
+
+    fn clear_references(&self) {
+        *self.value.borrow_mut() = NilReciever::get();
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new("Association", "");
+        Some(
+            s.slot("key", self.key.clone())
+                .slot("value", self.value.borrow().clone()),
+        )
+    }
+}
+
+/// values looked up by keys, keys are compared like the elements of a set.
+/// It enumerates its values in the order their keys were first added.
+pub struct DictionaryReceiver {
+    myself: Weak<DictionaryReceiver>,
+    entries: RefCell<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    items: Vec<(Rc<dyn Receiver>, Rc<dyn Receiver>)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Entries {
+    fn get(&self, key: &Value) -> Option<Value> {
+        let idx = *self.index.get(&Key::of_value(key))?;
+        Some(Value::from_receiver(self.items[idx].1.clone()))
+    }
+
+    fn put(&mut self, key: Rc<dyn Receiver>, value: Rc<dyn Receiver>) {
+        match self.index.get(&Key::of(&key)) {
+            Some(&idx) => self.items[idx].1 = value,
+            None => {
+                self.index.insert(Key::of(&key), self.items.len());
+                self.items.push((key, value));
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Value) -> Option<Value> {
+        let idx = *self.index.get(&Key::of_value(key))?;
+        let (_, value) = self.items.remove(idx);
+        self.index = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, (k, _))| (Key::of(k), i))
+            .collect();
+        Some(Value::from_receiver(value))
+    }
+}
+
+impl DictionaryReceiver {
+    pub fn new(items: Vec<(Rc<dyn Receiver>, Rc<dyn Receiver>)>) -> Rc<Self> {
+        let mut entries = Entries::default();
+        items.into_iter().for_each(|(k, v)| entries.put(k, v));
+        let dictionary = Rc::new_cyclic(|myself| Self {
+            myself: myself.clone(),
+            entries: RefCell::new(entries),
+        });
+        heap::track_receiver(&(dictionary.clone() as Rc<dyn Receiver>));
+        dictionary
+    }
+
+    fn myself(&self) -> Rc<dyn Receiver> {
+        self.myself.upgrade().unwrap()
+    }
+
+    fn items(&self) -> Vec<(Rc<dyn Receiver>, Rc<dyn Receiver>)> {
+        self.entries.borrow().items.clone()
+    }
+
+    fn associations(&self) -> Vec<Rc<dyn Receiver>> {
+        let items = self.items().into_iter();
+        items.map(|(k, v)| AssociationReceiver::new(k, v) as Rc<dyn Receiver>).collect()
+    }
+
+    fn key_not_found(&self, key: &Value) -> Value {
+        exc::signal_error("KeyNotFound", &format!("key {} not found", key))
+    }
+
+    /// the entries with the values the block answers true for
+    fn select(&self, block: &Value, wanted: bool) -> Value {
+        let items = self.items().into_iter();
+        let selected = items.filter(|(_, v)| {
+            col::is_true(&block.send("value:", vec![Value::from_receiver(v.clone())])) == wanted
+        });
+        Value::Object(DictionaryReceiver::new(selected.collect()))
+    }
+
+    /// the enumeration protocol, it enumerates the values
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let values = self.items().into_iter().map(|(_, v)| v).collect();
+        match col::enumerate(self.myself(), values, Species::Bag, Species::Bag, selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+}
+
+impl Receiver for DictionaryReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                col::write_elements(self.class_name(), &self.associations(), &args[0])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.enumerate(selector, args),
+            "at:" => {
+                let value = self.entries.borrow().get(&args[0]);
+                value.unwrap_or_else(|| self.key_not_found(&args[0]))
+            }
+            "at:ifAbsent:" => {
+                let value = self.entries.borrow().get(&args[0]);
+                value.unwrap_or_else(|| args[1].send("value", vec![]))
+            }
+            "at:ifAbsentPut:" => {
+                let value = self.entries.borrow().get(&args[0]);
+                value.unwrap_or_else(|| {
+                    let value = args[1].send("value", vec![]);
+                    self.entries.borrow_mut().put(args[0].to_receiver(), value.to_receiver());
+                    value
+                })
+            }
+            "at:put:" => {
+                self.entries.borrow_mut().put(args[0].to_receiver(), args[1].to_receiver());
+                args[1].clone()
+            }
+            "add:" => {
+                let key = args[0].send("key", vec![]);
+                let value = args[0].send("value", vec![]);
+                self.entries.borrow_mut().put(key.to_receiver(), value.to_receiver());
+                args[0].clone()
+            }
+            "removeKey:" => {
+                let value = self.entries.borrow_mut().remove(&args[0]);
+                value.unwrap_or_else(|| self.key_not_found(&args[0]))
+            }
+            "removeKey:ifAbsent:" => {
+                let value = self.entries.borrow_mut().remove(&args[0]);
+                value.unwrap_or_else(|| args[1].send("value", vec![]))
+            }
+            "includesKey:" => Value::Bool(self.entries.borrow().get(&args[0]).is_some()),
+            "keys" => Value::Object(Rc::new(ArrayReceiver(self.items().into_iter().map(|(k, _)| k).collect()))),
+            "values" => Value::Object(Rc::new(ArrayReceiver(self.items().into_iter().map(|(_, v)| v).collect()))),
+            "associations" => Value::Object(Rc::new(ArrayReceiver(self.associations()))),
+            "keysAndValuesDo:" => {
+                for (k, v) in self.items() {
+                    args[0].send("value:value:", vec![Value::from_receiver(k), Value::from_receiver(v)]);
+                }
+                Value::Object(self.myself())
+            }
+            "keysDo:" => {
+                for (k, _) in self.items() {
+                    args[0].send("value:", vec![Value::from_receiver(k)]);
+                }
+                Value::Object(self.myself())
+            }
+            "associationsDo:" => {
+                for (k, v) in self.items() {
+                    args[0].send("value:", vec![Value::Object(AssociationReceiver::new(k, v))]);
+                }
+                Value::Object(self.myself())
+            }
+            "keyAtValue:" => {
+                let found = self.items().into_iter().find(|(_, v)| col::equal(v, &args[0]));
+                found.map_or(Value::Nil, |(k, _)| Value::from_receiver(k))
+            }
+            "select:" => self.select(&args[0], true),
+            "reject:" => self.select(&args[0], false),
+            "collect:" => {
+                let items = self.items().into_iter();
+                let collected = items.map(|(k, v)| {
+                    (k, args[0].send("value:", vec![Value::from_receiver(v)]).to_receiver())
+                });
+                Value::Object(DictionaryReceiver::new(collected.collect()))
+            }
+            _ => self.enumerate(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// the values, the keys are only for looking them up
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(self.items().into_iter().map(|(_, v)| v).collect())
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Dictionary"
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(entries) = self.entries.try_borrow() {
+            for (k, v) in entries.items.iter() {
+                visit(heap::address(k));
+                visit(heap::address(v));
+            }
+        }
+    }
+
+    fn clear_references(&self) {
+        *self.entries.borrow_mut() = Entries::default();
+    }
+
+    /// keys and values take turns in the slots
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new("Dictionary", "");
+        Some(
+            self.items()
+                .into_iter()
+                .fold(s, |s, (k, v)| s.slot("key", k).slot("value", v)),
+        )
+    }
+}
@@ -0,0 +1,447 @@
+//! Exceptions. Handlers installed by `on:do:` are kept on a stack, `signal`
+//! runs the innermost matching handler on top of the signaling code and the
+//! handler leaves by unwinding to the `on:do:` activation, or to the signal
+//! for `resume:`. Failures of the runtime itself (Rust panics) are handled as
+//! `Error` after the stack has been unwound.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+};
+
+use crate::{
+    heap, trace,
+    unwind::{self, Exit, Home, UnwindKind},
+};
+
+use super::{
+    cls::{ClassDef, ClassReceiver, ClassTable, InstanceReceiver},
+    int::IntReceiver,
+    msg::MessageReceiver,
+    nil::NilReciever,
+    str::{self, StringReceiver},
+    val::Value,
+    Receiver,
+};
+
+/// the messages exceptions understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "signal",
+    "signal:",
+    "messageText:",
+    "messageText",
+    "description",
+    "message",
+    "receiver",
+    "stackTrace",
+    "isResumable",
+    "return",
+    "return:",
+    "retry",
+    "resume",
+    "resume:",
+    "pass",
+    "outer",
+];
+
+/// the messages the exception classes understand natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &["signal", "signal:", ","];
+
+/// the messages exception sets understand natively
+pub(crate) const SET_PROTOCOL: &[&str] = &[","];
+
+thread_local! {
+    static HANDLERS: RefCell<Vec<Handler>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Handler {
+    home: Home,
+    filter: Rc<dyn Receiver>,
+    block: Value,
+    /// a running handler and the ones above it are not searched
+    disabled: bool,
+}
+
+/// the filters and blocks of the installed handlers, roots for the heap
+pub(crate) fn references(visit: &mut dyn FnMut(usize)) {
+    HANDLERS.with(|h| {
+        if let Ok(h) = h.try_borrow() {
+            for x in h.iter() {
+                visit(heap::address(&x.filter));
+                x.block.references(visit);
+            }
+        }
+    });
+}
+
+/// removes the handlers installed by an `on:do:` however it is left
+struct HandlerScope(usize);
+
+impl Drop for HandlerScope {
+    fn drop(&mut self) {
+        HANDLERS.with(|h| h.borrow_mut().truncate(self.0));
+    }
+}
+
+/// unwinding payload of an exception without handler
+pub struct UnhandledException(pub String);
+
+/// the predefined exception classes
+pub(crate) fn builtin_classes() -> Vec<ClassDef> {
+    vec![
+        ClassDef::new(
+            "Exception",
+            Some("Object"),
+            &["messageText", "handlerContext", "signalContext", "stackTrace"],
+            &[],
+        ),
+        ClassDef::new("Error", Some("Exception"), &[], &[]),
+        ClassDef::new("Warning", Some("Exception"), &[], &[]),
+        ClassDef::new("ArithmeticError", Some("Error"), &[], &[]),
+        ClassDef::new("ZeroDivide", Some("ArithmeticError"), &[], &[]),
+        ClassDef::new("MessageNotUnderstood", Some("Error"), &["message", "receiver"], &[]),
+        ClassDef::new("SubscriptOutOfBounds", Some("Error"), &[], &[]),
+        ClassDef::new("BlockCannotReturn", Some("Error"), &[], &[]),
+        ClassDef::new("WrongArgumentCount", Some("Error"), &[], &[]),
+        ClassDef::new("IllegalResumeAttempt", Some("Error"), &[], &[]),
+        ClassDef::new("NotFound", Some("Error"), &[], &[]),
+        ClassDef::new("KeyNotFound", Some("Error"), &[], &[]),
+        ClassDef::new("UndeclaredVariable", Some("Error"), &[], &[]),
+        ClassDef::new("CollectionIsEmpty", Some("Error"), &[], &[]),
+        ClassDef::new("FileException", Some("Error"), &[], &[]),
+        ClassDef::new("FileDoesNotExist", Some("FileException"), &[], &[]),
+    ]
+}
+
+/// creates an instance of one of the exception classes and signals it
+pub fn signal_error(class_name: &str, text: &str) -> Value {
+    let class = ClassTable::get(class_name).unwrap();
+    let exception: Rc<dyn Receiver> = InstanceReceiver::new(class);
+    exception.set_inst_var("messageText", Rc::new(StringReceiver::new(text.to_string())));
+    signal(exception)
+}
+
+/// signals that a block was evaluated with the wrong number of arguments
+pub fn wrong_argument_count(takes: usize, given: usize) -> Value {
+    let text = format!("wrong number of arguments: the block takes {}, not {}", takes, given);
+    signal_error("WrongArgumentCount", &text)
+}
+
+/// the default `doesNotUnderstand:`, signals a `MessageNotUnderstood`.
+/// Built-in receivers can't pass themselves, `receiver` is nil for them.
+pub fn does_not_understand(
+    receiver: Option<Rc<dyn Receiver>>,
+    class_name: &str,
+    selector: &'static str,
+    args: Vec<Value>,
+) -> Value {
+    let class = ClassTable::get("MessageNotUnderstood").unwrap();
+    let exception: Rc<dyn Receiver> = InstanceReceiver::new(class);
+    let text = format!("Instance of {} did not understand #{}", class_name, selector);
+    exception.set_inst_var("messageText", Rc::new(StringReceiver::new(text)));
+    exception.set_inst_var("message", Rc::new(MessageReceiver::new(selector, args)));
+    exception.set_inst_var("receiver", receiver.unwrap_or_else(NilReciever::get));
+    signal(exception)
+}
+
+/// answers the resumption value
+pub fn signal(exception: Rc<dyn Receiver>) -> Value {
+    let class = exception.class_def().unwrap();
+    let trace = trace::stack_trace().join("\n");
+    exception.set_inst_var("stackTrace", Rc::new(StringReceiver::new(trace)));
+    let found = HANDLERS.with(|h| {
+        let h = h.borrow();
+        h.iter()
+            .rposition(|x| !x.disabled && handles(&x.filter, &class))
+            .map(|idx| (idx, h[idx].home, h[idx].block.clone()))
+    });
+    let (idx, home, block) = match found {
+        Some(found) => found,
+        None => return default_action(&exception, &class),
+    };
+    let signal_home = unwind::new_home();
+    set_context(&exception, "handlerContext", home);
+    set_context(&exception, "signalContext", signal_home);
+    let flags = disable_from(idx);
+    let exit = unwind::run_as(signal_home, || {
+        let value = block.receive_message("value:", vec![Value::Object(exception.clone())]);
+        unwind::unwind_to(home, UnwindKind::Return, value)
+    });
+    restore_from(idx, flags);
+    match exit {
+        Exit::Normal(v) | Exit::Unwound(_, v) => v,
+        Exit::Failed(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// `[body] on: filter do: handler`, the filter is an exception class or
+/// an `ExceptionSet`
+pub fn on_do<F: Fn() -> Value>(body: F, filter: Value, handler: Value) -> Value {
+    let filter = filter.to_receiver();
+    if filter_classes(&filter).is_none() {
+        let text = format!("an instance of {} is not an exception class", filter.class_name());
+        return signal_error("Error", &text);
+    }
+    loop {
+        let home = unwind::new_home();
+        let exit = {
+            let _scope = HANDLERS.with(|h| {
+                let mut h = h.borrow_mut();
+                h.push(Handler {
+                    home,
+                    filter: filter.clone(),
+                    block: handler.clone(),
+                    disabled: false,
+                });
+                HandlerScope(h.len() - 1)
+            });
+            unwind::run_as(home, &body)
+        };
+        let exit = match exit {
+            Exit::Failed(payload) => {
+                // the stack is gone already, the handler runs in place of the body
+                if payload.is::<UnhandledException>() {
+                    panic::resume_unwind(payload);
+                }
+                let class = ClassTable::get("Error").unwrap();
+                if !handles(&filter, &class) {
+                    panic::resume_unwind(payload);
+                }
+                let exception: Rc<dyn Receiver> = InstanceReceiver::new(class);
+                let text = unwind::failure_message(&payload);
+                exception.set_inst_var("messageText", Rc::new(StringReceiver::new(text)));
+                set_context(&exception, "handlerContext", home);
+                unwind::run_as(home, || handler.receive_message("value:", vec![Value::Object(exception.clone())]))
+            }
+            exit => exit,
+        };
+        match exit {
+            Exit::Unwound(UnwindKind::Retry, _) => continue,
+            Exit::Normal(v) | Exit::Unwound(_, v) => return v,
+            Exit::Failed(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// `[body] ensure: cleanup`, the cleanup also runs when the body is unwound
+pub fn ensure<F: FnOnce() -> Value>(body: F, cleanup: Value) -> Value {
+    let r = panic::catch_unwind(AssertUnwindSafe(body));
+    cleanup.receive_message("value", vec![]);
+    match r {
+        Ok(v) => v,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// `[body] ifCurtailed: cleanup`, the cleanup only runs when the body is unwound
+pub fn if_curtailed<F: FnOnce() -> Value>(body: F, cleanup: Value) -> Value {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(v) => v,
+        Err(payload) => {
+            cleanup.receive_message("value", vec![]);
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// messages understood by instances of the exception classes
+pub(crate) fn primitive(
+    myself: &Rc<dyn Receiver>,
+    class: &Rc<ClassDef>,
+    selector: &'static str,
+    args: &[Value],
+) -> Option<Value> {
+    if !PROTOCOL.contains(&selector) {
+        return None;
+    }
+    let r = match selector {
+        "signal" => signal(myself.clone()),
+        "signal:" => {
+            myself.set_inst_var("messageText", args[0].to_receiver());
+            signal(myself.clone())
+        }
+        "messageText:" => {
+            myself.set_inst_var("messageText", args[0].to_receiver());
+            Value::Object(myself.clone())
+        }
+        "messageText" | "description" => Value::Object(Rc::new(StringReceiver::new(description(myself, class)))),
+        "message" | "receiver" | "stackTrace" => Value::from_receiver(myself.inst_var(selector)?),
+        "isResumable" => Value::Bool(is_resumable(class)),
+        "return" | "return:" | "retry" if !unwind::is_active(context(myself, "handlerContext")) => {
+            signal_error("IllegalResumeAttempt", "the exception has no active handler")
+        }
+        "return" => unwind::unwind_to(handler_context(myself), UnwindKind::Return, Value::Nil),
+        "return:" => unwind::unwind_to(handler_context(myself), UnwindKind::Return, args[0].clone()),
+        "retry" => unwind::unwind_to(handler_context(myself), UnwindKind::Retry, Value::Nil),
+        "resume" | "resume:" => {
+            if !is_resumable(class) {
+                let text = format!("{} is not resumable", class.name);
+                return Some(signal_error("IllegalResumeAttempt", &text));
+            }
+            if !unwind::is_active(context(myself, "signalContext")) {
+                return Some(signal_error("IllegalResumeAttempt", "the signal has already returned"));
+            }
+            let value = args.first().cloned().unwrap_or(Value::Nil);
+            unwind::unwind_to(context(myself, "signalContext"), UnwindKind::Resume, value)
+        }
+        "pass" | "outer" => {
+            let handler = context(myself, "handlerContext");
+            let signal_home = context(myself, "signalContext");
+            let value = signal(myself.clone());
+            set_context(myself, "handlerContext", handler);
+            set_context(myself, "signalContext", signal_home);
+            unwind::unwind_to(signal_home, UnwindKind::Resume, value)
+        }
+        _ => return None,
+    };
+    Some(r)
+}
+
+/// messages understood by the exception classes, `Error signal: 'text'`
+/// signals a new instance
+pub(crate) fn class_primitive(class: &Rc<ClassDef>, selector: &'static str, args: &[Value]) -> Option<Value> {
+    if !CLASS_PROTOCOL.contains(&selector) {
+        return None;
+    }
+    let r = match selector {
+        "signal" | "signal:" => {
+            let exception: Rc<dyn Receiver> = InstanceReceiver::new(class.clone());
+            exception.receive_message(selector, args.to_vec())
+        }
+        "," => {
+            let filters = vec![Rc::new(ClassReceiver(class.clone())) as Rc<dyn Receiver>, args[0].to_receiver()];
+            Value::Object(Rc::new(ExceptionSetReceiver(filters)))
+        }
+        _ => return None,
+    };
+    Some(r)
+}
+
+/// `Error, ZeroDivide`, a filter for several exception classes
+pub struct ExceptionSetReceiver(pub Vec<Rc<dyn Receiver>>);
+
+impl Receiver for ExceptionSetReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new("an ExceptionSet".to_string());
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !SET_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "," => {
+                let mut filters = self.0.clone();
+                filters.push(args[0].to_receiver());
+                Value::Object(Rc::new(ExceptionSetReceiver(filters)))
+            }
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(self.0.clone())
+    }
+
+    fn class_name(&self) -> &'static str {
+        "ExceptionSet"
+    }
+}
+
+/// the message of a failure that ended an evaluation
+pub fn failure_message(payload: &Box<dyn Any + Send>) -> String {
+    match payload.downcast_ref::<UnhandledException>() {
+        Some(UnhandledException(msg)) => msg.clone(),
+        None => unwind::failure_message(payload),
+    }
+}
+
+/// unhandled warnings resume with nil, everything else ends the evaluation
+fn default_action(exception: &Rc<dyn Receiver>, class: &Rc<ClassDef>) -> Value {
+    if class.inherits_from("Warning") {
+        return Value::Nil;
+    }
+    let mut msg = format!("Unhandled {}: {}", class.name, description(exception, class));
+    if let Some(trace) = exception.inst_var("stackTrace").and_then(|t| str::text_of(&Value::Object(t))) {
+        msg.push('\n');
+        msg.push_str(&trace);
+    }
+    panic::resume_unwind(Box::new(UnhandledException(msg)))
+}
+
+/// errors are not resumable, except for a message not understood
+fn is_resumable(class: &ClassDef) -> bool {
+    !class.inherits_from("Error") || class.inherits_from("MessageNotUnderstood")
+}
+
+/// the exception matches one of the classes of the filter or one of their subclasses
+fn handles(filter: &Rc<dyn Receiver>, class: &ClassDef) -> bool {
+    filter_classes(filter)
+        .unwrap_or_default()
+        .iter()
+        .any(|c| class.inherits_from(c.name))
+}
+
+/// the classes of an exception class or an `ExceptionSet`, `None` for
+/// anything else
+fn filter_classes(filter: &Rc<dyn Receiver>) -> Option<Vec<Rc<ClassDef>>> {
+    if let Some(set) = filter.elements().filter(|_| filter.class_name() == "ExceptionSet") {
+        return set.iter().map(filter_classes).collect::<Option<Vec<_>>>().map(|c| c.concat());
+    }
+    match (filter.class_key(), filter.class_def()) {
+        ((_, true), Some(c)) if c.inherits_from("Exception") => Some(vec![c]),
+        _ => None,
+    }
+}
+
+fn description(exception: &Rc<dyn Receiver>, class: &ClassDef) -> String {
+    match exception.inst_var("messageText").and_then(|t| str::text_of(&Value::Object(t))) {
+        Some(text) => text,
+        None => class.name.to_string(),
+    }
+}
+
+fn handler_context(exception: &Rc<dyn Receiver>) -> Home {
+    context(exception, "handlerContext")
+}
+
+fn context(exception: &Rc<dyn Receiver>, name: &str) -> Home {
+    exception
+        .inst_var(name)
+        .and_then(|v| v.as_int())
+        .map(|n| n as Home)
+        .unwrap_or(unwind::NO_HOME)
+}
+
+fn set_context(exception: &Rc<dyn Receiver>, name: &str, home: Home) {
+    exception.set_inst_var(name, Rc::new(IntReceiver::new(home as isize)));
+}
+
+/// disables the handlers from `idx` up, answers their previous flags
+fn disable_from(idx: usize) -> Vec<bool> {
+    HANDLERS.with(|h| {
+        let mut h = h.borrow_mut();
+        let old = h[idx..].iter().map(|x| x.disabled).collect();
+        for x in h[idx..].iter_mut() {
+            x.disabled = true;
+        }
+        old
+    })
+}
+
+fn restore_from(idx: usize, flags: Vec<bool>) {
+    HANDLERS.with(|h| {
+        let mut h = h.borrow_mut();
+        for (x, flag) in h[idx..].iter_mut().zip(flags) {
+            x.disabled = flag;
+        }
+    })
+}
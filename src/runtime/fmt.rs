@@ -1,6 +1,5 @@
-use super::Receiver;
-use crate::runtime::nil::NilReciever;
-use std::{rc::Rc, sync::Mutex};
+use super::{str, val::Value, Receiver};
+use std::sync::Mutex;
 
 pub struct Formatter<'a, 'b> {
     f: Mutex<&'a mut std::fmt::Formatter<'b>>,
@@ -13,22 +12,22 @@ impl<'a, 'b> Formatter<'a, 'b> {
 }
 
 impl Receiver for Formatter<'_, '_> {
-    fn receive_message(&self, selector: &'static str, _args: Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
+    fn receive_message(&self, selector: &'static str, _args: Vec<Value>) -> Value {
         match selector {
             "write" => {
                 let mut f = self.f.lock().unwrap();
-                write!(f, "{}", _args[0].as_str().unwrap()).unwrap();
-                NilReciever::get()
+                write!(f, "{}", str::text_of(&_args[0]).unwrap()).unwrap();
+                Value::Nil
             }
             _ => todo!("message {} for Formatter", selector),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
     }
 }
@@ -0,0 +1,427 @@
+//! Globals and pool dictionaries. A program declares them next to its
+//! classes, their initializers run after the classes are defined, each one
+//! after the initializers of the variables it uses.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::Display,
+    rc::Rc,
+};
+
+use super::{
+    arr::ArrayReceiver,
+    exc,
+    nil::NilReciever,
+    str::{self, StringReceiver},
+    sym::SymbolReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::{code, heap, parser::AST, tsort::TopSort, MethodContext};
+
+#[derive(Clone)]
+struct Variable {
+    value: Rc<dyn Receiver>,
+    constant: bool,
+}
+
+impl Variable {
+    fn new(constant: bool) -> Self {
+        Self {
+            value: NilReciever::get(),
+            constant,
+        }
+    }
+}
+
+type Variables = BTreeMap<&'static str, Variable>;
+
+thread_local! {
+    static GLOBALS: RefCell<Variables> = const { RefCell::new(BTreeMap::new()) };
+    static POOLS: RefCell<BTreeMap<&'static str, Variables>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// declares a global, it is nil until it is initialized or assigned
+pub fn declare(name: &'static str, constant: bool) {
+    GLOBALS.with(|g| g.borrow_mut().insert(name, Variable::new(constant)));
+}
+
+pub fn get(name: &str) -> Option<Rc<dyn Receiver>> {
+    GLOBALS.with(|g| g.borrow().get(name).map(|v| v.value.clone()))
+}
+
+/// answers false if there is no global with this name, signals an error
+/// for a constant
+pub fn assign(name: &str, value: Rc<dyn Receiver>) -> bool {
+    GLOBALS.with(|g| set(&mut g.borrow_mut(), name, value))
+}
+
+/// declares a pool dictionary, replacing an earlier one with the same name
+pub fn declare_pool(name: &'static str) {
+    POOLS.with(|p| p.borrow_mut().insert(name, BTreeMap::new()));
+}
+
+pub fn declare_pool_var(pool: &'static str, name: &'static str, constant: bool) {
+    POOLS.with(|p| {
+        p.borrow_mut()
+            .entry(pool)
+            .or_default()
+            .insert(name, Variable::new(constant))
+    });
+}
+
+pub fn pool_var(pool: &str, name: &str) -> Option<Rc<dyn Receiver>> {
+    POOLS.with(|p| p.borrow().get(pool)?.get(name).map(|v| v.value.clone()))
+}
+
+/// answers false if the pool has no variable with this name, signals an
+/// error for a constant
+pub fn assign_pool_var(pool: &str, name: &str, value: Rc<dyn Receiver>) -> bool {
+    POOLS.with(|p| match p.borrow_mut().get_mut(pool) {
+        Some(vars) => set(vars, name, value),
+        None => false,
+    })
+}
+
+/// the pool dictionary as an object, `None` if there is no pool with this name
+pub fn pool(name: &str) -> Option<Rc<dyn Receiver>> {
+    let name = POOLS.with(|p| p.borrow().get_key_value(name).map(|(k, _)| *k))?;
+    Some(Rc::new(PoolReceiver(name)))
+}
+
+/// the value of a global or a pool dictionary
+pub fn named(name: &str) -> Option<Rc<dyn Receiver>> {
+    get(name).or_else(|| pool(name))
+}
+
+/// the values of the globals and the pool variables, roots for the heap
+pub(crate) fn references(visit: &mut dyn FnMut(usize)) {
+    GLOBALS.with(|g| {
+        if let Ok(g) = g.try_borrow() {
+            g.values().for_each(|v| visit(heap::address(&v.value)));
+        }
+    });
+    POOLS.with(|p| {
+        if let Ok(p) = p.try_borrow() {
+            p.values().flat_map(|vars| vars.values()).for_each(|v| visit(heap::address(&v.value)));
+        }
+    });
+}
+
+/// the names of the globals and the pools
+pub fn names() -> Vec<&'static str> {
+    let mut names = GLOBALS.with(|g| g.borrow().keys().copied().collect::<Vec<_>>());
+    POOLS.with(|p| names.extend(p.borrow().keys()));
+    names
+}
+
+/// a variable as written to an image: its name, whether it is a constant
+/// and its value
+pub type Entry = (&'static str, bool, Rc<dyn Receiver>);
+
+fn entries(vars: &Variables) -> Vec<Entry> {
+    vars.iter().map(|(n, v)| (*n, v.constant, v.value.clone())).collect()
+}
+
+pub fn globals() -> Vec<Entry> {
+    GLOBALS.with(|g| entries(&g.borrow()))
+}
+
+/// the pools with their variables
+pub fn pools() -> Vec<(&'static str, Vec<Entry>)> {
+    POOLS.with(|p| p.borrow().iter().map(|(n, vars)| (*n, entries(vars))).collect())
+}
+
+/// declares a global read from an image with its value, constants too
+pub fn restore(name: &'static str, constant: bool, value: Rc<dyn Receiver>) {
+    GLOBALS.with(|g| g.borrow_mut().insert(name, Variable { value, constant }));
+}
+
+/// declares a pool variable read from an image with its value, constants too
+pub fn restore_pool_var(pool: &'static str, name: &'static str, constant: bool, value: Rc<dyn Receiver>) {
+    POOLS.with(|p| {
+        p.borrow_mut()
+            .entry(pool)
+            .or_default()
+            .insert(name, Variable { value, constant })
+    });
+}
+
+fn set(vars: &mut Variables, name: &str, value: Rc<dyn Receiver>) -> bool {
+    match vars.get_mut(name) {
+        Some(v) if v.constant => {
+            exc::signal_error("Error", &format!("{} is a constant", name));
+            true
+        }
+        Some(v) => {
+            v.value = value;
+            true
+        }
+        None => false,
+    }
+}
+
+/// the value of a constant is set once, by its initializer
+fn initialize(target: Target, value: Rc<dyn Receiver>) {
+    let variable = |vars: &mut Variables, name| {
+        if let Some(v) = vars.get_mut(name) {
+            v.value = value.clone();
+        }
+    };
+    match target {
+        Target::Global(name) => GLOBALS.with(|g| variable(&mut g.borrow_mut(), name)),
+        Target::PoolVar(pool, name) => POOLS.with(|p| {
+            if let Some(vars) = p.borrow_mut().get_mut(pool) {
+                variable(vars, name)
+            }
+        }),
+        Target::Program => {}
+    }
+}
+
+/// the messages pool dictionaries understand natively
+pub(crate) const POOL_PROTOCOL: &[&str] = &["at:", "at:ifAbsent:", "at:put:", "includesKey:", "keys", "name"];
+
+/// a pool dictionary, the classes importing it see its variables like
+/// their class variables
+pub struct PoolReceiver(&'static str);
+
+impl PoolReceiver {
+    fn key(&self, key: &Value) -> String {
+        str::text_of(key).unwrap_or_else(|| key.to_string())
+    }
+}
+
+impl Receiver for PoolReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(self.0.to_string());
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !POOL_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "at:" | "at:ifAbsent:" => match (pool_var(self.0, &self.key(&args[0])), args.get(1)) {
+                (Some(v), _) => Value::from_receiver(v),
+                (None, Some(block)) => block.send("value", vec![]),
+                (None, None) => exc::signal_error("KeyNotFound", &format!("key {} not found", args[0])),
+            },
+            "at:put:" => {
+                if !assign_pool_var(self.0, &self.key(&args[0]), args[1].to_receiver()) {
+                    let text = format!("{} has no variable {}", self.0, args[0]);
+                    return exc::signal_error("KeyNotFound", &text);
+                }
+                args[1].clone()
+            }
+            "includesKey:" => Value::Bool(pool_var(self.0, &self.key(&args[0])).is_some()),
+            "keys" => {
+                let names = POOLS.with(|p| p.borrow().get(self.0).map(|v| v.keys().copied().collect::<Vec<_>>()));
+                let keys = names.unwrap_or_default().into_iter();
+                let keys = keys.map(|n| Rc::new(SymbolReceiver::new(n)) as Rc<dyn Receiver>);
+                Value::Object(Rc::new(ArrayReceiver(keys.collect())))
+            }
+            "name" => Value::Object(Rc::new(StringReceiver::new(self.0.to_string()))),
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "PoolDictionary"
+    }
+}
+
+/// what an initializer initializes
+#[derive(Clone, Copy)]
+enum Target {
+    Global(&'static str),
+    PoolVar(&'static str, &'static str),
+    Program,
+}
+
+/// a global, a pool variable or a program initializer with the names it uses
+struct Initializer {
+    target: Target,
+    code: AST,
+    uses: BTreeSet<&'static str>,
+}
+
+#[derive(Debug)]
+pub struct InitializationError(pub String);
+
+impl Display for InitializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for InitializationError {}
+
+/// the globals, pools and program initializers of a program
+#[derive(Default)]
+pub struct Program {
+    initializers: Vec<Initializer>,
+}
+
+impl Program {
+    /// declares a global or a pool and remembers the initializers, other
+    /// elements are ignored
+    pub fn add(&mut self, element: &AST) {
+        match element {
+            AST::Global { name, constant, init } => {
+                declare(name, *constant);
+                self.add_initializer(Target::Global(name), init.as_deref());
+            }
+            AST::Pool { name, vars } => {
+                declare_pool(name);
+                for v in vars {
+                    if let AST::Global {
+                        name: var,
+                        constant,
+                        init,
+                    } = v
+                    {
+                        declare_pool_var(name, var, *constant);
+                        self.add_initializer(Target::PoolVar(name, var), init.as_deref());
+                    }
+                }
+            }
+            AST::Initializer(code) => self.add_initializer(Target::Program, Some(code)),
+            _ => {}
+        }
+    }
+
+    fn add_initializer(&mut self, target: Target, code: Option<&AST>) {
+        if let Some(code) = code {
+            let mut uses = BTreeSet::new();
+            names_used(code, &mut uses);
+            self.initializers.push(Initializer {
+                target,
+                code: code.clone(),
+                uses,
+            });
+        }
+    }
+
+    /// runs the initializers, the ones of the variables an initializer uses
+    /// before it and program initializers in the order they were given.
+    /// Nothing runs if the initializers depend on each other in a circle.
+    pub fn initialize(self) -> Result<(), InitializationError> {
+        let order = self.order()?;
+        for idx in order {
+            let initializer = &self.initializers[idx];
+            let ctx = MethodContext::new();
+            if let Target::PoolVar(pool, _) = initializer.target {
+                for name in initializer.uses.iter() {
+                    if let Some(v) = pool_var(pool, name) {
+                        ctx.variables().borrow_mut().insert(name, Value::from_receiver(v));
+                    }
+                }
+            }
+            let value = code::run_initializer(&initializer.code, ctx)
+                .map_err(|e| InitializationError(format!("{}: {}", initializer.target, e)))?;
+            initialize(initializer.target, value.to_receiver());
+        }
+        Ok(())
+    }
+
+    /// the indices of the initializers in the order they run, node 0 is
+    /// before all of them so that every initializer is sorted
+    fn order(&self) -> Result<Vec<usize>, InitializationError> {
+        let mut tsort = TopSort::new();
+        let mut program = 0;
+        for (idx, initializer) in self.initializers.iter().enumerate() {
+            tsort.add(0, idx + 1);
+            if let Target::Program = initializer.target {
+                tsort.add(program, idx + 1);
+                program = idx + 1;
+            }
+            for (pred, other) in self.initializers.iter().enumerate() {
+                if self.depends(initializer, other) {
+                    tsort.add(pred + 1, idx + 1);
+                }
+            }
+        }
+        let unsorted = tsort.unsorted();
+        if !unsorted.is_empty() {
+            let names: Vec<String> = unsorted
+                .iter()
+                .map(|idx| self.initializers[idx - 1].target.to_string())
+                .collect();
+            return Err(InitializationError(format!(
+                "circular initialization of {}",
+                names.join(", ")
+            )));
+        }
+        Ok(tsort.sorted().into_iter().skip(1).map(|idx| idx - 1).collect())
+    }
+
+    /// whether the initializer uses the variable the other one initializes,
+    /// the variables of a pool are used by their name inside the pool and
+    /// through the pool outside
+    fn depends(&self, initializer: &Initializer, other: &Initializer) -> bool {
+        match (initializer.target, other.target) {
+            (_, Target::Program) => false,
+            (Target::PoolVar(pool, _), Target::PoolVar(p, name)) if pool == p => initializer.uses.contains(name),
+            (_, Target::PoolVar(pool, _)) => initializer.uses.contains(pool),
+            (_, Target::Global(name)) => initializer.uses.contains(name),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Global(name) => write!(f, "{}", name),
+            Target::PoolVar(pool, name) => write!(f, "{}.{}", pool, name),
+            Target::Program => write!(f, "a program initializer"),
+        }
+    }
+}
+
+/// the variables the code refers to, temporaries and block arguments
+/// excluded
+fn names_used(code: &AST, names: &mut BTreeSet<&'static str>) {
+    let mut declared = BTreeSet::new();
+    collect_names(code, names, &mut declared);
+    names.retain(|n| !declared.contains(n));
+}
+
+fn collect_names(code: &AST, names: &mut BTreeSet<&'static str>, declared: &mut BTreeSet<&'static str>) {
+    match code {
+        AST::Variable(name, _) => {
+            names.insert(name);
+        }
+        AST::Method { temps, body, .. } => {
+            declared.extend(temps);
+            collect_names(body, names, declared);
+        }
+        AST::Block {
+            params, temps, body, ..
+        } => {
+            declared.extend(params.iter().chain(temps));
+            collect_names(body, names, declared);
+        }
+        AST::Return(x) => collect_names(x, names, declared),
+        AST::Assign(target, x) => {
+            collect_names(target, names, declared);
+            collect_names(x, names, declared);
+        }
+        AST::Statements(v) | AST::LiteralArray(v) => v.iter().for_each(|x| collect_names(x, names, declared)),
+        AST::Table(v) => v.iter().for_each(|x| collect_names(x, names, declared)),
+        AST::InvokeSequence(x, v) | AST::InvokeCascade(x, v) => {
+            collect_names(x, names, declared);
+            v.iter().for_each(|x| collect_names(x, names, declared));
+        }
+        AST::Message { args, .. } => args.iter().for_each(|x| collect_names(x, names, declared)),
+        _ => {}
+    }
+}
@@ -1,12 +1,24 @@
 use core::panic;
 use std::rc::Rc;
 
-use super::{
-    boo::{FalseReceiver, TrueReceiver},
-    pnt::PointReceiver,
-    str::StringReceiver,
-    Receiver,
-};
+use num_bigint::BigInt;
+
+use super::{exc, ivl::IntervalReceiver, num::Number, pnt::PointReceiver, str::{self, StringReceiver}, val::Value, Receiver};
+use crate::image::Snapshot;
+
+/// the messages the class `Integer` understands natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &["readFrom:ifFail:"];
+
+/// the messages integers understand natively besides those of all numbers
+pub(crate) const PROTOCOL: &[&str] = &[
+    "@",
+    "to:",
+    "to:by:",
+    "to:do:",
+    "to:by:do:",
+    "timesRepeat:",
+    "asCharacter",
+];
 
 pub struct IntMetaReceiver;
 
@@ -16,23 +28,36 @@ impl Receiver for IntMetaReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(String::from("Integer"));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !CLASS_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
             "readFrom:ifFail:" => {
-                let n: isize = isize::from_str_radix(args[0].as_str().unwrap(), 10).unwrap();
-                Rc::new(IntReceiver(n))
+                let n: BigInt = str::text_of(&args[0]).unwrap().parse().unwrap();
+                Number::Large(n).into_value()
             }
-            _ => todo!("int meta select {}", selector),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        Some("Integer")
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Integer class"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", "Integer"))
     }
 }
 
@@ -40,53 +65,74 @@ impl IntReceiver {
     pub fn new(n: isize) -> Self {
         Self(n)
     }
+
+    /// the protocol of integers only, `None` for other messages
+    fn receive(&self, selector: &'static str, args: &[Value]) -> Option<Value> {
+        if !PROTOCOL.contains(&selector) {
+            return None;
+        }
+        let r = match selector {
+            "@" => Value::Object(Rc::new(PointReceiver::new(self.0, args[0].as_int().unwrap()))),
+            "to:" => IntervalReceiver::from_args(Some(self.0), &args[0], None),
+            "to:by:" => IntervalReceiver::from_args(Some(self.0), &args[0], Some(&args[1])),
+            "to:do:" | "to:by:do:" => {
+                let (by, block) = match args {
+                    [_, by, block] => (Some(by), block),
+                    _ => (None, &args[1]),
+                };
+                let interval = IntervalReceiver::from_args(Some(self.0), &args[0], by);
+                interval.receive_message("do:", vec![block.clone()]);
+                Value::Int(self.0)
+            }
+            "asCharacter" => match u32::try_from(self.0).ok().and_then(char::from_u32) {
+                Some(c) => Value::Char(c),
+                None => exc::signal_error("Error", &format!("{} is not a code point", self.0)),
+            },
+            "timesRepeat:" => {
+                for _ in 0..self.0 {
+                    args[0].receive_message("value", vec![]);
+                }
+                Value::Int(self.0)
+            }
+            _ => return None,
+        };
+        Some(r)
+    }
 }
 
 impl Receiver for IntReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
-        match selector {
-            "==" => {
-                if self.0 == args[0].as_int().unwrap() {
-                    TrueReceiver::get()
-                } else {
-                    FalseReceiver::get()
-                }
-            }
-            "+" => Rc::new(IntReceiver(self.0 + args[0].as_int().unwrap())),
-            "*" => Rc::new(IntReceiver(self.0 * args[0].as_int().unwrap())),
-            "<" => {
-                if self.0 < args[0].as_int().unwrap() {
-                    TrueReceiver::get()
-                } else {
-                    FalseReceiver::get()
-                }
-            }
-            ">" => {
-                if self.0 > args[0].as_int().unwrap() {
-                    TrueReceiver::get()
-                } else {
-                    FalseReceiver::get()
-                }
-            }
-            "asString" => Rc::new(StringReceiver::new(format!("{}", self.0))),
-            "basic_write_to" => {
-                let a0 = StringReceiver::new(format!("{}", self.0));
-                args[0].receive_message("write", vec![Rc::new(a0)])
-            }
-            "@" => Rc::new(PointReceiver::new(self.0, args[0].as_int().unwrap())),
-            _ => todo!("message '{}' for int", selector),
+        args: Vec<Value>,
+    ) -> Value {
+        let r = self
+            .receive(selector, &args)
+            .or_else(|| Number::Small(self.0).receive(selector, &args));
+        match r {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
         Some(self.0)
     }
+
+    fn as_number(&self) -> Option<Number> {
+        Some(Number::Small(self.0))
+    }
+
     fn as_str(&self) -> Option<&'static str> {
         panic!("use asString instead.")
         // Some(SelectorSet::get(format!("{}", self.0).as_str()))
     }
+
+    fn class_name(&self) -> &'static str {
+        "SmallInteger"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("SmallInteger", self.0.to_string()))
+    }
 }
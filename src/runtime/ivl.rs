@@ -0,0 +1,121 @@
+use std::rc::Rc;
+
+use super::{
+    col::{self, Species},
+    exc,
+    int::IntReceiver,
+    str::StringReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::image::Snapshot;
+
+/// the messages the class `Interval` understands natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &["from:to:", "from:to:by:"];
+
+/// the messages intervals understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &["first", "last", "increment", "at:"];
+
+/// the integers from `from` to `to` in steps of `by`, `1 to: 10` makes one
+pub struct IntervalReceiver {
+    from: isize,
+    to: isize,
+    by: isize,
+}
+
+impl IntervalReceiver {
+    pub fn new(from: isize, to: isize, by: isize) -> Self {
+        Self { from, to, by }
+    }
+
+    /// the interval of `from to: to by: by`, the bounds must be integers
+    pub fn from_args(from: Option<isize>, to: &Value, by: Option<&Value>) -> Value {
+        let by = by.map_or(Some(1), |b| b.as_int());
+        match (from, to.as_int(), by) {
+            (_, _, Some(0)) => exc::signal_error("Error", "the step of an interval can't be zero"),
+            (Some(from), Some(to), Some(by)) => Value::Object(Rc::new(Self::new(from, to, by))),
+            _ => exc::signal_error("Error", "the bounds of an interval must be integers"),
+        }
+    }
+
+    fn values(&self) -> Vec<isize> {
+        let mut values = vec![];
+        let mut i = self.from;
+        while (self.by > 0 && i <= self.to) || (self.by < 0 && i >= self.to) {
+            values.push(i);
+            i += self.by;
+        }
+        values
+    }
+
+    /// intervals don't change, an equal one stands in for the receiver
+    fn myself(&self) -> Rc<dyn Receiver> {
+        Rc::new(Self::new(self.from, self.to, self.by))
+    }
+
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let elements = self.elements().unwrap_or_default();
+        match col::enumerate(self.myself(), elements, Species::Array, Species::Array, selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+}
+
+impl Receiver for IntervalReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = match self.by {
+                    1 => format!("({} to: {})", self.from, self.to),
+                    by => format!("({} to: {} by: {})", self.from, self.to, by),
+                };
+                args[0].receive_message("write", vec![Value::Object(Rc::new(StringReceiver::new(a0)))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.enumerate(selector, args),
+            "first" => Value::Int(self.from),
+            "last" => match self.values().last() {
+                Some(last) => Value::Int(*last),
+                None => exc::signal_error("CollectionIsEmpty", "the Interval is empty"),
+            },
+            "increment" => Value::Int(self.by),
+            "at:" => {
+                let values = self.values();
+                match col::index(values.len(), &args[0]) {
+                    Some(idx) => Value::Int(values[idx]),
+                    None => Value::Nil,
+                }
+            }
+            _ => self.enumerate(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(
+            self.values()
+                .into_iter()
+                .map(|i| Rc::new(IntReceiver::new(i)) as Rc<dyn Receiver>)
+                .collect(),
+        )
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Interval"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new(
+            "Interval",
+            format!("{} {} {}", self.from, self.to, self.by),
+        ))
+    }
+}
@@ -0,0 +1,57 @@
+use std::rc::Rc;
+
+use super::{arr::ArrayReceiver, str::StringReceiver, sym::SymbolReceiver, val::Value, Receiver};
+use crate::image::Snapshot;
+
+/// the messages a message understands natively
+pub(crate) const PROTOCOL: &[&str] = &["selector", "arguments", "argument", "sendTo:"];
+
+/// a reified message send, the argument of `doesNotUnderstand:`
+pub struct MessageReceiver {
+    selector: &'static str,
+    args: Vec<Value>,
+}
+
+impl MessageReceiver {
+    pub fn new(selector: &'static str, args: Vec<Value>) -> Self {
+        Self { selector, args }
+    }
+}
+
+impl Receiver for MessageReceiver {
+    fn receive_message(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(format!("#{}", self.selector));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "selector" => Value::Object(Rc::new(SymbolReceiver::new(self.selector))),
+            "arguments" => Value::Object(Rc::new(ArrayReceiver(self.args.iter().map(Value::to_receiver).collect()))),
+            "argument" => self.args.first().cloned().unwrap_or(Value::Nil),
+            "sendTo:" => args[0].receive_message(self.selector, self.args.clone()),
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Message"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new("Message", self.selector);
+        Some(self.args.iter().fold(s, |s, x| s.slot("", x.to_receiver())))
+    }
+}
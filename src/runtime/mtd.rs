@@ -0,0 +1,365 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use num_integer::Integer;
+
+use super::{
+    cls::{ClassDef, ClassTable},
+    dct::AssociationReceiver,
+    exc, rfl,
+    sel::Selector,
+    val::Value,
+};
+use crate::{
+    code::{CompileError, CompiledMethod, MethodCache},
+    parser::AST,
+    MethodContext,
+};
+
+/// a method implemented in Rust, it answers `None` if it fails and the
+/// receiver handles the message itself
+pub type Primitive = fn(&Value, &[Value]) -> Option<Value>;
+
+#[derive(Clone)]
+pub enum Method {
+    Primitive(Primitive),
+    /// compiled code, with the class defining it and the side for the
+    /// methods of user defined classes
+    Compiled(Rc<CompiledMethod>, Option<(Rc<ClassDef>, bool)>),
+}
+
+/// a class as methods are looked up in it, its name and whether it is the
+/// class side
+pub type ClassKey = (&'static str, bool);
+
+/// the classes a send site remembers before it stops caching
+const POLYMORPHIC: usize = 4;
+
+thread_local! {
+    static DICTIONARIES: RefCell<HashMap<ClassKey, HashMap<Selector, Method>>> = RefCell::new(primitives());
+}
+
+/// counts the changes of methods, shared by all threads since a change costs
+/// the others no more than a lookup
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// adds a method to the dictionary of a class, it is found before the
+/// methods the receivers implement themselves
+pub fn define(class: ClassKey, selector: Selector, method: Method) {
+    DICTIONARIES.with(|d| d.borrow_mut().entry(class).or_default().insert(selector, method));
+    changed();
+}
+
+/// answers whether the class had the method
+pub fn remove(class: ClassKey, selector: Selector) -> bool {
+    let removed = DICTIONARIES.with(|d| d.borrow_mut().get_mut(&class).and_then(|m| m.remove(&selector)));
+    changed();
+    removed.is_some()
+}
+
+/// drops what the send sites remember, called whenever a method changes
+pub fn changed() {
+    EPOCH.fetch_add(1, Ordering::Relaxed);
+}
+
+/// the selectors of a class dictionary, sorted
+pub fn selectors(class: ClassKey) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = DICTIONARIES.with(|d| {
+        d.borrow()
+            .get(&class)
+            .map(|m| m.keys().map(|s| s.name()).collect())
+            .unwrap_or_default()
+    });
+    names.sort();
+    names
+}
+
+/// the classes implemented in Rust, methods can be added to them
+const BUILTINS: &[&str] = &[
+    "Object",
+    "Magnitude",
+    "Number",
+    "Integer",
+    "SmallInteger",
+    "LargeInteger",
+    "Fraction",
+    "Float",
+    "Character",
+    "Boolean",
+    "True",
+    "False",
+    "UndefinedObject",
+    "Collection",
+    "ArrayedCollection",
+    "Array",
+    "ByteArray",
+    "String",
+    "Symbol",
+    "BlockClosure",
+    "Point",
+    "Stream",
+    "PositionableStream",
+    "ReadStream",
+    "WriteStream",
+    "ReadWriteStream",
+    "FileStream",
+    "Message",
+    "SystemDictionary",
+    "PoolDictionary",
+    "SequenceableCollection",
+    "OrderedCollection",
+    "SortedCollection",
+    "Interval",
+    "HashedCollection",
+    "Set",
+    "Bag",
+    "Dictionary",
+    "LookupKey",
+    "Association",
+];
+
+/// the name of the class implemented in Rust, `None` for other names
+pub fn builtin(name: &str) -> Option<&'static str> {
+    BUILTINS.iter().find(|b| **b == name).copied()
+}
+
+/// the names of the classes implemented in Rust
+pub fn builtins() -> &'static [&'static str] {
+    BUILTINS
+}
+
+/// compiles a method into the dictionary of a class implemented in Rust,
+/// answers its selector
+pub fn define_method(class: &'static str, class_side: bool, method: &AST) -> Result<&'static str, CompileError> {
+    let AST::Method { name, .. } = method else {
+        panic!("not a method: {:?}", method)
+    };
+    MethodCache::forget(class, class_side, Some(name));
+    let code = MethodCache::lookup_or_compile(class, class_side, method)?;
+    define((class, class_side), Selector::get(name), Method::Compiled(code, None));
+    Ok(name)
+}
+
+/// the superclasses of the classes implemented in Rust
+pub(crate) fn builtin_superclass(name: &str) -> Option<&'static str> {
+    let superclass = match name {
+        "SmallInteger" | "LargeInteger" => "Integer",
+        "Integer" | "Fraction" | "Float" => "Number",
+        "Number" | "Character" => "Magnitude",
+        "Symbol" => "String",
+        "String" | "Array" | "ByteArray" => "ArrayedCollection",
+        "SortedCollection" => "OrderedCollection",
+        "ArrayedCollection" | "OrderedCollection" | "Interval" => "SequenceableCollection",
+        "Set" | "Dictionary" => "HashedCollection",
+        "SequenceableCollection" | "HashedCollection" | "Bag" => "Collection",
+        "Association" => "LookupKey",
+        "LookupKey" => "Magnitude",
+        "True" | "False" => "Boolean",
+        "FileStream" => "ReadWriteStream",
+        "ReadWriteStream" => "WriteStream",
+        "ReadStream" | "WriteStream" => "PositionableStream",
+        "PositionableStream" => "Stream",
+        "Object" => return None,
+        _ => "Object",
+    };
+    Some(superclass)
+}
+
+/// finds the method in the dictionary of the class or one of its
+/// superclasses, the methods of user defined classes are compiled on the way
+pub fn lookup(class: ClassKey, selector: Selector) -> Option<Method> {
+    let (mut name, class_side) = class;
+    loop {
+        let found = DICTIONARIES.with(|d| {
+            d.borrow()
+                .get(&(name, class_side))
+                .and_then(|m| m.get(&selector))
+                .cloned()
+        });
+        if found.is_some() {
+            return found;
+        }
+        name = match ClassTable::get(name) {
+            Some(def) => {
+                if let Some(ast) = def.own_method(selector.name(), class_side) {
+                    // a method that doesn't compile is not understood after the error
+                    match MethodCache::lookup_or_compile(def.name, class_side, &ast) {
+                        Ok(code) => return Some(Method::Compiled(code, Some((def, class_side)))),
+                        Err(e) => {
+                            exc::signal_error("Error", &e.to_string());
+                            return None;
+                        }
+                    }
+                }
+                def.superclass.or_else(|| builtin_superclass(name))?
+            }
+            None => builtin_superclass(name)?,
+        };
+    }
+}
+
+/// sends a message without a send site
+pub fn send(receiver: &Value, selector: &'static str, args: Vec<Value>) -> Value {
+    let method = lookup(receiver.class_key(), Selector::get(selector));
+    invoke(method, receiver, selector, args)
+}
+
+fn invoke(method: Option<Method>, receiver: &Value, selector: &'static str, args: Vec<Value>) -> Value {
+    match method {
+        Some(Method::Primitive(f)) => {
+            if let Some(v) = f(receiver, &args) {
+                return v;
+            }
+        }
+        Some(Method::Compiled(code, class)) => {
+            let ctx = MethodContext::for_method(receiver.to_receiver(), class, args);
+            return code.run(ctx);
+        }
+        None => {}
+    }
+    receiver.receive_message(selector, args)
+}
+
+/// a send of compiled code, it remembers the methods found for the classes
+/// of its receivers
+pub struct SendSite {
+    selector: &'static str,
+    id: Selector,
+    cache: RefCell<InlineCache>,
+}
+
+struct InlineCache {
+    /// the methods remembered are valid as long as no method changed since
+    epoch: u64,
+    entries: Vec<(ClassKey, Option<Method>)>,
+}
+
+impl SendSite {
+    pub fn new(selector: &str) -> Self {
+        let id = Selector::get(selector);
+        Self {
+            selector: id.name(),
+            id,
+            cache: RefCell::new(InlineCache {
+                epoch: 0,
+                entries: vec![],
+            }),
+        }
+    }
+
+    pub fn send(&self, receiver: &Value, args: Vec<Value>) -> Value {
+        let method = self.method_for(receiver.class_key());
+        invoke(method, receiver, self.selector, args)
+    }
+
+    fn method_for(&self, class: ClassKey) -> Option<Method> {
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        {
+            let mut cache = self.cache.borrow_mut();
+            if cache.epoch != epoch {
+                cache.epoch = epoch;
+                cache.entries.clear();
+            }
+            if let Some((_, m)) = cache.entries.iter().find(|(c, _)| same_class(*c, class)) {
+                return m.clone();
+            }
+        }
+        let method = lookup(class, self.id);
+        // a send site seeing more classes is megamorphic, it looks them up every time
+        let mut cache = self.cache.borrow_mut();
+        if cache.epoch == EPOCH.load(Ordering::Relaxed) && cache.entries.len() < POLYMORPHIC {
+            cache.entries.push((class, method.clone()));
+        }
+        method
+    }
+}
+
+/// class names are mostly the same literal, comparing the text is the exception
+fn same_class(a: ClassKey, b: ClassKey) -> bool {
+    a.1 == b.1 && (std::ptr::eq(a.0, b.0) || a.0 == b.0)
+}
+
+/// the methods implemented in Rust that are part of the dictionaries
+fn primitives() -> HashMap<ClassKey, HashMap<Selector, Method>> {
+    let small_int: &[(&str, Primitive)] = &[
+        ("+", |r, a| small_int(r, a, |x, y| x.checked_add(y).map(Value::Int))),
+        ("-", |r, a| small_int(r, a, |x, y| x.checked_sub(y).map(Value::Int))),
+        ("*", |r, a| small_int(r, a, |x, y| x.checked_mul(y).map(Value::Int))),
+        ("//", |r, a| {
+            small_int(r, a, |x, y| {
+                divisible(x, y).then(|| Value::Int(Integer::div_floor(&x, &y)))
+            })
+        }),
+        ("\\\\", |r, a| {
+            small_int(r, a, |x, y| {
+                divisible(x, y).then(|| Value::Int(Integer::mod_floor(&x, &y)))
+            })
+        }),
+        ("<", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x < y)))),
+        (">", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x > y)))),
+        ("<=", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x <= y)))),
+        (">=", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x >= y)))),
+        ("=", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x == y)))),
+        ("==", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x == y)))),
+        ("~=", |r, a| small_int(r, a, |x, y| Some(Value::Bool(x != y)))),
+    ];
+    let object: &[(&str, Primitive)] = &[
+        ("yourself", |r, _| Some(r.clone())),
+        ("isNil", |r, _| Some(Value::Bool(matches!(r, Value::Nil)))),
+        ("notNil", |r, _| Some(Value::Bool(!matches!(r, Value::Nil)))),
+        ("ifNil:", |r, a| Some(if_nil(r, Some(&a[0]), None))),
+        ("ifNotNil:", |r, a| Some(if_nil(r, None, Some(&a[0])))),
+        ("ifNil:ifNotNil:", |r, a| Some(if_nil(r, Some(&a[0]), Some(&a[1])))),
+        ("ifNotNil:ifNil:", |r, a| Some(if_nil(r, Some(&a[1]), Some(&a[0])))),
+        ("->", |r, a| Some(Value::Object(AssociationReceiver::new(r.to_receiver(), a[0].to_receiver())))),
+    ];
+    let reflective = rfl::primitives();
+    let class_side = [reflective.as_slice(), &rfl::class_primitives()].concat();
+    let object = [object, &reflective].concat();
+    let mut d = HashMap::new();
+    for (class, methods) in [
+        (("SmallInteger", false), small_int),
+        (("Object", false), &object),
+        (("Object", true), &class_side),
+    ] {
+        let methods = methods
+            .iter()
+            .map(|(s, f)| (Selector::get(s), Method::Primitive(*f)))
+            .collect();
+        d.insert(class, methods);
+    }
+    d
+}
+
+/// the `ifNil:` family, the block for objects other than nil may take the
+/// object as argument. Without a block nil answers nil and others themselves.
+fn if_nil(r: &Value, nil_block: Option<&Value>, not_nil_block: Option<&Value>) -> Value {
+    match (r, nil_block, not_nil_block) {
+        (Value::Nil, Some(block), _) => send(block, "value", vec![]),
+        (Value::Nil, None, _) => Value::Nil,
+        (_, _, Some(block)) if matches!(send(block, "numArgs", vec![]), Value::Int(1)) => {
+            send(block, "value:", vec![r.clone()])
+        }
+        (_, _, Some(block)) => send(block, "value", vec![]),
+        (_, _, None) => r.clone(),
+    }
+}
+
+/// a binary operation of two small integers, `None` if the argument is
+/// something else or the result doesn't fit into a word
+fn small_int(r: &Value, args: &[Value], op: fn(isize, isize) -> Option<Value>) -> Option<Value> {
+    match (r, args) {
+        (Value::Int(x), [Value::Int(y)]) => op(*x, *y),
+        _ => None,
+    }
+}
+
+/// division by zero signals, and the one division that overflows goes to
+/// large integers
+fn divisible(x: isize, y: isize) -> bool {
+    y != 0 && !(x == isize::MIN && y == -1)
+}
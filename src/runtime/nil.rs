@@ -2,7 +2,8 @@ use std::rc::Rc;
 
 use once_cell::sync::Lazy;
 
-use super::{Object, ObjectPtr, Receiver, str::StringReceiver};
+use super::{Object, ObjectPtr, Receiver, str::StringReceiver, val::Value};
+use crate::image::Snapshot;
 
 pub static NIL: Lazy<ObjectPtr> = Lazy::new(|| Object::new());
 
@@ -12,22 +13,30 @@ impl Receiver for NilReciever {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
             "basic_write_to" => {
                 let a0 = StringReceiver::new(format!("Nil"));
-                args[0].receive_message("write", vec![Rc::new(a0)])
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            _ => todo!("implement {} for nil", selector),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "UndefinedObject"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("nil", ""))
     }
 }
 // pub static NIL_RECIEVER: Lazy<Rc<dyn Receiver>> = Lazy::new(|| Rc::new(NilReciever));
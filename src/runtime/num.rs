@@ -0,0 +1,499 @@
+use std::{cmp::Ordering, fmt::Display, rc::Rc};
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+
+use super::{exc, int::IntReceiver, str::StringReceiver, val::Value, Receiver};
+use crate::image::Snapshot;
+
+/// the messages all numbers understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "+",
+    "-",
+    "*",
+    "/",
+    "//",
+    "\\\\",
+    "quo:",
+    "rem:",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "=",
+    "~=",
+    "==",
+    "max:",
+    "min:",
+    "abs",
+    "negated",
+    "squared",
+    "sqrt",
+    "reciprocal",
+    "asFloat",
+    "asInteger",
+    "truncated",
+    "rounded",
+    "numerator",
+    "denominator",
+    "isZero",
+    "asString",
+    "printString",
+];
+
+/// a value of the numeric tower, ordered by generality:
+/// small integers, large integers, fractions and floats.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Small(isize),
+    Large(BigInt),
+    Fraction(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    /// answers the simplest representation of the value, large integers
+    /// that fit into a machine word become small and whole fractions
+    /// become integers
+    pub fn normalize(self) -> Number {
+        match self {
+            Number::Large(n) => match n.to_isize() {
+                Some(s) => Number::Small(s),
+                None => Number::Large(n),
+            },
+            Number::Fraction(f) => {
+                if f.is_integer() {
+                    Number::Large(f.to_integer()).normalize()
+                } else {
+                    Number::Fraction(f)
+                }
+            }
+            n => n,
+        }
+    }
+
+    pub fn into_receiver(self) -> Rc<dyn Receiver> {
+        match self.normalize() {
+            Number::Small(n) => Rc::new(IntReceiver::new(n)),
+            Number::Large(n) => Rc::new(LargeIntReceiver(n)),
+            Number::Fraction(f) => Rc::new(FractionReceiver(f)),
+            Number::Float(f) => Rc::new(FloatReceiver(f)),
+        }
+    }
+
+    /// small integers are immediates
+    pub fn into_value(self) -> Value {
+        match self.normalize() {
+            Number::Small(n) => Value::Int(n),
+            n => Value::Object(n.into_receiver()),
+        }
+    }
+
+    fn generality(&self) -> u8 {
+        match self {
+            Number::Small(_) => 0,
+            Number::Large(_) => 1,
+            Number::Fraction(_) => 2,
+            Number::Float(_) => 3,
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            Number::Small(n) => BigInt::from(*n),
+            Number::Large(n) => n.clone(),
+            Number::Fraction(f) => f.to_integer(),
+            Number::Float(f) => BigInt::from_f64(*f).unwrap_or_default(),
+        }
+    }
+
+    fn to_rational(&self) -> BigRational {
+        match self {
+            Number::Fraction(f) => f.clone(),
+            Number::Float(f) => BigRational::from_float(*f).unwrap_or_default(),
+            n => BigRational::from_integer(n.to_big()),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Small(n) => *n as f64,
+            Number::Large(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Fraction(f) => f.to_f64().unwrap_or(f64::NAN),
+            Number::Float(f) => *f,
+        }
+    }
+
+    fn raise(&self, generality: u8) -> Number {
+        if self.generality() >= generality {
+            return self.clone();
+        }
+        match generality {
+            1 => Number::Large(self.to_big()),
+            2 => Number::Fraction(self.to_rational()),
+            _ => Number::Float(self.to_f64()),
+        }
+    }
+
+    /// converts both operands to the more general of the two representations
+    fn coerce(&self, other: &Number) -> (Number, Number) {
+        let g = self.generality().max(other.generality());
+        (self.raise(g), other.raise(g))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Small(n) => *n == 0,
+            Number::Large(n) => n.is_zero(),
+            Number::Fraction(f) => f.is_zero(),
+            Number::Float(f) => *f == 0.0,
+        }
+    }
+
+    pub fn add(&self, other: &Number) -> Number {
+        match self.coerce(other) {
+            (Number::Small(a), Number::Small(b)) => match a.checked_add(b) {
+                Some(r) => Number::Small(r),
+                None => Number::Large(BigInt::from(a) + BigInt::from(b)),
+            },
+            (Number::Large(a), Number::Large(b)) => Number::Large(a + b).normalize(),
+            (Number::Fraction(a), Number::Fraction(b)) => Number::Fraction(a + b).normalize(),
+            (a, b) => Number::Float(a.to_f64() + b.to_f64()),
+        }
+    }
+
+    pub fn sub(&self, other: &Number) -> Number {
+        match self.coerce(other) {
+            (Number::Small(a), Number::Small(b)) => match a.checked_sub(b) {
+                Some(r) => Number::Small(r),
+                None => Number::Large(BigInt::from(a) - BigInt::from(b)),
+            },
+            (Number::Large(a), Number::Large(b)) => Number::Large(a - b).normalize(),
+            (Number::Fraction(a), Number::Fraction(b)) => Number::Fraction(a - b).normalize(),
+            (a, b) => Number::Float(a.to_f64() - b.to_f64()),
+        }
+    }
+
+    pub fn mul(&self, other: &Number) -> Number {
+        match self.coerce(other) {
+            (Number::Small(a), Number::Small(b)) => match a.checked_mul(b) {
+                Some(r) => Number::Small(r),
+                None => Number::Large(BigInt::from(a) * BigInt::from(b)),
+            },
+            (Number::Large(a), Number::Large(b)) => Number::Large(a * b).normalize(),
+            (Number::Fraction(a), Number::Fraction(b)) => Number::Fraction(a * b).normalize(),
+            (a, b) => Number::Float(a.to_f64() * b.to_f64()),
+        }
+    }
+
+    /// signals `ZeroDivide`, answers the resumption value or 0 if that is
+    /// no number
+    fn zero_divide(&self, selector: &str) -> Number {
+        let r = exc::signal_error("ZeroDivide", &format!("{} {} 0", self, selector));
+        r.as_number().unwrap_or(Number::Small(0))
+    }
+
+    /// exact division, integers that do not divide evenly answer a fraction
+    pub fn div(&self, other: &Number) -> Number {
+        if other.is_zero() {
+            return self.zero_divide("/");
+        }
+        match self.coerce(other) {
+            (Number::Float(a), Number::Float(b)) => Number::Float(a / b),
+            (a, b) => Number::Fraction(a.to_rational() / b.to_rational()).normalize(),
+        }
+    }
+
+    /// division truncated towards negative infinity
+    pub fn int_div(&self, other: &Number) -> Number {
+        if other.is_zero() {
+            return self.zero_divide("//");
+        }
+        match self.coerce(other) {
+            (Number::Small(a), Number::Small(b)) => match a.checked_div(b) {
+                Some(_) => Number::Small(Integer::div_floor(&a, &b)),
+                None => Number::Large(BigInt::from(a).div_floor(&BigInt::from(b))),
+            },
+            (Number::Large(a), Number::Large(b)) => Number::Large(a.div_floor(&b)).normalize(),
+            (Number::Float(a), Number::Float(b)) => {
+                Number::Large(BigInt::from_f64((a / b).floor()).unwrap_or_default()).normalize()
+            }
+            (a, b) => Number::Large((a.to_rational() / b.to_rational()).floor().to_integer()).normalize(),
+        }
+    }
+
+    /// remainder of `int_div`, it has the sign of the divisor
+    pub fn modulo(&self, other: &Number) -> Number {
+        let q = self.int_div(other);
+        self.sub(&q.mul(other))
+    }
+
+    /// division truncated towards zero
+    pub fn quo(&self, other: &Number) -> Number {
+        if other.is_zero() {
+            return self.zero_divide("quo:");
+        }
+        self.div(other).truncated()
+    }
+
+    /// remainder of `quo`, it has the sign of the receiver
+    pub fn rem(&self, other: &Number) -> Number {
+        let q = self.quo(other);
+        self.sub(&q.mul(other))
+    }
+
+    pub fn compare(&self, other: &Number) -> Option<Ordering> {
+        match self.coerce(other) {
+            (Number::Small(a), Number::Small(b)) => a.partial_cmp(&b),
+            (Number::Large(a), Number::Large(b)) => a.partial_cmp(&b),
+            (Number::Fraction(a), Number::Fraction(b)) => a.partial_cmp(&b),
+            (a, b) => a.to_f64().partial_cmp(&b.to_f64()),
+        }
+    }
+
+    pub fn negated(&self) -> Number {
+        Number::Small(0).sub(self)
+    }
+
+    pub fn abs(&self) -> Number {
+        match self {
+            Number::Small(n) => match n.checked_abs() {
+                Some(r) => Number::Small(r),
+                None => Number::Large(BigInt::from(*n).abs()),
+            },
+            Number::Large(n) => Number::Large(n.abs()),
+            Number::Fraction(f) => Number::Fraction(f.abs()),
+            Number::Float(f) => Number::Float(f.abs()),
+        }
+    }
+
+    pub fn truncated(&self) -> Number {
+        match self {
+            Number::Fraction(f) => Number::Large(f.trunc().to_integer()).normalize(),
+            Number::Float(f) => Number::Large(BigInt::from_f64(f.trunc()).unwrap_or_default()).normalize(),
+            n => n.clone(),
+        }
+    }
+
+    pub fn rounded(&self) -> Number {
+        match self {
+            Number::Fraction(f) => Number::Large(f.round().to_integer()).normalize(),
+            Number::Float(f) => Number::Large(BigInt::from_f64(f.round()).unwrap_or_default()).normalize(),
+            n => n.clone(),
+        }
+    }
+
+    /// the protocol shared by all numeric receivers, answers `None` for
+    /// selectors that are not arithmetic or whose argument is not a number
+    pub fn receive(&self, selector: &'static str, args: &[Value]) -> Option<Value> {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(self.to_string());
+                return Some(args[0].receive_message("write", vec![Value::Object(Rc::new(a0))]));
+            }
+            _ if !PROTOCOL.contains(&selector) => return None,
+            _ => {}
+        }
+        let arg = || args.first().and_then(|a| a.as_number());
+        let divisor = match selector {
+            "/" | "//" | "\\\\" | "quo:" | "rem:" => arg(),
+            "reciprocal" => Some(self.clone()),
+            _ => None,
+        };
+        if divisor.map(|d| d.is_zero()) == Some(true) {
+            return Some(exc::signal_error("ZeroDivide", &format!("{} {} 0", self, selector)));
+        }
+        let r = match selector {
+            "+" => self.add(&arg()?),
+            "-" => self.sub(&arg()?),
+            "*" => self.mul(&arg()?),
+            "/" => self.div(&arg()?),
+            "//" => self.int_div(&arg()?),
+            "\\\\" => self.modulo(&arg()?),
+            "quo:" => self.quo(&arg()?),
+            "rem:" => self.rem(&arg()?),
+            "max:" => {
+                let o = arg()?;
+                match self.compare(&o) {
+                    Some(Ordering::Less) => o,
+                    _ => self.clone(),
+                }
+            }
+            "min:" => {
+                let o = arg()?;
+                match self.compare(&o) {
+                    Some(Ordering::Greater) => o,
+                    _ => self.clone(),
+                }
+            }
+            "abs" => self.abs(),
+            "negated" => self.negated(),
+            "squared" => self.mul(self),
+            "sqrt" => Number::Float(self.to_f64().sqrt()),
+            "reciprocal" => Number::Small(1).div(self),
+            "asFloat" => Number::Float(self.to_f64()),
+            "asInteger" | "truncated" => self.truncated(),
+            "rounded" => self.rounded(),
+            "numerator" => match self {
+                Number::Fraction(f) => Number::Large(f.numer().clone()),
+                n => n.truncated(),
+            },
+            "denominator" => match self {
+                Number::Fraction(f) => Number::Large(f.denom().clone()),
+                _ => Number::Small(1),
+            },
+            "<" | ">" | "<=" | ">=" | "=" | "~=" | "==" => {
+                let ord = args.first().and_then(|a| a.as_number()).and_then(|o| self.compare(&o));
+                let b = match selector {
+                    "<" => ord == Some(Ordering::Less),
+                    ">" => ord == Some(Ordering::Greater),
+                    "<=" => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    ">=" => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                    "~=" => ord != Some(Ordering::Equal),
+                    _ => ord == Some(Ordering::Equal),
+                };
+                return Some(Value::Bool(b));
+            }
+            "isZero" => return Some(Value::Bool(self.is_zero())),
+            "asString" | "printString" => return Some(Value::Object(Rc::new(StringReceiver::new(self.to_string())))),
+            _ => return None,
+        };
+        Some(r.into_value())
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Small(n) => write!(f, "{}", n),
+            Number::Large(n) => write!(f, "{}", n),
+            Number::Fraction(r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Number::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+/// integers that do not fit into a machine word
+pub struct LargeIntReceiver(pub BigInt);
+
+/// exact results of integer divisions
+pub struct FractionReceiver(pub BigRational);
+
+pub struct FloatReceiver(pub f64);
+
+impl FloatReceiver {
+    pub fn new(f: f64) -> Self {
+        Self(f)
+    }
+}
+
+impl Receiver for LargeIntReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match Number::Large(self.0.clone()).receive(selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "LargeInteger"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("LargeInteger", self.0.to_string()))
+    }
+
+    fn as_number(&self) -> Option<Number> {
+        Some(Number::Large(self.0.clone()))
+    }
+}
+
+impl Receiver for FractionReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match Number::Fraction(self.0.clone()).receive(selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Fraction"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Fraction", self.0.to_string()))
+    }
+
+    fn as_number(&self) -> Option<Number> {
+        Some(Number::Fraction(self.0.clone()))
+    }
+}
+
+impl Receiver for FloatReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match Number::Float(self.0).receive(selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Float"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Float", format!("{:?}", self.0)))
+    }
+
+    fn as_number(&self) -> Option<Number> {
+        Some(Number::Float(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_promotes() {
+        let n = Number::Small(isize::MAX).add(&Number::Small(1));
+        assert!(matches!(n, Number::Large(_)));
+        assert_eq!(n.sub(&Number::Small(1)), Number::Small(isize::MAX));
+    }
+
+    #[test]
+    fn division_is_exact() {
+        let third = Number::Small(1).div(&Number::Small(3));
+        assert_eq!(third.to_string(), "1/3");
+        assert_eq!(third.mul(&Number::Small(3)), Number::Small(1));
+        assert_eq!(Number::Small(-7).int_div(&Number::Small(2)), Number::Small(-4));
+        assert_eq!(Number::Small(-7).modulo(&Number::Small(2)), Number::Small(1));
+        assert_eq!(Number::Small(-7).rem(&Number::Small(2)), Number::Small(-1));
+    }
+}
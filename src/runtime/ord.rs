@@ -0,0 +1,235 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    col::{self, Species},
+    exc,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
+
+/// the messages ordered collections understand natively besides those of
+/// all collections
+pub(crate) const PROTOCOL: &[&str] = &[
+    "add:",
+    "addLast:",
+    "addFirst:",
+    "addAll:",
+    "at:",
+    "at:put:",
+    "first",
+    "last",
+    "removeFirst",
+    "removeLast",
+    "remove:",
+    "remove:ifAbsent:",
+    "removeAll",
+];
+
+/// the messages sorted collections understand besides those of ordered ones
+pub(crate) const SORTED_PROTOCOL: &[&str] = &["sortBlock"];
+
+/// the messages the class `SortedCollection` understands natively
+pub(crate) const SORTED_CLASS_PROTOCOL: &[&str] = &["sortBlock:"];
+
+/// a growable sequence, with a sort block it is a `SortedCollection` that
+/// keeps its elements sorted. Indices start at 1 like the ones of arrays.
+pub struct OrderedCollectionReceiver {
+    myself: Weak<OrderedCollectionReceiver>,
+    items: RefCell<Vec<Rc<dyn Receiver>>>,
+    /// the sort block of a sorted collection, nil sorts ascending
+    sort_block: Option<Rc<dyn Receiver>>,
+}
+
+impl OrderedCollectionReceiver {
+    pub fn new(items: Vec<Rc<dyn Receiver>>, sort_block: Option<Rc<dyn Receiver>>) -> Rc<Self> {
+        let items = match &sort_block {
+            Some(block) => col::sort(items, block),
+            None => items,
+        };
+        let collection = Rc::new_cyclic(|myself| Self {
+            myself: myself.clone(),
+            items: RefCell::new(items),
+            sort_block,
+        });
+        heap::track_receiver(&(collection.clone() as Rc<dyn Receiver>));
+        collection
+    }
+
+    fn myself(&self) -> Rc<dyn Receiver> {
+        self.myself.upgrade().unwrap()
+    }
+
+    fn species(&self) -> Species {
+        match &self.sort_block {
+            Some(block) => Species::Sorted(Some(block.clone())),
+            None => Species::OrderedCollection,
+        }
+    }
+
+    /// adds the element where it belongs, at the end unless it is sorted
+    fn add(&self, x: Rc<dyn Receiver>) {
+        let idx = match &self.sort_block {
+            Some(block) => {
+                let items = self.items.borrow().clone();
+                items
+                    .iter()
+                    .position(|e| !col::before(block, e, &x))
+                    .unwrap_or(items.len())
+            }
+            None => self.items.borrow().len(),
+        };
+        self.items.borrow_mut().insert(idx, x);
+    }
+
+    /// removes the element at the index, `None` if there is none
+    fn remove_at(&self, idx: Option<usize>) -> Option<Value> {
+        let mut items = self.items.borrow_mut();
+        match idx {
+            Some(idx) if idx < items.len() => Some(Value::from_receiver(items.remove(idx))),
+            _ => None,
+        }
+    }
+
+    fn empty_error(&self) -> Value {
+        exc::signal_error("CollectionIsEmpty", &format!("the {} is empty", self.class_name()))
+    }
+
+    fn understands(&self, selector: &str) -> bool {
+        PROTOCOL.contains(&selector) || self.sort_block.is_some() && SORTED_PROTOCOL.contains(&selector)
+    }
+
+    /// the enumeration protocol, a sorted collection collects into an
+    /// ordered one
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let species = self.species();
+        let collected = match species {
+            Species::Sorted(_) => Species::OrderedCollection,
+            _ => species.clone(),
+        };
+        let elements = self.items.borrow().clone();
+        match col::enumerate(self.myself(), elements, species, collected, selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    /// the messages that would put an element out of order
+    fn not_sorted(&self, selector: &'static str) -> Value {
+        let text = format!("a SortedCollection decides the place of its elements, #{}", selector);
+        exc::signal_error("Error", &text)
+    }
+}
+
+impl Receiver for OrderedCollectionReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let items = self.items.borrow().clone();
+                col::write_elements(self.class_name(), &items, &args[0])
+            }
+            _ if !self.understands(selector) => self.enumerate(selector, args),
+            "addLast:" | "addFirst:" | "at:put:" if self.sort_block.is_some() => self.not_sorted(selector),
+            "add:" | "addLast:" => {
+                self.add(args[0].to_receiver());
+                args[0].clone()
+            }
+            "addFirst:" => {
+                self.items.borrow_mut().insert(0, args[0].to_receiver());
+                args[0].clone()
+            }
+            "addAll:" => {
+                col::elements_of(&args[0]).into_iter().for_each(|x| {
+                    self.add(x);
+                });
+                args[0].clone()
+            }
+            "at:" => {
+                let len = self.items.borrow().len();
+                match col::index(len, &args[0]) {
+                    Some(idx) => Value::from_receiver(self.items.borrow()[idx].clone()),
+                    None => Value::Nil,
+                }
+            }
+            "at:put:" => {
+                let len = self.items.borrow().len();
+                if let Some(idx) = col::index(len, &args[0]) {
+                    self.items.borrow_mut()[idx] = args[1].to_receiver();
+                }
+                args[1].clone()
+            }
+            "first" => {
+                let first = self.items.borrow().first().cloned();
+                first.map_or_else(|| self.empty_error(), Value::from_receiver)
+            }
+            "last" => {
+                let last = self.items.borrow().last().cloned();
+                last.map_or_else(|| self.empty_error(), Value::from_receiver)
+            }
+            "removeFirst" => self.remove_at(Some(0)).unwrap_or_else(|| self.empty_error()),
+            "removeLast" => {
+                let last = self.items.borrow().len().checked_sub(1);
+                self.remove_at(last).unwrap_or_else(|| self.empty_error())
+            }
+            "remove:" | "remove:ifAbsent:" => {
+                let idx = self.items.borrow().iter().position(|x| col::equal(x, &args[0]));
+                match self.remove_at(idx) {
+                    Some(x) => x,
+                    None if args.len() > 1 => args[1].send("value", vec![]),
+                    None => exc::signal_error("NotFound", &format!("{} not found", args[0])),
+                }
+            }
+            "removeAll" => {
+                self.items.borrow_mut().clear();
+                Value::Object(self.myself())
+            }
+            "sortBlock" => self.sort_block.clone().map_or(Value::Nil, Value::from_receiver),
+            _ => self.enumerate(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(self.items.borrow().clone())
+    }
+
+    fn class_name(&self) -> &'static str {
+        match self.sort_block {
+            Some(_) => "SortedCollection",
+            None => "OrderedCollection",
+        }
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(items) = self.items.try_borrow() {
+            items.iter().for_each(|x| visit(heap::address(x)));
+        }
+        if let Some(block) = &self.sort_block {
+            visit(heap::address(block));
+        }
+    }
+
+    fn clear_references(&self) {
+        self.items.borrow_mut().clear();
+    }
+
+    /// a sorted collection has its sort block as first slot
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new(self.class_name(), "");
+        let s = match &self.sort_block {
+            Some(block) => s.slot("sortBlock", block.clone()),
+            None => s,
+        };
+        Some(self.items.borrow().iter().fold(s, |s, x| s.slot("", x.clone())))
+    }
+}
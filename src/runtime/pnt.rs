@@ -1,62 +1,121 @@
-use super::{int::IntReceiver, str::StringReceiver, Receiver};
-use std::rc::Rc;
+use super::{str::StringReceiver, val::Value, Receiver};
+use std::{cell::Cell, rc::Rc};
+use crate::image::Snapshot;
+
+/// the messages the class `Point` understands natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &["x:y:"];
+
+/// the messages a point understands natively
+pub(crate) const PROTOCOL: &[&str] = &["x", "y", "+"];
 
 pub struct PointMetaReceiver;
 
 impl Receiver for PointMetaReceiver {
-    fn receive_message(&self, selector: &'static str, args: Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
         match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(String::from("Point"));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !CLASS_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
             "x:y:" => {
                 let x = args[0].as_int().unwrap();
                 let y = args[1].as_int().unwrap();
 
-                Rc::new(PointReceiver::new(x, y))
+                Value::Object(Rc::new(PointReceiver::new(x, y)))
             }
-            _ => todo!("{}", selector),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        Some("Point")
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Point class"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", "Point"))
     }
 }
 
-pub struct PointReceiver(isize, isize);
+/// the instance variables of a point, in the order `instVarAt:` counts them
+const INST_VARS: &[&str] = &["x", "y"];
+
+pub struct PointReceiver(Cell<isize>, Cell<isize>);
 
 impl PointReceiver {
     pub fn new(x: isize, y: isize) -> Self {
-        Self(x, y)
+        Self(Cell::new(x), Cell::new(y))
+    }
+
+    fn coordinate(&self, name: &str) -> Option<&Cell<isize>> {
+        match name {
+            "x" => Some(&self.0),
+            "y" => Some(&self.1),
+            _ => None,
+        }
     }
 }
 
 impl Receiver for PointReceiver {
-    fn receive_message(&self, selector: &'static str, args: Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
         match selector {
-            "x" => Rc::new(IntReceiver::new(self.0)),
-            "y" => Rc::new(IntReceiver::new(self.1)),
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(format!("{}@{}", self.0.get(), self.1.get()));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "x" => Value::Int(self.0.get()),
+            "y" => Value::Int(self.1.get()),
             "+" => {
                 let arg = args[0].clone();
                 let x = arg.receive_message("x", vec![]).as_int().unwrap();
                 let y = arg.receive_message("y", vec![]).as_int().unwrap();
-                Rc::new(PointReceiver::new(self.0 + x, self.1 + y))
+                Value::Object(Rc::new(PointReceiver::new(self.0.get() + x, self.1.get() + y)))
             }
-            "basic_write_to" => {
-                let a0 = StringReceiver::new(format!("{}@{}", self.0, self.1));
-                args[0].receive_message("write", vec![Rc::new(a0)])
-            }
-            _ => todo!("{}", selector),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
+    }
+
+    fn inst_var_names(&self) -> Vec<&'static str> {
+        INST_VARS.to_vec()
+    }
+
+    fn inst_var(&self, name: &str) -> Option<Rc<dyn Receiver>> {
+        self.coordinate(name).map(|c| Value::Int(c.get()).to_receiver())
+    }
+
+    /// the coordinates are integers, other values are refused
+    fn set_inst_var(&self, name: &str, value: Rc<dyn Receiver>) -> bool {
+        match (self.coordinate(name), value.as_int()) {
+            (Some(c), Some(v)) => {
+                c.set(v);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Point"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Point", format!("{} {}", self.0.get(), self.1.get())))
     }
 }
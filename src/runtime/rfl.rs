@@ -0,0 +1,453 @@
+use std::rc::Rc;
+
+use super::{
+    arr::{self, ArrayReceiver},
+    blk, boo, byt, chr,
+    cls::{ClassReceiver, ClassTable},
+    col::{self, CollectionClass},
+    dct, exc, glb,
+    int::{self, IntMetaReceiver},
+    ivl,
+    mtd::{self, ClassKey, Primitive},
+    msg, num, ord,
+    pnt::{self, PointMetaReceiver},
+    sel::{self, Selector, SelectorSet},
+    set,
+    stm::{self, StreamClass},
+    str::{self, StringMetaReceiver, StringReceiver},
+    sym::{self, SymbolReceiver},
+    sys,
+    val::Value,
+    Receiver,
+};
+use crate::{image::Snapshot, parser::AST};
+
+/// the selectors the receivers implemented in Rust understand without a
+/// method in a dictionary, by class and side. The receivers dispatch on the
+/// same tables.
+const PROTOCOLS: &[(&str, bool, &[&str])] = &[
+    ("Boolean", false, boo::PROTOCOL),
+    ("BlockClosure", false, blk::PROTOCOL),
+    ("Number", false, num::PROTOCOL),
+    ("Integer", false, int::PROTOCOL),
+    ("Integer", true, int::CLASS_PROTOCOL),
+    ("Character", false, chr::PROTOCOL),
+    ("String", false, str::PROTOCOL),
+    ("String", true, str::CLASS_PROTOCOL),
+    ("Symbol", false, sym::PROTOCOL),
+    ("Collection", false, col::PROTOCOL),
+    ("Collection", true, col::CLASS_PROTOCOL),
+    ("Array", false, arr::PROTOCOL),
+    ("ByteArray", false, byt::PROTOCOL),
+    ("OrderedCollection", false, ord::PROTOCOL),
+    ("SortedCollection", false, ord::SORTED_PROTOCOL),
+    ("SortedCollection", true, ord::SORTED_CLASS_PROTOCOL),
+    ("Set", false, set::PROTOCOL),
+    ("Bag", false, set::PROTOCOL),
+    ("Bag", false, set::BAG_PROTOCOL),
+    ("Dictionary", false, dct::PROTOCOL),
+    ("Association", false, dct::ASSOCIATION_PROTOCOL),
+    ("Association", true, dct::ASSOCIATION_CLASS_PROTOCOL),
+    ("Interval", false, ivl::PROTOCOL),
+    ("Interval", true, ivl::CLASS_PROTOCOL),
+    ("Point", false, pnt::PROTOCOL),
+    ("Point", true, pnt::CLASS_PROTOCOL),
+    ("Message", false, msg::PROTOCOL),
+    ("PositionableStream", false, stm::PROTOCOL),
+    ("PositionableStream", false, stm::READ_PROTOCOL),
+    ("WriteStream", false, stm::WRITE_PROTOCOL),
+    ("ReadStream", true, stm::READ_CLASS_PROTOCOL),
+    ("WriteStream", true, stm::WRITE_CLASS_PROTOCOL),
+    ("FileStream", true, stm::FILE_CLASS_PROTOCOL),
+    ("SystemDictionary", false, sys::PROTOCOL),
+    ("PoolDictionary", false, glb::POOL_PROTOCOL),
+    ("Exception", false, exc::PROTOCOL),
+    ("Exception", true, exc::CLASS_PROTOCOL),
+    ("ExceptionSet", false, exc::SET_PROTOCOL),
+];
+
+/// the class object with this name, user defined classes and the classes
+/// implemented in Rust, `None` for other names
+pub fn class_named(name: &str) -> Option<Rc<dyn Receiver>> {
+    let class: Rc<dyn Receiver> = match name {
+        "Point" => Rc::new(PointMetaReceiver),
+        "Integer" => Rc::new(IntMetaReceiver),
+        "String" => Rc::new(StringMetaReceiver {}),
+        _ => match ClassTable::get(name) {
+            Some(def) => Rc::new(ClassReceiver(def)),
+            None => {
+                return CollectionClass::get(name)
+                    .or_else(|| StreamClass::get(name))
+                    .or_else(|| Some(Rc::new(BuiltinClass(mtd::builtin(name)?))))
+            }
+        },
+    };
+    Some(class)
+}
+
+/// the names of all classes, sorted
+pub fn class_names() -> Vec<&'static str> {
+    let mut names = ClassTable::names();
+    names.extend_from_slice(mtd::builtins());
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// the class of an object, for a class object its metaclass
+pub fn class_of(class: ClassKey) -> Rc<dyn Receiver> {
+    match class {
+        (name, true) => Rc::new(Metaclass(name)),
+        (name, false) => class_named(name).unwrap_or_else(|| Rc::new(BuiltinClass(name))),
+    }
+}
+
+pub fn superclass(name: &str) -> Option<&'static str> {
+    match ClassTable::get(name) {
+        Some(def) => def.superclass.or_else(|| mtd::builtin_superclass(name)),
+        None => mtd::builtin_superclass(name),
+    }
+}
+
+/// the class and its superclasses, the class first
+fn lineage(name: &'static str) -> Vec<&'static str> {
+    let mut names = vec![name];
+    while let Some(s) = superclass(names[names.len() - 1]) {
+        if names.contains(&s) {
+            break;
+        }
+        names.push(s);
+    }
+    names
+}
+
+/// the selectors the built in classes understand natively, they are only
+/// interned once they are sent
+pub fn native_selectors() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = PROTOCOLS.iter().flat_map(|(_, _, s)| s.iter().copied()).collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+fn protocol(name: &str, class_side: bool) -> impl Iterator<Item = &'static str> + '_ {
+    PROTOCOLS
+        .iter()
+        .filter(move |(n, side, _)| *n == name && *side == class_side)
+        .flat_map(|(_, _, selectors)| selectors.iter().copied())
+}
+
+/// whether the instances of the class understand the selector, or the
+/// class itself for the class side
+pub fn understands(class: ClassKey, selector: &str) -> bool {
+    if mtd::lookup(class, Selector::get(selector)).is_some() {
+        return true;
+    }
+    let (name, class_side) = class;
+    if class_side && ClassTable::get(name).is_some() && matches!(selector, "new" | "basicNew") {
+        return true;
+    }
+    lineage(name)
+        .iter()
+        .any(|n| protocol(n, class_side).any(|s| s == selector))
+}
+
+/// the selectors the class defines itself, sorted
+pub fn selectors(class: ClassKey) -> Vec<&'static str> {
+    let (name, class_side) = class;
+    let mut names = mtd::selectors(class);
+    if let Some(def) = ClassTable::get(name) {
+        names.extend(def.methods(class_side).iter().filter_map(|m| match &**m {
+            AST::Method { name, .. } => Some(*name),
+            _ => None,
+        }));
+    }
+    names.extend(protocol(name, class_side));
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// `==`, immediates are the same if they are equal. Symbols and class
+/// objects are made when they are needed, they are the same if they
+/// stand for the same name.
+pub fn identical(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Object(x), Value::Object(y)) => {
+            if std::ptr::eq(Rc::as_ptr(x) as *const u8, Rc::as_ptr(y) as *const u8) {
+                return true;
+            }
+            match (x.as_symbol(), y.as_symbol()) {
+                (Some(s), Some(t)) => std::ptr::eq(s, t),
+                _ => x.class_key().1 && x.class_key() == y.class_key(),
+            }
+        }
+        (Value::Nil, Value::Nil) => true,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Int(x), Value::Int(y)) => x == y,
+        (Value::Char(x), Value::Char(y)) => x == y,
+        _ => false,
+    }
+}
+
+/// numbers other than small integers are the same if they are equal,
+/// they compare themselves
+fn is_number(r: &Value) -> bool {
+    matches!(r, Value::Object(o) if o.as_number().is_some())
+}
+
+/// `printString`, strings are quoted
+pub fn print_string(r: &Rc<dyn Receiver>) -> String {
+    match r.class_name() {
+        "String" => format!("'{}'", r.to_string().replace('\'', "''")),
+        _ => r.to_string(),
+    }
+}
+
+fn string(s: impl Into<String>) -> Value {
+    Value::Object(Rc::new(StringReceiver::new(s.into())))
+}
+
+fn symbols(names: Vec<&'static str>) -> Value {
+    let symbols = names
+        .into_iter()
+        .map(|n| Rc::new(SymbolReceiver::new(n)) as Rc<dyn Receiver>);
+    Value::Object(Rc::new(ArrayReceiver(symbols.collect())))
+}
+
+/// a symbol or a string as selector
+fn selector_of(v: &Value) -> Option<&'static str> {
+    str::text_of(v).map(|t| SelectorSet::get(&t))
+}
+
+/// the name of a class object
+fn class_name_of(v: &Value) -> Option<&'static str> {
+    match v {
+        Value::Object(o) if o.class_key().1 => Some(o.class_key().0),
+        _ => None,
+    }
+}
+
+/// sends the message `perform:` and its relatives name
+fn perform(r: &Value, selector: &Value, args: Vec<Value>) -> Value {
+    let selector = match selector_of(selector) {
+        Some(s) => s,
+        None => return exc::signal_error("Error", &format!("{} is not a selector", selector.to_receiver())),
+    };
+    if sel::arity(selector) != args.len() {
+        let text = format!(
+            "#{} takes {} arguments, not {}",
+            selector,
+            sel::arity(selector),
+            args.len()
+        );
+        return exc::signal_error("Error", &text);
+    }
+    mtd::send(r, selector, args)
+}
+
+fn is_kind_of(r: &Value, class: &Value) -> bool {
+    match (r.class_key(), class_name_of(class)) {
+        ((name, false), Some(class)) => lineage(name).contains(&class),
+        ((_, true), Some(class)) => class == "Object",
+        (_, None) => false,
+    }
+}
+
+/// the names of the instance variables, superclass ones first
+pub fn inst_var_names(r: &Value) -> Vec<&'static str> {
+    match r {
+        Value::Object(o) if !o.class_key().1 => o.inst_var_names(),
+        _ => vec![],
+    }
+}
+
+/// reads the instance variable, writes it if there is a value
+fn inst_var(r: &Value, name: &'static str, value: Option<&Value>) -> Value {
+    let o = r.to_receiver();
+    match value {
+        Some(v) => {
+            if !o.set_inst_var(name, v.to_receiver()) {
+                let text = format!("a {} can't hold {} in {}", o.class_name(), v.to_receiver(), name);
+                return exc::signal_error("Error", &text);
+            }
+            v.clone()
+        }
+        None => o.inst_var(name).map_or(Value::Nil, Value::from_receiver),
+    }
+}
+
+fn inst_var_at(r: &Value, args: &[Value]) -> Option<Value> {
+    let names = inst_var_names(r);
+    let v = match col::index(names.len(), &args[0]) {
+        Some(idx) => inst_var(r, names[idx], args.get(1)),
+        None => Value::Nil,
+    };
+    Some(v)
+}
+
+fn inst_var_named(r: &Value, args: &[Value]) -> Option<Value> {
+    let names = inst_var_names(r);
+    let v = match selector_of(&args[0]).filter(|n| names.contains(n)) {
+        Some(name) => inst_var(r, name, args.get(1)),
+        None => {
+            let text = format!(
+                "a {} has no instance variable {}",
+                r.class_name(),
+                args[0].to_receiver()
+            );
+            exc::signal_error("NotFound", &text)
+        }
+    };
+    Some(v)
+}
+
+/// the reflective methods of every object, classes included
+pub(crate) fn primitives() -> Vec<(&'static str, Primitive)> {
+    vec![
+        ("class", |r, _| Some(Value::Object(class_of(r.class_key())))),
+        ("==", |r, a| (!is_number(r)).then(|| Value::Bool(identical(r, &a[0])))),
+        ("~~", |r, a| {
+            Some(Value::Bool(!matches!(
+                mtd::send(r, "==", a.to_vec()),
+                Value::Bool(true)
+            )))
+        }),
+        ("printString", |r, _| Some(string(print_string(&r.to_receiver())))),
+        ("displayString", |r, _| Some(string(r.to_receiver().to_string()))),
+        ("respondsTo:", |r, a| {
+            Some(Value::Bool(
+                selector_of(&a[0]).is_some_and(|s| understands(r.class_key(), s)),
+            ))
+        }),
+        ("isKindOf:", |r, a| Some(Value::Bool(is_kind_of(r, &a[0])))),
+        ("isMemberOf:", |r, a| {
+            let (name, class_side) = r.class_key();
+            Some(Value::Bool(!class_side && class_name_of(&a[0]) == Some(name)))
+        }),
+        ("perform:", |r, a| Some(perform(r, &a[0], vec![]))),
+        ("perform:with:", |r, a| Some(perform(r, &a[0], a[1..].to_vec()))),
+        ("perform:with:with:", |r, a| Some(perform(r, &a[0], a[1..].to_vec()))),
+        ("perform:with:with:with:", |r, a| {
+            Some(perform(r, &a[0], a[1..].to_vec()))
+        }),
+        ("perform:withArguments:", |r, a| {
+            let args = col::elements_of(&a[1]);
+            Some(perform(r, &a[0], args.into_iter().map(Value::from_receiver).collect()))
+        }),
+        ("instVarAt:", inst_var_at),
+        ("instVarAt:put:", inst_var_at),
+        ("instVarNamed:", inst_var_named),
+        ("instVarNamed:put:", inst_var_named),
+    ]
+}
+
+/// the reflective methods of class objects
+pub(crate) fn class_primitives() -> Vec<(&'static str, Primitive)> {
+    vec![
+        ("name", |r, _| Some(string(r.class_key().0))),
+        ("superclass", |r, _| {
+            let superclass = superclass(r.class_key().0).and_then(class_named);
+            Some(superclass.map_or(Value::Nil, Value::Object))
+        }),
+        ("selectors", |r, _| Some(symbols(selectors((r.class_key().0, false))))),
+        ("includesSelector:", |r, a| {
+            let defined = selectors((r.class_key().0, false));
+            Some(Value::Bool(selector_of(&a[0]).is_some_and(|s| defined.contains(&s))))
+        }),
+        ("canUnderstand:", |r, a| {
+            let class = (r.class_key().0, false);
+            Some(Value::Bool(selector_of(&a[0]).is_some_and(|s| understands(class, s))))
+        }),
+        ("inheritsFrom:", |r, a| {
+            let superclasses = lineage(r.class_key().0);
+            Some(Value::Bool(
+                class_name_of(&a[0]).is_some_and(|c| superclasses[1..].contains(&c)),
+            ))
+        }),
+        ("allSuperclasses", |r, _| {
+            let superclasses = lineage(r.class_key().0).into_iter().skip(1).filter_map(class_named);
+            Some(Value::Object(Rc::new(ArrayReceiver(superclasses.collect()))))
+        }),
+        ("instanceVariableNames", |r, _| {
+            let names = ClassTable::get(r.class_key().0).map(|c| c.inst_vars.clone());
+            Some(symbols(names.unwrap_or_default()))
+        }),
+        ("allInstVarNames", |r, _| {
+            let names = ClassTable::get(r.class_key().0).map(|c| c.all_inst_vars());
+            Some(symbols(names.unwrap_or_default()))
+        }),
+        ("category", |r, _| {
+            let category = ClassTable::get(r.class_key().0).map(|c| c.category());
+            Some(category.map_or(Value::Nil, string))
+        }),
+        ("sourceCodeAt:", |r, a| {
+            let method = ClassTable::get(r.class_key().0)
+                .zip(selector_of(&a[0]))
+                .and_then(|(c, s)| c.own_method(s, false));
+            Some(method.map_or(Value::Nil, |m| string(m.to_source())))
+        }),
+    ]
+}
+
+/// the class object of a class implemented in Rust without a class side
+/// of its own, it only understands the reflective methods
+pub struct BuiltinClass(&'static str);
+
+impl Receiver for BuiltinClass {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => args[0].receive_message("write", vec![Value::Object(Rc::new(StringReceiver::new(self.0.into())))]),
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        Some(self.0)
+    }
+
+    fn class_name(&self) -> &'static str {
+        SelectorSet::get(&format!("{} class", self.0))
+    }
+
+    fn class_key(&self) -> ClassKey {
+        (self.0, true)
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", self.0))
+    }
+}
+
+/// the class of a class object, `Name class`
+pub struct Metaclass(&'static str);
+
+impl Receiver for Metaclass {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "soleInstance" => Value::Object(class_of((self.0, false))),
+            "name" => Value::Object(Rc::new(StringReceiver::new(format!("{} class", self.0)))),
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(format!("{} class", self.0));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Metaclass"
+    }
+}
@@ -1,40 +1,122 @@
-use std::{collections::HashSet, sync::Mutex};
+use std::{cell::RefCell, collections::HashMap, sync::Mutex};
 
 use once_cell::sync::Lazy;
 use tracing::info;
 
+/// the interned selectors and names, they are numbered in the order they
+/// are interned. Strings are not interned, so the table only grows with the
+/// code. Every thread keeps a copy of the part it has seen, so only names
+/// new to the thread lock it.
 pub struct SelectorSet {
-    cache: Mutex<HashSet<&'static str>>,
+    cache: Mutex<Interned>,
 }
 
+thread_local! {
+    static SEEN: RefCell<Interned> = RefCell::new(Interned::default());
+}
+
+#[derive(Default)]
+struct Interned {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, u32>,
+}
+
+/// the number of an interned selector, method dictionaries are keyed by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Selector(u32);
+
 impl SelectorSet {
     pub fn get(name: &str) -> &'static str {
-        let mut lck = SELECTOR_SET.cache.lock().unwrap();
-        match lck.get(name) {
-            Some(s) => *s,
+        Selector::get(name).name()
+    }
+
+    /// the number of strings interned
+    pub fn len() -> usize {
+        SELECTOR_SET.cache.lock().unwrap().names.len()
+    }
+
+    /// the interned strings starting with the prefix, sorted
+    pub fn starting_with(prefix: &str) -> Vec<&'static str> {
+        let lck = SELECTOR_SET.cache.lock().unwrap();
+        let mut names: Vec<_> = lck.names.iter().copied().filter(|n| n.starts_with(prefix)).collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn stats() {
+        for x in SELECTOR_SET.cache.lock().unwrap().names.iter() {
+            info!("selector {}", x);
+        }
+    }
+}
+
+impl Interned {
+    fn intern(&mut self, name: &str) -> u32 {
+        match self.ids.get(name) {
+            Some(id) => *id,
             None => {
                 let s0 = Box::new(name.to_string());
                 let s1: &'static String = Box::leak(s0);
-                lck.insert(s1);
-                s1
+                let id = self.names.len() as u32;
+                self.names.push(s1);
+                self.ids.insert(s1, id);
+                id
             }
         }
     }
+}
 
-    pub fn stats() {
-        for x in SELECTOR_SET.cache.lock().unwrap().iter() {
-            info!("selector {}", x);
-        }
+impl Selector {
+    pub fn get(name: &str) -> Self {
+        SEEN.with(|seen| {
+            if let Some(id) = seen.borrow().ids.get(name) {
+                return Selector(*id);
+            }
+            let id = SELECTOR_SET.cache.lock().unwrap().intern(name);
+            catch_up(&mut seen.borrow_mut());
+            Selector(id)
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        SEEN.with(|seen| {
+            if let Some(name) = seen.borrow().names.get(self.0 as usize) {
+                return *name;
+            }
+            let mut seen = seen.borrow_mut();
+            catch_up(&mut seen);
+            seen.names[self.0 as usize]
+        })
+    }
+}
+
+/// copies the strings interned since the thread last looked
+fn catch_up(seen: &mut Interned) {
+    let all = SELECTOR_SET.cache.lock().unwrap();
+    for (id, name) in all.names.iter().enumerate().skip(seen.names.len()) {
+        seen.names.push(name);
+        seen.ids.insert(name, id as u32);
+    }
+}
+
+/// the number of arguments a message with this selector takes
+pub fn arity(selector: &str) -> usize {
+    if selector.ends_with(':') {
+        selector.matches(':').count()
+    } else if selector.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        0
+    } else {
+        1
     }
 }
 
 static SELECTOR_SET: Lazy<SelectorSet> = Lazy::new(|| SelectorSet {
-    cache: Mutex::new(HashSet::new()),
+    cache: Mutex::new(Interned::default()),
 });
 
 #[cfg(test)]
 mod test {
-    use super::SelectorSet;
+    use super::{Selector, SelectorSet};
 
     #[test]
     fn selector() {
@@ -42,5 +124,16 @@ mod test {
         let sel2 = SelectorSet::get(format!("{}:{}:", "a", "b").as_str());
         assert_eq!(sel1, sel2);
         assert_eq!(sel1.as_ptr(), sel2.as_ptr());
+        let id = Selector::get("a:b:");
+        assert_eq!(id, Selector::get(sel2));
+        assert_eq!(id.name().as_ptr(), sel1.as_ptr());
+        assert_ne!(id, Selector::get("a:"));
+    }
+
+    #[test]
+    fn selectors_of_other_threads() {
+        let id = std::thread::spawn(|| Selector::get("fromAnotherThread:")).join().unwrap();
+        assert_eq!(id.name(), "fromAnotherThread:");
+        assert_eq!(Selector::get("fromAnotherThread:"), id);
     }
 }
@@ -0,0 +1,207 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    col::{self, Key, Species},
+    exc,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
+
+/// the messages sets and bags understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &[
+    "add:",
+    "addAll:",
+    "remove:",
+    "remove:ifAbsent:",
+    "includes:",
+    "occurrencesOf:",
+];
+
+/// the messages bags understand besides those of sets
+pub(crate) const BAG_PROTOCOL: &[&str] = &["add:withOccurrences:"];
+
+/// a collection without duplicates, a `Bag` counts how often each element
+/// was added. Elements are enumerated in the order they were first added.
+pub struct SetReceiver {
+    myself: Weak<SetReceiver>,
+    counted: bool,
+    entries: RefCell<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    items: Vec<(Rc<dyn Receiver>, usize)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Entries {
+    fn add(&mut self, x: Rc<dyn Receiver>, n: usize, counted: bool) {
+        let key = Key::of(&x);
+        match self.index.get(&key) {
+            Some(&idx) if counted => self.items[idx].1 += n,
+            Some(_) => {}
+            None => {
+                self.index.insert(key, self.items.len());
+                self.items.push((x, if counted { n } else { 1 }));
+            }
+        }
+    }
+
+    /// answers whether the element was there
+    fn remove(&mut self, x: &Value) -> bool {
+        let idx = match self.index.get(&Key::of_value(x)) {
+            Some(&idx) => idx,
+            None => return false,
+        };
+        self.items[idx].1 -= 1;
+        if self.items[idx].1 == 0 {
+            self.items.remove(idx);
+            self.index = self
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, (x, _))| (Key::of(x), i))
+                .collect();
+        }
+        true
+    }
+
+    fn elements(&self) -> Vec<Rc<dyn Receiver>> {
+        self.items
+            .iter()
+            .flat_map(|(x, n)| std::iter::repeat_n(x.clone(), *n))
+            .collect()
+    }
+}
+
+impl SetReceiver {
+    pub fn new(items: Vec<Rc<dyn Receiver>>, counted: bool) -> Rc<Self> {
+        let mut entries = Entries::default();
+        items.into_iter().for_each(|x| entries.add(x, 1, counted));
+        let set = Rc::new_cyclic(|myself| Self {
+            myself: myself.clone(),
+            counted,
+            entries: RefCell::new(entries),
+        });
+        heap::track_receiver(&(set.clone() as Rc<dyn Receiver>));
+        set
+    }
+
+    fn myself(&self) -> Rc<dyn Receiver> {
+        self.myself.upgrade().unwrap()
+    }
+
+    fn understands(&self, selector: &str) -> bool {
+        PROTOCOL.contains(&selector) || self.counted && BAG_PROTOCOL.contains(&selector)
+    }
+
+    fn enumerate(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let elements = self.entries.borrow().elements();
+        match col::enumerate(self.myself(), elements, self.species(), self.species(), selector, &args) {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn species(&self) -> Species {
+        if self.counted {
+            Species::Bag
+        } else {
+            Species::Set
+        }
+    }
+}
+
+impl Receiver for SetReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let elements = self.entries.borrow().elements();
+                col::write_elements(self.class_name(), &elements, &args[0])
+            }
+            _ if !self.understands(selector) => self.enumerate(selector, args),
+            "add:" => {
+                self.entries.borrow_mut().add(args[0].to_receiver(), 1, self.counted);
+                args[0].clone()
+            }
+            "add:withOccurrences:" if self.counted => {
+                let n = args[1].as_int().unwrap_or(0).max(0) as usize;
+                self.entries.borrow_mut().add(args[0].to_receiver(), n, true);
+                args[0].clone()
+            }
+            "addAll:" => {
+                for x in col::elements_of(&args[0]) {
+                    self.entries.borrow_mut().add(x, 1, self.counted);
+                }
+                args[0].clone()
+            }
+            "remove:" | "remove:ifAbsent:" => {
+                if self.entries.borrow_mut().remove(&args[0]) {
+                    args[0].clone()
+                } else if args.len() > 1 {
+                    args[1].send("value", vec![])
+                } else {
+                    exc::signal_error("NotFound", &format!("{} not found", args[0]))
+                }
+            }
+            "includes:" => Value::Bool(self.entries.borrow().index.contains_key(&Key::of_value(&args[0]))),
+            "occurrencesOf:" => {
+                let entries = self.entries.borrow();
+                let n = entries
+                    .index
+                    .get(&Key::of_value(&args[0]))
+                    .map_or(0, |&idx| entries.items[idx].1);
+                Value::Int(n as isize)
+            }
+            _ => self.enumerate(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        Some(self.entries.borrow().elements())
+    }
+
+    fn class_name(&self) -> &'static str {
+        if self.counted {
+            "Bag"
+        } else {
+            "Set"
+        }
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(entries) = self.entries.try_borrow() {
+            entries.items.iter().for_each(|(x, _)| visit(heap::address(x)));
+        }
+    }
+
+    fn clear_references(&self) {
+        *self.entries.borrow_mut() = Entries::default();
+    }
+
+    /// the elements of a bag are repeated as often as they occur
+    fn snapshot(&self) -> Option<Snapshot> {
+        let s = Snapshot::new(self.class_name(), "");
+        Some(
+            self.entries
+                .borrow()
+                .elements()
+                .into_iter()
+                .fold(s, |s, x| s.slot("", x)),
+        )
+    }
+}
@@ -1,84 +1,496 @@
-use std::{rc::Rc, sync::Mutex};
+use std::{
+    cell::RefCell,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    rc::{Rc, Weak},
+};
 
-use super::{int::IntReceiver, str::StringReceiver, Receiver};
+use super::{
+    arr::ArrayReceiver,
+    chr::CharReceiver,
+    col, exc,
+    sel::SelectorSet,
+    str::{self, StringReceiver},
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
 
+/// the messages that read a stream, a write stream doesn't understand them
+pub(crate) const READ_PROTOCOL: &[&str] = &[
+    "next",
+    "next:",
+    "peek",
+    "skip:",
+    "upTo:",
+    "upToEnd",
+    "nextLine",
+    "skipSeparators",
+    "do:",
+];
+
+/// the messages that write a stream, a read stream doesn't understand them
+pub(crate) const WRITE_PROTOCOL: &[&str] = &["nextPut:", "nextPutAll:", "print:", "<<", "tab", "space", "cr"];
+
+/// the messages all streams understand natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "atEnd",
+    "contents",
+    "position",
+    "position:",
+    "reset",
+    "setToEnd",
+    "isEmpty",
+    "size",
+    "close",
+    "flush",
+];
+
+/// the messages the class `ReadStream` understands natively
+pub(crate) const READ_CLASS_PROTOCOL: &[&str] = &["on:"];
+
+/// the messages the classes `WriteStream` and `ReadWriteStream` understand
+/// natively
+pub(crate) const WRITE_CLASS_PROTOCOL: &[&str] = &["on:", "with:"];
+
+/// the messages the class `FileStream` understands natively
+pub(crate) const FILE_CLASS_PROTOCOL: &[&str] = &["readOnlyFileNamed:", "forceNewFileNamed:", "fileNamed:"];
+
+/// what a stream may do with its elements
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// a position in a sequence of elements. A stream on a string reads and
+/// writes characters and its `contents` is a string, one on an array any
+/// objects. A file stream reads the file when it is opened and writes it
+/// when it is closed.
 pub struct StreamReceiver {
-    buf: Rc<dyn Receiver>,
-    idx: Mutex<isize>,
+    myself: Weak<StreamReceiver>,
+    access: Access,
+    text: bool,
+    state: RefCell<State>,
+    file: Option<PathBuf>,
+}
+
+struct State {
+    items: Vec<Rc<dyn Receiver>>,
+    position: usize,
+    /// the end of what can be read, writing moves it
+    limit: usize,
 }
 
 impl StreamReceiver {
-    pub fn new(buf: Rc<dyn Receiver>) -> Self {
-        Self {
-            buf,
-            idx: Mutex::new(0),
+    fn new(text: bool, access: Access, items: Vec<Rc<dyn Receiver>>, position: usize, limit: usize) -> Rc<Self> {
+        Self::create(text, access, State { items, position, limit }, None)
+    }
+
+    fn create(text: bool, access: Access, state: State, file: Option<PathBuf>) -> Rc<Self> {
+        let stream = Rc::new_cyclic(|myself| Self {
+            myself: myself.clone(),
+            access,
+            text,
+            state: RefCell::new(state),
+            file,
+        });
+        heap::track_receiver(&(stream.clone() as Rc<dyn Receiver>));
+        stream
+    }
+
+    /// a stream on the elements of a string or another collection, a read
+    /// stream reads all of them, the others start empty like in `on:`
+    pub fn on(collection: &Value, access: Access) -> Rc<Self> {
+        let (text, items) = Self::elements(collection);
+        let limit = if access == Access::Read { items.len() } else { 0 };
+        Self::new(text, access, items, 0, limit)
+    }
+
+    /// a stream positioned after the elements, writing appends to them
+    pub fn with(collection: &Value, access: Access) -> Rc<Self> {
+        let (text, items) = Self::elements(collection);
+        let end = items.len();
+        Self::new(text, access, items, end, end)
+    }
+
+    /// a stream that is stored again, `data` is written by `snapshot`
+    pub(crate) fn restore(kind: &str, data: &str, collection: &Value) -> Option<Rc<Self>> {
+        let access = match kind {
+            "ReadStream" => Access::Read,
+            "WriteStream" => Access::Write,
+            "ReadWriteStream" => Access::ReadWrite,
+            _ => return None,
+        };
+        let (text, items) = Self::elements(collection);
+        let (position, limit) = match data.split_once(' ') {
+            Some((p, l)) => (p.parse().ok()?, l.parse().ok()?),
+            None => (data.parse().ok()?, items.len()),
+        };
+        (position <= limit && limit <= items.len()).then(|| Self::new(text, access, items, position, limit))
+    }
+
+    /// opens a file, `Read` needs it to exist, `Write` starts it empty
+    fn open(path: &str, access: Access) -> Value {
+        let content = match access {
+            Access::Write => Ok(String::new()),
+            _ => fs::read_to_string(path),
+        };
+        let content = match content {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound && access == Access::ReadWrite => String::new(),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return exc::signal_error("FileDoesNotExist", &format!("{} does not exist", path))
+            }
+            Err(e) => return exc::signal_error("FileException", &format!("{}: {}", path, e)),
+        };
+        let items: Vec<Rc<dyn Receiver>> = content.chars().map(char_receiver).collect();
+        let limit = items.len();
+        let state = State {
+            items,
+            position: 0,
+            limit,
+        };
+        Value::Object(Self::create(true, access, state, Some(PathBuf::from(path))))
+    }
+
+    fn elements(collection: &Value) -> (bool, Vec<Rc<dyn Receiver>>) {
+        match str::text_of(collection) {
+            Some(text) => (true, text.chars().map(char_receiver).collect()),
+            None => (false, col::elements_of(collection)),
+        }
+    }
+
+    fn myself(&self) -> Value {
+        Value::Object(self.myself.upgrade().unwrap())
+    }
+
+    /// a string or an array of the elements
+    fn collection(&self, items: &[Rc<dyn Receiver>]) -> Rc<dyn Receiver> {
+        if self.text {
+            let text = items
+                .iter()
+                .filter_map(|c| char::from_u32(c.as_int()? as u32))
+                .collect();
+            Rc::new(StringReceiver::new(text))
+        } else {
+            Rc::new(ArrayReceiver(items.to_vec()))
+        }
+    }
+
+    fn contents(&self) -> Vec<Rc<dyn Receiver>> {
+        let state = self.state.borrow();
+        let end = match self.access {
+            Access::Write => state.position,
+            _ => state.limit,
+        };
+        state.items[..end].to_vec()
+    }
+
+    fn next(&self) -> Option<Rc<dyn Receiver>> {
+        let mut state = self.state.borrow_mut();
+        let next = state.items[..state.limit].get(state.position).cloned()?;
+        state.position += 1;
+        Some(next)
+    }
+
+    fn peek(&self) -> Option<Rc<dyn Receiver>> {
+        let state = self.state.borrow();
+        state.items[..state.limit].get(state.position).cloned()
+    }
+
+    /// the elements up to the first one the test answers true for, that
+    /// one is skipped
+    fn next_until(&self, stop: impl Fn(&Rc<dyn Receiver>) -> bool) -> Vec<Rc<dyn Receiver>> {
+        let mut found = vec![];
+        while let Some(x) = self.next() {
+            if stop(&x) {
+                break;
+            }
+            found.push(x);
+        }
+        found
+    }
+
+    fn put(&self, x: Rc<dyn Receiver>) {
+        let mut state = self.state.borrow_mut();
+        let position = state.position;
+        match state.items.get_mut(position) {
+            Some(item) => *item = x,
+            None => state.items.push(x),
         }
+        state.position += 1;
+        state.limit = state.limit.max(state.position);
     }
 
-    fn next_element(&self) -> Rc<dyn Receiver> {
-        let idxobj;
-        {
-            let mut idx = self.idx.lock().unwrap();
-            idxobj = Rc::new(IntReceiver::new(*idx));
-            *idx += 1;
+    /// writes one element, a character if the stream is on a string
+    fn put_element(&self, x: &Value) -> Option<Value> {
+        if self.text && !matches!(x, Value::Char(_)) {
+            return Some(exc::signal_error("Error", &format!("{} is not a character", x)));
         }
-        self.buf.receive_message("basicAt:", vec![idxobj])
+        self.put(x.to_receiver());
+        None
+    }
+
+    fn put_text(&self, text: &str) {
+        text.chars().for_each(|c| self.put(char_receiver(c)));
     }
 
-    fn next_put(&self, args: &Vec<Rc<dyn Receiver>>) -> Rc<dyn Receiver> {
-        let idxobj;
-        {
-            let mut idx = self.idx.lock().unwrap();
-            idxobj = Rc::new(IntReceiver::new(*idx));
-            *idx += 1;
+    fn put_all(&self, x: &Value) {
+        match str::text_of(x) {
+            Some(text) if self.text => self.put_text(&text),
+            _ => col::elements_of(x).into_iter().for_each(|e| {
+                self.put_element(&Value::from_receiver(e));
+            }),
         }
-        self.buf
-            .receive_message("basicAt:put:", vec![idxobj, args[0].clone()])
     }
+
+    /// the messages a stream doesn't understand because of its access
+    fn not_allowed(&self, selector: &'static str) -> Option<Value> {
+        let reading = READ_PROTOCOL.contains(&selector);
+        let writing = WRITE_PROTOCOL.contains(&selector);
+        let allowed = match self.access {
+            Access::Read => !writing,
+            Access::Write => !reading,
+            Access::ReadWrite => true,
+        };
+        let text = format!("a {} can't {}", self.class_name(), selector);
+        (!allowed).then(|| exc::signal_error("Error", &text))
+    }
+
+    /// writes the file of the stream, the contents replace what was there
+    fn close(&self) -> Option<Value> {
+        let path = self.file.as_ref().filter(|_| self.access != Access::Read)?;
+        let text = self.collection(&self.contents()).to_string();
+        match fs::write(path, text) {
+            Ok(()) => None,
+            Err(e) => Some(exc::signal_error(
+                "FileException",
+                &format!("{}: {}", path.display(), e),
+            )),
+        }
+    }
+}
+
+fn char_receiver(c: char) -> Rc<dyn Receiver> {
+    Rc::new(CharReceiver::new(c))
 }
 
 impl Receiver for StreamReceiver {
-    fn receive_message(
-        &self,
-        selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        if let Some(error) = self.not_allowed(selector) {
+            return error;
+        }
+        let collection = |items: &[Rc<dyn Receiver>]| Value::Object(self.collection(items));
         match selector {
-            "next" => self.next_element(),
-            "nextPut:" => self.next_put(&args),
-            "nextPutAll:" => self.next_put(&args),
-            "atEnd" => {
-                let n = self.buf.receive_message("size", vec![]);
-                {
-                    let idx = self.idx.lock().unwrap();
-                    if *idx < n.as_int().unwrap() {
-                        Rc::new(IntReceiver::new(0))
-                    } else {
-                        Rc::new(IntReceiver::new(1))
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(format!("a {}", self.class_name()));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if ![READ_PROTOCOL, WRITE_PROTOCOL, PROTOCOL].iter().any(|p| p.contains(&selector)) => {
+                self.does_not_understand(selector, args)
+            }
+            "next" => self.next().map_or(Value::Nil, Value::from_receiver),
+            "next:" => {
+                let n = args[0].as_int().unwrap_or(0).max(0) as usize;
+                let items: Vec<_> = (0..n).map_while(|_| self.next()).collect();
+                collection(&items)
+            }
+            "peek" => self.peek().map_or(Value::Nil, Value::from_receiver),
+            "skip:" => {
+                let mut state = self.state.borrow_mut();
+                let n = args[0].as_int().unwrap_or(0);
+                state.position = (state.position as isize + n).clamp(0, state.limit as isize) as usize;
+                drop(state);
+                self.myself()
+            }
+            "upTo:" => {
+                let items = self.next_until(|x| col::equal(x, &args[0]));
+                collection(&items)
+            }
+            "upToEnd" => {
+                let items = self.next_until(|_| false);
+                collection(&items)
+            }
+            "nextLine" => match self.peek() {
+                Some(_) => {
+                    let mut items = self.next_until(|x| x.as_int() == Some('\n' as isize));
+                    if items.last().is_some_and(|x| x.as_int() == Some('\r' as isize)) {
+                        items.pop();
                     }
+                    collection(&items)
                 }
+                None => Value::Nil,
+            },
+            "skipSeparators" => {
+                let separator = |c: &Rc<dyn Receiver>| c.as_int().and_then(|c| char::from_u32(c as u32));
+                while self.peek().and_then(|c| separator(&c)).is_some_and(char::is_whitespace) {
+                    self.next();
+                }
+                self.myself()
             }
-            "upTo:" => {
-                let end_c = char::from_u32(args[0].as_int().unwrap() as u32).unwrap();
-                let mut buf = vec![];
-                loop {
-                    let c = char::from_u32(self.next_element().as_int().unwrap() as u32).unwrap();
-                    if c == end_c {
-                        break;
+            "do:" => {
+                while let Some(x) = self.next() {
+                    args[0].send("value:", vec![Value::from_receiver(x)]);
+                }
+                self.myself()
+            }
+            "atEnd" => {
+                let state = self.state.borrow();
+                Value::Bool(state.position >= state.limit)
+            }
+            "nextPut:" => self.put_element(&args[0]).unwrap_or_else(|| args[0].clone()),
+            "nextPutAll:" => {
+                self.put_all(&args[0]);
+                args[0].clone()
+            }
+            "print:" | "<<" => {
+                match str::text_of(&args[0]) {
+                    Some(text) if selector == "<<" => self.put_text(&text),
+                    _ if self.text => self.put_text(&args[0].to_string()),
+                    _ => self.put(args[0].to_receiver()),
+                }
+                self.myself()
+            }
+            "tab" | "space" | "cr" => {
+                let c = match selector {
+                    "tab" => '\t',
+                    "space" => ' ',
+                    _ => '\n',
+                };
+                self.put(char_receiver(c));
+                self.myself()
+            }
+            "contents" => collection(&self.contents()),
+            "position" => Value::Int(self.state.borrow().position as isize),
+            "position:" => {
+                let mut state = self.state.borrow_mut();
+                match args[0].as_int() {
+                    Some(p) if p >= 0 && p as usize <= state.limit => state.position = p as usize,
+                    _ => {
+                        drop(state);
+                        let text = format!("position {} out of bounds", args[0]);
+                        return exc::signal_error("SubscriptOutOfBounds", &text);
                     }
-                    buf.push(c);
                 }
-                Rc::new(StringReceiver::new(buf.iter().collect()))
+                drop(state);
+                self.myself()
+            }
+            "reset" => {
+                self.state.borrow_mut().position = 0;
+                self.myself()
+            }
+            "setToEnd" => {
+                let mut state = self.state.borrow_mut();
+                state.position = state.limit;
+                drop(state);
+                self.myself()
+            }
+            "isEmpty" => Value::Bool(self.state.borrow().limit == 0),
+            "size" => Value::Int(self.state.borrow().limit as isize),
+            "close" | "flush" => self.close().unwrap_or_else(|| self.myself()),
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        match (self.access, &self.file) {
+            (_, Some(_)) => "FileStream",
+            (Access::Read, None) => "ReadStream",
+            (Access::Write, None) => "WriteStream",
+            (Access::ReadWrite, None) => "ReadWriteStream",
+        }
+    }
+
+    fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Ok(state) = self.state.try_borrow() {
+            state.items.iter().for_each(|x| visit(heap::address(x)));
+        }
+    }
+
+    fn clear_references(&self) {
+        self.state.borrow_mut().items.clear();
+    }
+
+    /// the position and the read limit, the elements are the slot. A file
+    /// stream can't be stored.
+    fn snapshot(&self) -> Option<Snapshot> {
+        if self.file.is_some() {
+            return None;
+        }
+        let state = self.state.borrow();
+        let collection = self.collection(&state.items);
+        let data = format!("{} {}", state.position, state.limit);
+        Some(Snapshot::new(self.class_name(), data).slot("collection", collection))
+    }
+}
+
+/// the class side of the stream classes
+pub struct StreamClass(&'static str);
+
+const CLASSES: &[&str] = &["ReadStream", "WriteStream", "ReadWriteStream", "FileStream"];
+
+impl StreamClass {
+    /// the class with this name, `None` if it isn't a stream class
+    pub fn get(name: &str) -> Option<Rc<dyn Receiver>> {
+        let name = CLASSES.iter().find(|c| **c == name)?;
+        Some(Rc::new(StreamClass(name)))
+    }
+
+    fn understands(&self, selector: &str) -> bool {
+        let protocol = match self.0 {
+            "ReadStream" => READ_CLASS_PROTOCOL,
+            "FileStream" => FILE_CLASS_PROTOCOL,
+            _ => WRITE_CLASS_PROTOCOL,
+        };
+        protocol.contains(&selector)
+    }
+}
+
+impl Receiver for StreamClass {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let path = || str::text_of(&args[0]).unwrap_or_else(|| args[0].to_string());
+        match (self.0, selector) {
+            (_, "basic_write_to") => {
+                let a0 = StringReceiver::new(self.0.to_string());
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
             }
-            _ => todo!("method {}", selector),
+            _ if !self.understands(selector) => self.does_not_understand(selector, args),
+            ("ReadStream", "on:") => Value::Object(StreamReceiver::on(&args[0], Access::Read)),
+            ("WriteStream", "on:") => Value::Object(StreamReceiver::on(&args[0], Access::Write)),
+            ("WriteStream", "with:") => Value::Object(StreamReceiver::with(&args[0], Access::Write)),
+            ("ReadWriteStream", "on:") => Value::Object(StreamReceiver::on(&args[0], Access::ReadWrite)),
+            ("ReadWriteStream", "with:") => Value::Object(StreamReceiver::with(&args[0], Access::ReadWrite)),
+            ("FileStream", "readOnlyFileNamed:") => StreamReceiver::open(&path(), Access::Read),
+            ("FileStream", "forceNewFileNamed:") => StreamReceiver::open(&path(), Access::Write),
+            ("FileStream", "fileNamed:") => StreamReceiver::open(&path(), Access::ReadWrite),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        SelectorSet::get(&format!("{} class", self.0))
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", self.0))
     }
 }
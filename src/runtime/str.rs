@@ -1,10 +1,196 @@
-use std::{fs::File, io::Read, path::Path, rc::Rc, sync::Mutex};
+use std::{rc::Rc, sync::Mutex};
 
+use num_bigint::BigInt;
 use tracing::info;
 
-use crate::{parse_method, parser::AST, runtime::stm::StreamReceiver, Context};
+use crate::{image::Snapshot, repo, runtime::stm::{Access, StreamReceiver}, MethodContext};
 
-use super::{int::IntReceiver, sel::SelectorSet, Receiver, chr::CharReceiver};
+use super::{
+    arr::ArrayReceiver,
+    chr::CharReceiver,
+    col::{self, Species},
+    exc,
+    num::{FloatReceiver, Number},
+    sym::SymbolReceiver,
+    val::Value,
+    Receiver,
+};
+
+/// the text of strings and symbols, `None` for other objects
+pub fn text_of(r: &Value) -> Option<String> {
+    match r {
+        Value::Object(r) => r.as_text(),
+        _ => None,
+    }
+}
+
+fn char_of(r: &Value) -> Option<char> {
+    match r {
+        Value::Char(c) => Some(*c),
+        _ => None,
+    }
+}
+
+fn string(s: String) -> Value {
+    Value::Object(Rc::new(StringReceiver::new(s)))
+}
+
+fn strings<'a>(parts: impl Iterator<Item = &'a str>) -> Value {
+    let parts = parts.map(|s| Rc::new(StringReceiver::new(s.to_string())) as Rc<dyn Receiver>);
+    Value::Object(Rc::new(ArrayReceiver(parts.collect())))
+}
+
+fn not_a_string(r: &Value) -> Value {
+    exc::signal_error("Error", &format!("{} is not a string", r))
+}
+
+/// `12`, `-3` or `2.5`, nil for anything else
+fn as_number(text: &str) -> Value {
+    let text = text.trim();
+    if let Ok(n) = text.parse::<BigInt>() {
+        return Number::Large(n).into_value();
+    }
+    match text.parse::<f64>() {
+        Ok(f) if f.is_finite() && text.contains('.') => Value::Object(Rc::new(FloatReceiver::new(f))),
+        _ => Value::Nil,
+    }
+}
+
+/// the messages the class `String` understands natively
+pub(crate) const CLASS_PROTOCOL: &[&str] = &["new", "new:", "new:streamContents:"];
+
+/// the messages strings understand natively besides those of all
+/// collections
+pub(crate) const PROTOCOL: &[&str] = &[
+    ",",
+    "=",
+    "~=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "sameAs:",
+    "size",
+    "at:",
+    "first",
+    "last",
+    "copyFrom:to:",
+    "indexOf:",
+    "includesSubstring:",
+    "beginsWith:",
+    "endsWith:",
+    "asUppercase",
+    "asLowercase",
+    "trimBoth",
+    "reversed",
+    "reverse",
+    "substrings",
+    "substrings:",
+    "lines",
+    "asSymbol",
+    "asString",
+    "asNumber",
+    "isString",
+    "species",
+    "readStream",
+    "writeStream",
+    "readWriteStream",
+    "basicAt:",
+    "basicAt:put:",
+];
+
+/// the messages strings and symbols share, `None` for others. Indices
+/// count characters, not bytes, and start at 1 like the ones of arrays.
+pub fn receive_text(text: &str, selector: &'static str, args: &[Value]) -> Option<Value> {
+    if !PROTOCOL.contains(&selector) {
+        let elements = text.chars().map(|c| Rc::new(CharReceiver::new(c)) as Rc<dyn Receiver>);
+        let myself = Rc::new(StringReceiver::new(text.to_string()));
+        return col::enumerate(myself, elements.collect(), Species::Array, Species::Array, selector, args);
+    }
+    let chars = || text.chars().collect::<Vec<_>>();
+    let r = match selector {
+        "," => match text_of(&args[0]) {
+            Some(t) => string(format!("{}{}", text, t)),
+            None => not_a_string(&args[0]),
+        },
+        "=" => Value::Bool(text_of(&args[0]).is_some_and(|t| t == text)),
+        "~=" => Value::Bool(text_of(&args[0]).is_none_or(|t| t != text)),
+        "<" | ">" | "<=" | ">=" => match text_of(&args[0]) {
+            Some(t) => {
+                let order = text.cmp(t.as_str());
+                Value::Bool(match selector {
+                    "<" => order.is_lt(),
+                    ">" => order.is_gt(),
+                    "<=" => order.is_le(),
+                    _ => order.is_ge(),
+                })
+            }
+            None => not_a_string(&args[0]),
+        },
+        "sameAs:" => Value::Bool(text_of(&args[0]).is_some_and(|t| t.to_lowercase() == text.to_lowercase())),
+        "size" => Value::Int(text.chars().count() as isize),
+        "at:" => {
+            let chars = chars();
+            match col::index(chars.len(), &args[0]) {
+                Some(idx) => Value::Char(chars[idx]),
+                None => Value::Nil,
+            }
+        }
+        "first" | "last" => {
+            let c = if selector == "first" { text.chars().next() } else { text.chars().last() };
+            match c {
+                Some(c) => Value::Char(c),
+                None => exc::signal_error("CollectionIsEmpty", "the String is empty"),
+            }
+        }
+        "copyFrom:to:" => {
+            let chars = chars();
+            match (args[0].as_int(), args[1].as_int()) {
+                (Some(from), Some(to)) if 1 <= from && from <= to + 1 && to <= chars.len() as isize => {
+                    string(chars[(from - 1) as usize..to as usize].iter().collect())
+                }
+                _ => {
+                    let text = format!("{} to {} out of bounds", args[0], args[1]);
+                    exc::signal_error("SubscriptOutOfBounds", &text)
+                }
+            }
+        }
+        "indexOf:" => {
+            let idx = char_of(&args[0]).and_then(|c| text.chars().position(|x| x == c));
+            // 0 if the character is not found
+            Value::Int(idx.map_or(0, |i| i as isize + 1))
+        }
+        "includesSubstring:" => match text_of(&args[0]) {
+            Some(t) => Value::Bool(text.contains(t.as_str())),
+            None => not_a_string(&args[0]),
+        },
+        "beginsWith:" | "endsWith:" => match text_of(&args[0]) {
+            Some(t) if selector == "beginsWith:" => Value::Bool(text.starts_with(t.as_str())),
+            Some(t) => Value::Bool(text.ends_with(t.as_str())),
+            None => not_a_string(&args[0]),
+        },
+        "asUppercase" => string(text.to_uppercase()),
+        "asLowercase" => string(text.to_lowercase()),
+        "trimBoth" => string(text.trim().to_string()),
+        "reversed" | "reverse" => string(text.chars().rev().collect()),
+        "substrings" => strings(text.split_whitespace()),
+        "substrings:" => {
+            let separators: Vec<char> = match (char_of(&args[0]), text_of(&args[0])) {
+                (Some(c), _) => vec![c],
+                (None, Some(t)) => t.chars().collect(),
+                (None, None) => return Some(not_a_string(&args[0])),
+            };
+            strings(text.split(|c| separators.contains(&c)).filter(|s| !s.is_empty()))
+        }
+        "lines" => strings(text.lines()),
+        "asSymbol" => Value::Object(Rc::new(SymbolReceiver::new(text))),
+        "asString" => string(text.to_string()),
+        "asNumber" => as_number(text),
+        "isString" => Value::Bool(true),
+        _ => return None,
+    };
+    Some(r)
+}
 
 pub struct StringMetaReceiver {}
 
@@ -12,25 +198,34 @@ impl Receiver for StringMetaReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        args: Vec<Value>,
+    ) -> Value {
         match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(String::from("String"));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !CLASS_PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            "new" | "new:" => string(String::new()),
             "new:streamContents:" => {
-                let result = Rc::new(StringReceiver::new(String::new()));
-                let stream = Rc::new(StreamReceiver::new(result.clone()));
-                args[1].receive_message("value:", vec![stream]);
-                result
+                let stream = Value::Object(StreamReceiver::on(&string(String::new()), Access::Write));
+                args[1].receive_message("value:", vec![stream.clone()]);
+                stream.receive_message("contents", vec![])
             }
-            _ => todo!("{}", selector),
+            _ => self.does_not_understand(selector, args),
         }
     }
 
     fn as_int(&self) -> Option<isize> {
-        todo!()
+        None
     }
 
     fn as_str(&self) -> Option<&'static str> {
-        todo!()
+        Some("String")
+    }
+
+    fn class_name(&self) -> &'static str {
+        "String class"
     }
 }
 
@@ -42,43 +237,46 @@ impl Receiver for StringReceiver {
     fn receive_message(
         &self,
         selector: &'static str,
-        _args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
+        _args: Vec<Value>,
+    ) -> Value {
         match selector {
-            "species" => Rc::new(StringMetaReceiver {}),
-            "size" => Rc::new(IntReceiver::new(self.val.lock().unwrap().len() as isize)),
-            "readStream" => {
-                let s = self.val.lock().unwrap();
-                let stream = Rc::new(StreamReceiver::new(Rc::new(StringReceiver::new(s.clone()))));
-                stream
+            "write" => {
+                let mut s = self.val.lock().unwrap();
+                s.push_str(&text_of(&_args[0]).unwrap());
+                _args[0].clone()
+            }
+            "basic_write_to" => {
+                let s = {
+                    let content = self.val.lock().unwrap();
+                    content.clone()
+                };
+                _args[0].receive_message("write", vec![Value::Object(Rc::new(StringReceiver::new(s)))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.execute_stored_method(selector, _args),
+            "species" => Value::Object(Rc::new(StringMetaReceiver {})),
+            "readStream" | "writeStream" | "readWriteStream" => {
+                let access = match selector {
+                    "readStream" => Access::Read,
+                    "writeStream" => Access::Write,
+                    _ => Access::ReadWrite,
+                };
+                Value::Object(StreamReceiver::on(&string(self.value()), access))
             }
             "basicAt:" => {
                 let s = self.val.lock().unwrap();
                 let idx = _args[0].as_int().unwrap();
-                let c = s.chars().nth(idx as usize).unwrap();
-                Rc::new(CharReceiver::new(c))
+                Value::Char(s.chars().nth(idx as usize).unwrap())
             }
             "basicAt:put:" => {
                 let result = _args[1].clone();
                 {
                     let mut s = self.val.lock().unwrap();
-                    let idx = _args[0].as_int().unwrap();
-                    s.insert_str(idx as usize, result.as_str().unwrap());
+                    let idx = _args[0].as_int().unwrap() as usize;
+                    let at = s.char_indices().nth(idx).map_or(s.len(), |(i, _)| i);
+                    s.insert_str(at, &char_of(&result).map(String::from).or_else(|| text_of(&result)).unwrap());
                 }
                 result
             }
-            "write" => {
-                let mut s = self.val.lock().unwrap();
-                s.push_str(_args[0].as_str().unwrap());
-                _args[0].clone()
-            }
-            "basic_write_to" => {
-                let s = {
-                    let content = self.val.lock().unwrap();
-                    content.clone()
-                };
-                _args[0].receive_message("write", vec![Rc::new(StringReceiver::new(s))])
-            }
             _ => self.execute_stored_method(selector, _args),
         }
 
@@ -92,7 +290,24 @@ impl Receiver for StringReceiver {
         }
     }
     fn as_str(&self) -> Option<&'static str> {
-        Some(SelectorSet::get(self.val.lock().unwrap().as_str()))
+        None
+    }
+
+    fn as_text(&self) -> Option<String> {
+        Some(self.value())
+    }
+
+    fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        let s = self.val.lock().unwrap();
+        Some(s.chars().map(|c| Rc::new(CharReceiver::new(c)) as Rc<dyn Receiver>).collect())
+    }
+
+    fn class_name(&self) -> &'static str {
+        "String"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("String", self.val.lock().unwrap().clone()))
     }
 }
 
@@ -103,44 +318,23 @@ impl StringReceiver {
         }
     }
 
-    fn execute_stored_method(
-        &self,
-        selector: &str,
-        args: Vec<Rc<dyn Receiver>>,
-    ) -> Rc<dyn Receiver> {
-        let p = format!("defs/string/{}", selector).replace(r":", "_");
-        let p = Path::new(&p);
-        if !p.exists() {
-            panic!("unresolved method {}", selector);
-        }
+    /// a copy of the string, `as_str` interns it and keeps it for good
+    pub fn value(&self) -> String {
+        self.val.lock().unwrap().clone()
+    }
 
-        let mut f = File::open(p).unwrap();
-        let mut buf = String::new();
-        f.read_to_string(&mut buf).unwrap();
-
-        let t = parse_method(buf).unwrap()[0].clone();
-        // trace!("tree: {}", t);
-        let m = t.as_abstract_syntax_tree();
-        match m {
-            AST::Method {
-                name,
-                params,
-                temps,
-                body,
-            } => {
-                let myself: Rc<dyn Receiver> =
-                    Rc::new(StringReceiver::new(self.val.lock().unwrap().clone()));
-                let mut ctx = Context::new(myself);
-                info!("name: {}", name);
-                info!("params: {:?}", params);
-                info!("temps: {:?}", temps);
-                // trace!("AST: {:#?}", &body);
-                for idx in 0..params.len() {
-                    ctx.set_receiver(params[idx], args[idx].clone());
+    fn execute_stored_method(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        let code = match repo::current().compiled("String", selector) {
+            Some(code) => code,
+            None => {
+                return match receive_text(&self.value(), selector, &args) {
+                    Some(r) => r,
+                    None => self.does_not_understand(selector, args),
                 }
-                ctx.eval_to_reciever(&body)
             }
-            _ => todo!("I only know how to deal with a method."),
-        }
+        };
+        info!("name: {}", selector);
+        let myself: Rc<dyn Receiver> = Rc::new(StringReceiver::new(self.val.lock().unwrap().clone()));
+        code.run(MethodContext::for_method(myself, None, args))
     }
 }
@@ -0,0 +1,82 @@
+use std::rc::Rc;
+
+use super::{
+    sel::{self, SelectorSet},
+    str::{self, StringReceiver},
+    val::Value,
+    Receiver,
+};
+use crate::image::Snapshot;
+
+/// the messages symbols understand natively besides those of all strings
+pub(crate) const PROTOCOL: &[&str] = &["==", "=", "size", "numArgs", "asString", "asSymbol"];
+
+/// a symbol is an interned string from the `SelectorSet`, two symbols with
+/// the same name are the same object
+pub struct SymbolReceiver(&'static str);
+
+impl SymbolReceiver {
+    pub fn new(name: &str) -> Self {
+        Self(SelectorSet::get(name))
+    }
+
+    /// the protocol of symbols only, `None` for other messages
+    fn receive(&self, selector: &'static str, args: &[Value]) -> Option<Value> {
+        if !PROTOCOL.contains(&selector) {
+            return None;
+        }
+        let r = match selector {
+            "==" | "=" => Value::Bool(args[0].as_symbol().is_some_and(|s| std::ptr::eq(s, self.0))),
+            "size" => Value::Int(self.0.chars().count() as isize),
+            "numArgs" => Value::Int(sel::arity(self.0) as isize),
+            "asString" => Value::Object(Rc::new(StringReceiver::new(self.0.to_string()))),
+            "asSymbol" => Value::Object(Rc::new(SymbolReceiver(self.0))),
+            _ => return None,
+        };
+        Some(r)
+    }
+}
+
+impl Receiver for SymbolReceiver {
+    fn receive_message(
+        &self,
+        selector: &'static str,
+        args: Vec<Value>,
+    ) -> Value {
+        if selector == "basic_write_to" {
+            let a0 = StringReceiver::new(format!("#{}", self.0));
+            return args[0].receive_message("write", vec![Value::Object(Rc::new(a0))]);
+        }
+        let r = self
+            .receive(selector, &args)
+            .or_else(|| str::receive_text(self.0, selector, &args));
+        match r {
+            Some(r) => r,
+            None => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        Some(self.0)
+    }
+
+    fn as_text(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+
+    fn class_name(&self) -> &'static str {
+        "Symbol"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Symbol", self.0))
+    }
+
+    fn as_symbol(&self) -> Option<&'static str> {
+        Some(self.0)
+    }
+}
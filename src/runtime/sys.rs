@@ -0,0 +1,90 @@
+use std::rc::Rc;
+
+use super::{
+    arr::ArrayReceiver,
+    exc, glb, rfl,
+    sel::SelectorSet,
+    str::{self, StringReceiver},
+    sym::SymbolReceiver,
+    val::Value,
+    Receiver,
+};
+use crate::{heap, image::Snapshot};
+
+/// the messages `Smalltalk` understands natively
+pub(crate) const PROTOCOL: &[&str] = &[
+    "garbageCollect",
+    "memoryStatistics",
+    "allClasses",
+    "classNames",
+    "at:",
+    "at:ifAbsent:",
+    "at:put:",
+    "includesKey:",
+];
+
+/// `Smalltalk`, the system itself
+pub struct SystemReceiver;
+
+impl Receiver for SystemReceiver {
+    fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        match selector {
+            "basic_write_to" => {
+                let a0 = StringReceiver::new(String::from("Smalltalk"));
+                args[0].receive_message("write", vec![Value::Object(Rc::new(a0))])
+            }
+            _ if !PROTOCOL.contains(&selector) => self.does_not_understand(selector, args),
+            // answers the number of objects freed
+            "garbageCollect" => Value::Int(heap::collect() as isize),
+            "memoryStatistics" => Value::Object(Rc::new(StringReceiver::new(heap::stats().to_string()))),
+            "allClasses" => {
+                let classes = rfl::class_names().into_iter().filter_map(rfl::class_named);
+                Value::Object(Rc::new(ArrayReceiver(classes.collect())))
+            }
+            "classNames" => {
+                let names = rfl::class_names().into_iter();
+                Value::Object(Rc::new(ArrayReceiver(
+                    names
+                        .map(|n| Rc::new(SymbolReceiver::new(n)) as Rc<dyn Receiver>)
+                        .collect(),
+                )))
+            }
+            "at:" | "at:ifAbsent:" | "includesKey:" => {
+                let name = str::text_of(&args[0]).unwrap_or_default();
+                let value = rfl::class_named(&name).or_else(|| glb::named(&name));
+                match (selector, value) {
+                    ("includesKey:", value) => Value::Bool(value.is_some()),
+                    (_, Some(value)) => Value::from_receiver(value),
+                    ("at:ifAbsent:", None) => args[1].send("value", vec![]),
+                    (_, None) => exc::signal_error("KeyNotFound", &format!("key {} not found", args[0])),
+                }
+            }
+            // declares the global if there is none with this name
+            "at:put:" => {
+                let name = SelectorSet::get(&str::text_of(&args[0]).unwrap_or_else(|| args[0].to_string()));
+                if !glb::assign(name, args[1].to_receiver()) {
+                    glb::declare(name, false);
+                    glb::assign(name, args[1].to_receiver());
+                }
+                args[1].clone()
+            }
+            _ => self.does_not_understand(selector, args),
+        }
+    }
+
+    fn as_int(&self) -> Option<isize> {
+        None
+    }
+
+    fn as_str(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn class_name(&self) -> &'static str {
+        "SystemDictionary"
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot::new("Global", "Smalltalk"))
+    }
+}
@@ -0,0 +1,165 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::heap;
+
+use super::{
+    boo::{FalseReceiver, TrueReceiver},
+    chr::CharReceiver,
+    cls::ClassDef,
+    int::IntReceiver,
+    mtd::{self, ClassKey},
+    nil::NilReciever,
+    num::Number,
+    Receiver,
+};
+
+/// a value as the machine keeps it, small integers, characters, Booleans
+/// and nil are immediates, only other objects live on the heap
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(isize),
+    Char(char),
+    Object(Rc<dyn Receiver>),
+}
+
+impl Value {
+    /// the receiver as a value, receivers of immediates are unboxed
+    pub fn from_receiver(r: Rc<dyn Receiver>) -> Self {
+        match r.class_key() {
+            ("SmallInteger", false) => Value::Int(r.as_int().unwrap()),
+            ("Character", false) => Value::Char(char::from_u32(r.as_int().unwrap() as u32).unwrap()),
+            ("True", false) => Value::Bool(true),
+            ("False", false) => Value::Bool(false),
+            ("UndefinedObject", false) => Value::Nil,
+            _ => Value::Object(r),
+        }
+    }
+
+    /// boxes an immediate for the receivers that take only objects
+    pub fn to_receiver(&self) -> Rc<dyn Receiver> {
+        match self {
+            Value::Nil => NilReciever::get(),
+            Value::Bool(true) => TrueReceiver::get(),
+            Value::Bool(false) => FalseReceiver::get(),
+            Value::Int(n) => Rc::new(IntReceiver::new(*n)),
+            Value::Char(c) => Rc::new(CharReceiver::new(*c)),
+            Value::Object(r) => r.clone(),
+        }
+    }
+
+    pub fn as_int(&self) -> Option<isize> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Object(r) if r.class_name() == "SmallInteger" => r.as_int(),
+            _ => None,
+        }
+    }
+
+    /// see `Receiver::as_str`
+    pub fn as_str(&self) -> Option<&'static str> {
+        self.with_receiver(|r| r.as_str())
+    }
+
+    /// the text of a string or a symbol, see `str::text_of`
+    pub fn as_text(&self) -> Option<String> {
+        super::str::text_of(self)
+    }
+
+    /// the interned name if the value is a symbol
+    pub fn as_symbol(&self) -> Option<&'static str> {
+        match self {
+            Value::Object(r) => r.as_symbol(),
+            _ => None,
+        }
+    }
+
+    /// the numeric value if the value is a number
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Int(n) => Some(Number::Small(*n)),
+            Value::Object(r) => r.as_number(),
+            _ => None,
+        }
+    }
+
+    /// the elements if the value is a collection
+    pub fn elements(&self) -> Option<Vec<Rc<dyn Receiver>>> {
+        match self {
+            Value::Object(r) => r.elements(),
+            _ => None,
+        }
+    }
+
+    /// the user defined class behind the value
+    pub fn class_def(&self) -> Option<Rc<ClassDef>> {
+        match self {
+            Value::Object(r) => r.class_def(),
+            _ => None,
+        }
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "UndefinedObject",
+            Value::Bool(true) => "True",
+            Value::Bool(false) => "False",
+            Value::Int(_) => "SmallInteger",
+            Value::Char(_) => "Character",
+            Value::Object(r) => r.class_name(),
+        }
+    }
+
+    /// the class methods are looked up in
+    pub fn class_key(&self) -> ClassKey {
+        match self {
+            Value::Object(r) => r.class_key(),
+            v => (v.class_name(), false),
+        }
+    }
+
+    /// reports the object to the collector, immediates refer to nothing
+    pub fn references(&self, visit: &mut dyn FnMut(usize)) {
+        if let Value::Object(r) = self {
+            visit(heap::address(r));
+        }
+    }
+
+    /// sends a message, the method is looked up in the dictionaries of
+    /// the class first
+    pub fn send(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        mtd::send(self, selector, args)
+    }
+
+    /// lets the receiver handle the message itself, immediates answer it
+    /// without being boxed
+    pub fn receive_message(&self, selector: &'static str, args: Vec<Value>) -> Value {
+        self.with_receiver(|r| r.receive_message(selector, args))
+    }
+
+    /// calls `f` with the receiver of the value, the ones of immediates
+    /// live on the stack
+    fn with_receiver<T>(&self, f: impl FnOnce(&dyn Receiver) -> T) -> T {
+        match self {
+            Value::Nil => f(&NilReciever),
+            Value::Bool(true) => f(&TrueReceiver),
+            Value::Bool(false) => f(&FalseReceiver),
+            Value::Int(n) => f(&IntReceiver::new(*n)),
+            Value::Char(c) => f(&CharReceiver::new(*c)),
+            Value::Object(r) => f(r.as_ref()),
+        }
+    }
+}
+
+impl From<Rc<dyn Receiver>> for Value {
+    fn from(r: Rc<dyn Receiver>) -> Self {
+        Value::from_receiver(r)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_receiver())
+    }
+}
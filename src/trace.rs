@@ -0,0 +1,64 @@
+//! The stack of running methods and blocks, used for the stack traces
+//! carried by exceptions.
+
+use std::cell::RefCell;
+
+use santiago::lexer::Position;
+
+thread_local! {
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Frame {
+    /// `Class>>selector`, or `[] in Class>>selector` for blocks
+    label: &'static str,
+    position: Option<Position>,
+}
+
+/// pops the frame again, also when the activation is unwound
+pub struct FrameGuard(usize);
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        FRAMES.with(|f| f.borrow_mut().truncate(self.0));
+    }
+}
+
+pub fn enter(label: &'static str) -> FrameGuard {
+    FRAMES.with(|f| {
+        let mut f = f.borrow_mut();
+        f.push(Frame {
+            label,
+            position: None,
+        });
+        FrameGuard(f.len() - 1)
+    })
+}
+
+/// the number of active frames
+pub fn depth() -> usize {
+    FRAMES.with(|f| f.borrow().len())
+}
+
+/// records the source position the innermost frame is executing
+pub fn set_position(position: &Position) {
+    FRAMES.with(|f| {
+        if let Some(frame) = f.borrow_mut().last_mut() {
+            frame.position = Some(position.clone());
+        }
+    })
+}
+
+/// one line per active frame, innermost first
+pub fn stack_trace() -> Vec<String> {
+    FRAMES.with(|f| {
+        f.borrow()
+            .iter()
+            .rev()
+            .map(|frame| match &frame.position {
+                Some(p) => format!("{} ({})", frame.label, p),
+                None => frame.label.to_string(),
+            })
+            .collect()
+    })
+}
@@ -22,7 +22,18 @@ impl TopSort {
         self.pairs.push(Pair(pred, succ));
     }
 
+    /// the nodes with each one after its predecessors, nodes on a cycle and
+    /// the ones after them are left out
     pub fn sorted(&self) -> Vec<usize> {
+        self.sort().0
+    }
+
+    /// the nodes `sorted` leaves out, empty if there is no cycle
+    pub fn unsorted(&self) -> Vec<usize> {
+        self.sort().1
+    }
+
+    fn sort(&self) -> (Vec<usize>, Vec<usize>) {
         let mut result = vec![];
         let mut map = BTreeMap::<usize, Node>::new();
         for x in self.pairs.iter() {
@@ -59,6 +70,9 @@ impl TopSort {
                 .filter(|(_, x)| x.count == 0)
                 .map(|(x, _)| *x)
                 .collect();
+            if zeros.is_empty() {
+                break;
+            }
 
             for x in zeros {
                 result.push(x);
@@ -75,7 +89,7 @@ impl TopSort {
                 }
             }
         }
-        result
+        (result, map.into_keys().collect())
     }
 }
 
@@ -94,3 +108,14 @@ fn test1() {
     tsort.add(2, 8);
     assert_eq!(tsort.sorted(), vec![1, 9, 2, 3, 7, 4, 5, 8, 6]);
 }
+
+#[test]
+fn test_cycle() {
+    let mut tsort = TopSort::new();
+    tsort.add(1, 2);
+    tsort.add(2, 3);
+    tsort.add(3, 2);
+    tsort.add(3, 4);
+    assert_eq!(tsort.sorted(), vec![1]);
+    assert_eq!(tsort.unsorted(), vec![2, 3, 4]);
+}
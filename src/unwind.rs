@@ -0,0 +1,111 @@
+//! Unwinding of activations. A `^` inside a block returns from the method
+//! that created the block, its home, and exception handlers return from,
+//! retry or resume the activation that installed them. All of them unwind the
+//! Rust stack up to the targeted activation, which is identified by a number
+//! handed out per activation.
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::runtime::{exc, val::Value};
+
+pub type Home = usize;
+
+/// home of contexts that are not part of a method activation
+pub const NO_HOME: Home = 0;
+
+thread_local! {
+    static NEXT_HOME: Cell<Home> = const { Cell::new(NO_HOME + 1) };
+    static ACTIVE: RefCell<Vec<Home>> = const { RefCell::new(Vec::new()) };
+    static UNWIND_VALUE: RefCell<Option<Value>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnwindKind {
+    /// leave the activation with a value
+    Return,
+    /// start the activation over, used by `retry`
+    Retry,
+    /// continue after a signal, used by `resume:`
+    Resume,
+}
+
+/// unwinding payload, names the activation it is caught by
+struct Unwind(Home, UnwindKind);
+
+/// how a guarded activation ended
+pub enum Exit {
+    Normal(Value),
+    Unwound(UnwindKind, Value),
+    /// a Rust panic, the payload is kept to continue unwinding
+    Failed(Box<dyn Any + Send>),
+}
+
+pub fn new_home() -> Home {
+    NEXT_HOME.with(|n| {
+        let home = n.get();
+        n.set(home + 1);
+        home
+    })
+}
+
+pub fn is_active(home: Home) -> bool {
+    ACTIVE.with(|a| a.borrow().contains(&home))
+}
+
+/// runs `f` as the activation `home`, catching unwinds targeted at it and
+/// Rust panics. Unwinds to other activations pass through.
+pub fn run_as<F: FnOnce() -> Value>(home: Home, f: F) -> Exit {
+    ACTIVE.with(|a| a.borrow_mut().push(home));
+    let r = panic::catch_unwind(AssertUnwindSafe(f));
+    ACTIVE.with(|a| a.borrow_mut().retain(|h| *h != home));
+    match r {
+        Ok(v) => Exit::Normal(v),
+        Err(payload) => match payload.downcast::<Unwind>() {
+            Ok(u) if u.0 == home => {
+                let value = UNWIND_VALUE.with(|v| v.borrow_mut().take()).unwrap();
+                Exit::Unwound(u.1, value)
+            }
+            Ok(u) => panic::resume_unwind(u),
+            Err(other) => Exit::Failed(other),
+        },
+    }
+}
+
+/// runs `f` as the activation `home`, a non-local return to `home`
+/// ends it with the returned value
+pub fn activate<F: FnOnce() -> Value>(home: Home, f: F) -> Value {
+    match run_as(home, f) {
+        Exit::Normal(v) | Exit::Unwound(_, v) => v,
+        Exit::Failed(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// unwinds to the activation `home`
+pub fn unwind_to(home: Home, kind: UnwindKind, value: Value) -> ! {
+    UNWIND_VALUE.with(|v| *v.borrow_mut() = Some(value));
+    panic::resume_unwind(Box::new(Unwind(home, kind)))
+}
+
+/// answers `value` from the activation of `home`. If that has already
+/// returned it signals `BlockCannotReturn` and answers the resumption value.
+pub fn return_from(home: Home, value: Value) -> Value {
+    if !is_active(home) {
+        return exc::signal_error("BlockCannotReturn", "the home of the block has already returned");
+    }
+    unwind_to(home, UnwindKind::Return, value)
+}
+
+/// the message of a Rust panic
+pub fn failure_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("unknown failure")
+    }
+}
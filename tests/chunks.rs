@@ -0,0 +1,86 @@
+use tt_rust::{
+    chunk::{chunks, file_in, file_out},
+    runtime::cls::ClassTable,
+    Workspace, TRACING,
+};
+
+const ACCOUNT: &str = "\"a bank account\"
+Object subclass: #Account
+    instanceVariableNames: 'balance'
+    classVariableNames: 'Count'
+    category: 'Bank'!
+
+!Account methodsFor: 'accessing'!
+balance
+    ^ balance
+!
+deposit: amount
+    balance := balance + amount
+! !
+
+!Account methodsFor: 'initialization'!
+init
+    balance := 0
+! !
+
+!Account class methodsFor: 'instance creation'!
+opened
+    ^ self new init
+! !
+
+a := Account opened.
+a deposit: 40; deposit: 2!
+";
+
+#[test]
+fn split_into_chunks() {
+    assert!(TRACING.clone());
+    let c: Vec<String> = chunks("a!! b! !x!\n y").into_iter().map(|c| c.text).collect();
+    assert_eq!(c, vec!["a! b", " ", "x", "\n y"]);
+}
+
+#[test]
+fn file_in_classes_methods_and_doits() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    let classes = file_in(&mut w, "account.st", ACCOUNT).unwrap();
+    assert_eq!(classes, vec!["Account"]);
+    let o = w.evaluate("a balance").unwrap();
+    assert_eq!(o.as_int(), Some(42));
+
+    let class = ClassTable::get("Account").unwrap();
+    assert_eq!(class.category(), "Bank");
+    assert_eq!(class.class_vars, vec!["Count"]);
+    assert_eq!(class.method_category(false, "deposit:"), "accessing");
+    assert_eq!(class.method_category(true, "opened"), "instance creation");
+}
+
+#[test]
+fn file_out_reads_back() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    file_in(&mut w, "account.st", ACCOUNT).unwrap();
+    let out = file_out(&["Account"]).unwrap();
+    assert!(out.starts_with(
+        "Object subclass: #Account\n    instanceVariableNames: 'balance'\n    classVariableNames: 'Count'\n    category: 'Bank'!\n\n!Account methodsFor: 'accessing'!\nbalance\n    ^balance\n!\ndeposit: amount\n"
+    ));
+    assert!(out.contains("!Account class methodsFor: 'instance creation'!\nopened\n"));
+
+    let mut w = Workspace::new();
+    assert_eq!(file_in(&mut w, "out.st", &out).unwrap(), vec!["Account"]);
+    assert_eq!(file_out(&["Account"]).unwrap(), out);
+    let o = w.evaluate("b := Account opened. b deposit: 5. b balance").unwrap();
+    assert_eq!(o.as_int(), Some(5));
+}
+
+#[test]
+fn file_in_errors_point_into_the_file() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    let e = file_in(&mut w, "bad.st", "Object subclass: #Bad!\n\n!Bad methodsFor: 'x'!\nfoo\n    ^ (1 + ]\n! !\n")
+        .err()
+        .unwrap();
+    assert!(format!("{}", e).starts_with("bad.st:5:12: unexpected ']'\n    ^ (1 + ]\n"));
+    let e = file_in(&mut w, "bad.st", "!Missing methodsFor: 'x'!\nfoo ^1! !").err().unwrap();
+    assert!(format!("{}", e).starts_with("bad.st:1:2: class Missing is not defined"));
+}
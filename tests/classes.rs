@@ -0,0 +1,110 @@
+use tt_rust::{define_classes, evaluate_script, TRACING};
+
+#[test]
+fn instance_variables() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Account [
+        | balance |
+        init [ balance := 0 ]
+        deposit: amount [ balance := balance + amount ]
+        balance [ ^balance ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from(
+        "
+    a := Account new.
+    a init.
+    a deposit: 30.
+    a deposit: 12.
+    a balance.",
+    ))
+    .unwrap();
+    assert_eq!(o.as_int(), Some(42));
+}
+
+#[test]
+fn superclass_lookup() {
+    assert!(TRACING.clone());
+    let names = define_classes(String::from(
+        "
+    Object subclass: Shape [
+        | name |
+        name [ ^name ]
+        area [ ^0 ]
+        describe [ ^self area ]
+    ]
+    Shape subclass: Square [
+        | side |
+        side: n [ side := n ]
+        area [ ^side * side ]
+        base [ ^super area ]
+    ]",
+    ))
+    .unwrap();
+    assert_eq!(names, vec!["Shape", "Square"]);
+    let o = evaluate_script(String::from("s := Square new. s side: 3. s describe.")).unwrap();
+    assert_eq!(o.as_int(), Some(9));
+    let o = evaluate_script(String::from("s := Square new. s side: 3. s base.")).unwrap();
+    assert_eq!(o.as_int(), Some(0));
+    let o = evaluate_script(String::from("Square new.")).unwrap();
+    assert_eq!(format!("{}", o), "a Square");
+}
+
+#[test]
+fn class_variables_and_methods() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Counter [
+        | count |
+        Instances := 0.
+        count [ ^count ]
+        setUp [ count := 10 ]
+        Counter class >> new [ Instances := Instances + 1. ^super new setUp ]
+        Counter class >> instances [ ^Instances ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from("Counter new. Counter new count.")).unwrap();
+    assert_eq!(o.as_int(), Some(10));
+    let o = evaluate_script(String::from("Counter instances.")).unwrap();
+    assert_eq!(o.as_int(), Some(2));
+}
+
+#[test]
+fn non_local_return() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Finder [
+        classify: n [ n > 3 ifTrue: [ ^'big' ]. ^'small' ]
+        deep: n [ self run: [:x | x > 0 ifTrue: [ ^x * 10 ]. x] with: n. ^1 ]
+        run: aBlock with: n [ aBlock value: n. ^7 ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from("Finder new classify: 5.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("big"));
+    let o = evaluate_script(String::from("Finder new classify: 2.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("small"));
+    let o = evaluate_script(String::from("Finder new deep: 2.")).unwrap();
+    assert_eq!(o.as_int(), Some(20));
+    let o = evaluate_script(String::from("Finder new deep: 0.")).unwrap();
+    assert_eq!(o.as_int(), Some(1));
+}
+
+#[test]
+fn return_from_dead_method() {
+    assert!(TRACING.clone());
+    define_classes(String::from("Object subclass: Escaper [ escape [ ^[:x | ^x] ] ]")).unwrap();
+    let e = evaluate_script(String::from("(Escaper new escape) value: 3.")).err().unwrap();
+    assert!(format!("{}", e).contains("Unhandled BlockCannotReturn"), "{}", e);
+    let o = evaluate_script(String::from(
+        "[(Escaper new escape) value: 3] on: BlockCannotReturn do: [:e | e class name].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("BlockCannotReturn"));
+}
@@ -0,0 +1,73 @@
+use std::{fs, rc::Rc};
+
+use tt_rust::{
+    code::{
+        compile_script,
+        file::{self, load_defs, precompile_defs, read, read_current, write},
+    },
+    MethodContext, TRACING,
+};
+
+mod common;
+
+#[test]
+fn write_and_read_back() {
+    assert!(TRACING.clone());
+    let source = "n := 12345678901234567890. k := 3. f := [:x | | t | t := x * k. t]. {(f value: 4) + #(1 $a 2.5 #[7]) size}";
+    let code = compile_script(String::from(source)).unwrap();
+    let bytes = write(&code);
+    assert_eq!(&bytes[..4], b"TTCM");
+    let loaded = read(&bytes).unwrap();
+    assert_eq!(format!("{}", loaded), format!("{}", code));
+    assert_eq!(loaded.source(), source);
+
+    let loaded = Rc::new(loaded);
+    let r = loaded.run(MethodContext::new());
+    assert_eq!(format!("{}", r), "#(16)");
+}
+
+#[test]
+fn stale_and_broken_code() {
+    assert!(TRACING.clone());
+    let m = file::compile("Point", "double\n    ^ self * 2").unwrap();
+    assert_eq!(m.label(), "Point>>double");
+    assert_eq!(m.selector(), Some("double"));
+    let bytes = write(&m);
+    assert!(read_current(&bytes, "double\n    ^ self * 2").is_ok());
+    let e = read_current(&bytes, "double\n    ^ self * 3").err().unwrap();
+    assert_eq!(e.to_string(), "the compiled code is stale");
+
+    let e = read(&bytes[..bytes.len() - 3]).err().unwrap();
+    assert_eq!(e.to_string(), "truncated compiled code");
+    let e = read(b"TTCX").err().unwrap();
+    assert_eq!(e.to_string(), "not compiled code");
+    let mut newer = bytes.clone();
+    newer[4] = 9;
+    let e = read(&newer).err().unwrap();
+    assert_eq!(e.to_string(), "compiled code of version 9, expected 1");
+}
+
+#[test]
+fn precompiled_defs() {
+    assert!(TRACING.clone());
+    let defs = common::temp_path("precompiled_defs");
+    let out = defs.join("compiled");
+    fs::create_dir_all(defs.join("src/point")).unwrap();
+    fs::write(defs.join("src/point/double"), "double\n    ^ self * 2").unwrap();
+    fs::write(defs.join("src/point/x_y_"), "x: a y: b\n    ^ a @ b").unwrap();
+    assert_eq!(precompile_defs(&defs.join("src"), &out).unwrap(), 2);
+    let compiled = out.join("point/double.ttc");
+    assert!(compiled.exists());
+
+    let methods = load_defs(&defs.join("src"), &out).unwrap();
+    let labels: Vec<&str> = methods.iter().map(|(_, m)| m.label()).collect();
+    assert_eq!(labels, vec!["Point>>double", "Point>>x:y:"]);
+    assert_eq!(methods[0].0, "Point");
+
+    // a changed source makes the compiled file stale, it is compiled again
+    fs::write(defs.join("src/point/double"), "double\n    ^ self * 4").unwrap();
+    let methods = load_defs(&defs.join("src"), &out).unwrap();
+    assert_eq!(methods[0].1.source(), "double\n    ^ self * 4");
+    assert!(read_current(&fs::read(&compiled).unwrap(), "double\n    ^ self * 4").is_ok());
+    fs::remove_dir_all(defs).unwrap();
+}
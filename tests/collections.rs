@@ -0,0 +1,187 @@
+use tt_rust::{define_classes, evaluate_script, Workspace, TRACING};
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn ordered_collections() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("c := OrderedCollection new. c add: 3; add: 4; addFirst: 1.")
+        .unwrap();
+    assert_eq!("an OrderedCollection(1 3 4)", eval(&mut w, "c."));
+    assert_eq!("3", eval(&mut w, "c size."));
+    assert_eq!("3", eval(&mut w, "c at: 2."));
+    assert_eq!("an OrderedCollection(2 6 8)", eval(&mut w, "c collect: [:x | x * 2]."));
+    assert_eq!(
+        "an OrderedCollection(3)",
+        eval(&mut w, "c select: [:x | x > 1 and: [x < 4]].")
+    );
+    assert_eq!("an OrderedCollection(4)", eval(&mut w, "c reject: [:x | x < 4]."));
+    assert_eq!("8", eval(&mut w, "c inject: 0 into: [:a :b | a + b]."));
+    assert_eq!("4", eval(&mut w, "c detect: [:x | x > 3] ifNone: [nil]."));
+    assert_eq!("0", eval(&mut w, "c detect: [:x | x > 9] ifNone: [0]."));
+    assert_eq!("True", eval(&mut w, "c includes: 4."));
+    assert_eq!("1", eval(&mut w, "c removeFirst."));
+    assert_eq!("4", eval(&mut w, "c removeLast."));
+    assert_eq!("#(3)", eval(&mut w, "c asArray."));
+    assert_eq!("False", eval(&mut w, "c isEmpty."));
+    assert_eq!("True", eval(&mut w, "OrderedCollection new isEmpty."));
+    assert_eq!(
+        "7",
+        eval(
+            &mut w,
+            "[OrderedCollection new removeFirst] on: CollectionIsEmpty do: [:e | 7]."
+        )
+    );
+}
+
+#[test]
+fn dictionaries_and_associations() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("d := Dictionary new. d at: #a put: 1; at: 'b' put: 2; add: #c -> 3.")
+        .unwrap();
+    assert_eq!("2", eval(&mut w, "d at: #b."));
+    assert_eq!("3", eval(&mut w, "d size."));
+    assert_eq!("0", eval(&mut w, "d at: #z ifAbsent: [0]."));
+    assert_eq!("True", eval(&mut w, "d includesKey: #c."));
+    assert_eq!("#(#a b #c)", eval(&mut w, "d keys."));
+    assert_eq!("6", eval(&mut w, "d inject: 0 into: [:a :b | a + b]."));
+    assert_eq!(
+        "a Dictionary(#a->10 b->20 #c->30)",
+        eval(&mut w, "d collect: [:x | x * 10].")
+    );
+    assert_eq!("a Dictionary(b->2)", eval(&mut w, "d select: [:x | x = 2]."));
+    assert_eq!("2", eval(&mut w, "d removeKey: #b."));
+    assert_eq!(
+        "#c",
+        eval(
+            &mut w,
+            "k := nil. d keysAndValuesDo: [:key :v | v > 2 ifTrue: [k := key]]. k."
+        )
+    );
+    assert_eq!("5", eval(&mut w, "[d at: #z] on: KeyNotFound do: [:e | 5]."));
+    assert_eq!("#x->4", eval(&mut w, "#x -> 4."));
+    assert_eq!("4", eval(&mut w, "(#x -> 4) value."));
+}
+
+#[test]
+fn sets_and_bags() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("a Set(1 2 3)", eval(&mut w, "#(1 2 1 3 2) asSet."));
+    assert_eq!("3", eval(&mut w, "(Set withAll: #(1 2 1 3 2)) size."));
+    assert_eq!("a Set(1 0)", eval(&mut w, "#(1 2 3) asSet collect: [:x | x \\\\ 2]."));
+    w.evaluate("b := Bag new. b add: #x; add: #y; add: #x.").unwrap();
+    assert_eq!("2", eval(&mut w, "b occurrencesOf: #x."));
+    assert_eq!("3", eval(&mut w, "b size."));
+    assert_eq!("#x", eval(&mut w, "b remove: #x."));
+    assert_eq!("1", eval(&mut w, "b occurrencesOf: #x."));
+    assert_eq!("True", eval(&mut w, "#(1 2) asSet includes: 2."));
+}
+
+#[test]
+fn intervals() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("(1 to: 5)", eval(&mut w, "1 to: 5."));
+    assert_eq!("15", eval(&mut w, "(1 to: 5) inject: 0 into: [:a :b | a + b]."));
+    assert_eq!("#(1 4 9)", eval(&mut w, "(1 to: 3) collect: [:x | x * x]."));
+    assert_eq!("#(10 8 6)", eval(&mut w, "(10 to: 5 by: 0 - 2) asArray."));
+    assert_eq!("0", eval(&mut w, "(5 to: 1) size."));
+    assert_eq!(
+        "#(5 7 9)",
+        eval(&mut w, "(1 to: 3) with: #(4 5 6) collect: [:a :b | a + b].")
+    );
+    assert_eq!(
+        "an OrderedCollection(1 2 3)",
+        eval(&mut w, "c := OrderedCollection new. (1 to: 3) do: [:i | c add: i]. c.")
+    );
+}
+
+#[test]
+fn sorted_collections() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!(
+        "a SortedCollection(1 2 3 5)",
+        eval(&mut w, "#(3 1 5 2) asSortedCollection.")
+    );
+    w.evaluate("s := #(3 1 5 2) asSortedCollection: [:a :b | a > b].")
+        .unwrap();
+    assert_eq!("a SortedCollection(5 3 2 1)", eval(&mut w, "s."));
+    assert_eq!("a SortedCollection(5 4 3 2 1)", eval(&mut w, "s add: 4. s."));
+    assert_eq!("5", eval(&mut w, "s first."));
+    assert_eq!(
+        "an OrderedCollection(50 40 30 20 10)",
+        eval(&mut w, "s collect: [:x | x * 10].")
+    );
+    assert_eq!(
+        "a SortedCollection(1 9)",
+        eval(
+            &mut w,
+            "(SortedCollection sortBlock: [:a :b | a < b]) add: 9; add: 1; yourself."
+        )
+    );
+}
+
+#[test]
+fn collections_in_the_evaluator() {
+    assert!(TRACING.clone());
+    // class variable initializers are evaluated without compiling them
+    define_classes(String::from(
+        "
+    Object subclass: Squares [
+        Table := (1 to: 4) collect: [:x | x * x].
+        Sum := Table inject: 0 into: [:a :b | a + b].
+        Pair := #one -> 1.
+        Squares class >> sum [ ^Sum ]
+        Squares class >> pair [ ^Pair ]
+    ]",
+    ))
+    .unwrap();
+    assert_eq!(
+        Some(30),
+        evaluate_script(String::from("Squares sum.")).unwrap().as_int()
+    );
+    let r = evaluate_script(String::from("Squares pair.")).unwrap();
+    assert_eq!("#one->1", format!("{}", r));
+}
+
+#[test]
+fn indices_out_of_bounds() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("a := #(1 2). b := #[1 2].").unwrap();
+    assert_eq!("2", eval(&mut w, "a at: 2."));
+    assert_eq!("oob", eval(&mut w, "[a at: 3] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("oob", eval(&mut w, "[a at: 0] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("oob", eval(&mut w, "[b at: 5] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!(
+        "oob",
+        eval(&mut w, "[b at: 0 put: 3] on: SubscriptOutOfBounds do: [:e | 'oob'].")
+    );
+    assert_eq!("7", eval(&mut w, "b at: 1 put: 7. b at: 1."));
+    assert_eq!("err", eval(&mut w, "[b at: 1 put: 300] on: Error do: [:e | 'err']."));
+}
+
+#[test]
+fn indices_count_from_one() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("a := #(10 20 30). o := OrderedCollection new. o add: 10; add: 20. b := #[7 8].")
+        .unwrap();
+    assert_eq!("True", eval(&mut w, "(a at: 1) = a first."));
+    assert_eq!("30", eval(&mut w, "a at: a size."));
+    assert_eq!("1", eval(&mut w, "(1 to: 5) at: 1."));
+    assert_eq!("9", eval(&mut w, "(1 to: 9 by: 2) at: 5."));
+    assert_eq!("10", eval(&mut w, "o at: 1."));
+    assert_eq!("an OrderedCollection(10 25)", eval(&mut w, "o at: 2 put: 25. o."));
+    assert_eq!("7", eval(&mut w, "b at: 1."));
+    assert_eq!("True", eval(&mut w, "((ReadStream on: a) next) = (a at: 1)."));
+    assert_eq!("#(11 22 33)", eval(&mut w, "r := OrderedCollection new. a doWithIndex: [:x :i | r add: x + i]. r asArray."));
+    assert_eq!("oob", eval(&mut w, "[o at: 0] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("oob", eval(&mut w, "[(1 to: 5) at: 6] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+}
@@ -0,0 +1,11 @@
+use std::{fs, path::PathBuf};
+
+/// a path in the temporary directory of this test run, anything left
+/// there by an earlier run is removed
+pub fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tt-rust-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::remove_dir_all(&path).or_else(|_| fs::remove_file(&path)).ok();
+    path
+}
@@ -2,8 +2,8 @@ use std::{fs::File, io::Read, path::Path};
 
 use tt_rust::{
     code::{compile_script, CompiledMethod, Operation},
-    parse_method,
-    parser::AST::Method,
+    define_classes, evaluate_script, parse_method,
+    parser::AST::{self, Method},
     ContextRef, MethodContext, TRACING,
 };
 
@@ -141,3 +141,173 @@ fn compile_stored_method() {
         _ => todo!("I only know how to deal with a method."),
     }
 }
+
+#[test]
+fn compile_cascade() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from(
+        "
+        '' species new: 10 streamContents: [ :s | s nextPut: $a; nextPut: $b ].
+        ",
+    ))
+    .unwrap();
+    println!("{}", code);
+    let f = MethodContext::new();
+    let r = format!("{}", code.run(f));
+    assert_eq!("ab", r);
+}
+
+#[test]
+fn compile_literals() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("(#(#a #(#b 7)) at: 2) at: 2.")).unwrap();
+    println!("{}", code);
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(7), r.as_int());
+    let code = compile_script(String::from("#[4 5] size.")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(2), r.as_int());
+}
+
+#[test]
+fn compile_non_local_return() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("a := 1. a < 2 ifTrue: [^5]. ^7")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(5), r.as_int());
+    let code = compile_script(String::from("a := 1. a > 2 ifTrue: [^5]. ^7")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(7), r.as_int());
+}
+
+#[test]
+fn compile_block_activations() {
+    assert!(TRACING.clone());
+    // every evaluation of a block has its own arguments and temporaries
+    let code = compile_script(String::from(
+        "
+        make := [:x | [:y | | t | t := x + y. t]].
+        a := make value: 1.
+        b := make value: 10.
+        (a value: 5) + (b value: 5) + (make numArgs).",
+    ))
+    .unwrap();
+    println!("{}", code);
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(22), r.as_int());
+}
+
+#[test]
+fn compile_loops_and_braces() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from(
+        "
+        i := 0. sum := 0.
+        [i < 5] whileTrue: [i := i + 1. sum := sum + i].
+        {i * sum}.",
+    ))
+    .unwrap();
+    let r = format!("{}", code.run(MethodContext::new()));
+    assert_eq!("#(75)", r);
+}
+
+#[test]
+fn compiled_user_methods() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Shape [
+        | sides |
+        sides: n [ sides := n ]
+        sides [ ^sides ]
+        describe [ ^self sides ]
+    ]
+    Shape subclass: Square [
+        describe [ | extra | extra := 100. ^super describe + extra ]
+        firstAbove: n [
+            | check |
+            check := [:x | x > n ifTrue: [^x]].
+            check value: 1. check value: 5. check value: 9.
+            ^0
+        ]
+    ]",
+    ))
+    .unwrap();
+    let r = evaluate_script(String::from("s := Square new sides: 4; yourself. s describe.")).unwrap();
+    assert_eq!(Some(104), r.as_int());
+    let r = evaluate_script(String::from("Square new firstAbove: 3.")).unwrap();
+    assert_eq!(Some(5), r.as_int());
+
+    // a redefined method is compiled again
+    define_classes(String::from("Object subclass: Shape [ sides [ ^7 ] describe [ ^self sides ] ]")).unwrap();
+    let r = evaluate_script(String::from("Shape new describe.")).unwrap();
+    assert_eq!(Some(7), r.as_int());
+}
+
+#[test]
+fn optimized_control_structures() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from(
+        "
+        sum := 0.
+        1 to: 10 do: [:k | sum := sum + k].
+        a := sum > 50 ifTrue: ['big'] ifFalse: ['small'].
+        b := (sum < 50 and: [sum odd]) or: [sum = 55].
+        c := sum < 50 ifTrue: [1].
+        {sum}",
+    ))
+    .unwrap();
+    let listing = format!("{}", code);
+    println!("{}", listing);
+    assert!(listing.contains("JumpIf"));
+    assert!(!listing.contains("Block("));
+    assert!(!listing.contains("to:do:"));
+    let ctx = MethodContext::new();
+    let r = code.run(ctx.clone());
+    assert_eq!("#(55)", format!("{}", r));
+    let vars = ctx.variables();
+    assert_eq!("big", format!("{}", vars.borrow()["a"]));
+    assert_eq!("True", format!("{}", vars.borrow()["b"]));
+    assert_eq!("Nil", format!("{}", vars.borrow()["c"]));
+}
+
+#[test]
+fn optimized_constants() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("3 + 4 * 2 - (7 // 2).")).unwrap();
+    let listing = format!("{}", code);
+    println!("{}", listing);
+    assert!(listing.contains("Int(11)"));
+    assert!(!listing.contains("Invoke"));
+    assert_eq!(Some(11), code.run(MethodContext::new()).as_int());
+
+    // a block that is not a literal argument stays a block
+    let code = compile_script(String::from("b := [3 > 4]. b value ifTrue: [1] ifFalse: [2].")).unwrap();
+    let listing = format!("{}", code);
+    assert!(listing.contains("Block(1)"));
+    assert_eq!(Some(2), code.run(MethodContext::new()).as_int());
+}
+
+#[test]
+fn unsupported_nodes_are_errors() {
+    assert!(TRACING.clone());
+    let mut code = CompiledMethod::new();
+    let e = code.compile(&AST::Statements(vec![AST::Int(1), AST::Name("x")])).unwrap_err();
+    assert_eq!(e.to_string(), "can't compile Name(\"x\")");
+}
+
+#[test]
+fn braces_temporaries_and_empty_blocks() {
+    assert!(TRACING.clone());
+    let run = |s: &str| format!("{}", evaluate_script(String::from(s)).unwrap());
+    assert_eq!(run("{1. 2 + 3. 'x'}"), "#(1 5 x)");
+    assert_eq!(run("{1. 2.}"), "#(1 2)");
+    assert_eq!(run("{} size"), "0");
+    assert_eq!(run("| x y | x := 3. y := x * 2. {x. y}"), "#(3 6)");
+    assert_eq!(run("[] value"), "Nil");
+    assert_eq!(run("[:i | ] value: 4"), "Nil");
+    assert_eq!(run("[1. 2.] value"), "2");
+    assert_eq!(run("[:i | | t | t := i. t + 1.] value: 4."), "5");
+    define_classes(String::from("Global BraceGlobal := {1. 2}.")).unwrap();
+    assert_eq!(run("BraceGlobal"), "#(1 2)");
+}
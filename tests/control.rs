@@ -0,0 +1,103 @@
+use tt_rust::{define_classes, evaluate_script, Workspace, TRACING};
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn booleans() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    // blocks held in variables are sent, not inlined
+    w.evaluate("t := 3 > 2. f := 3 < 2. b := [7]. c := [8].").unwrap();
+    assert_eq!("7", eval(&mut w, "t and: b."));
+    assert_eq!("False", eval(&mut w, "f and: b."));
+    assert_eq!("True", eval(&mut w, "t or: b."));
+    assert_eq!("7", eval(&mut w, "f or: b."));
+    assert_eq!("False", eval(&mut w, "t & f."));
+    assert_eq!("True", eval(&mut w, "t | f."));
+    assert_eq!("False", eval(&mut w, "t not."));
+    assert_eq!("True", eval(&mut w, "t xor: f."));
+    assert_eq!("False", eval(&mut w, "t xor: t."));
+    assert_eq!("8", eval(&mut w, "t ifFalse: b ifTrue: c."));
+    assert_eq!("7", eval(&mut w, "f ifFalse: b ifTrue: c."));
+    assert_eq!("Nil", eval(&mut w, "t ifFalse: b."));
+    assert_eq!("Nil", eval(&mut w, "f ifTrue: b."));
+    assert_eq!(
+        "#mustBeBoolean",
+        eval(
+            &mut w,
+            "[t & 3] on: MessageNotUnderstood do: [:e | e message selector]."
+        )
+    );
+}
+
+#[test]
+fn loops() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("i := 0. c := [i < 5]. b := [i := i + 1].").unwrap();
+    assert_eq!("5", eval(&mut w, "c whileTrue: b. i."));
+    assert_eq!("8", eval(&mut w, "c := [i = 8]. c whileFalse: b. i."));
+    assert_eq!("10", eval(&mut w, "c := [i := i + 1. i < 10]. c whileTrue. i."));
+    assert_eq!("12", eval(&mut w, "c := [i := i + 1. i >= 12]. c whileFalse. i."));
+    assert_eq!(
+        "15",
+        eval(
+            &mut w,
+            "c := [i := i + 1. i = 15 ifTrue: [Error signal: 'done']]. [c repeat] on: Error do: [:e | i]."
+        )
+    );
+    w.evaluate("s := 0. b := [:k | s := s + k].").unwrap();
+    assert_eq!("10", eval(&mut w, "1 to: 4 do: b. s."));
+    assert_eq!("32", eval(&mut w, "10 to: 1 by: 0 - 3 do: b. s."));
+    assert_eq!("32", eval(&mut w, "5 to: 1 do: b. s."));
+    assert_eq!("35", eval(&mut w, "b := [s := s + 1]. 3 timesRepeat: b. s."));
+    assert_eq!(
+        "#mustBeBoolean",
+        eval(
+            &mut w,
+            "c := [3]. [c whileTrue] on: MessageNotUnderstood do: [:e | e message selector]."
+        )
+    );
+}
+
+#[test]
+fn nil_tests() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("1", eval(&mut w, "nil ifNil: [1]."));
+    assert_eq!("3", eval(&mut w, "3 ifNil: [1]."));
+    assert_eq!("4", eval(&mut w, "3 ifNotNil: [:x | x + 1]."));
+    assert_eq!("5", eval(&mut w, "3 ifNotNil: [5]."));
+    assert_eq!("Nil", eval(&mut w, "nil ifNotNil: [:x | x]."));
+    assert_eq!("1", eval(&mut w, "nil ifNil: [1] ifNotNil: [:x | x]."));
+    assert_eq!("#a", eval(&mut w, "#a ifNotNil: [:x | x] ifNil: [1]."));
+    assert_eq!("True", eval(&mut w, "nil isNil."));
+    assert_eq!("False", eval(&mut w, "#() isNil."));
+}
+
+#[test]
+fn control_in_the_evaluator() {
+    assert!(TRACING.clone());
+    // class variable initializers are evaluated without compiling them
+    define_classes(String::from(
+        "
+    Object subclass: Loops [
+        Both := (3 > 2) and: [2 > 1].
+        Either := (3 < 2) or: [(2 < 1) not].
+        Items := OrderedCollection new.
+        Filled := [Items size < 3] whileTrue: [Items add: Items size].
+        Squares := OrderedCollection new.
+        Done := 1 to: 3 do: [:k | Squares add: k * k].
+        Default := nil ifNil: [9].
+        Loops class >> all [ ^Array with: Both & Either with: Items with: Squares with: Default ]
+    ]",
+    ))
+    .unwrap();
+    let r = evaluate_script(String::from("Loops all.")).unwrap();
+    assert_eq!(
+        "#(True an OrderedCollection(0 1 2) an OrderedCollection(1 4 9) 9)",
+        format!("{}", r)
+    );
+}
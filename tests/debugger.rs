@@ -0,0 +1,176 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use tt_rust::{
+    code::CodeAddress,
+    debug::{self, Action, Breakpoint},
+    define_classes,
+    repl::{self, Session},
+    Workspace, TRACING,
+};
+
+mod common;
+
+fn define_counter(name: &str) {
+    define_classes(format!(
+        "
+    Object subclass: {} [
+        | count |
+        init [ count := 0 ]
+        add: n [
+            | t |
+            t := count + n.
+            count := t.
+            ^t
+        ]
+    ]",
+        name
+    ))
+    .unwrap();
+}
+
+const SCRIPT: &str = "c := DebugCounter new init.
+x := c add: 2.
+y := c add: x.
+#(1 2) do: [:e | c add: e].
+c add: 1";
+
+/// attaches a handler that answers the actions in turn, it records what it saw
+fn attach(actions: Vec<Action>) -> Rc<RefCell<Vec<String>>> {
+    let stops = Rc::new(RefCell::new(vec![]));
+    let seen = stops.clone();
+    let mut actions = VecDeque::from(actions);
+    debug::attach(move |stop| {
+        let vars: Vec<String> = stop
+            .variables()
+            .into_iter()
+            .map(|(n, v)| format!("{}={}", n, v.to_receiver()))
+            .collect();
+        seen.borrow_mut()
+            .push(format!("{} [{}] {}", stop.describe(), vars.join(" "), stop.depth()));
+        actions.pop_front().unwrap_or(Action::Continue)
+    });
+    stops
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    assert!(TRACING.clone());
+    define_counter("DebugCounter");
+    let stops = attach(vec![
+        Action::StepInto,
+        Action::StepOut,
+        Action::StepOver,
+        Action::StepInto,
+        Action::StepOut,
+    ]);
+    assert_eq!(
+        1,
+        debug::add_breakpoint(Breakpoint::parse("UndefinedObject>>DoIt 2").unwrap())
+    );
+    let mut w = Workspace::new();
+    assert_eq!("8", format!("{}", w.evaluate(SCRIPT).unwrap()));
+    assert_eq!(
+        *stops.borrow(),
+        vec![
+            "UndefinedObject>>DoIt r0-6 line 2 column 8, sends #add: [] 1",
+            "DebugCounter>>add: r0-3 line 7 column 24, sends #+ [n=2 t=Nil] 2",
+            "UndefinedObject>>DoIt r0-10 line 3 column 8, sends #add: [] 1",
+            "UndefinedObject>>DoIt r0-14 line 4 column 8, sends #do: [] 1",
+            "UndefinedObject>>DoIt r1-2 line 4 column 20, sends #add: [e=1] 2",
+            "UndefinedObject>>DoIt r0-17 line 5 column 3, sends #add: [] 1",
+        ]
+    );
+    assert_eq!("8", format!("{}", w.evaluate("c add: 0").unwrap()));
+}
+
+#[test]
+fn address_breakpoints_and_values() {
+    assert!(TRACING.clone());
+    define_counter("AddressCounter");
+    let seen = Rc::new(RefCell::new(vec![]));
+    let s = seen.clone();
+    debug::attach(move |stop| {
+        let count = stop.lookup("count").unwrap().to_receiver();
+        let step = stop.value_at(&CodeAddress(0, 2)).unwrap().to_receiver();
+        let myself = stop.lookup("self").unwrap().to_receiver();
+        s.borrow_mut()
+            .push(format!("{} {} {} {}", stop.address(), count, step, myself));
+        assert!(stop.stack_trace()[0].starts_with("AddressCounter>>add:"));
+        assert!(stop.lookup("nothing").is_none());
+        Action::Continue
+    });
+    let at = Breakpoint::parse("AddressCounter>>add: r0-3").unwrap();
+    assert_eq!(
+        Breakpoint::Address("AddressCounter>>add:".into(), CodeAddress(0, 3)),
+        at
+    );
+    debug::add_breakpoint(Breakpoint::parse("AddressCounter>>init 3").unwrap());
+    debug::add_breakpoint(at);
+    assert!(debug::remove_breakpoint(1));
+    assert!(!debug::remove_breakpoint(2));
+    assert_eq!(
+        vec!["AddressCounter>>add: r0-3"],
+        debug::breakpoints().iter().map(|b| b.to_string()).collect::<Vec<_>>()
+    );
+    let mut w = Workspace::new();
+    w.evaluate("c := AddressCounter new init. c add: 3; add: 4").unwrap();
+    assert_eq!(
+        *seen.borrow(),
+        vec!["r0-3 0 0 an AddressCounter", "r0-3 3 3 an AddressCounter"]
+    );
+    debug::clear_breakpoints();
+    w.evaluate("c add: 5").unwrap();
+    assert_eq!(2, seen.borrow().len());
+}
+
+#[test]
+fn debugger_commands() {
+    assert!(TRACING.clone());
+    define_counter("ReplCounter");
+    let inputs = Rc::new(RefCell::new(VecDeque::new()));
+    let output = Rc::new(RefCell::new(vec![]));
+    let (read, written) = (inputs.clone(), output.clone());
+    debug::attach(repl::debugger(
+        move |_| read.borrow_mut().pop_front(),
+        move |text| written.borrow_mut().push(text.to_string()),
+    ));
+    let mut s = Session::new(&common::temp_path("debugger_commands.image"));
+    assert_eq!("", s.run(":do c := ReplCounter new init.").unwrap());
+    assert_eq!(
+        "breakpoint 1: ReplCounter>>add: line 7",
+        s.run(":break ReplCounter>>add: 7").unwrap()
+    );
+    inputs.borrow_mut().extend(
+        ["vars", "p count", "p self", "p r0-0", "p x", "frob", "n"]
+            .into_iter()
+            .map(String::from),
+    );
+    assert_eq!("5", s.run("c add: 5.").unwrap());
+    assert_eq!(
+        *output.borrow(),
+        vec![
+            "ReplCounter>>add: r0-3 line 7 column 24, sends #+",
+            "n: 5\nt: Nil",
+            "count: 0",
+            "self: a ReplCounter",
+            "r0-0: 5",
+            "x has no value here",
+            "unknown debugger command frob, help lists them",
+        ]
+    );
+    assert_eq!("1: ReplCounter>>add: line 7", s.run(":breakpoints").unwrap());
+    assert_eq!("", s.run(":clear 1").unwrap());
+    assert_eq!("error: no breakpoint 1", s.run(":clear 1").unwrap());
+    output.borrow_mut().clear();
+    inputs.borrow_mut().extend(["s", "o"].into_iter().map(String::from));
+    assert_eq!("6", s.run(":debug c add: 1.").unwrap());
+    assert_eq!(
+        *output.borrow(),
+        vec![
+            "UndefinedObject>>DoIt r0-2 line 1 column 3, sends #add:",
+            "ReplCounter>>add: r0-3 line 7 column 24, sends #+",
+        ]
+    );
+    assert_eq!("7", s.run("c add: 1.").unwrap());
+    assert_eq!(2, output.borrow().len());
+}
@@ -0,0 +1,141 @@
+use tt_rust::{define_classes, evaluate_script, parse_method, parse_script, parser::AST, TRACING};
+
+#[test]
+fn nodes_carry_positions() {
+    assert!(TRACING.clone());
+    let t = parse_method(String::from("foo: x\n    ^x bar: 3 + 4")).unwrap();
+    let m = t[0].as_abstract_syntax_tree();
+    let pos = m.position().unwrap();
+    assert_eq!((pos.line, pos.column), (1, 1));
+    if let AST::Method { body, .. } = m {
+        let pos = body.position().unwrap();
+        assert_eq!((pos.line, pos.column), (2, 6));
+    } else {
+        panic!("not a method");
+    }
+}
+
+#[test]
+fn parse_error_with_caret() {
+    assert!(TRACING.clone());
+    let e = parse_script(String::from("a := 3.\nb := (a + ) * 2.")).err().unwrap();
+    assert_eq!(format!("{}", e), "input:2:11: unexpected ')'\nb := (a + ) * 2.\n          ^");
+    let e = parse_script(String::from("3 +")).err().unwrap();
+    assert!(format!("{}", e).starts_with("input:1:4: unexpected end of input"));
+}
+
+#[test]
+fn several_errors_in_one_pass() {
+    assert!(TRACING.clone());
+    let e = parse_script(String::from("a := ).\nb := 4.\nc := 5 ` + 6.\nd := (].")).err().unwrap();
+    let lines: Vec<String> = format!("{}", e)
+        .lines()
+        .filter(|l| l.starts_with("input:"))
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "input:1:6: unexpected ')'",
+            "input:3:8: invalid character '`'",
+            "input:4:7: unexpected ']'",
+        ]
+    );
+    let e = define_classes(String::from(
+        "
+    Object subclass: A [ foo [ ^) ] ]
+    Object subclass: B [ bar [ ^1 ] ]
+    Object subclass: C [ baz [ ^( ] ]",
+    ))
+    .err()
+    .unwrap();
+    let lines: Vec<String> = format!("{}", e)
+        .lines()
+        .filter(|l| l.starts_with("input:"))
+        .map(String::from)
+        .collect();
+    assert_eq!(lines, vec!["input:2:33: unexpected ')'", "input:4:35: unexpected ']'"]);
+    assert!(evaluate_script(String::from("3 + . 4")).is_err());
+}
+
+#[test]
+fn wrong_keywords_in_class_definitions() {
+    assert!(TRACING.clone());
+    let e = define_classes(String::from(
+        "
+    Object subklass: WrongKeyword [ foo [ ^1 ] ]
+    Object subclass: WrongClassSide [ WrongClassSide klass >> bar [ ^2 ] ]",
+    ))
+    .err()
+    .unwrap();
+    let lines: Vec<String> = format!("{}", e)
+        .lines()
+        .filter(|l| l.starts_with("input:"))
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "input:2:12: class definition expects 'subclass:', not 'subklass:'",
+            "input:3:54: class method definition expects 'class >>', not 'klass >>'",
+        ]
+    );
+}
+
+#[test]
+fn byte_array_elements_out_of_range() {
+    assert!(TRACING.clone());
+    let e = parse_script(String::from("#[1 2 300 4]")).err().unwrap();
+    assert!(format!("{}", e).starts_with("input:1:7: byte array element 300 out of range"));
+}
+
+#[test]
+fn invalid_integer_literals() {
+    assert!(TRACING.clone());
+    let message = |source: &str| {
+        let e = parse_script(String::from(source)).err().unwrap();
+        format!("{}", e).lines().next().unwrap().to_string()
+    };
+    assert_eq!("input:1:5: radix of 99r1 must be between 2 and 36", message("3 + 99r1"));
+    assert_eq!("input:1:1: radix of 1r0 must be between 2 and 36", message("1r0"));
+    assert_eq!("input:1:4: invalid digits in 2r102", message("#( 2r102 )"));
+    assert_eq!(
+        "input:1:1: exponent of 1e99999999999 must be at most 10000",
+        message("1e99999999999")
+    );
+    assert_eq!("input:1:1: exponent of 1e20000 must be at most 10000", message("1e20000"));
+    assert_eq!("1000", format!("{}", evaluate_script(String::from("1e3")).unwrap()));
+    let mut w = tt_rust::Workspace::new();
+    assert!(w.evaluate("x := 2r102.").is_err());
+    assert_eq!("5", format!("{}", w.evaluate("x := 16r5.").unwrap()));
+}
+
+#[test]
+fn wrong_keywords_in_programs() {
+    assert!(TRACING.clone());
+    let e = define_classes(String::from(
+        "
+    Globl Misspelt := 3.
+    Pol Tints [ Red := 1. ]
+    Pool Shades [ Constnt Dark := 1. ]
+    Evaluate [ 3 ]
+    Object subclass: Importer [ <imports: Shades> ]",
+    ))
+    .err()
+    .unwrap();
+    let lines: Vec<String> = format!("{}", e)
+        .lines()
+        .filter(|l| l.starts_with("input:"))
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "input:2:5: global definition expects 'Global' or 'Constant', not 'Globl'",
+            "input:3:5: pool definition expects 'Pool', not 'Pol'",
+            "input:4:19: global definition expects 'Global' or 'Constant', not 'Constnt'",
+            "input:5:5: program initializer expects 'Eval', not 'Evaluate'",
+            "input:6:33: pool import expects '<import: ...>', not '< imports: ... >'",
+        ]
+    );
+}
@@ -0,0 +1,86 @@
+use tt_rust::{
+    chunk::file_in,
+    define_classes,
+    runtime::{mtd, sel::Selector},
+    Workspace, TRACING,
+};
+
+const EXTENSIONS: &str = "!SmallInteger methodsFor: 'arithmetic'!
+double
+    ^ self * 2
+! !
+
+!Object methodsFor: 'testing'!
+isThing
+    ^ true
+! !
+";
+
+#[test]
+fn extend_builtin_classes() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    file_in(&mut w, "extensions.st", EXTENSIONS).unwrap();
+    assert_eq!(Some(42), w.evaluate("21 double.").unwrap().as_int());
+    assert_eq!("True", format!("{}", w.evaluate("'abc' isThing.").unwrap()));
+    assert_eq!("True", format!("{}", w.evaluate("3 isThing.").unwrap()));
+    assert!(mtd::selectors(("SmallInteger", false)).contains(&"double"));
+    assert_eq!(Selector::get("double"), Selector::get("double"));
+}
+
+#[test]
+fn redefine_cached_methods() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Greeter [
+        greet [ ^1 ]
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate("g := Greeter new. b := [g greet].").unwrap();
+    assert_eq!(Some(1), w.evaluate("b value.").unwrap().as_int());
+    define_classes(String::from(
+        "
+    Object subclass: Greeter [
+        greet [ ^2 ]
+    ]",
+    ))
+    .unwrap();
+    // the send site in the block must not answer the old method
+    assert_eq!(Some(2), w.evaluate("g := Greeter new. b value.").unwrap().as_int());
+}
+
+#[test]
+fn polymorphic_sends() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Shape [
+        sides [ ^0 ]
+    ]
+    Shape subclass: Triangle [
+        sides [ ^3 ]
+    ]
+    Shape subclass: Square [
+        sides [ ^4 ]
+    ]
+    Shape subclass: Pentagon [
+        sides [ ^5 ]
+    ]
+    Shape subclass: Circle [
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate("b := [:s | s sides].").unwrap();
+    // more classes than the send site remembers
+    let r = w
+        .evaluate("(b value: Triangle new) + (b value: Square new) + (b value: Circle new) + (b value: Pentagon new) + (b value: Shape new) + (b value: Triangle new).")
+        .unwrap();
+    assert_eq!(Some(15), r.as_int());
+    assert_eq!("True", format!("{}", w.evaluate("nil isNil.").unwrap()));
+    assert_eq!("False", format!("{}", w.evaluate("3 isNil.").unwrap()));
+    assert_eq!(Some(5), w.evaluate("5 yourself.").unwrap().as_int());
+}
@@ -0,0 +1,151 @@
+use tt_rust::{code::compile_script, define_classes, evaluate_script, MethodContext, TRACING};
+
+#[test]
+fn zero_divide_handler() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("[1 / 0] on: ZeroDivide do: [:e | 5].")).unwrap();
+    assert_eq!(o.as_int(), Some(5));
+    let o = evaluate_script(String::from("[1 / 0. 7] on: Error do: [:e | e return: 6].")).unwrap();
+    assert_eq!(o.as_int(), Some(6));
+    let o = evaluate_script(String::from("[3 / 4] on: ZeroDivide do: [:e | 5].")).unwrap();
+    assert_eq!(format!("{}", o), "3/4");
+}
+
+#[test]
+fn signal_with_message() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from(
+        "[Error signal: 'boom'] on: Error do: [:e | e messageText].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("boom"));
+    let o = evaluate_script(String::from(
+        "[ZeroDivide new signal] on: ArithmeticError do: [:e | e description].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("ZeroDivide"));
+}
+
+#[test]
+fn pass_and_resume() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from(
+        "[[1 / 0] on: ZeroDivide do: [:e | e pass]] on: Error do: [:e | 'outer'].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("outer"));
+    let o = evaluate_script(String::from(
+        "[(Warning signal: 'w') + 1] on: Warning do: [:e | e resume: 41].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_int(), Some(42));
+}
+
+#[test]
+fn retry_ensure_and_curtailed() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Retrier [
+        | tries log |
+        log [ ^log ]
+        run [
+            tries := 0.
+            ^[tries := tries + 1. tries < 3 ifTrue: [Error signal: 'again']. tries]
+                on: Error do: [:e | e retry] ]
+        cleanup [
+            log := 'none'.
+            ^[[1 / 0] ensure: [log := 'ensured']] on: ZeroDivide do: [:e | e return: log] ]
+        curtail [ [^1] ifCurtailed: [log := 'curtailed']. ^2 ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from("Retrier new run.")).unwrap();
+    assert_eq!(o.as_int(), Some(3));
+    let o = evaluate_script(String::from("Retrier new cleanup.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("none"));
+    let o = evaluate_script(String::from("r := Retrier new. r cleanup. r log.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("ensured"));
+    let o = evaluate_script(String::from("r := Retrier new. r curtail. r log.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("curtailed"));
+}
+
+#[test]
+fn user_defined_exception() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Error subclass: OutOfStock [
+        | item |
+        item: x [ item := x ]
+        item [ ^item ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from(
+        "[(OutOfStock new item: 'tea') signal] on: Error do: [:e | e item].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("tea"));
+}
+
+#[test]
+fn failures_do_not_escape() {
+    assert!(TRACING.clone());
+    let r = evaluate_script(String::from("1 / 0."));
+    let msg = format!("{}", r.err().unwrap());
+    assert!(msg.contains("Unhandled ZeroDivide"), "{}", msg);
+    let o = evaluate_script(String::from("[nil foo] on: Error do: [:e | 'caught'].")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("caught"));
+    assert!(evaluate_script(String::from("nil foo.")).is_err());
+}
+
+#[test]
+fn compile_exceptions() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("[1 / 0] on: ZeroDivide do: [:e | 5].")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some(5), r.as_int());
+    let code = compile_script(String::from("[Error signal: 'x'] on: Error do: [:e | e messageText].")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(Some("x"), r.as_text().as_deref());
+}
+
+#[test]
+fn runtime_errors_are_signalled() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("[[:x | x] value: 1 value: 2] on: WrongArgumentCount do: [:e | 'args'].")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("args"));
+    let o = evaluate_script(String::from("[[:x | x] value] on: Error do: [:e | e messageText].")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("wrong number of arguments: the block takes 1, not 0"));
+    define_classes(String::from("Object subclass: DeadHome [ escape [ ^[:x | ^x] ] ]")).unwrap();
+    let o = evaluate_script(String::from(
+        "[DeadHome new escape value: 3] on: BlockCannotReturn do: [:e | 'dead'].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("dead"));
+    let o = evaluate_script(String::from(
+        "log := nil. [[DeadHome new escape value: 3] ensure: [log := 'ensured']] on: Error do: [:e | e return: 4]. log",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("ensured"));
+}
+
+#[test]
+fn filters_and_illegal_resumes() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("[[1 / 0] on: [:e | e] do: [:e | 5]] on: Error do: [:e | e messageText].")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("an instance of BlockClosure is not an exception class"));
+    let o = evaluate_script(String::from("[1 / 0] on: NotFound, ZeroDivide do: [:e | 5].")).unwrap();
+    assert_eq!(o.as_int(), Some(5));
+    let o = evaluate_script(String::from(
+        "[[Error signal: 'x'] on: Error do: [:e | e resume: 3]] on: IllegalResumeAttempt do: [:e | 'illegal'].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("illegal"));
+    let o = evaluate_script(String::from(
+        "saved := nil. [Error signal] on: Error do: [:e | saved := e]. [saved return: 1] on: IllegalResumeAttempt do: [:e | 'gone'].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("gone"));
+}
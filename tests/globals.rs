@@ -0,0 +1,88 @@
+use tt_rust::{define_classes, Workspace, TRACING};
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn globals() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Global Answer := Half * 2.
+    Constant Half := 21.
+    Global Unset.
+    Global Log := OrderedCollection new.
+    Eval [ Log add: Answer ]
+    Eval [ | n | n := Answer + 1. Log add: n ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    assert_eq!("42", eval(&mut w, "Answer."));
+    assert_eq!("Nil", eval(&mut w, "Unset."));
+    assert_eq!("an OrderedCollection(42 43)", eval(&mut w, "Log."));
+    eval(&mut w, "Unset := 3.");
+    assert_eq!("3", eval(&mut w, "Unset."));
+    assert_eq!("3", eval(&mut w, "Smalltalk at: #Unset."));
+    assert_eq!("const", eval(&mut w, "[Half := 1] on: Error do: [:e | 'const']."));
+    assert_eq!("21", eval(&mut w, "Half."));
+    eval(&mut w, "Smalltalk at: #Added put: 5.");
+    assert_eq!("5", eval(&mut w, "Added."));
+    assert_eq!("True", eval(&mut w, "Smalltalk includesKey: #Added."));
+    assert_eq!("unknown", eval(&mut w, "[NotDeclaredAnywhere] on: UndeclaredVariable do: [:e | 'unknown']."));
+}
+
+#[test]
+fn pools() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Pool Colors [
+        Red := 1.
+        Green := Red + 1.
+        Constant Blue := Green + 1.
+        Alpha.
+    ]
+    Object subclass: Palette [
+        <import: Colors>
+        blue [ ^Blue ]
+        mix [ ^Red + Green ]
+        transparent [ Alpha := 0. ^Alpha ]
+    ]
+    Global Sum := (Colors at: #Red) + (Colors at: #Blue).",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    assert_eq!("3", eval(&mut w, "Palette new blue."));
+    assert_eq!("3", eval(&mut w, "Palette new mix."));
+    assert_eq!("0", eval(&mut w, "Palette new transparent."));
+    assert_eq!("0", eval(&mut w, "Colors at: #Alpha."));
+    assert_eq!("4", eval(&mut w, "Sum."));
+    assert_eq!("#(#Alpha #Blue #Green #Red)", eval(&mut w, "Colors keys."));
+    assert_eq!("False", eval(&mut w, "Colors includesKey: #Black."));
+    assert_eq!("const", eval(&mut w, "[Colors at: #Blue put: 7] on: Error do: [:e | 'const']."));
+}
+
+#[test]
+fn circular_initialization() {
+    assert!(TRACING.clone());
+    let e = define_classes(String::from(
+        "
+    Global Ping := Pong.
+    Global Pong := Ping.
+    Global Fine := 1.",
+    ))
+    .unwrap_err();
+    assert_eq!(e.to_string(), "circular initialization of Ping, Pong");
+    let mut w = Workspace::new();
+    assert_eq!("Nil", eval(&mut w, "Fine."));
+}
+
+#[test]
+fn failing_initializers_are_errors() {
+    assert!(TRACING.clone());
+    let e = define_classes(String::from("Global Broken := 1 / 0.")).unwrap_err();
+    assert!(e.to_string().starts_with("Broken: Unhandled ZeroDivide"));
+    let e = define_classes(String::from("Object subclass: BrokenInit [ X := nil foo. ]")).unwrap_err();
+    assert!(e.to_string().starts_with("BrokenInit.X: Unhandled MessageNotUnderstood"));
+}
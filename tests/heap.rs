@@ -0,0 +1,87 @@
+use tt_rust::{define_classes, heap, runtime::sel::SelectorSet, Workspace, TRACING};
+
+#[test]
+fn collect_cycles() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Link [
+        | next |
+        next: aLink [ next := aLink ]
+    ]",
+    ))
+    .unwrap();
+    heap::collect();
+    let before = heap::stats().objects;
+    for _ in 0..10 {
+        // a block kept in a variable of the activation it was created in
+        let mut w = Workspace::new();
+        w.evaluate("b := [b]. b").unwrap();
+        // an instance referring to itself
+        w.evaluate("l := Link new. l next: l.").unwrap();
+    }
+    assert!(heap::stats().objects > before);
+    assert!(heap::collect() >= 40);
+    assert_eq!(before, heap::stats().objects);
+    assert!(heap::stats().collections >= 2);
+}
+
+#[test]
+fn keep_reachable_objects() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("b := [:x | x + 1]. c := [:x | b value: x].").unwrap();
+    heap::collect();
+    let r = w.evaluate("c value: 41.").unwrap();
+    assert_eq!(Some(42), r.as_int());
+
+    // the activations running while it collects survive
+    let r = w.evaluate("[:x | Smalltalk garbageCollect. b value: x] value: 2.").unwrap();
+    assert_eq!(Some(3), r.as_int());
+}
+
+#[test]
+fn smalltalk_primitives() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("1 to: 5 do: [:i | x := [x]].").unwrap();
+    let r = w.evaluate("x := nil. Smalltalk garbageCollect.").unwrap();
+    assert!(r.as_int().unwrap() >= 1);
+    let r = w.evaluate("Smalltalk memoryStatistics.").unwrap();
+    let stats = format!("{}", r);
+    assert!(stats.starts_with("objects: "), "{}", stats);
+    assert!(stats.contains("collections: "));
+}
+
+#[test]
+fn keep_roots() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Holder [
+        | item |
+        item [ ^item ]
+        item: anObject [ item := anObject ]
+    ]",
+    ))
+    .unwrap();
+    // a global holding a block, dropped by the workspace that made it
+    Workspace::new().evaluate("Smalltalk at: #Kept put: (Holder new item: [42]; yourself).").unwrap();
+    heap::collect();
+    let r = Workspace::new().evaluate("Kept item value.").unwrap();
+    assert_eq!(Some(42), r.as_int());
+
+    // the blocks of the installed handlers
+    let mut w = Workspace::new();
+    let r = w.evaluate("[Smalltalk garbageCollect. Error signal: 'x'] on: Error do: [:e | 7].").unwrap();
+    assert_eq!(Some(7), r.as_int());
+}
+
+#[test]
+fn strings_are_not_interned() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("s := 'zqx literal'. 1 to: 3 do: [:i | t := 'zqy', i printString. t printString].").unwrap();
+    assert_eq!(Some("zqx literal".to_string()), w.evaluate("s.").unwrap().as_text());
+    assert!(SelectorSet::starting_with("zq").is_empty());
+}
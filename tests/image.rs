@@ -0,0 +1,260 @@
+use std::{path::PathBuf, rc::Rc, thread};
+
+use tt_rust::{
+    define_classes, parse_method, runtime::str::StringMetaReceiver, Workspace, TRACING,
+};
+
+mod common;
+
+/// evaluates the scripts in a fresh thread, so nothing but the image is shared
+fn in_new_session(path: PathBuf, scripts: &'static [&'static str]) -> Vec<String> {
+    thread::spawn(move || {
+        let mut w = Workspace::load_image(&path).unwrap();
+        scripts
+            .iter()
+            .map(|s| format!("{}", w.evaluate(s).unwrap()))
+            .collect()
+    })
+    .join()
+    .unwrap()
+}
+
+#[test]
+fn save_and_load() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Counter [
+        | count next |
+        init [ count := 0 ]
+        increment [ count := count + 1 ]
+        count [ ^count ]
+        next [ ^next ]
+        next: aCounter [ next := aCounter ]
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate(
+        "
+    c := Counter new.
+    c init.
+    c increment.
+    c increment.
+    c next: c.
+    n := 12345678901234567890123.
+    f := 3 / 4.
+    s := 'hello'.
+    a := #(1 $a #sym 2.5).
+    k := 10.
+    b := [:x | x + k + c count].",
+    )
+    .unwrap();
+    let path = common::temp_path("save_and_load.image");
+    w.save_image(&path).unwrap();
+
+    let r = in_new_session(
+        path.clone(),
+        &[
+            "c count",
+            "c next == c",
+            "n",
+            "f",
+            "s",
+            "a",
+            "b value: 5",
+            "(Counter new init; increment; yourself) count",
+        ],
+    );
+    assert_eq!(
+        r,
+        vec![
+            "2",
+            "True",
+            "12345678901234567890123",
+            "3/4",
+            "hello",
+            "#(1 a #sym 2.5)",
+            "17",
+            "1"
+        ]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn objects_without_image_form() {
+    assert!(TRACING.clone());
+    let w = Workspace::new();
+    w.bind("m", Rc::new(StringMetaReceiver {}));
+    let path = common::temp_path("objects_without_image_form.image");
+    let e = w.save_image(&path).unwrap_err();
+    assert_eq!(e.to_string(), "a String class can't be stored in an image");
+    assert!(!path.exists());
+    // a failed save keeps the image there is
+    let mut saved = Workspace::new();
+    saved.evaluate("x := 42.").unwrap();
+    saved.save_image(&path).unwrap();
+    assert!(w.save_image(&path).is_err());
+    assert_eq!(in_new_session(path.clone(), &["x"]), vec!["42"]);
+    let temps = std::fs::read_dir(path.parent().unwrap()).unwrap();
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    assert!(!temps.flatten().any(|e| e.file_name().to_string_lossy().contains(&format!("{}.tmp", name))));
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn method_source_round_trip() {
+    assert!(TRACING.clone());
+    let source = "at: i put: v
+    | old |
+    old := (items at: i) ifNil: [ #() ].
+    items at: i put: v + 2 * 3 - (4 max: 5) negated.
+    ^ old";
+    let t = parse_method(source.to_string()).unwrap();
+    let printed = t[0].as_abstract_syntax_tree().to_source();
+    let t = parse_method(printed.clone()).unwrap();
+    assert_eq!(t[0].as_abstract_syntax_tree().to_source(), printed);
+    assert!(printed.starts_with("at: i put: v\n    | old |\n"));
+}
+
+#[test]
+fn save_collections() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate(
+        "
+    o := OrderedCollection new. o add: 1; add: 'two'.
+    d := Dictionary new. d at: #a put: o; at: 2 put: 3 -> 4.
+    b := #(1 1 2) asBag.
+    r := 1 to: 9 by: 2.
+    s := #(3 1 2) asSortedCollection.",
+    )
+    .unwrap();
+    let path = common::temp_path("save_collections.image");
+    w.save_image(&path).unwrap();
+    let r = in_new_session(
+        path.clone(),
+        &["(d at: #a) add: 3. o size.", "d at: 2.", "b occurrencesOf: 1.", "r.", "s add: 0. s."],
+    );
+    assert_eq!(
+        r,
+        vec!["3", "3->4", "2", "(1 to: 9 by: 2)", "a SortedCollection(0 1 2 3)"]
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn save_streams() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("r := 'abc' readStream. r next. s := WriteStream on: String new. s nextPutAll: 'xy'.")
+        .unwrap();
+    let path = common::temp_path("save_streams.image");
+    w.save_image(&path).unwrap();
+    let r = in_new_session(path.clone(), &["r upToEnd.", "s nextPut: $z. s contents."]);
+    assert_eq!(r, vec!["bc", "xyz"]);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn save_compiled_blocks() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: BlockMaker [
+        BlockMaker class >> adder [ ^[:x | x + self base] ]
+        BlockMaker class >> base [ ^100 ]
+        counter [ | count | count := 0. ^[count := count + 1] ]
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate("a := BlockMaker adder. c := BlockMaker new counter. c value. t := [:x :y :z | x + y + z].")
+        .unwrap();
+    let path = common::temp_path("save_compiled_blocks.image");
+    w.save_image(&path).unwrap();
+    let r = in_new_session(
+        path.clone(),
+        &["a printString", "a value: 1", "c value", "c value", "t value: 1 value: 2 value: 3"],
+    );
+    assert_eq!(r, vec!["a BlockClosure", "101", "2", "3", "6"]);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn restored_blocks_share_their_context() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: SharedCounter [
+        pair [ | n | n := 0. ^{[n := n + 1]. [n]} ]
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate("k := 1. get := [k]. inc := [k := k + 1]. p := SharedCounter new pair. p first value.")
+        .unwrap();
+    let path = common::temp_path("restored_blocks_share_their_context.image");
+    w.save_image(&path).unwrap();
+    let r = in_new_session(
+        path.clone(),
+        &["inc value. k", "get value", "k := 10. get value", "p first value. p last value"],
+    );
+    assert_eq!(r, vec!["2", "2", "10", "2"]);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn save_globals_and_pools() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Constant ImageHalf := 21.
+    Global ImageLog := OrderedCollection new.
+    Pool Shades [
+        Dark := 1.
+        Constant Light := 2.
+    ]
+    Object subclass: Shader [
+        <import: Shades>
+        dark [ ^Dark ]
+        dark: n [ Dark := n ]
+        lighten [ Light := 3 ]
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    w.evaluate("Smalltalk at: #ImageAdded put: 5. ImageLog add: 7. Shader new dark: 10.").unwrap();
+    let path = common::temp_path("save_globals_and_pools.image");
+    w.save_image(&path).unwrap();
+    let r = in_new_session(
+        path.clone(),
+        &[
+            "ImageAdded",
+            "Smalltalk at: #ImageHalf",
+            "[ImageHalf := 1] on: Error do: [:e | 'const']",
+            "ImageLog",
+            "Shader new dark",
+            "Shades at: #Light",
+            "[Shader new lighten] on: Error do: [:e | 'const']",
+        ],
+    );
+    assert_eq!(
+        r,
+        vec!["5", "21", "const", "an OrderedCollection(7)", "10", "2", "const"]
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn load_a_file_that_is_no_image() {
+    assert!(TRACING.clone());
+    let path = common::temp_path("no_image.image");
+    std::fs::write(&path, "Object subclass: Counter [ ]\n".repeat(100)).unwrap();
+    let e = Workspace::load_image(&path).err().unwrap();
+    assert_eq!(e.to_string(), format!("{} is not an image of version 3", path.display()));
+    let e = Workspace::new().save_image(&path.join("sub.image")).err().unwrap();
+    assert!(e.to_string().starts_with("can't write"));
+    std::fs::remove_file(path).unwrap();
+}
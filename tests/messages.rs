@@ -0,0 +1,64 @@
+use tt_rust::{define_classes, evaluate_script, TRACING};
+
+#[test]
+fn message_not_understood() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from(
+        "[3 foo] on: MessageNotUnderstood do: [:e | e messageText].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("Instance of SmallInteger did not understand #foo"));
+    let o = evaluate_script(String::from(
+        "[3 foo: 4 bar: 5] on: MessageNotUnderstood do: [:e | e message arguments].",
+    ))
+    .unwrap();
+    assert_eq!(format!("{}", o), "#(4 5)");
+    let o = evaluate_script(String::from(
+        "[(nil foo) + 1] on: MessageNotUnderstood do: [:e | e resume: 41].",
+    ))
+    .unwrap();
+    assert_eq!(o.as_int(), Some(42));
+}
+
+#[test]
+fn does_not_understand_override() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Proxy [
+        | target |
+        target: anObject [ target := anObject ]
+        doesNotUnderstand: aMessage [ ^aMessage sendTo: target ]
+    ]
+    Object subclass: Recorder [
+        doesNotUnderstand: aMessage [ ^aMessage selector ]
+    ]",
+    ))
+    .unwrap();
+    let o = evaluate_script(String::from("(Proxy new target: 3) + 4.")).unwrap();
+    assert_eq!(o.as_int(), Some(7));
+    let o = evaluate_script(String::from("Recorder new at: 1 put: 2.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("at:put:"));
+}
+
+#[test]
+fn unhandled_with_stack_trace() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Broken [
+        run [ ^[:x | self helper] value: 1 ]
+        helper [ ^3 zork ]
+    ]",
+    ))
+    .unwrap();
+    let e = evaluate_script(String::from("3 zork.")).err().unwrap();
+    let msg = format!("{}", e);
+    assert!(msg.contains("Instance of SmallInteger did not understand #zork"));
+    assert!(msg.contains("UndefinedObject>>DoIt"));
+    let e = evaluate_script(String::from("Broken new run.")).err().unwrap();
+    let lines: Vec<String> = format!("{}", e).lines().skip(1).map(String::from).collect();
+    assert_eq!(lines[0], "Broken>>helper (4:21)");
+    assert_eq!(lines[1], "[] in Broken>>run (3:27)");
+    assert_eq!(lines.last().unwrap(), "UndefinedObject>>DoIt (1:12)");
+}
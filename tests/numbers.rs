@@ -0,0 +1,78 @@
+use tt_rust::{code::compile_script, evaluate_script, MethodContext, TRACING};
+
+fn eval(src: &str) -> String {
+    let o = evaluate_script(String::from(src)).unwrap();
+    format!("{}", o)
+}
+
+#[test]
+fn float_and_radix_literals() {
+    assert!(TRACING.clone());
+    assert_eq!(eval("3.14."), "3.14");
+    assert_eq!(eval("1.5e10."), "15000000000.0");
+    assert_eq!(eval("16rFF."), "255");
+    assert_eq!(eval("2r1010 + 1e3."), "1010");
+    assert_eq!(eval("#(1.5 16r10)."), "#(1.5 16)");
+}
+
+#[test]
+fn negative_literals() {
+    assert!(TRACING.clone());
+    assert_eq!(eval("-7."), "-7");
+    assert_eq!(eval("3 - -7."), "10");
+    assert_eq!(eval("3-7."), "-4");
+    assert_eq!(eval("-7 abs."), "7");
+    assert_eq!(eval("-2.5 + 1."), "-1.5");
+    assert_eq!(eval("-9223372036854775808 class."), "SmallInteger");
+    assert_eq!(eval("-9223372036854775809."), "-9223372036854775809");
+    assert!(evaluate_script(String::from("3 + - 7.")).is_err());
+}
+
+#[test]
+fn large_integers() {
+    assert!(TRACING.clone());
+    assert_eq!(eval("9223372036854775807 + 1."), "9223372036854775808");
+    assert_eq!(eval("100000000000 * 100000000000."), "10000000000000000000000");
+    assert_eq!(eval("(100000000000000000000 - 99999999999999999999) + 1."), "2");
+    let o = evaluate_script(String::from("(100000000000000000000 // 100000000000) * 2.")).unwrap();
+    assert_eq!(o.as_int(), Some(2000000000));
+}
+
+#[test]
+fn fractions() {
+    assert!(TRACING.clone());
+    assert_eq!(eval("1 / 3."), "1/3");
+    assert_eq!(eval("(1 / 3) + (2 / 3)."), "1");
+    assert_eq!(eval("6 / 4."), "3/2");
+    assert_eq!(eval("(3 / 4) * 2."), "3/2");
+    assert_eq!(eval("(1 / 2) + 0.25."), "0.75");
+    assert_eq!(eval("(1 / 2) < (2 / 3)."), "True");
+}
+
+#[test]
+fn arithmetic_protocol() {
+    assert!(TRACING.clone());
+    assert_eq!(eval("7 - 10."), "-3");
+    assert_eq!(eval("(0 - 7) // 2."), "-4");
+    assert_eq!(eval("(0 - 7) \\\\ 2."), "1");
+    assert_eq!(eval("(0 - 7) rem: 2."), "-1");
+    assert_eq!(eval("3 <= 3."), "True");
+    assert_eq!(eval("3 >= 4."), "False");
+    assert_eq!(eval("3 ~= 4."), "True");
+    assert_eq!(eval("3 = 3.0."), "True");
+    assert_eq!(eval("(3 - 5) abs."), "2");
+    assert_eq!(eval("16 sqrt."), "4.0");
+    assert_eq!(eval("3 max: 4.5."), "4.5");
+    assert_eq!(eval("(7 / 2) truncated."), "3");
+}
+
+#[test]
+fn compile_numbers() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("(1 / 3) + 2.5.")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(format!("{}", r), "2.8333333333333335");
+    let code = compile_script(String::from("(100000000000000000000 // 16r10) \\\\ 7.")).unwrap();
+    let r = code.run(MethodContext::new());
+    assert_eq!(format!("{}", r), (100000000000000000000u128 / 16 % 7).to_string());
+}
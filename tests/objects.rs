@@ -4,10 +4,10 @@ use tt_rust::{evaluate_script, runtime::sel::SelectorSet, TRACING};
 fn string_format() {
     assert!(TRACING.clone());
     let o = evaluate_script(String::from("
-    'Five is {0}.' format: {1 + 4}.
+    'Five is {1}.' format: {1 + 4}.
     ")).unwrap();
     SelectorSet::stats();
-    assert_eq!(o.as_str(), Some("Five is 5."));
+    assert_eq!(o.as_text().as_deref(), Some("Five is 5."));
 }
 
 #[test]
@@ -17,7 +17,7 @@ fn eval_bug_1(){
         '' species new: 10 streamContents: [ :result | result nextPut: $X ].
     ")).unwrap();
     SelectorSet::stats();
-    assert_eq!(o.as_str(), Some("X"));
+    assert_eq!(o.as_text().as_deref(), Some("X"));
 }
 
 #[test]
@@ -105,3 +105,55 @@ fn eval_to_7() {
 }
 
 
+
+#[test]
+fn cascade() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("
+        '' species new: 10 streamContents: [ :s | s nextPut: $a; nextPut: $b; nextPut: $c ].
+    ")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("abc"));
+}
+
+#[test]
+fn cascade_result() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("
+        a := 3 @ 4.
+        a x; y.
+    ")).unwrap();
+    assert_eq!(o.as_int(), Some(4));
+}
+
+#[test]
+fn symbols() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("#foo:bar: == #foo:bar:.")).unwrap();
+    assert_eq!(format!("{}", o), "True");
+    let o = evaluate_script(String::from("#foo == 'foo'.")).unwrap();
+    assert_eq!(format!("{}", o), "False");
+    let o = evaluate_script(String::from("#at:put: numArgs.")).unwrap();
+    assert_eq!(o.as_int(), Some(2));
+    let o = evaluate_script(String::from("#'hello world' size.")).unwrap();
+    assert_eq!(o.as_int(), Some(11));
+}
+
+#[test]
+fn literal_arrays() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("#(1 $a 'x' #(2 3) foo true) size.")).unwrap();
+    assert_eq!(o.as_int(), Some(6));
+    let o = evaluate_script(String::from("(#(1 #(2 3)) at: 2) at: 1.")).unwrap();
+    assert_eq!(o.as_int(), Some(2));
+    let o = evaluate_script(String::from("#(1 foo #+ (2 3)).")).unwrap();
+    assert_eq!(format!("{}", o), "#(1 #foo #+ #(2 3))");
+    let o = evaluate_script(String::from("#[1 2 255].")).unwrap();
+    assert_eq!(format!("{}", o), "#[1 2 255]");
+}
+
+#[test]
+fn script_return() {
+    assert!(TRACING.clone());
+    let o = evaluate_script(String::from("a := 1. a < 2 ifTrue: [^'early']. 'late'.")).unwrap();
+    assert_eq!(o.as_text().as_deref(), Some("early"));
+}
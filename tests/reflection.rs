@@ -0,0 +1,139 @@
+use tt_rust::{define_classes, Workspace, TRACING};
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn classes_of_objects() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("SmallInteger", eval(&mut w, "3 class."));
+    assert_eq!("True", eval(&mut w, "3 class == SmallInteger."));
+    assert_eq!("String", eval(&mut w, "'abc' class."));
+    assert_eq!("OrderedCollection", eval(&mut w, "OrderedCollection new class."));
+    assert_eq!("UndefinedObject", eval(&mut w, "nil class."));
+    assert_eq!("SmallInteger class", eval(&mut w, "3 class class."));
+    assert_eq!("Metaclass", eval(&mut w, "3 class class class."));
+    assert_eq!("Integer", eval(&mut w, "SmallInteger superclass."));
+    assert_eq!("Nil", eval(&mut w, "Object superclass."));
+    assert_eq!("True", eval(&mut w, "3 isKindOf: Number."));
+    assert_eq!("False", eval(&mut w, "3 isKindOf: String."));
+    assert_eq!("True", eval(&mut w, "#abc isKindOf: String."));
+    assert_eq!("False", eval(&mut w, "3 isMemberOf: Integer."));
+    assert_eq!("True", eval(&mut w, "SmallInteger inheritsFrom: Magnitude."));
+    assert_eq!("False", eval(&mut w, "SmallInteger inheritsFrom: SmallInteger."));
+}
+
+#[test]
+fn identity_and_printing() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("True", eval(&mut w, "nil == nil."));
+    assert_eq!("True", eval(&mut w, "$a == $a."));
+    assert_eq!("False", eval(&mut w, "'abc' == 'abc'."));
+    assert_eq!("True", eval(&mut w, "'abc' = 'abc'."));
+    assert_eq!("True", eval(&mut w, "#abc == #abc."));
+    assert_eq!("True", eval(&mut w, "'abc' ~~ 'abc'."));
+    assert_eq!("False", eval(&mut w, "3 ~~ 3."));
+    assert_eq!("'abc'", eval(&mut w, "'abc' printString."));
+    assert_eq!("abc", eval(&mut w, "'abc' displayString."));
+    assert_eq!("#(1 2)", eval(&mut w, "#(1 2) printString."));
+    assert_eq!("#abc", eval(&mut w, "#abc printString."));
+}
+
+#[test]
+fn computed_sends() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("True", eval(&mut w, "3 respondsTo: #+."));
+    assert_eq!("True", eval(&mut w, "3 respondsTo: #printString."));
+    assert_eq!("False", eval(&mut w, "3 respondsTo: #foo."));
+    assert_eq!("True", eval(&mut w, "'abc' respondsTo: #asUppercase."));
+    assert_eq!("True", eval(&mut w, "OrderedCollection new respondsTo: #inject:into:."));
+    assert_eq!("-3", eval(&mut w, "3 perform: #negated."));
+    assert_eq!("7", eval(&mut w, "3 perform: #+ with: 4."));
+    assert_eq!("bc", eval(&mut w, "'abc' perform: 'copyFrom:to:' with: 2 with: 3."));
+    assert_eq!("bc", eval(&mut w, "'abc' perform: #copyFrom:to: withArguments: #(2 3)."));
+    assert_eq!(
+        "wrong",
+        eval(&mut w, "[3 perform: #+ with: 1 with: 2] on: Error do: [:e | 'wrong']."),
+    );
+}
+
+#[test]
+fn instance_variables() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: ReflectedPoint [
+        | x y |
+        x: ax y: ay [ x := ax. y := ay ]
+        x [ ^x ]
+    ]
+    ReflectedPoint subclass: ReflectedPoint3 [
+        | z |
+    ]",
+    ))
+    .unwrap();
+    let mut w = Workspace::new();
+    eval(&mut w, "p := ReflectedPoint3 new x: 1 y: 2.");
+    assert_eq!("1", eval(&mut w, "p instVarAt: 1."));
+    assert_eq!("2", eval(&mut w, "p instVarNamed: 'y'."));
+    assert_eq!("Nil", eval(&mut w, "p instVarNamed: #z."));
+    eval(&mut w, "p instVarAt: 1 put: 5.");
+    assert_eq!("5", eval(&mut w, "p x."));
+    eval(&mut w, "p instVarNamed: #z put: 3.");
+    assert_eq!("3", eval(&mut w, "p instVarAt: 3."));
+    assert_eq!("#(#x #y #z)", eval(&mut w, "ReflectedPoint3 allInstVarNames."));
+    assert_eq!("#(#z)", eval(&mut w, "ReflectedPoint3 instanceVariableNames."));
+    assert_eq!("#(#x #x:y:)", eval(&mut w, "ReflectedPoint selectors."));
+    assert_eq!("True", eval(&mut w, "ReflectedPoint3 canUnderstand: #x:y:."));
+    assert_eq!("False", eval(&mut w, "ReflectedPoint3 includesSelector: #x:y:."));
+    assert_eq!("True", eval(&mut w, "p isKindOf: ReflectedPoint."));
+    assert_eq!("x\n    ^x", eval(&mut w, "ReflectedPoint sourceCodeAt: #x."));
+    assert_eq!("oob", eval(&mut w, "[p instVarAt: 4] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("nf", eval(&mut w, "[p instVarNamed: #w] on: NotFound do: [:e | 'nf']."));
+}
+
+#[test]
+fn system_classes() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("True", eval(&mut w, "Smalltalk allClasses includes: OrderedCollection."));
+    assert_eq!("True", eval(&mut w, "Smalltalk classNames includes: #Object."));
+    assert_eq!("Dictionary", eval(&mut w, "Smalltalk at: #Dictionary."));
+    assert_eq!("True", eval(&mut w, "Smalltalk includesKey: #Set."));
+    assert_eq!("none", eval(&mut w, "Smalltalk at: #NoSuchClass ifAbsent: ['none']."));
+    assert_eq!("Bag", eval(&mut w, "(Smalltalk at: #Bag) name."));
+    assert_eq!("2", eval(&mut w, "((Smalltalk at: #Bag) withAll: #(4 4)) perform: #size."));
+}
+
+#[test]
+fn native_instance_variables() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    eval(&mut w, "p := 3 @ 4.");
+    assert_eq!("3", eval(&mut w, "p instVarAt: 1."));
+    assert_eq!("4", eval(&mut w, "p instVarNamed: #y."));
+    eval(&mut w, "p instVarAt: 2 put: 7.");
+    assert_eq!("3@7", eval(&mut w, "p."));
+    assert_eq!("err", eval(&mut w, "[p instVarAt: 1 put: 'a'] on: Error do: [:e | 'err']."));
+    assert_eq!("oob", eval(&mut w, "[p instVarAt: 3] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("oob", eval(&mut w, "[3 instVarAt: 1] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+}
+
+#[test]
+fn native_protocols() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("True", eval(&mut w, "#[1 2 3] respondsTo: #inject:into:."));
+    assert_eq!("6", eval(&mut w, "#[1 2 3] inject: 0 into: [:a :b | a + b]."));
+    assert_eq!("True", eval(&mut w, "3 respondsTo: #numerator."));
+    assert_eq!("3", eval(&mut w, "3 numerator."));
+    assert_eq!("True", eval(&mut w, "(1 to: 3) respondsTo: #increment."));
+    assert_eq!("False", eval(&mut w, "(ReadStream on: #()) respondsTo: #nextPut:."));
+    assert_eq!("True", eval(&mut w, "(ReadWriteStream on: String new) respondsTo: #nextPut:."));
+    assert_eq!("True", eval(&mut w, "(3 @ 4) respondsTo: #x."));
+    assert_eq!("True", eval(&mut w, "(Point selectors) includes: #x."));
+}
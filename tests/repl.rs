@@ -0,0 +1,112 @@
+use std::thread;
+
+use tt_rust::{
+    repl::{is_complete, Command, Session},
+    TRACING,
+};
+
+mod common;
+
+fn run(s: &mut Session, input: &str) -> String {
+    s.run(input).unwrap()
+}
+
+#[test]
+fn commands() {
+    assert!(TRACING.clone());
+    assert_eq!(Ok(Command::PrintIt("3 + 4.")), Command::parse(" 3 + 4.\n"));
+    assert_eq!(Ok(Command::DoIt("x := 3.")), Command::parse(":do x := 3."));
+    assert_eq!(Ok(Command::InspectIt("x")), Command::parse(":inspect x"));
+    assert_eq!(Ok(Command::Load("a.st")), Command::parse(":load a.st"));
+    assert_eq!(Ok(Command::Save(None)), Command::parse(":save"));
+    assert_eq!(Ok(Command::Quit), Command::parse(":quit"));
+    assert!(Command::parse(":load").is_err());
+    assert!(Command::parse(":frobnicate").is_err());
+}
+
+#[test]
+fn variables_survive_between_inputs() {
+    assert!(TRACING.clone());
+    let mut s = Session::new(&common::temp_path("variables.image"));
+    assert_eq!("", run(&mut s, ":do x := 3 + 4."));
+    assert_eq!("10", run(&mut s, "x + 3."));
+    assert_eq!("'abc'", run(&mut s, ":print y := 'abc'."));
+    assert_eq!("'abcabc'", run(&mut s, "y , y."));
+    assert_eq!("#(1 2) (Array)\n  [0]: 1\n  [1]: 2", run(&mut s, ":inspect #(1 2)."));
+    assert!(run(&mut s, "x foo.").starts_with("error: "));
+    assert!(run(&mut s, ":help").contains(":inspect"));
+    assert_eq!(None, s.run(":quit"));
+}
+
+#[test]
+fn multi_line_input() {
+    assert!(TRACING.clone());
+    assert!(is_complete("3 + 4."));
+    assert!(!is_complete("#(1 2) do: [:x |\n"));
+    assert!(is_complete("#(1 2) do: [:x |\n x printNl]."));
+    assert!(!is_complete("'an open ( string"));
+    assert!(is_complete("'a closed [ string'"));
+    assert!(!is_complete("\"a comment"));
+    assert!(is_complete("$( printNl."));
+    assert!(is_complete(":load x.st"));
+    let mut s = Session::new(&common::temp_path("multi_line.image"));
+    assert_eq!(
+        "6",
+        run(&mut s, "#(1 2 3) inject: 0\n    into: [:a :b |\n        a + b].")
+    );
+}
+
+#[test]
+fn completion() {
+    assert!(TRACING.clone());
+    let mut s = Session::new(&common::temp_path("completion.image"));
+    run(&mut s, ":do counterValue := 3.");
+    assert_eq!((0, vec!["counterValue".to_string()]), s.completions("counterV", 8));
+    let (start, names) = s.completions("x := OrderedColl", 16);
+    assert_eq!(5, start);
+    assert!(names.contains(&"OrderedCollection".to_string()));
+    let (start, names) = s.completions("#(1 2) inject", 13);
+    assert_eq!(7, start);
+    assert!(names.contains(&"inject:into:".to_string()));
+    assert_eq!((3, vec![]), s.completions("3 + 4", 3));
+}
+
+#[test]
+fn load_and_save() {
+    assert!(TRACING.clone());
+    let source = common::temp_path("repl_counter.st");
+    std::fs::write(
+        &source,
+        "Object subclass: #ReplCounter
+    instanceVariableNames: 'count'
+    classVariableNames: ''
+    category: 'Repl'!
+
+!ReplCounter methodsFor: 'counting'!
+increment
+    count := (count ifNil: [0]) + 1
+! !
+",
+    )
+    .unwrap();
+    let image = common::temp_path("repl_counter.image");
+    let mut s = Session::new(&image);
+    assert_eq!(
+        "loaded ReplCounter",
+        run(&mut s, &format!(":load {}", source.display()))
+    );
+    run(&mut s, ":do c := ReplCounter new. c increment; increment.");
+    assert_eq!("a ReplCounter (ReplCounter)\n  count: 2", run(&mut s, ":inspect c."));
+    assert_eq!(format!("saved {}", image.display()), run(&mut s, ":save"));
+    let path = image.clone();
+    let loaded = thread::spawn(move || {
+        let mut s = Session::open(&path).unwrap();
+        run(&mut s, "c increment; yourself.");
+        run(&mut s, "c instVarNamed: #count.")
+    })
+    .join()
+    .unwrap();
+    assert_eq!("3", loaded);
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(image).unwrap();
+}
@@ -0,0 +1,79 @@
+use std::fs;
+
+use tt_rust::{
+    evaluate_script,
+    repo::{self, MethodRepository},
+    TRACING,
+};
+
+mod common;
+
+#[test]
+fn versions_and_rollback() {
+    assert!(TRACING.clone());
+    let r = MethodRepository::open(None);
+    let v1 = r.store("Point", "double\n    ^ self * 2", "ann").unwrap();
+    let v2 = r.store("Point", "double\n    | two |\n    two := 2.\n    ^ self * two", "bob").unwrap();
+    assert_eq!((v1.version, v2.version), (1, 2));
+    assert_eq!(v1.selector, "double");
+    // storing the current source again is not a new version
+    assert_eq!(r.store("Point", &v2.source, "bob").unwrap().version, 2);
+
+    let authors: Vec<String> = r.history("Point", "double").into_iter().map(|m| m.author).collect();
+    assert_eq!(authors, vec!["ann", "bob"]);
+    assert_eq!(
+        r.diff("Point", "double", 1, 2).unwrap(),
+        "  double\n-     ^ self * 2\n+     | two |\n+     two := 2.\n+     ^ self * two"
+    );
+
+    let v3 = r.rollback("Point", "double", 1, "ann").unwrap();
+    assert_eq!(v3.version, 3);
+    assert_eq!(r.current("Point", "double").unwrap().source, v1.source);
+    assert_eq!(r.history("Point", "double").len(), 3);
+    assert!(r.diff("Point", "double", 1, 7).is_err());
+    assert!(r.store("Point", "double [", "ann").is_err());
+}
+
+#[test]
+fn import_and_export_defs() {
+    assert!(TRACING.clone());
+    let r = MethodRepository::open(None);
+    assert_eq!(r.import_defs("defs".as_ref(), "defs").unwrap(), 1);
+    assert_eq!(r.classes(), vec!["String"]);
+    assert_eq!(r.selectors("String"), vec!["format:"]);
+
+    let dir = common::temp_path("export_defs");
+    assert_eq!(r.export_defs(&dir).unwrap(), 1);
+    assert_eq!(
+        fs::read_to_string(dir.join("string/format_")).unwrap(),
+        fs::read_to_string("defs/string/format_").unwrap()
+    );
+    fs::remove_dir_all(&dir).unwrap();
+
+    // only the initial changes case, so the class name survives the round trip
+    r.store("OrderedCollection", "second\n    ^ self at: 2", "ann").unwrap();
+    assert_eq!(r.export_defs(&dir).unwrap(), 2);
+    assert!(dir.join("orderedCollection/second").exists());
+    let back = MethodRepository::open(None);
+    assert_eq!(back.import_defs(&dir, "defs").unwrap(), 2);
+    assert_eq!(back.classes(), vec!["OrderedCollection", "String"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stored_methods_come_from_the_repository() {
+    assert!(TRACING.clone());
+    let dir = common::temp_path("stored_methods");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("methods.db");
+    let r = MethodRepository::open(path.to_str());
+    r.store("String", "shout\n    ^ 42", "ann").unwrap();
+    repo::install(r);
+    let o = evaluate_script(String::from("'abc' shout.")).unwrap();
+    assert_eq!(o.as_int(), Some(42));
+
+    // the versions are kept in the file
+    let r = MethodRepository::open(path.to_str());
+    assert_eq!(r.current("String", "shout").unwrap().version, 1);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,130 @@
+use tt_rust::{Workspace, TRACING};
+
+mod common;
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn read_streams() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("s := ReadStream on: 'hello world'.").unwrap();
+    assert_eq!("h", eval(&mut w, "s peek."));
+    assert_eq!("hel", eval(&mut w, "s next: 3."));
+    assert_eq!("o", eval(&mut w, "s skip: 1. s next."));
+    assert_eq!(" wor", eval(&mut w, "s upTo: $l."));
+    assert_eq!("False", eval(&mut w, "s atEnd."));
+    assert_eq!("d", eval(&mut w, "s upToEnd."));
+    assert_eq!("True", eval(&mut w, "s atEnd."));
+    assert_eq!("Nil", eval(&mut w, "s next."));
+    assert_eq!("hello world", eval(&mut w, "s contents."));
+    w.evaluate("s := 'one\r\ntwo\nthree' readStream.").unwrap();
+    assert_eq!("one", eval(&mut w, "s nextLine."));
+    assert_eq!("two", eval(&mut w, "s nextLine."));
+    assert_eq!("three", eval(&mut w, "s nextLine."));
+    assert_eq!("Nil", eval(&mut w, "s nextLine."));
+    assert_eq!(
+        "#(2 3)",
+        eval(&mut w, "s := ReadStream on: #(1 2 3 4). s next. s next: 2.")
+    );
+    assert_eq!("9", eval(&mut w, "[s nextPut: 5] on: Error do: [:e | 9]."));
+}
+
+#[test]
+fn write_streams() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("s := WriteStream on: String new.").unwrap();
+    assert_eq!(
+        "ab\tc d\n12",
+        eval(
+            &mut w,
+            "s nextPutAll: 'ab'; tab; nextPut: $c; space; nextPutAll: 'd'; cr; print: 12. s contents."
+        )
+    );
+    assert_eq!(
+        "abc!",
+        eval(&mut w, "s := WriteStream with: 'abc'. s nextPut: $!. s contents.")
+    );
+    assert_eq!(
+        "#(1 2 3)",
+        eval(
+            &mut w,
+            "s := WriteStream on: #(). s nextPut: 1; nextPutAll: #(2 3). s contents."
+        )
+    );
+    assert_eq!(
+        "Five is 5.",
+        eval(
+            &mut w,
+            "String new: 10 streamContents: [:s | s nextPutAll: 'Five is '; print: 5; nextPut: $.]."
+        )
+    );
+    assert_eq!(
+        "7",
+        eval(
+            &mut w,
+            "[(WriteStream on: String new) nextPut: 3] on: Error do: [:e | 7]."
+        )
+    );
+}
+
+#[test]
+fn read_write_streams() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    w.evaluate("s := ReadWriteStream on: String new. s nextPutAll: 'abcdef'.")
+        .unwrap();
+    assert_eq!("True", eval(&mut w, "s atEnd."));
+    assert_eq!("abc", eval(&mut w, "s reset. s next: 3."));
+    assert_eq!("abcXYf", eval(&mut w, "s nextPutAll: 'XY'. s contents."));
+    assert_eq!("f", eval(&mut w, "s upToEnd."));
+    assert_eq!(
+        "abc",
+        eval(&mut w, "s := ReadWriteStream with: 'abc'. s reset. s upToEnd.")
+    );
+}
+
+#[test]
+fn file_streams() {
+    assert!(TRACING.clone());
+    let path = common::temp_path("file_streams.txt");
+    let path = path.to_str().unwrap();
+    let mut w = Workspace::new();
+    w.evaluate(&format!(
+        "f := FileStream forceNewFileNamed: '{}'. f nextPutAll: 'first'; cr; nextPutAll: 'second'; cr. f close.",
+        path
+    ))
+    .unwrap();
+    assert_eq!("first\nsecond\n", std::fs::read_to_string(path).unwrap());
+    assert_eq!(
+        "an OrderedCollection(first second)",
+        eval(
+            &mut w,
+            &format!(
+                "f := FileStream readOnlyFileNamed: '{}'. lines := OrderedCollection new. \
+                [f atEnd] whileFalse: [lines add: f nextLine]. f close. lines.",
+                path
+            )
+        )
+    );
+    w.evaluate(&format!(
+        "f := FileStream fileNamed: '{}'. f setToEnd. f nextPutAll: 'third'. f close.",
+        path
+    ))
+    .unwrap();
+    assert_eq!("first\nsecond\nthird", std::fs::read_to_string(path).unwrap());
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(
+        "3",
+        eval(
+            &mut w,
+            &format!(
+                "[FileStream readOnlyFileNamed: '{}'] on: FileDoesNotExist do: [:e | 3].",
+                path
+            )
+        )
+    );
+}
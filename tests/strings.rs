@@ -0,0 +1,85 @@
+use tt_rust::{Workspace, TRACING};
+
+fn eval(w: &mut Workspace, source: &str) -> String {
+    format!("{}", w.evaluate(source).unwrap())
+}
+
+#[test]
+fn string_protocol() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("hello world", eval(&mut w, "'hello', ' ', 'world'."));
+    assert_eq!("ab", eval(&mut w, "'a', #b."));
+    assert_eq!("True", eval(&mut w, "'abc' = 'abc'."));
+    assert_eq!("False", eval(&mut w, "'abc' = 'abd'."));
+    assert_eq!("True", eval(&mut w, "'abc' < 'abd'."));
+    assert_eq!("False", eval(&mut w, "'b' <= 'abc'."));
+    assert_eq!("True", eval(&mut w, "'Hello' sameAs: 'hELLO'."));
+    assert_eq!("ell", eval(&mut w, "'hello' copyFrom: 2 to: 4."));
+    assert_eq!("", eval(&mut w, "'hello' copyFrom: 2 to: 1."));
+    assert_eq!("3", eval(&mut w, "'hello' indexOf: $l."));
+    assert_eq!("0", eval(&mut w, "'hello' indexOf: $z."));
+    assert_eq!("True", eval(&mut w, "'hello world' includesSubstring: 'o w'."));
+    assert_eq!("HELLO", eval(&mut w, "'Hello' asUppercase."));
+    assert_eq!("hello", eval(&mut w, "'Hello' asLowercase."));
+    assert_eq!("a b", eval(&mut w, "'  a b  ' trimBoth."));
+    assert_eq!("#(a b c)", eval(&mut w, "'a,b,,c' substrings: ','."));
+    assert_eq!("#(a b)", eval(&mut w, "'a b' substrings."));
+    assert_eq!("#abc", eval(&mut w, "'abc' asSymbol."));
+    assert_eq!("42", eval(&mut w, "'42' asNumber + 0."));
+    assert_eq!("2.5", eval(&mut w, "'2.5' asNumber."));
+    assert_eq!("Nil", eval(&mut w, "'abc' asNumber."));
+    assert_eq!("cba", eval(&mut w, "'abc' reversed."));
+    assert_eq!("#(one two)", eval(&mut w, "'one\ntwo' lines."));
+    assert_eq!("#(97 98)", eval(&mut w, "'ab' collect: [:c | c asInteger]."));
+    assert_eq!(
+        "8",
+        eval(
+            &mut w,
+            "[('abc' copyFrom: 1 to: 5) size] on: SubscriptOutOfBounds do: [:e | 8]."
+        )
+    );
+}
+
+#[test]
+fn indices_count_from_one() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("h", eval(&mut w, "'hello' at: 1."));
+    assert_eq!("True", eval(&mut w, "('hello' at: 1) = 'hello' first."));
+    assert_eq!("o", eval(&mut w, "'hello' at: 5."));
+    assert_eq!("hello", eval(&mut w, "'hello' copyFrom: 1 to: 5."));
+    assert_eq!("1", eval(&mut w, "'hello' indexOf: $h."));
+    assert_eq!("True", eval(&mut w, "('hello' readStream next) = ('hello' at: 1)."));
+    assert_eq!("oob", eval(&mut w, "['hello' at: 0] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+    assert_eq!("oob", eval(&mut w, "['hello' copyFrom: 0 to: 2] on: SubscriptOutOfBounds do: [:e | 'oob']."));
+}
+
+#[test]
+fn unicode_text() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("5", eval(&mut w, "'grüße' size."));
+    assert_eq!("ü", eval(&mut w, "'grüße' at: 3."));
+    assert_eq!("üß", eval(&mut w, "'grüße' copyFrom: 3 to: 4."));
+    assert_eq!("4", eval(&mut w, "'grüße' indexOf: $ß."));
+    assert_eq!("eßürg", eval(&mut w, "'grüße' reversed."));
+    assert_eq!("ÄBC", eval(&mut w, "'äbc' asUppercase."));
+}
+
+#[test]
+fn character_protocol() {
+    assert!(TRACING.clone());
+    let mut w = Workspace::new();
+    assert_eq!("True", eval(&mut w, "$a isVowel."));
+    assert_eq!("False", eval(&mut w, "$b isVowel."));
+    assert_eq!("True", eval(&mut w, "$7 isDigit."));
+    assert_eq!("False", eval(&mut w, "$x isDigit."));
+    assert_eq!("97", eval(&mut w, "$a asInteger."));
+    assert_eq!("A", eval(&mut w, "$a asUppercase."));
+    assert_eq!("b", eval(&mut w, "98 asCharacter."));
+    assert_eq!("True", eval(&mut w, "$a < $b."));
+    assert_eq!("True", eval(&mut w, "$a = $a."));
+    assert_eq!("True", eval(&mut w, "$ü isLetter."));
+    assert_eq!("7", eval(&mut w, "$7 digitValue."));
+}
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use tt_rust::{
+    code::compile_script, define_classes, evaluate_script, runtime::val::Value, MethodContext, TRACING,
+};
+
+#[test]
+fn immediate_values() {
+    assert!(TRACING.clone());
+    let code = compile_script(String::from("a := 3 + 4. b := $x. c := a > 5. d := nil.")).unwrap();
+    let ctx = MethodContext::new();
+    code.run(ctx.clone());
+    let vars = ctx.variables();
+    let vars = vars.borrow();
+    assert!(matches!(vars["a"], Value::Int(7)));
+    assert!(matches!(vars["b"], Value::Char('x')));
+    assert!(matches!(vars["c"], Value::Bool(true)));
+    assert!(matches!(vars["d"], Value::Nil));
+    assert_eq!("7", format!("{}", vars["a"]));
+}
+
+#[test]
+fn small_integer_overflow() {
+    assert!(TRACING.clone());
+    let r = evaluate_script(String::from("n := 4611686018427387904. n + n.")).unwrap();
+    assert_eq!("9223372036854775808", format!("{}", r));
+    let r = evaluate_script(String::from("n := 9223372036854775807. n * 2 // 2.")).unwrap();
+    assert_eq!("9223372036854775807", format!("{}", r));
+    assert_eq!(Some(-4), evaluate_script(String::from("n := 0 - 7. n // 2.")).unwrap().as_int());
+    assert_eq!(Some(1), evaluate_script(String::from("n := 0 - 7. n \\\\ 2.")).unwrap().as_int());
+    let v = Value::Int(isize::MAX).send("+", vec![Value::Int(1)]);
+    assert!(matches!(v, Value::Object(_)));
+    assert_eq!("LargeInteger", v.class_name());
+}
+
+/// a loop summing a million integers, run it with
+/// `cargo test --release --test values -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn sum_a_million() {
+    assert!(TRACING.clone());
+    define_classes(String::from(
+        "
+    Object subclass: Bench [
+        sumTo: n [ | sum | sum := 0. 1 to: n do: [:k | sum := sum + k]. ^sum ]
+    ]",
+    ))
+    .unwrap();
+    let start = Instant::now();
+    let r = evaluate_script(String::from("Bench new sumTo: 1000000.")).unwrap();
+    println!("sum of a million integers in {:?}", start.elapsed());
+    assert_eq!(Some(500000500000), r.as_int());
+}